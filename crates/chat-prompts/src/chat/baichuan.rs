@@ -108,4 +108,15 @@ impl BuildChatPrompt for Baichuan2ChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["用户:"]
+    }
+}
+
+#[test]
+fn test_post_process_baichuan2() {
+    let prompt = Baichuan2ChatPrompt;
+    assert_eq!(prompt.post_process(" Hello!\n\n用户:"), "Hello!");
+    assert_eq!(prompt.post_process("Hello!\n"), "Hello!");
 }
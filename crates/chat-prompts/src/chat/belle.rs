@@ -85,4 +85,15 @@ impl BuildChatPrompt for HumanAssistantChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["Human:"]
+    }
+}
+
+#[test]
+fn test_post_process_human_assistant() {
+    let prompt = HumanAssistantChatPrompt;
+    assert_eq!(prompt.post_process("Hello!\nHuman: Hi"), "Hello!");
+    assert_eq!(prompt.post_process(" Hello! "), "Hello!");
 }
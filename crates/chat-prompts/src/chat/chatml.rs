@@ -1,5 +1,8 @@
-use super::BuildChatPrompt;
-use crate::error::{PromptError, Result};
+use super::{truncate_at_end_of_turn, BuildChatPrompt};
+use crate::{
+    error::{PromptError, Result},
+    tool::{PartialToolCalls, ToolCallName, ToolCallParser, ToolCallSyntax},
+};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionSystemMessage,
     ChatCompletionToolMessage, ChatCompletionUserMessage, ChatCompletionUserMessageContent,
@@ -137,6 +140,14 @@ impl BuildChatPrompt for ChatMLPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|im_end|>", "<|im_start|>"]
+    }
+
+    fn post_process(&self, output: &str) -> String {
+        post_process_chatml(output, self.end_of_turn_markers())
+    }
 }
 
 /// Generate prompts for the models using ChatML template.
@@ -281,6 +292,19 @@ impl ChatMLToolPrompt {
         )
     }
 }
+impl ToolCallParser for ChatMLToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: "<tool_call>",
+            end: Some("</tool_call>"),
+            leading: false,
+            name: ToolCallName::Field {
+                arguments: "arguments",
+            },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for ChatMLToolPrompt {
     fn build(&self, messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         if messages.is_empty() {
@@ -367,6 +391,14 @@ impl BuildChatPrompt for ChatMLToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|im_end|>", "<|im_start|>"]
+    }
+
+    fn post_process(&self, output: &str) -> String {
+        post_process_chatml(output, self.end_of_turn_markers())
+    }
 }
 
 /// Generate prompts for InternLM-2.5 models in tool use scenario.
@@ -500,6 +532,19 @@ impl InternLM2ToolPrompt {
         )
     }
 }
+impl ToolCallParser for InternLM2ToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: "<|action_start|><|plugin|>",
+            end: Some("<|action_end|>"),
+            leading: false,
+            name: ToolCallName::Field {
+                arguments: "parameters",
+            },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for InternLM2ToolPrompt {
     fn build(&self, messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         if messages.is_empty() {
@@ -584,4 +629,37 @@ impl BuildChatPrompt for InternLM2ToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|im_end|>", "<|im_start|>"]
+    }
+
+    fn post_process(&self, output: &str) -> String {
+        post_process_chatml(output, self.end_of_turn_markers())
+    }
+}
+
+/// Cleans up the output of the models using ChatML template, which sometimes starts with the separator after the role.
+pub(super) fn post_process_chatml(output: &str, markers: &[&str]) -> String {
+    let output = truncate_at_end_of_turn(output, markers).trim();
+
+    output.strip_prefix(": ").unwrap_or(output).to_owned()
+}
+
+#[test]
+fn test_post_process_chatml() {
+    for prompt in [
+        &ChatMLPrompt as &dyn BuildChatPrompt,
+        &ChatMLToolPrompt,
+        &InternLM2ToolPrompt,
+    ] {
+        assert_eq!(prompt.post_process("Hello!<|im_end|>"), "Hello!");
+        assert_eq!(
+            prompt.post_process("Hello!<|im_end|>\n<|im_start|>user\nHi<|im_end|>"),
+            "Hello!"
+        );
+        assert_eq!(prompt.post_process("Hello!\n<|im_start|>user"), "Hello!");
+        assert_eq!(prompt.post_process(": Hello!<|im_end|>"), "Hello!");
+        assert_eq!(prompt.post_process(" Hello!\n"), "Hello!");
+    }
 }
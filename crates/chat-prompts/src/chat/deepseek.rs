@@ -85,6 +85,10 @@ impl BuildChatPrompt for DeepseekChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|end_of_sentence|>"]
+    }
 }
 
 /// Generate prompts for the `DeepSeek-Coder` model.
@@ -194,6 +198,10 @@ impl BuildChatPrompt for DeepseekCoderPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|EOT|>"]
+    }
 }
 
 /// Generate prompts for the `DeepSeek-V2` models.
@@ -303,6 +311,10 @@ impl BuildChatPrompt for DeepseekChat2Prompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|end_of_sentence|>"]
+    }
 }
 
 /// Generate prompts for the `DeepSeek-V2.5` models.
@@ -413,4 +425,28 @@ impl BuildChatPrompt for DeepseekChat25Prompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|end_of_sentence|>"]
+    }
+}
+
+#[test]
+fn test_post_process_deepseek() {
+    for prompt in [
+        &DeepseekChatPrompt as &dyn BuildChatPrompt,
+        &DeepseekChat2Prompt,
+        &DeepseekChat25Prompt,
+    ] {
+        assert_eq!(
+            prompt.post_process("Hello!<|end_of_sentence|>User: Hi"),
+            "Hello!"
+        );
+    }
+
+    let prompt = DeepseekCoderPrompt;
+    assert_eq!(
+        prompt.post_process("```rust\nfn main() {}\n```\n<|EOT|>"),
+        "```rust\nfn main() {}\n```"
+    );
 }
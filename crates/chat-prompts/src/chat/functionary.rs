@@ -1,5 +1,8 @@
 use super::BuildChatPrompt;
-use crate::error::{PromptError, Result};
+use crate::{
+    error::{PromptError, Result},
+    tool::{PartialToolCalls, ToolCallName, ToolCallParser, ToolCallSyntax},
+};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionToolMessage,
     ChatCompletionUserMessage, ChatCompletionUserMessageContent, ContentPart, Tool,
@@ -127,6 +130,17 @@ ${content}"###;
         )
     }
 }
+impl ToolCallParser for FunctionaryV32ToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: ">>>",
+            end: None,
+            leading: false,
+            name: ToolCallName::Tag { delimiter: "\n" },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for FunctionaryV32ToolPrompt {
     fn build(&self, _messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         unimplemented!()
@@ -165,6 +179,10 @@ impl BuildChatPrompt for FunctionaryV32ToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|eot_id|>"]
+    }
 }
 
 /// Generate prompts for `functionary-v3.1` models.
@@ -290,6 +308,17 @@ Reminder:
         )
     }
 }
+impl ToolCallParser for FunctionaryV31ToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: "<function=",
+            end: Some("</function>"),
+            leading: false,
+            name: ToolCallName::Tag { delimiter: ">" },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for FunctionaryV31ToolPrompt {
     fn build(&self, _messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         unimplemented!()
@@ -328,4 +357,21 @@ impl BuildChatPrompt for FunctionaryV31ToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|eot_id|>", "<|eom_id|>"]
+    }
+}
+
+#[test]
+fn test_post_process_functionary() {
+    let prompt = FunctionaryV32ToolPrompt;
+    assert_eq!(prompt.post_process("all\nHello!<|eot_id|>"), "all\nHello!");
+
+    let prompt = FunctionaryV31ToolPrompt;
+    assert_eq!(prompt.post_process("Hello!<|eot_id|>"), "Hello!");
+    assert_eq!(
+        prompt.post_process("<function=get_time>{}</function><|eom_id|>"),
+        "<function=get_time>{}</function>"
+    );
 }
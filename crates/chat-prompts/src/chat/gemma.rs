@@ -86,4 +86,18 @@ impl BuildChatPrompt for GemmaInstructPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<end_of_turn>"]
+    }
+}
+
+#[test]
+fn test_post_process_gemma() {
+    let prompt = GemmaInstructPrompt;
+    assert_eq!(prompt.post_process("Hello!<end_of_turn>\n"), "Hello!");
+    assert_eq!(
+        prompt.post_process("Hello!<end_of_turn>\n<start_of_turn>user"),
+        "Hello!"
+    );
 }
@@ -105,4 +105,15 @@ impl BuildChatPrompt for Glm4ChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|user|>", "<|observation|>"]
+    }
+}
+
+#[test]
+fn test_post_process_glm4() {
+    let prompt = Glm4ChatPrompt;
+    assert_eq!(prompt.post_process("\nHello!<|user|>\nHi"), "Hello!");
+    assert_eq!(prompt.post_process("Hello!<|observation|>"), "Hello!");
 }
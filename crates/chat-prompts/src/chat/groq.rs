@@ -1,5 +1,8 @@
 use super::BuildChatPrompt;
-use crate::error::{PromptError, Result};
+use crate::{
+    error::{PromptError, Result},
+    tool::{PartialToolCalls, ToolCallName, ToolCallParser, ToolCallSyntax},
+};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionToolMessage,
     ChatCompletionUserMessage, ChatCompletionUserMessageContent, ContentPart, Tool,
@@ -111,6 +114,19 @@ impl GroqLlama3ToolPrompt {
         )
     }
 }
+impl ToolCallParser for GroqLlama3ToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: "<tool_call>",
+            end: Some("</tool_call>"),
+            leading: false,
+            name: ToolCallName::Field {
+                arguments: "arguments",
+            },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for GroqLlama3ToolPrompt {
     fn build(&self, _messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         Err(PromptError::Operation("The GroqToolPrompt struct is only designed for `Groq/Llama-3-Groq-8B-Tool-Use` model, which is for tool use ONLY instead of general knowledge or open-ended tasks.".to_string()))
@@ -149,4 +165,17 @@ impl BuildChatPrompt for GroqLlama3ToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|eot_id|>"]
+    }
+}
+
+#[test]
+fn test_post_process_groq_llama3_tool() {
+    let prompt = GroqLlama3ToolPrompt;
+    assert_eq!(
+        prompt.post_process("<tool_call>\n{}\n</tool_call><|eot_id|>"),
+        "<tool_call>\n{}\n</tool_call>"
+    );
 }
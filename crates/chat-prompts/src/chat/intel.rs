@@ -108,3 +108,9 @@ impl BuildChatPrompt for NeuralChatPrompt {
         Ok(prompt)
     }
 }
+
+#[test]
+fn test_post_process_neural_chat() {
+    let prompt = NeuralChatPrompt;
+    assert_eq!(prompt.post_process(" Hello!\n"), "Hello!");
+}
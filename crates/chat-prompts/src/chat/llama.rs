@@ -1,5 +1,8 @@
 use super::BuildChatPrompt;
-use crate::error::{PromptError, Result};
+use crate::{
+    error::{PromptError, Result},
+    tool::{PartialToolCalls, ToolCallName, ToolCallParser, ToolCallSyntax},
+};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionSystemMessage,
     ChatCompletionToolMessage, ChatCompletionUserMessage, ChatCompletionUserMessageContent,
@@ -118,6 +121,10 @@ impl BuildChatPrompt for Llama2ChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
 }
 
 /// Generate prompts for the `Codellama-instruct` model.
@@ -222,6 +229,10 @@ impl BuildChatPrompt for CodeLlamaInstructPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
 }
 
 /// Generate prompts for the `Codellama-70b-instruct-hf` model.
@@ -328,6 +339,10 @@ impl BuildChatPrompt for CodeLlamaSuperInstructPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<step>"]
+    }
 }
 
 /// Generate prompts for the `Llama-3-chat` model.
@@ -436,6 +451,10 @@ impl BuildChatPrompt for Llama3ChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|eot_id|>"]
+    }
 }
 
 /// Generate prompts for the `Llama-3.1-instruct` model.
@@ -584,6 +603,19 @@ impl Llama3ToolPrompt {
         )
     }
 }
+impl ToolCallParser for Llama3ToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: "",
+            end: None,
+            leading: true,
+            name: ToolCallName::Field {
+                arguments: "parameters",
+            },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for Llama3ToolPrompt {
     fn build(&self, messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         if messages.is_empty() {
@@ -672,4 +704,33 @@ impl BuildChatPrompt for Llama3ToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|eot_id|>", "<|eom_id|>"]
+    }
+}
+
+#[test]
+fn test_post_process_llama() {
+    for prompt in [
+        &Llama2ChatPrompt as &dyn BuildChatPrompt,
+        &CodeLlamaInstructPrompt,
+    ] {
+        assert_eq!(prompt.post_process(" Hello! </s>"), "Hello!");
+        assert_eq!(prompt.post_process("Hello!</s><s>[INST] Hi"), "Hello!");
+    }
+
+    let prompt = CodeLlamaSuperInstructPrompt;
+    assert_eq!(prompt.post_process(" Hello! <step> Source: user"), "Hello!");
+
+    for prompt in [&Llama3ChatPrompt as &dyn BuildChatPrompt, &Llama3ToolPrompt] {
+        assert_eq!(prompt.post_process("Hello!<|eot_id|>"), "Hello!");
+        assert_eq!(
+            prompt.post_process("Hello!<|eot_id|><|start_header_id|>user"),
+            "Hello!"
+        );
+    }
+
+    let prompt = Llama3ToolPrompt;
+    assert_eq!(prompt.post_process("{}<|eom_id|>"), "{}");
 }
@@ -103,4 +103,14 @@ impl BuildChatPrompt for BreezeInstructPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
+}
+
+#[test]
+fn test_post_process_breeze() {
+    let prompt = BreezeInstructPrompt;
+    assert_eq!(prompt.post_process("Hello!</s>"), "Hello!");
 }
@@ -1,12 +1,9 @@
-use super::BuildChatPrompt;
+use super::{chatml::post_process_chatml, interleave_content_parts, BuildChatPrompt};
 use crate::error::{PromptError, Result};
-use base64::{engine::general_purpose, Engine as _};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionSystemMessage,
-    ChatCompletionUserMessage, ChatCompletionUserMessageContent, ContentPart,
+    ChatCompletionUserMessage, ChatCompletionUserMessageContent,
 };
-use image::io::Reader as ImageReader;
-use std::io::Cursor;
 
 /// Generate prompts for the models using ChatML template.
 #[derive(Debug, Default, Clone)]
@@ -47,52 +44,27 @@ impl MiniCPMVPrompt {
                 }
             }
             ChatCompletionUserMessageContent::Parts(parts) => {
-                let mut content = String::new();
-                let mut image_content = String::new();
-                for part in parts {
-                    match part {
-                        ContentPart::Text(text_content) => {
-                            content.push_str(text_content.text());
-                            content.push('\n');
-                        }
-                        ContentPart::Image(part) => {
-                            image_content = match part.image().is_url() {
-                                true => String::from("<image>"),
-                                false => {
-                                    let base64_str = part.image().url.as_str();
-                                    let format = is_image_format(base64_str)?;
-                                    format!(
-                                        r#"<img src="data:image/{};base64,{}">"#,
-                                        format, base64_str
-                                    )
-                                }
-                            };
-                        }
-                    }
-                }
+                let content = interleave_content_parts(parts)?;
 
                 match chat_history.as_ref().is_empty() {
                     true => match system_prompt.as_ref().is_empty() {
                         true => {
                             format!(
-                                "<|im_start|>user\n{image_embeddings}\n{user_message}<|im_end|>",
-                                image_embeddings = image_content.trim(),
+                                "<|im_start|>user\n{user_message}<|im_end|>",
                                 user_message = content.trim(),
                             )
                         }
                         false => {
                             format!(
-                                "{system_prompt}\n<|im_start|>user\n{image_embeddings}\n{user_message}<|im_end|>",
+                                "{system_prompt}\n<|im_start|>user\n{user_message}<|im_end|>",
                                 system_prompt = system_prompt.as_ref().trim(),
-                                image_embeddings = image_content.trim(),
                                 user_message = content.trim(),
                             )
                         }
                     },
                     false => format!(
-                        "{chat_history}\n<|im_start|>user\n{image_embeddings}\n{user_message}<|im_end|>",
+                        "{chat_history}\n<|im_start|>user\n{user_message}<|im_end|>",
                         chat_history = chat_history.as_ref().trim(),
-                        image_embeddings = image_content.trim(),
                         user_message = content.trim(),
                     ),
                 }
@@ -156,37 +128,19 @@ impl BuildChatPrompt for MiniCPMVPrompt {
 
         Ok(prompt)
     }
-}
-
-fn is_image_format(base64_str: &str) -> Result<String> {
-    let image_data = match general_purpose::STANDARD.decode(base64_str) {
-        Ok(data) => data,
-        Err(_) => {
-            return Err(PromptError::Operation(
-                "Failed to decode base64 string.".to_string(),
-            ))
-        }
-    };
 
-    let format = ImageReader::new(Cursor::new(image_data))
-        .with_guessed_format()
-        .unwrap()
-        .format();
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|im_end|>", "<|im_start|>"]
+    }
 
-    let image_format = match format {
-        Some(image::ImageFormat::Png) => "png".to_string(),
-        Some(image::ImageFormat::Jpeg) => "jpeg".to_string(),
-        Some(image::ImageFormat::Tga) => "tga".to_string(),
-        Some(image::ImageFormat::Bmp) => "bmp".to_string(),
-        Some(image::ImageFormat::Gif) => "gif".to_string(),
-        Some(image::ImageFormat::Hdr) => "hdr".to_string(),
-        Some(image::ImageFormat::Pnm) => "pnm".to_string(),
-        _ => {
-            return Err(PromptError::Operation(
-                "Unsupported image format.".to_string(),
-            ))
-        }
-    };
+    fn post_process(&self, output: &str) -> String {
+        post_process_chatml(output, self.end_of_turn_markers())
+    }
+}
 
-    Ok(image_format)
+#[test]
+fn test_post_process_minicpmv() {
+    let prompt = MiniCPMVPrompt;
+    assert_eq!(prompt.post_process("Hello!<|im_end|>"), "Hello!");
+    assert_eq!(prompt.post_process(": Hello!\n<|im_start|>user"), "Hello!");
 }
@@ -1,5 +1,8 @@
 use super::BuildChatPrompt;
-use crate::error::{PromptError, Result};
+use crate::{
+    error::{PromptError, Result},
+    tool::{PartialToolCalls, ToolCallName, ToolCallParser, ToolCallSyntax},
+};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionToolMessage,
     ChatCompletionUserMessage, ChatCompletionUserMessageContent, ContentPart, Tool,
@@ -86,6 +89,10 @@ impl BuildChatPrompt for MistralInstructPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
 }
 
 /// Generate prompts for the amazon `MistralLite-7B` model.
@@ -172,6 +179,10 @@ impl BuildChatPrompt for MistralLitePrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
 }
 
 /// Generate prompts for the `Mistral-instruct` model.
@@ -319,6 +330,19 @@ impl MistralToolPrompt {
         )
     }
 }
+impl ToolCallParser for MistralToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: "[",
+            end: Some("]"),
+            leading: false,
+            name: ToolCallName::Field {
+                arguments: "arguments",
+            },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for MistralToolPrompt {
     fn build(&self, messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         if messages.is_empty() {
@@ -374,4 +398,20 @@ impl BuildChatPrompt for MistralToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
+}
+
+#[test]
+fn test_post_process_mistral() {
+    for prompt in [
+        &MistralInstructPrompt as &dyn BuildChatPrompt,
+        &MistralLitePrompt,
+        &MistralToolPrompt,
+    ] {
+        assert_eq!(prompt.post_process("Hello!</s>"), "Hello!");
+        assert_eq!(prompt.post_process("Hello! </s><"), "Hello!");
+    }
 }
@@ -20,16 +20,21 @@ pub mod vicuna;
 pub mod wizard;
 pub mod zephyr;
 
-use crate::{error::Result, PromptTemplateType};
+use crate::{
+    error::{PromptError, Result},
+    PromptTemplateType,
+};
 use baichuan::*;
+use base64::{engine::general_purpose, Engine as _};
 use belle::*;
 use chatml::*;
 use deepseek::*;
-use endpoints::chat::{ChatCompletionRequestMessage, Tool};
+use endpoints::chat::{ChatCompletionRequestMessage, ContentPart, Image, Tool};
 use functionary::{FunctionaryV31ToolPrompt, FunctionaryV32ToolPrompt};
 use gemma::*;
 use glm::*;
 use groq::*;
+use image::io::Reader as ImageReader;
 use intel::*;
 use llama::*;
 use mediatek::BreezeInstructPrompt;
@@ -40,6 +45,7 @@ use octopus::*;
 use openchat::*;
 use phi::*;
 use solar::*;
+use std::io::Cursor;
 use vicuna::*;
 use wizard::*;
 use zephyr::*;
@@ -56,6 +62,91 @@ pub trait BuildChatPrompt: Send {
     ) -> Result<String> {
         self.build(messages)
     }
+
+    /// Returns the markers which end the turn of the assistant, e.g. `<|im_end|>`. The model may generate them as text, so the output is truncated at the first one found.
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Cleans up the output of the model, i.e. truncates it at the first end-of-turn marker and trims the whitespaces around it.
+    fn post_process(&self, output: &str) -> String {
+        truncate_at_end_of_turn(output, self.end_of_turn_markers())
+            .trim()
+            .to_owned()
+    }
+}
+
+/// Truncates the text at the first of the given end-of-turn markers, if any.
+pub fn truncate_at_end_of_turn<'a>(text: &'a str, markers: &[&str]) -> &'a str {
+    match markers
+        .iter()
+        .filter(|marker| !marker.is_empty())
+        .filter_map(|marker| text.find(marker))
+        .min()
+    {
+        Some(pos) => &text[..pos],
+        None => text,
+    }
+}
+
+/// Detects the format of the encoded image, e.g. `png` or `jpeg`. Returns `None` if the format is not supported.
+pub fn image_format(data: &[u8]) -> Option<&'static str> {
+    let format = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .format()?;
+
+    match format {
+        image::ImageFormat::Png => Some("png"),
+        image::ImageFormat::Jpeg => Some("jpeg"),
+        image::ImageFormat::Tga => Some("tga"),
+        image::ImageFormat::Bmp => Some("bmp"),
+        image::ImageFormat::Gif => Some("gif"),
+        image::ImageFormat::Hdr => Some("hdr"),
+        image::ImageFormat::Pnm => Some("pnm"),
+        _ => None,
+    }
+}
+
+/// Creates the embedding of an image in the prompt. Images referenced by URL are replaced with the `<image>` placeholder, while the images given as data URIs or base64 encoded data are embedded inline.
+fn image_embedding(image: &Image) -> Result<String> {
+    if image.is_url() {
+        return Ok(String::from("<image>"));
+    }
+
+    let base64_str = match image.data_uri() {
+        Some((_, data)) => data,
+        None => image.url.as_str(),
+    };
+
+    let data = general_purpose::STANDARD
+        .decode(base64_str)
+        .map_err(|_| PromptError::Operation("Failed to decode base64 string.".to_string()))?;
+
+    let format = image_format(&data)
+        .ok_or_else(|| PromptError::Operation("Unsupported image format.".to_string()))?;
+
+    Ok(format!(
+        r#"<img src="data:image/{};base64,{}">"#,
+        format, base64_str
+    ))
+}
+
+/// Creates the content of a user message from its parts, keeping the texts and the image embeddings in the order of the parts.
+pub(crate) fn interleave_content_parts(parts: &[ContentPart]) -> Result<String> {
+    let mut segments = Vec::with_capacity(parts.len());
+    for part in parts {
+        let segment = match part {
+            ContentPart::Text(text_content) => text_content.text().trim().to_string(),
+            ContentPart::Image(part) => image_embedding(part.image())?,
+        };
+
+        if !segment.is_empty() {
+            segments.push(segment);
+        }
+    }
+
+    Ok(segments.join("\n"))
 }
 
 #[enum_dispatch::enum_dispatch(BuildChatPrompt)]
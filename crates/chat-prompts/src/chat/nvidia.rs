@@ -1,5 +1,8 @@
 use super::BuildChatPrompt;
-use crate::error::{PromptError, Result};
+use crate::{
+    error::{PromptError, Result},
+    tool::{PartialToolCalls, ToolCallName, ToolCallParser, ToolCallSyntax},
+};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionSystemMessage,
     ChatCompletionToolMessage, ChatCompletionUserMessage, ChatCompletionUserMessageContent,
@@ -110,6 +113,10 @@ impl BuildChatPrompt for NemotronChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>", "<extra_id_1>"]
+    }
 }
 
 /// Generate prompts for the models using ChatML template.
@@ -238,6 +245,19 @@ impl NemotronToolPrompt {
         )
     }
 }
+impl ToolCallParser for NemotronToolPrompt {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        ToolCallSyntax {
+            start: "<toolcall>",
+            end: Some("</toolcall>"),
+            leading: false,
+            name: ToolCallName::Field {
+                arguments: "arguments",
+            },
+        }
+        .parse_partial(output, finished)
+    }
+}
 impl BuildChatPrompt for NemotronToolPrompt {
     fn build(&self, messages: &mut Vec<ChatCompletionRequestMessage>) -> Result<String> {
         if messages.is_empty() {
@@ -328,4 +348,24 @@ impl BuildChatPrompt for NemotronToolPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>", "<extra_id_1>", "<|im_end|>"]
+    }
+}
+
+#[test]
+fn test_post_process_nemotron() {
+    let prompt = NemotronChatPrompt;
+    assert_eq!(prompt.post_process("Hello!</s>"), "Hello!");
+    assert_eq!(
+        prompt.post_process("Hello!\n<extra_id_1>User\nHi"),
+        "Hello!"
+    );
+
+    let prompt = NemotronToolPrompt;
+    assert_eq!(
+        prompt.post_process("<toolcall> {} </toolcall><|im_end|>"),
+        "<toolcall> {} </toolcall>"
+    );
 }
@@ -108,3 +108,12 @@ impl BuildChatPrompt for OctopusPrompt {
         Ok(prompt)
     }
 }
+
+#[test]
+fn test_post_process_octopus() {
+    let prompt = OctopusPrompt;
+    assert_eq!(
+        prompt.post_process(" functional_1(a) \n"),
+        "functional_1(a)"
+    );
+}
@@ -89,4 +89,14 @@ impl BuildChatPrompt for OpenChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|end_of_turn|>"]
+    }
+}
+
+#[test]
+fn test_post_process_openchat() {
+    let prompt = OpenChatPrompt;
+    assert_eq!(prompt.post_process("Hello!<|end_of_turn|>"), "Hello!");
 }
@@ -172,6 +172,10 @@ impl BuildChatPrompt for Phi3InstructPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|end|>"]
+    }
 }
 
 /// Generate chat prompt for the `microsoft/phi-2` model.
@@ -274,4 +278,19 @@ impl BuildChatPrompt for Phi3ChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|end|>"]
+    }
+}
+
+#[test]
+fn test_post_process_phi() {
+    let prompt = Phi2ChatPrompt;
+    assert_eq!(prompt.post_process(" Hello!\n"), "Hello!");
+
+    for prompt in [&Phi3ChatPrompt as &dyn BuildChatPrompt, &Phi3InstructPrompt] {
+        assert_eq!(prompt.post_process("Hello!<|end|>"), "Hello!");
+        assert_eq!(prompt.post_process("Hello!<|end|>\n<|user|>"), "Hello!");
+    }
 }
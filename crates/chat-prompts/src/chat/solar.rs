@@ -1,4 +1,4 @@
-use super::BuildChatPrompt;
+use super::{truncate_at_end_of_turn, BuildChatPrompt};
 use crate::error::{PromptError, Result};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionUserMessage,
@@ -86,4 +86,32 @@ impl BuildChatPrompt for SolarInstructPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
+
+    fn post_process(&self, output: &str) -> String {
+        let output = truncate_at_end_of_turn(output, self.end_of_turn_markers()).trim();
+
+        // the model may start the reply with `### Answer:\n`
+        match output.starts_with("### Answer") {
+            true => {
+                let output = output.trim_start_matches("###").trim();
+
+                match output.starts_with("Answer:\n") {
+                    true => output.replacen("Answer:\n", "Answer: ", 1),
+                    false => output.to_owned(),
+                }
+            }
+            false => output.to_owned(),
+        }
+    }
+}
+
+#[test]
+fn test_post_process_solar() {
+    let prompt = SolarInstructPrompt;
+    assert_eq!(prompt.post_process("### Answer:\n42</s>"), "Answer: 42");
+    assert_eq!(prompt.post_process(" Hello! "), "Hello!");
 }
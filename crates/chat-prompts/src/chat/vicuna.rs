@@ -1,12 +1,9 @@
-use super::BuildChatPrompt;
+use super::{interleave_content_parts, BuildChatPrompt};
 use crate::error::{PromptError, Result};
-use base64::{engine::general_purpose, Engine as _};
 use endpoints::chat::{
     ChatCompletionAssistantMessage, ChatCompletionRequestMessage, ChatCompletionSystemMessage,
     ChatCompletionUserMessage, ChatCompletionUserMessageContent, ContentPart,
 };
-use image::io::Reader as ImageReader;
-use std::io::Cursor;
 
 /// Vicuna-1.0 Prompt Template
 #[derive(Debug, Default, Clone)]
@@ -229,41 +226,17 @@ impl VicunaLlavaPrompt {
                 }
             }
             ChatCompletionUserMessageContent::Parts(parts) => {
-                let mut content = String::new();
-                let mut image_content = String::new();
-                for part in parts {
-                    match part {
-                        ContentPart::Text(text_content) => {
-                            content.push_str(text_content.text());
-                            content.push('\n');
-                        }
-                        ContentPart::Image(part) => {
-                            image_content = match part.image().is_url() {
-                                true => String::from("<image>"),
-                                false => {
-                                    let base64_str = part.image().url.as_str();
-                                    let format = is_image_format(base64_str)?;
-                                    format!(
-                                        r#"<img src="data:image/{};base64,{}">"#,
-                                        format, base64_str
-                                    )
-                                }
-                            };
-                        }
-                    }
-                }
+                let content = interleave_content_parts(parts)?;
 
                 match chat_history.as_ref().is_empty() {
                     true => format!(
-                        "{system_prompt}\nUSER:{image_embeddings}\n{user_message}",
+                        "{system_prompt}\nUSER:{user_message}",
                         system_prompt = system_prompt.as_ref().trim(),
-                        image_embeddings = image_content.trim(),
                         user_message = content.trim(),
                     ),
                     false => format!(
-                        "{chat_history}\nUSER:{image_embeddings}\n{user_message}",
+                        "{chat_history}\nUSER:{user_message}",
                         chat_history = chat_history.as_ref().trim(),
-                        image_embeddings = image_content.trim(),
                         user_message = content.trim(),
                     ),
                 }
@@ -329,35 +302,49 @@ impl BuildChatPrompt for VicunaLlavaPrompt {
     }
 }
 
-fn is_image_format(base64_str: &str) -> Result<String> {
-    let image_data = match general_purpose::STANDARD.decode(base64_str) {
-        Ok(data) => data,
-        Err(_) => {
-            return Err(PromptError::Operation(
-                "Failed to decode base64 string.".to_string(),
-            ))
-        }
-    };
+#[test]
+fn test_post_process_vicuna() {
+    for prompt in [
+        &VicunaChatPrompt as &dyn BuildChatPrompt,
+        &Vicuna11ChatPrompt,
+        &VicunaLlavaPrompt,
+    ] {
+        assert_eq!(prompt.post_process(" Hello!\n"), "Hello!");
+    }
+}
 
-    let format = ImageReader::new(Cursor::new(image_data))
-        .with_guessed_format()
-        .unwrap()
-        .format();
+#[test]
+fn test_vicuna_llava_interleaves_images() {
+    use endpoints::chat::{Image, ImageContentPart, TextContentPart};
 
-    let image_format = match format {
-        Some(image::ImageFormat::Png) => "png".to_string(),
-        Some(image::ImageFormat::Jpeg) => "jpeg".to_string(),
-        Some(image::ImageFormat::Tga) => "tga".to_string(),
-        Some(image::ImageFormat::Bmp) => "bmp".to_string(),
-        Some(image::ImageFormat::Gif) => "gif".to_string(),
-        Some(image::ImageFormat::Hdr) => "hdr".to_string(),
-        Some(image::ImageFormat::Pnm) => "pnm".to_string(),
-        _ => {
-            return Err(PromptError::Operation(
-                "Unsupported image format.".to_string(),
-            ))
-        }
-    };
+    let png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+    let image =
+        |url: String| ContentPart::Image(ImageContentPart::new(Image { url, detail: None }));
+
+    let mut messages = vec![ChatCompletionRequestMessage::User(
+        ChatCompletionUserMessage::new(
+            ChatCompletionUserMessageContent::Parts(vec![
+                ContentPart::Text(TextContentPart::new("First image:")),
+                image(png.to_string()),
+                ContentPart::Text(TextContentPart::new("Second image:")),
+                image(format!("data:image/png;base64,{}", png)),
+                ContentPart::Text(TextContentPart::new("Compare them.")),
+            ]),
+            None,
+        ),
+    )];
+    let prompt = VicunaLlavaPrompt.build(&mut messages).unwrap();
+
+    let embedding = format!(r#"<img src="data:image/png;base64,{}">"#, png);
+    assert!(prompt.ends_with(&format!(
+        "USER:First image:\n{embedding}\nSecond image:\n{embedding}\nCompare them.\nASSISTANT:"
+    )));
 
-    Ok(image_format)
+    let mut messages = vec![ChatCompletionRequestMessage::User(
+        ChatCompletionUserMessage::new(
+            ChatCompletionUserMessageContent::Parts(vec![image("bm90IGFuIGltYWdl".to_string())]),
+            None,
+        ),
+    )];
+    assert!(VicunaLlavaPrompt.build(&mut messages).is_err());
 }
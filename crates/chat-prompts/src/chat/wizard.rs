@@ -72,3 +72,9 @@ impl BuildChatPrompt for WizardCoderPrompt {
         Ok(prompt)
     }
 }
+
+#[test]
+fn test_post_process_wizard_coder() {
+    let prompt = WizardCoderPrompt;
+    assert_eq!(prompt.post_process("\nfn main() {}\n"), "fn main() {}");
+}
@@ -104,6 +104,10 @@ impl BuildChatPrompt for ZephyrChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["</s>"]
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -188,4 +192,17 @@ impl BuildChatPrompt for StableLMZephyrChatPrompt {
 
         Ok(prompt)
     }
+
+    fn end_of_turn_markers(&self) -> &'static [&'static str] {
+        &["<|endoftext|>"]
+    }
+}
+
+#[test]
+fn test_post_process_zephyr() {
+    let prompt = ZephyrChatPrompt;
+    assert_eq!(prompt.post_process("Hello!</s>\n<|user|>"), "Hello!");
+
+    let prompt = StableLMZephyrChatPrompt;
+    assert_eq!(prompt.post_process("Hello!<|endoftext|>"), "Hello!");
 }
@@ -19,6 +19,8 @@ pub enum PromptError {
     UnknownRole(ChatCompletionRole),
     #[error("Unknown prompt template type: {0}")]
     UnknownPromptTemplateType(String),
+    #[error("Unknown infill template type: {0}")]
+    UnknownInfillTemplateType(String),
     #[error("Failed to build prompt. Reason: {0}")]
     Operation(String),
 }
@@ -0,0 +1,166 @@
+//! Define the prompt templates for filling in the middle (FIM) of the code, i.e. generating the code between the given prefix and suffix.
+
+use crate::{error, PromptTemplateType};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Define the infill prompt template types.
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+pub enum InfillTemplateType {
+    #[value(name = "codellama")]
+    CodeLlama,
+    #[value(name = "deepseek-coder")]
+    DeepseekCoder,
+    #[value(name = "starcoder")]
+    StarCoder,
+}
+impl InfillTemplateType {
+    /// Returns the infill template of the models using the given chat prompt template, if they are trained to fill in the middle.
+    pub fn from_prompt_template(template: PromptTemplateType) -> Option<Self> {
+        match template {
+            PromptTemplateType::CodeLlama | PromptTemplateType::CodeLlamaSuper => {
+                Some(InfillTemplateType::CodeLlama)
+            }
+            PromptTemplateType::DeepseekCoder => Some(InfillTemplateType::DeepseekCoder),
+            _ => None,
+        }
+    }
+}
+impl FromStr for InfillTemplateType {
+    type Err = error::PromptError;
+
+    fn from_str(template: &str) -> std::result::Result<Self, Self::Err> {
+        match template {
+            "codellama" => Ok(InfillTemplateType::CodeLlama),
+            "deepseek-coder" => Ok(InfillTemplateType::DeepseekCoder),
+            "starcoder" => Ok(InfillTemplateType::StarCoder),
+            _ => Err(error::PromptError::UnknownInfillTemplateType(
+                template.to_string(),
+            )),
+        }
+    }
+}
+impl std::fmt::Display for InfillTemplateType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InfillTemplateType::CodeLlama => write!(f, "codellama"),
+            InfillTemplateType::DeepseekCoder => write!(f, "deepseek-coder"),
+            InfillTemplateType::StarCoder => write!(f, "starcoder"),
+        }
+    }
+}
+
+/// Trait for building prompts for filling in the middle of the code.
+#[enum_dispatch::enum_dispatch]
+pub trait BuildInfillPrompt: Send {
+    /// Builds the prompt asking the model to generate the code between `prefix` and `suffix`.
+    fn build(&self, prefix: &str, suffix: &str) -> String;
+
+    /// Returns the markers which end the generated code, e.g. `<EOT>`. The model may generate them as text, so the output is truncated at the first one found.
+    fn end_of_infill_markers(&self) -> &'static [&'static str] {
+        &[]
+    }
+}
+
+/// Infill prompt for the CodeLlama models, e.g. `<PRE> {prefix} <SUF>{suffix} <MID>`.
+#[derive(Debug, Default, Clone)]
+pub struct CodeLlamaInfillPrompt;
+impl BuildInfillPrompt for CodeLlamaInfillPrompt {
+    fn build(&self, prefix: &str, suffix: &str) -> String {
+        format!("<PRE> {} <SUF>{} <MID>", prefix, suffix)
+    }
+
+    fn end_of_infill_markers(&self) -> &'static [&'static str] {
+        &["<EOT>"]
+    }
+}
+
+/// Infill prompt for the DeepSeek-Coder models, e.g. `<｜fim▁begin｜>{prefix}<｜fim▁hole｜>{suffix}<｜fim▁end｜>`.
+#[derive(Debug, Default, Clone)]
+pub struct DeepseekCoderInfillPrompt;
+impl BuildInfillPrompt for DeepseekCoderInfillPrompt {
+    fn build(&self, prefix: &str, suffix: &str) -> String {
+        format!(
+            "<｜fim▁begin｜>{}<｜fim▁hole｜>{}<｜fim▁end｜>",
+            prefix, suffix
+        )
+    }
+
+    fn end_of_infill_markers(&self) -> &'static [&'static str] {
+        &["<|EOT|>", "<｜end▁of▁sentence｜>"]
+    }
+}
+
+/// Infill prompt for the StarCoder models and the others trained in the same format, e.g. `<fim_prefix>{prefix}<fim_suffix>{suffix}<fim_middle>`.
+#[derive(Debug, Default, Clone)]
+pub struct StarCoderInfillPrompt;
+impl BuildInfillPrompt for StarCoderInfillPrompt {
+    fn build(&self, prefix: &str, suffix: &str) -> String {
+        format!("<fim_prefix>{}<fim_suffix>{}<fim_middle>", prefix, suffix)
+    }
+
+    fn end_of_infill_markers(&self) -> &'static [&'static str] {
+        &["<|endoftext|>", "<file_sep>"]
+    }
+}
+
+#[enum_dispatch::enum_dispatch(BuildInfillPrompt)]
+pub enum InfillPrompt {
+    CodeLlamaInfillPrompt,
+    DeepseekCoderInfillPrompt,
+    StarCoderInfillPrompt,
+}
+impl From<InfillTemplateType> for InfillPrompt {
+    fn from(ty: InfillTemplateType) -> Self {
+        match ty {
+            InfillTemplateType::CodeLlama => {
+                InfillPrompt::CodeLlamaInfillPrompt(CodeLlamaInfillPrompt)
+            }
+            InfillTemplateType::DeepseekCoder => {
+                InfillPrompt::DeepseekCoderInfillPrompt(DeepseekCoderInfillPrompt)
+            }
+            InfillTemplateType::StarCoder => {
+                InfillPrompt::StarCoderInfillPrompt(StarCoderInfillPrompt)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_build_infill_prompt() {
+    let prefix = "def add(a, b):\n    ";
+    let suffix = "\n\nprint(add(1, 2))";
+
+    let prompt = InfillPrompt::from(InfillTemplateType::CodeLlama);
+    assert_eq!(
+        prompt.build(prefix, suffix),
+        "<PRE> def add(a, b):\n     <SUF>\n\nprint(add(1, 2)) <MID>"
+    );
+    assert_eq!(prompt.end_of_infill_markers(), &["<EOT>"]);
+
+    let prompt = InfillPrompt::from(InfillTemplateType::DeepseekCoder);
+    assert_eq!(
+        prompt.build(prefix, suffix),
+        "<｜fim▁begin｜>def add(a, b):\n    <｜fim▁hole｜>\n\nprint(add(1, 2))<｜fim▁end｜>"
+    );
+
+    let prompt = InfillPrompt::from(InfillTemplateType::StarCoder);
+    assert_eq!(
+        prompt.build(prefix, suffix),
+        "<fim_prefix>def add(a, b):\n    <fim_suffix>\n\nprint(add(1, 2))<fim_middle>"
+    );
+
+    assert_eq!(
+        InfillTemplateType::from_prompt_template(PromptTemplateType::CodeLlamaSuper),
+        Some(InfillTemplateType::CodeLlama)
+    );
+    assert_eq!(
+        InfillTemplateType::from_prompt_template(PromptTemplateType::Llama3Chat),
+        None
+    );
+    assert_eq!(
+        "starcoder".parse::<InfillTemplateType>(),
+        Ok(InfillTemplateType::StarCoder)
+    );
+}
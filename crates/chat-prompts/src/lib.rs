@@ -4,6 +4,8 @@
 
 pub mod chat;
 pub mod error;
+pub mod infill;
+pub mod tool;
 
 use clap::ValueEnum;
 use endpoints::chat::ChatCompletionRequestMessage;
@@ -11,7 +13,7 @@ use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
 /// Define the chat prompt template types.
-#[derive(Clone, Debug, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
 pub enum PromptTemplateType {
     #[value(name = "llama-2-chat")]
     Llama2Chat,
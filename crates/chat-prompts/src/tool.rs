@@ -0,0 +1,753 @@
+//! Define the parsers of the tool calls generated by the models.
+//!
+//! The parsers are looked up by name. Each tool-capable prompt template has a built-in parser named after the template, e.g. `chatml-tool`. [`register_tool_call_parser`] replaces a built-in parser, or adds a parser under a new name, e.g. for a fine-tuned model with its own tool syntax, which the model selects instead of the one of its prompt template.
+
+use crate::{
+    chat::{
+        chatml::{ChatMLToolPrompt, InternLM2ToolPrompt},
+        functionary::{FunctionaryV31ToolPrompt, FunctionaryV32ToolPrompt},
+        groq::GroqLlama3ToolPrompt,
+        llama::Llama3ToolPrompt,
+        mistral::MistralToolPrompt,
+        nvidia::NemotronToolPrompt,
+    },
+    PromptTemplateType,
+};
+use endpoints::chat::Function;
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+// key: name of the parser, value: parser of the tool calls registered by the users
+static TOOL_CALL_PARSERS: OnceLock<RwLock<HashMap<String, Arc<dyn ToolCallParser>>>> =
+    OnceLock::new();
+
+/// Trait for parsing the tool calls out of the output of the models.
+pub trait ToolCallParser: Send + Sync {
+    /// Splits the output of the model, which may be incomplete in the stream mode, into the content and the tool calls found so far. The text which may be the beginning of a tool call is held back from the content unless `finished` is `true`.
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls;
+
+    /// Parses the tool calls out of the complete output of the model. The tool calls without the name of the function are dropped.
+    fn parse(&self, output: &str) -> ParsedToolCalls {
+        let partial = self.parse_partial(output, true);
+
+        let content = partial.content.trim();
+        let tool_calls = partial
+            .tool_calls
+            .into_iter()
+            .filter_map(|tool_call| {
+                tool_call.name.map(|name| Function {
+                    name,
+                    arguments: tool_call.arguments,
+                })
+            })
+            .collect();
+
+        ParsedToolCalls {
+            content: match content.is_empty() {
+                true => None,
+                false => Some(content.to_owned()),
+            },
+            tool_calls,
+        }
+    }
+}
+
+/// Registers the parser of the tool calls under the given name. It replaces the parser registered under the same name before, or the built-in parser of the prompt template if the name is the one of a prompt template, e.g. `chatml-tool`.
+pub fn register_tool_call_parser(name: impl Into<String>, parser: impl ToolCallParser + 'static) {
+    let parsers = TOOL_CALL_PARSERS.get_or_init(|| RwLock::new(HashMap::new()));
+
+    // the map is always left in a consistent state, so a poisoned lock is recovered
+    let mut parsers = parsers.write().unwrap_or_else(|e| e.into_inner());
+    parsers.insert(name.into(), Arc::new(parser));
+}
+
+/// Returns the parser of the tool calls registered under the given name, or the built-in parser of the prompt template with the given name. `None` if neither exists.
+pub fn tool_call_parser(name: &str) -> Option<Arc<dyn ToolCallParser>> {
+    if let Some(parsers) = TOOL_CALL_PARSERS.get() {
+        let parsers = parsers.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(parser) = parsers.get(name) {
+            return Some(parser.clone());
+        }
+    }
+
+    let parser: Arc<dyn ToolCallParser> = match name.parse::<PromptTemplateType>().ok()? {
+        PromptTemplateType::MistralTool => Arc::new(MistralToolPrompt),
+        PromptTemplateType::ChatMLTool => Arc::new(ChatMLToolPrompt),
+        PromptTemplateType::GroqLlama3Tool => Arc::new(GroqLlama3ToolPrompt),
+        PromptTemplateType::Llama3Tool => Arc::new(Llama3ToolPrompt),
+        PromptTemplateType::InternLM2Tool => Arc::new(InternLM2ToolPrompt),
+        PromptTemplateType::NemotronTool => Arc::new(NemotronToolPrompt),
+        PromptTemplateType::FunctionaryV32 => Arc::new(FunctionaryV32ToolPrompt),
+        PromptTemplateType::FunctionaryV31 => Arc::new(FunctionaryV31ToolPrompt),
+        _ => return None,
+    };
+
+    Some(parser)
+}
+
+/// The content and the tool calls found in the output of the model, which may be incomplete.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartialToolCalls {
+    /// The text out of the tool calls.
+    pub content: String,
+    /// The tool calls found so far.
+    pub tool_calls: Vec<PartialToolCall>,
+}
+
+/// A tool call found in the output of the model, which may be incomplete.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartialToolCall {
+    /// The name of the function, which is `None` until it is complete.
+    pub name: Option<String>,
+    /// The arguments of the function generated so far.
+    pub arguments: String,
+}
+
+/// The content and the tool calls parsed out of the complete output of the model.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedToolCalls {
+    /// The text out of the tool calls, or `None` if it is empty.
+    pub content: Option<String>,
+    /// The functions that the model called.
+    pub tool_calls: Vec<Function>,
+}
+
+/// Describes the syntax of the tool calls in the output of a model, which covers the formats of most models: JSON objects, or function names followed by JSON objects, enclosed by markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ToolCallSyntax {
+    /// The marker which opens the tool calls, e.g. `<tool_call>`. An empty marker is only allowed if `leading` is `true`.
+    pub start: &'static str,
+    /// The marker which closes the tool calls, e.g. `</tool_call>`. A tool call ends with its JSON object if `None`.
+    pub end: Option<&'static str>,
+    /// Whether the tool calls are only recognized at the beginning of the output.
+    pub leading: bool,
+    /// Where the name of the function is in a tool call.
+    pub name: ToolCallName,
+}
+impl ToolCallParser for ToolCallSyntax {
+    fn parse_partial(&self, output: &str, finished: bool) -> PartialToolCalls {
+        let mut content = String::new();
+        let mut tool_calls = vec![];
+
+        if self.leading {
+            let rest = output.trim_start();
+
+            match rest.strip_prefix(self.start) {
+                Some(region) if !rest.is_empty() => {
+                    match parse_tool_call_region(region, self, &mut tool_calls) {
+                        ToolCallRegion::Complete(len) => content.push_str(&region[len..]),
+                        ToolCallRegion::Incomplete if !tool_calls.is_empty() || !finished => {}
+                        _ => {
+                            tool_calls.clear();
+                            content.push_str(output);
+                        }
+                    }
+                }
+                _ => {
+                    if !rest.is_empty() || finished {
+                        content.push_str(output);
+                    }
+                }
+            }
+
+            return PartialToolCalls {
+                content,
+                tool_calls,
+            };
+        }
+
+        let mut cursor = 0;
+        while cursor < output.len() {
+            let rest = &output[cursor..];
+
+            let idx = match rest.find(self.start) {
+                Some(idx) if !self.start.is_empty() => idx,
+                _ => {
+                    // hold back the text which may be the beginning of the start marker
+                    let held = match finished {
+                        true => 0,
+                        false => partial_marker_len(rest, self.start),
+                    };
+                    content.push_str(&rest[..rest.len() - held]);
+                    break;
+                }
+            };
+            content.push_str(&rest[..idx]);
+
+            let region = &rest[idx + self.start.len()..];
+            let found = tool_calls.len();
+            match parse_tool_call_region(region, self, &mut tool_calls) {
+                ToolCallRegion::Complete(len) => {
+                    cursor += idx + self.start.len() + len;
+                }
+                ToolCallRegion::Incomplete => {
+                    // not a tool call if nothing is found at the end of the output
+                    if finished && tool_calls.len() == found {
+                        content.push_str(&rest[idx..]);
+                    }
+                    break;
+                }
+                ToolCallRegion::Invalid => {
+                    tool_calls.truncate(found);
+
+                    // the start marker is a part of the content
+                    content.push_str(self.start);
+                    cursor += idx + self.start.len();
+                }
+            }
+        }
+
+        PartialToolCalls {
+            content,
+            tool_calls,
+        }
+    }
+}
+
+/// Defines where the name of the function is in a tool call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolCallName {
+    /// The `name` field of a JSON object which holds the arguments in the field with the given key, e.g. `{"name": "get_weather", "arguments": {...}}`. A sequence of such objects, e.g. the elements of a JSON array, may follow a start marker.
+    Field { arguments: &'static str },
+    /// The text between the start marker and the given delimiter, followed by the arguments in a JSON object, e.g. `<function=get_weather>{...}`.
+    Tag { delimiter: &'static str },
+}
+
+/// State of the text following the start marker of the tool calls.
+#[derive(Debug, PartialEq)]
+enum ToolCallRegion {
+    /// The tool calls end after the given length in bytes
+    Complete(usize),
+    /// The tool calls are not finished yet
+    Incomplete,
+    /// The text is not a tool call
+    Invalid,
+}
+
+/// Parses the tool calls in the text following the start marker, which may be incomplete.
+fn parse_tool_call_region(
+    region: &str,
+    syntax: &ToolCallSyntax,
+    tool_calls: &mut Vec<PartialToolCall>,
+) -> ToolCallRegion {
+    let mut pos = 0;
+    let mut found = false;
+    loop {
+        // skip the whitespaces, and the commas between the elements of a JSON array
+        let rest = region[pos..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        pos = region.len() - rest.len();
+
+        match syntax.end {
+            Some(end) if rest.starts_with(end) => {
+                return match found {
+                    true => ToolCallRegion::Complete(pos + end.len()),
+                    false => ToolCallRegion::Invalid,
+                };
+            }
+            Some(end) if end.starts_with(rest) => return ToolCallRegion::Incomplete,
+            _ => {}
+        }
+
+        // only one tool call follows a start marker, except for a sequence of JSON objects
+        if found && (syntax.end.is_none() || matches!(syntax.name, ToolCallName::Tag { .. })) {
+            return ToolCallRegion::Complete(pos);
+        }
+
+        if rest.is_empty() {
+            return ToolCallRegion::Incomplete;
+        }
+
+        let (name, arguments) = match syntax.name {
+            ToolCallName::Field { arguments } => {
+                if !rest.starts_with('{') {
+                    return match found {
+                        true => ToolCallRegion::Complete(pos),
+                        false => ToolCallRegion::Invalid,
+                    };
+                }
+
+                let (fields, len) = scan_json_object(rest);
+
+                let name = fields
+                    .iter()
+                    .find(|field| field.key == "name" && field.complete)
+                    .and_then(|field| serde_json::from_str::<String>(field.value).ok());
+                let args = fields
+                    .iter()
+                    .find(|field| field.key == arguments)
+                    .map(|field| field.value)
+                    .unwrap_or_default();
+
+                tool_calls.push(PartialToolCall {
+                    name,
+                    arguments: args.to_owned(),
+                });
+
+                match len {
+                    Some(len) => {
+                        pos += len;
+                        found = true;
+                        continue;
+                    }
+                    None => return ToolCallRegion::Incomplete,
+                }
+            }
+            ToolCallName::Tag { delimiter } => match rest.find(delimiter) {
+                Some(idx) => {
+                    let name = rest[..idx].trim();
+                    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        return ToolCallRegion::Invalid;
+                    }
+
+                    let value = rest[idx + delimiter.len()..].trim_start();
+                    pos = region.len() - value.len();
+
+                    (name.to_owned(), value)
+                }
+                None => return ToolCallRegion::Incomplete,
+            },
+        };
+
+        if arguments.is_empty() {
+            tool_calls.push(PartialToolCall {
+                name: Some(name),
+                arguments: String::new(),
+            });
+            return ToolCallRegion::Incomplete;
+        }
+
+        if !arguments.starts_with('{') {
+            return ToolCallRegion::Invalid;
+        }
+
+        match scan_json_value(arguments) {
+            Some(len) => {
+                tool_calls.push(PartialToolCall {
+                    name: Some(name),
+                    arguments: arguments[..len].to_owned(),
+                });
+                pos += len;
+                found = true;
+            }
+            None => {
+                tool_calls.push(PartialToolCall {
+                    name: Some(name),
+                    arguments: arguments.to_owned(),
+                });
+                return ToolCallRegion::Incomplete;
+            }
+        }
+    }
+}
+
+/// Returns the length in bytes of the longest suffix of the text which is the beginning of the marker.
+fn partial_marker_len(text: &str, marker: &str) -> usize {
+    marker
+        .char_indices()
+        .skip(1)
+        .map(|(idx, _)| &marker[..idx])
+        .filter(|prefix| text.ends_with(prefix))
+        .map(|prefix| prefix.len())
+        .max()
+        .unwrap_or_default()
+}
+
+/// A field of a JSON object, which may be incomplete.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonField<'a> {
+    /// The key of the field.
+    pub key: String,
+    /// The raw JSON value, or the part generated so far if it is incomplete.
+    pub value: &'a str,
+    /// Whether the value is complete.
+    pub complete: bool,
+}
+
+/// Scans the JSON object at the beginning of the text, which may be incomplete or malformed. Returns the fields found so far, and the length in bytes of the object if it is complete.
+///
+/// The scanning stops at the first malformed field, and the fields before it are still returned.
+pub fn scan_json_object(text: &str) -> (Vec<JsonField<'_>>, Option<usize>) {
+    let mut fields = vec![];
+
+    if !text.starts_with('{') {
+        return (fields, None);
+    }
+
+    let mut pos = 1;
+    loop {
+        let rest = text[pos..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        pos = text.len() - rest.len();
+
+        if rest.starts_with('}') {
+            return (fields, Some(pos + 1));
+        }
+        if !rest.starts_with('"') {
+            return (fields, None);
+        }
+
+        // key
+        let key = match scan_json_value(rest) {
+            Some(len) => match serde_json::from_str::<String>(&rest[..len]) {
+                Ok(key) => {
+                    pos += len;
+                    key
+                }
+                Err(_) => return (fields, None),
+            },
+            None => return (fields, None),
+        };
+
+        let rest = text[pos..].trim_start();
+        match rest.strip_prefix(':') {
+            Some(rest) => {
+                let rest = rest.trim_start();
+                pos = text.len() - rest.len();
+            }
+            None => return (fields, None),
+        }
+
+        // value
+        let rest = &text[pos..];
+        match scan_json_value(rest) {
+            Some(len) => {
+                fields.push(JsonField {
+                    key,
+                    value: &rest[..len],
+                    complete: true,
+                });
+                pos += len;
+            }
+            None => {
+                if !rest.is_empty() {
+                    fields.push(JsonField {
+                        key,
+                        value: rest,
+                        complete: false,
+                    });
+                }
+                return (fields, None);
+            }
+        }
+    }
+}
+
+/// Returns the length in bytes of the JSON value at the beginning of the text, or `None` if the value is incomplete.
+///
+/// The value is only checked for balanced brackets and strings, so that a malformed value, e.g. with a trailing comma, is still extracted.
+pub fn scan_json_value(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (idx, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return Some(idx + 1);
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx + 1);
+                }
+            }
+            // the end of a number or a literal at the top level
+            ',' | '}' | ']' if depth == 0 => return Some(idx),
+            _ if c.is_whitespace() && depth == 0 => return Some(idx),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Parses the output which grows by the given number of chars at a time as in the stream mode, and checks that the content and the arguments only grow, which the stream mode relies on. Returns the final result.
+#[cfg(test)]
+fn parse_incrementally(
+    template_ty: PromptTemplateType,
+    output: &str,
+    size: usize,
+) -> PartialToolCalls {
+    let parser = tool_call_parser(&template_ty.to_string()).unwrap();
+
+    let ends: Vec<usize> = output
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .skip(1)
+        .step_by(size)
+        .chain(std::iter::once(output.len()))
+        .collect();
+
+    let mut history: Vec<PartialToolCalls> = vec![];
+    for end in ends.iter() {
+        history.push(parser.parse_partial(&output[..*end], *end == output.len()));
+    }
+
+    let last = history.pop().unwrap();
+    for partial in history {
+        assert!(last.content.starts_with(&partial.content));
+        assert!(partial.tool_calls.len() <= last.tool_calls.len());
+        for (tool_call, final_call) in partial.tool_calls.iter().zip(last.tool_calls.iter()) {
+            assert!(final_call.arguments.starts_with(&tool_call.arguments));
+            if tool_call.name.is_some() {
+                assert_eq!(tool_call.name, final_call.name);
+            }
+        }
+    }
+
+    last
+}
+
+#[cfg(test)]
+fn tool_call(name: &str, arguments: &str) -> PartialToolCall {
+    PartialToolCall {
+        name: Some(name.to_string()),
+        arguments: arguments.to_string(),
+    }
+}
+
+#[test]
+fn test_parse_tool_calls_chatml() {
+    let output = "<tool_call>\n{\"arguments\": {\"location\": \"Paris\", \"unit\": \"celsius\"}, \"name\": \"get_current_weather\"}\n</tool_call>\n<tool_call>\n{\"name\": \"get_time\", \"arguments\": {\"city\": \"Paris\"}}\n</tool_call>";
+
+    for template_ty in [
+        PromptTemplateType::ChatMLTool,
+        PromptTemplateType::GroqLlama3Tool,
+    ] {
+        for size in [1, 3, 7, output.len()] {
+            let parsed = parse_incrementally(template_ty, output, size);
+            assert_eq!(parsed.content.trim(), "");
+            assert_eq!(
+                parsed.tool_calls,
+                vec![
+                    tool_call(
+                        "get_current_weather",
+                        r#"{"location": "Paris", "unit": "celsius"}"#
+                    ),
+                    tool_call("get_time", r#"{"city": "Paris"}"#),
+                ]
+            );
+        }
+    }
+
+    // no tool call
+    let output = "The weather in <b>Paris</b> is sunny.";
+    let parsed = parse_incrementally(PromptTemplateType::ChatMLTool, output, 2);
+    assert_eq!(parsed.content, output);
+    assert!(parsed.tool_calls.is_empty());
+
+    // malformed tool call
+    let output = "<tool_call>get_time(Paris)</tool_call>";
+    let parsed = parse_incrementally(PromptTemplateType::ChatMLTool, output, 1);
+    assert_eq!(parsed.content, output);
+    assert!(parsed.tool_calls.is_empty());
+}
+
+#[test]
+fn test_parse_tool_calls_mistral() {
+    let output = r#"[TOOL_CALLS] [{"name": "get_current_weather", "arguments": {"location": "Paris"}}, {"name": "get_time", "arguments": {"city": "Paris, \"FR\""}}]"#;
+
+    for size in [1, 5, output.len()] {
+        let parsed = parse_incrementally(PromptTemplateType::MistralTool, output, size);
+        assert_eq!(parsed.content, "[TOOL_CALLS] ");
+        assert_eq!(
+            parsed.tool_calls,
+            vec![
+                tool_call("get_current_weather", r#"{"location": "Paris"}"#),
+                tool_call("get_time", r#"{"city": "Paris, \"FR\""}"#),
+            ]
+        );
+    }
+
+    // brackets in the content
+    let output = "The answer is [1, 2] and [a].";
+    let parsed = parse_incrementally(PromptTemplateType::MistralTool, output, 1);
+    assert_eq!(parsed.content, output);
+    assert!(parsed.tool_calls.is_empty());
+}
+
+#[test]
+fn test_parse_tool_calls_llama3() {
+    let output = r#"{"name": "get_current_weather", "parameters": {"location": "Paris"}}"#;
+
+    for size in [1, 4, output.len()] {
+        let parsed = parse_incrementally(PromptTemplateType::Llama3Tool, output, size);
+        assert_eq!(parsed.content, "");
+        assert_eq!(
+            parsed.tool_calls,
+            vec![tool_call("get_current_weather", r#"{"location": "Paris"}"#)]
+        );
+    }
+
+    let output = "Hello, {name}!";
+    let parsed = parse_incrementally(PromptTemplateType::Llama3Tool, output, 1);
+    assert_eq!(parsed.content, output);
+    assert!(parsed.tool_calls.is_empty());
+}
+
+#[test]
+fn test_parse_tool_calls_internlm2() {
+    let output = "Let me check.<|action_start|><|plugin|>\n{\"name\": \"get_current_weather\", \"parameters\": {\"location\": \"Paris\"}}<|action_end|>";
+
+    for size in [1, 6, output.len()] {
+        let parsed = parse_incrementally(PromptTemplateType::InternLM2Tool, output, size);
+        assert_eq!(parsed.content, "Let me check.");
+        assert_eq!(
+            parsed.tool_calls,
+            vec![tool_call("get_current_weather", r#"{"location": "Paris"}"#)]
+        );
+    }
+}
+
+#[test]
+fn test_parse_tool_calls_nemotron() {
+    let output = "<toolcall> {\"name\": \"get_current_weather\", \"arguments\": {\"location\": \"Paris\"}} </toolcall>";
+
+    for size in [1, 6, output.len()] {
+        let parsed = parse_incrementally(PromptTemplateType::NemotronTool, output, size);
+        assert_eq!(parsed.content, "");
+        assert_eq!(
+            parsed.tool_calls,
+            vec![tool_call("get_current_weather", r#"{"location": "Paris"}"#)]
+        );
+    }
+}
+
+#[test]
+fn test_parse_tool_calls_functionary() {
+    let output = r#"<function=get_current_weather>{"location": "Paris"}</function>"#;
+    for size in [1, 8, output.len()] {
+        let parsed = parse_incrementally(PromptTemplateType::FunctionaryV31, output, size);
+        assert_eq!(parsed.content, "");
+        assert_eq!(
+            parsed.tool_calls,
+            vec![tool_call("get_current_weather", r#"{"location": "Paris"}"#)]
+        );
+    }
+
+    let output =
+        ">>>get_current_weather\n{\"location\": \"Paris\"}>>>get_time\n{\"city\": \"Paris\"}";
+    for size in [1, 8, output.len()] {
+        let parsed = parse_incrementally(PromptTemplateType::FunctionaryV32, output, size);
+        assert_eq!(parsed.content, "");
+        assert_eq!(
+            parsed.tool_calls,
+            vec![
+                tool_call("get_current_weather", r#"{"location": "Paris"}"#),
+                tool_call("get_time", r#"{"city": "Paris"}"#),
+            ]
+        );
+    }
+}
+
+#[test]
+fn test_parse_tool_calls() {
+    let parser = tool_call_parser("chatml-tool").unwrap();
+
+    // the content around the tool calls
+    let parsed =
+        parser.parse("Sure.\n<tool_call>{\"name\": \"get_time\", \"arguments\": {}}</tool_call>\n");
+    assert_eq!(parsed.content, Some("Sure.".to_string()));
+    assert_eq!(
+        parsed.tool_calls,
+        vec![Function {
+            name: "get_time".to_string(),
+            arguments: "{}".to_string()
+        }]
+    );
+
+    // the tool call without the name of the function is dropped
+    let parsed = parser.parse("<tool_call>{\"arguments\": {}}</tool_call>");
+    assert_eq!(parsed.content, None);
+    assert!(parsed.tool_calls.is_empty());
+
+    // the truncated output
+    let parsed =
+        parser.parse("<tool_call>{\"name\": \"get_time\", \"arguments\": {\"city\": \"Par");
+    assert_eq!(parsed.content, None);
+    assert_eq!(
+        parsed.tool_calls,
+        vec![Function {
+            name: "get_time".to_string(),
+            arguments: r#"{"city": "Par"#.to_string()
+        }]
+    );
+
+    // no parser for the prompt templates without tool use
+    assert!(tool_call_parser("chatml").is_none());
+    assert!(tool_call_parser("unknown").is_none());
+}
+
+#[test]
+fn test_register_tool_call_parser() {
+    assert!(tool_call_parser("phi-3-tool").is_none());
+
+    register_tool_call_parser(
+        "phi-3-tool",
+        ToolCallSyntax {
+            start: "<|tool|>",
+            end: Some("<|/tool|>"),
+            leading: false,
+            name: ToolCallName::Field {
+                arguments: "arguments",
+            },
+        },
+    );
+
+    let parser = tool_call_parser("phi-3-tool").unwrap();
+    let parsed = parser.parse("<|tool|>[{\"name\": \"get_time\", \"arguments\": {}}]<|/tool|>");
+    assert!(parsed.tool_calls.is_empty());
+    let parsed = parser.parse("<|tool|>{\"name\": \"get_time\", \"arguments\": {}}<|/tool|>");
+    assert_eq!(
+        parsed.tool_calls,
+        vec![Function {
+            name: "get_time".to_string(),
+            arguments: "{}".to_string()
+        }]
+    );
+}
+
+#[test]
+fn test_scan_json_object() {
+    let (fields, len) =
+        scan_json_object(r#"{"name": "get_time", "arguments": {"city": "Paris"}} tail"#);
+    assert_eq!(len, Some(52));
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields[0].key, "name");
+    assert_eq!(fields[0].value, r#""get_time""#);
+    assert_eq!(fields[1].key, "arguments");
+    assert_eq!(fields[1].value, r#"{"city": "Paris"}"#);
+    assert!(fields[1].complete);
+
+    // incomplete
+    let (fields, len) = scan_json_object(r#"{"name": "get_time", "arguments": {"city": "Pa"#);
+    assert_eq!(len, None);
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields[1].value, r#"{"city": "Pa"#);
+    assert!(!fields[1].complete);
+
+    // malformed, e.g. with a trailing comma or an unquoted key
+    let (fields, len) = scan_json_object(r#"{"count": 2, "arguments": {"a": [1, 2,],},}"#);
+    assert_eq!(len, Some(43));
+    assert_eq!(fields[0].value, "2");
+    assert_eq!(fields[1].value, r#"{"a": [1, 2,],}"#);
+
+    let (fields, len) = scan_json_object(r#"{"name": "get_time", city: "Paris"}"#);
+    assert_eq!(len, None);
+    assert_eq!(fields.len(), 1);
+}
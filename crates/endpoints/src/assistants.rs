@@ -0,0 +1,384 @@
+//! Define types for the `assistants` and `threads` endpoints.
+
+use crate::{
+    chat::{ChatCompletionRole, ContextTruncation, Tool, ToolCall},
+    common::Usage,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Request body of the `POST /v1/assistants` endpoint, which creates an assistant.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AssistantRequest {
+    /// The model used by the assistant.
+    pub model: String,
+    /// The name of the assistant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The system prompt used by the assistant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// A list of tools enabled on the assistant.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    /// The ids of the uploaded files, whose contents are attached to the system prompt.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_ids: Vec<String>,
+    /// Set of key-value pairs attached to the assistant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Represents an assistant that runs the threads with its model, system prompt, tools and files.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AssistantObject {
+    /// The assistant identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always `assistant`.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the assistant was created.
+    pub created_at: u64,
+    /// The model used by the assistant.
+    pub model: String,
+    /// The name of the assistant.
+    pub name: Option<String>,
+    /// The system prompt used by the assistant.
+    pub instructions: Option<String>,
+    /// A list of tools enabled on the assistant.
+    pub tools: Vec<Tool>,
+    /// The ids of the uploaded files, whose contents are attached to the system prompt.
+    pub file_ids: Vec<String>,
+    /// Set of key-value pairs attached to the assistant.
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Represent the response from the `GET /v1/assistants` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListAssistantsResponse {
+    /// The object type, which is always `list`.
+    pub object: String,
+    /// The list of assistant objects.
+    pub data: Vec<AssistantObject>,
+}
+
+/// Represents the status of an assistant or a thread deletion operation.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeletionStatus {
+    /// The identifier of the deleted object.
+    pub id: String,
+    /// The object type, e.g., `assistant.deleted` or `thread.deleted`.
+    pub object: String,
+    /// The status of the deletion operation.
+    pub deleted: bool,
+}
+
+/// Request body of the `POST /v1/threads` endpoint, which creates a thread.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ThreadRequest {
+    /// The messages to start the thread with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<MessageRequest>,
+    /// Set of key-value pairs attached to the thread.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Represents a thread, which keeps the messages of a conversation on the server.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ThreadObject {
+    /// The thread identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always `thread`.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the thread was created.
+    pub created_at: u64,
+    /// Set of key-value pairs attached to the thread.
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Request body of the `POST /v1/threads/{thread_id}/messages` endpoint, which adds a message to a thread.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageRequest {
+    /// The role of the author of the message, either `user` or `assistant`.
+    pub role: ChatCompletionRole,
+    /// The text contents of the message.
+    pub content: String,
+    /// The ids of the uploaded files attached to the message. The images are passed to the model as image parts, and the contents of the other files are appended to the message.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_ids: Vec<String>,
+    /// Set of key-value pairs attached to the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Represents a message in a thread.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageObject {
+    /// The message identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always `thread.message`.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the message was created.
+    pub created_at: u64,
+    /// The id of the thread that the message belongs to.
+    pub thread_id: String,
+    /// The role of the author of the message.
+    pub role: ChatCompletionRole,
+    /// The contents of the message.
+    pub content: Vec<MessageContent>,
+    /// The id of the assistant that authored the message, if any.
+    pub assistant_id: Option<String>,
+    /// The id of the run that generated the message, if any.
+    pub run_id: Option<String>,
+    /// The ids of the uploaded files attached to the message.
+    pub file_ids: Vec<String>,
+    /// Set of key-value pairs attached to the message.
+    pub metadata: Option<HashMap<String, String>>,
+}
+impl MessageObject {
+    /// Returns the text contents of the message.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .map(|content| match content {
+                MessageContent::Text { text } => text.value.as_str(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Represents a part of the contents of a message.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageContent {
+    /// The text part of the contents.
+    Text { text: MessageText },
+}
+
+/// Represents the text part of the contents of a message.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageText {
+    /// The text.
+    pub value: String,
+    /// The annotations of the text, which are always empty.
+    pub annotations: Vec<serde_json::Value>,
+}
+
+/// Represent the response from the `GET /v1/threads/{thread_id}/messages` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListMessagesResponse {
+    /// The object type, which is always `list`.
+    pub object: String,
+    /// The messages of the thread, in the order they were created.
+    pub data: Vec<MessageObject>,
+    /// The id of the first message.
+    pub first_id: Option<String>,
+    /// The id of the last message.
+    pub last_id: Option<String>,
+    /// Whether there are more messages, which is always false.
+    pub has_more: bool,
+}
+
+/// Request body of the `POST /v1/threads/{thread_id}/runs` endpoint, which runs a thread with an assistant.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RunRequest {
+    /// The id of the assistant that runs the thread.
+    pub assistant_id: String,
+    /// The model overriding the model of the assistant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The system prompt overriding the instructions of the assistant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// The instructions appended to the system prompt of this run only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub additional_instructions: Option<String>,
+    /// The tools overriding the tools of the assistant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    /// The sampling temperature of the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// The maximum number of tokens to generate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u64>,
+    /// Controls how the messages of the thread are truncated when the prompt exceeds the budget of prompt tokens. Defaults to the settings of the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_truncation: Option<ContextTruncation>,
+    /// Set of key-value pairs attached to the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// The status of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    /// The run is waiting to be processed.
+    Queued,
+    /// The model is generating the reply.
+    InProgress,
+    /// The model called the tools, and the run waits for their outputs.
+    RequiresAction,
+    /// The cancellation of the run has been initiated.
+    Cancelling,
+    /// The run was cancelled.
+    Cancelled,
+    /// The run failed, and the reason is in `last_error`.
+    Failed,
+    /// The reply of the model was added to the thread.
+    Completed,
+}
+impl RunStatus {
+    /// Whether the run is waiting or being processed, which blocks new runs on the same thread.
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            RunStatus::Queued
+                | RunStatus::InProgress
+                | RunStatus::RequiresAction
+                | RunStatus::Cancelling
+        )
+    }
+}
+
+/// Represents a run of a thread with an assistant.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RunObject {
+    /// The run identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always `thread.run`.
+    pub object: String,
+    /// The Unix timestamp (in seconds) for when the run was created.
+    pub created_at: u64,
+    /// The id of the thread that is run.
+    pub thread_id: String,
+    /// The id of the assistant that runs the thread.
+    pub assistant_id: String,
+    /// The current status of the run.
+    pub status: RunStatus,
+    /// The action required to continue the run, i.e., the outputs of the tool calls.
+    pub required_action: Option<RequiredAction>,
+    /// The last error of the run.
+    pub last_error: Option<RunError>,
+    /// The Unix timestamp (in seconds) for when the run was started.
+    pub started_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run was cancelled.
+    pub cancelled_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run failed.
+    pub failed_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the run was completed.
+    pub completed_at: Option<u64>,
+    /// The model used by the run.
+    pub model: String,
+    /// The system prompt used by the run.
+    pub instructions: String,
+    /// The tools used by the run.
+    pub tools: Vec<Tool>,
+    /// The sampling temperature of the model.
+    pub temperature: Option<f64>,
+    /// The maximum number of tokens to generate.
+    pub max_completion_tokens: Option<u64>,
+    /// Controls how the messages of the thread are truncated.
+    pub context_truncation: Option<ContextTruncation>,
+    /// The token usage of the run, once the run is finished.
+    pub usage: Option<Usage>,
+    /// Set of key-value pairs attached to the run.
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// The action required to continue a run.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RequiredAction {
+    /// The type of the action, which is always `submit_tool_outputs`.
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The tool calls whose outputs are required.
+    pub submit_tool_outputs: SubmitToolOutputs,
+}
+
+/// The tool calls whose outputs are required to continue a run.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubmitToolOutputs {
+    /// The tool calls generated by the model.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// The error of a failed run.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RunError {
+    /// An error code identifying the error type.
+    pub code: String,
+    /// A human-readable message providing more details about the error.
+    pub message: String,
+}
+
+/// Represent the response from the `GET /v1/threads/{thread_id}/runs` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListRunsResponse {
+    /// The object type, which is always `list`.
+    pub object: String,
+    /// The runs of the thread, in the order they were created.
+    pub data: Vec<RunObject>,
+    /// The id of the first run.
+    pub first_id: Option<String>,
+    /// The id of the last run.
+    pub last_id: Option<String>,
+    /// Whether there are more runs, which is always false.
+    pub has_more: bool,
+}
+
+/// Request body of the `POST /v1/threads/{thread_id}/runs/{run_id}/submit_tool_outputs` endpoint, which continues a run with the outputs of the tool calls.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SubmitToolOutputsRequest {
+    /// The outputs of all the tool calls in the required action of the run.
+    pub tool_outputs: Vec<ToolOutput>,
+}
+
+/// The output of a tool call.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ToolOutput {
+    /// The id of the tool call.
+    pub tool_call_id: String,
+    /// The output of the tool call.
+    pub output: String,
+}
+
+#[test]
+fn test_assistants_deserialize_run_request() {
+    let json = r#"{"assistant_id":"asst_abc","additional_instructions":"Answer in French.","context_truncation":{"strategy":"keep_last_turns","last_turns":4}}"#;
+    let request: RunRequest = serde_json::from_str(json).unwrap();
+    assert_eq!(request.assistant_id, "asst_abc");
+    assert_eq!(
+        request.additional_instructions.as_deref(),
+        Some("Answer in French.")
+    );
+    assert_eq!(request.context_truncation.unwrap().last_turns, Some(4));
+    assert!(request.tools.is_none());
+
+    let json = r#"{"messages":[{"role":"user","content":"Hello","file_ids":["file_abc"]}]}"#;
+    let request: ThreadRequest = serde_json::from_str(json).unwrap();
+    assert_eq!(request.messages[0].role, ChatCompletionRole::User);
+    assert_eq!(request.messages[0].file_ids, ["file_abc"]);
+}
+
+#[test]
+fn test_assistants_serialize_message_content() {
+    let content = MessageContent::Text {
+        text: MessageText {
+            value: "Hello".to_string(),
+            annotations: vec![],
+        },
+    };
+    assert_eq!(
+        serde_json::to_string(&content).unwrap(),
+        r#"{"type":"text","text":{"value":"Hello","annotations":[]}}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&RunStatus::RequiresAction).unwrap(),
+        r#""requires_action""#
+    );
+    assert!(RunStatus::RequiresAction.is_active());
+    assert!(!RunStatus::Completed.is_active());
+}
@@ -0,0 +1,208 @@
+//! Define types for the `batches` endpoint.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Request body of the `POST /v1/batches` endpoint, which creates a batch from an uploaded file of requests.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchRequest {
+    /// The id of the uploaded JSONL file, each line of which is a [`BatchRequestInput`].
+    pub input_file_id: String,
+    /// The endpoint targeted by all the requests in the batch. Supported values are `/v1/chat/completions` and `/v1/embeddings`.
+    pub endpoint: String,
+    /// The time frame within which the batch should be processed. Currently only `24h` is supported.
+    #[serde(default = "default_completion_window")]
+    pub completion_window: String,
+    /// Set of key-value pairs attached to the batch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+fn default_completion_window() -> String {
+    "24h".to_string()
+}
+
+/// The status of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    /// The input file is being validated before the batch can begin.
+    Validating,
+    /// The input file has failed the validation process.
+    Failed,
+    /// The input file was successfully validated and the batch is currently being run.
+    InProgress,
+    /// The batch has completed and the results are being prepared.
+    Finalizing,
+    /// The batch has been completed and the results are ready.
+    Completed,
+    /// The batch was not able to be completed within the completion window.
+    Expired,
+    /// The cancellation of the batch has been initiated.
+    Cancelling,
+    /// The batch was cancelled.
+    Cancelled,
+}
+impl BatchStatus {
+    /// Whether the batch has stopped processing the requests.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            BatchStatus::Failed
+                | BatchStatus::Completed
+                | BatchStatus::Expired
+                | BatchStatus::Cancelled
+        )
+    }
+}
+
+/// The number of the requests of a batch in each state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BatchRequestCounts {
+    /// Total number of requests in the batch.
+    pub total: u64,
+    /// Number of requests that have been completed successfully.
+    pub completed: u64,
+    /// Number of requests that have failed.
+    pub failed: u64,
+}
+
+/// The errors found while validating the input file of a batch.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchErrors {
+    /// The object type, which is always `list`.
+    pub object: String,
+    pub data: Vec<BatchError>,
+}
+
+/// An error of a batch or of a request in the batch.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchError {
+    /// An error code identifying the error type.
+    pub code: String,
+    /// A human-readable message providing more details about the error.
+    pub message: String,
+    /// The name of the parameter that caused the error, if applicable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub param: Option<String>,
+    /// The line number of the input file where the error occurred, if applicable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+}
+
+/// Represents a batch of requests processed in the background.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchObject {
+    /// The batch identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always `batch`.
+    pub object: String,
+    /// The endpoint targeted by the requests in the batch.
+    pub endpoint: String,
+    /// The errors found while validating the input file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<BatchErrors>,
+    /// The id of the input file of the batch.
+    pub input_file_id: String,
+    /// The time frame within which the batch should be processed.
+    pub completion_window: String,
+    /// The current status of the batch.
+    pub status: BatchStatus,
+    /// The id of the file containing the outputs of the successfully executed requests.
+    pub output_file_id: Option<String>,
+    /// The id of the file containing the outputs of the requests with errors.
+    pub error_file_id: Option<String>,
+    /// The Unix timestamp (in seconds) for when the batch was created.
+    pub created_at: u64,
+    /// The Unix timestamp (in seconds) for when the batch started processing.
+    pub in_progress_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch will expire.
+    pub expires_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch started finalizing.
+    pub finalizing_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch was completed.
+    pub completed_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch failed.
+    pub failed_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch expired.
+    pub expired_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch started cancelling.
+    pub cancelling_at: Option<u64>,
+    /// The Unix timestamp (in seconds) for when the batch was cancelled.
+    pub cancelled_at: Option<u64>,
+    /// The request counts for different statuses within the batch.
+    pub request_counts: BatchRequestCounts,
+    /// Set of key-value pairs attached to the batch.
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+/// Represent the response from the `GET /v1/batches` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListBatchesResponse {
+    /// The object type, which is always `list`.
+    pub object: String,
+    /// The list of batch objects.
+    pub data: Vec<BatchObject>,
+}
+
+/// A line of the input file of a batch.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchRequestInput {
+    /// The id of the request, which is unique in the batch and used to match the outputs to the inputs.
+    pub custom_id: String,
+    /// The HTTP method of the request, which is always `POST`.
+    pub method: String,
+    /// The endpoint of the request, which must be the same as the endpoint of the batch.
+    pub url: String,
+    /// The request body of the endpoint.
+    pub body: serde_json::Value,
+}
+
+/// A line of the output file or the error file of a batch.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchRequestOutput {
+    /// The id of the output.
+    pub id: String,
+    /// The id of the request in the input file.
+    pub custom_id: String,
+    /// The response of the request, if the request was executed.
+    pub response: Option<BatchResponse>,
+    /// The error of the request, if the request failed.
+    pub error: Option<BatchError>,
+}
+
+/// The response of a request in a batch.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchResponse {
+    /// The HTTP status code of the response.
+    pub status_code: u16,
+    /// The id of the request.
+    pub request_id: String,
+    /// The response body of the endpoint.
+    pub body: serde_json::Value,
+}
+
+#[test]
+fn test_batches_deserialize_batch_request() {
+    let json = r#"{"input_file_id":"file_abc","endpoint":"/v1/chat/completions"}"#;
+    let request: BatchRequest = serde_json::from_str(json).unwrap();
+    assert_eq!(request.input_file_id, "file_abc");
+    assert_eq!(request.endpoint, "/v1/chat/completions");
+    assert_eq!(request.completion_window, "24h");
+    assert!(request.metadata.is_none());
+
+    let json = r#"{"custom_id":"request-1","method":"POST","url":"/v1/embeddings","body":{"model":"all-minilm","input":"Hello"}}"#;
+    let input: BatchRequestInput = serde_json::from_str(json).unwrap();
+    assert_eq!(input.custom_id, "request-1");
+    assert_eq!(input.body["input"], "Hello");
+}
+
+#[test]
+fn test_batches_serialize_batch_status() {
+    assert_eq!(
+        serde_json::to_string(&BatchStatus::InProgress).unwrap(),
+        r#""in_progress""#
+    );
+    assert!(BatchStatus::Cancelled.is_terminal());
+    assert!(!BatchStatus::Cancelling.is_terminal());
+}
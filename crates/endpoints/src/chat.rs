@@ -246,6 +246,23 @@ impl ChatCompletionRequestBuilder {
         self
     }
 
+    /// Sets the seed for deterministic sampling.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.req.seed = Some(seed);
+        self
+    }
+
+    /// Enables returning the log probabilities of the output tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `top_logprobs` - The number of most likely tokens to return at each token position, each with an associated log probability. If `top_logprobs` is greater than 20, then sets to `20`.
+    pub fn with_logprobs(mut self, top_logprobs: u8) -> Self {
+        self.req.logprobs = Some(true);
+        self.req.top_logprobs = Some(top_logprobs.min(20));
+        self
+    }
+
     pub fn with_user(mut self, user: impl Into<String>) -> Self {
         self.req.user = Some(user.into());
         self
@@ -285,6 +302,24 @@ impl ChatCompletionRequestBuilder {
         self
     }
 
+    /// Sets how the chat history is truncated when the prompt exceeds the budget of prompt tokens.
+    pub fn with_context_truncation(mut self, context_truncation: ContextTruncation) -> Self {
+        self.req.context_truncation = Some(context_truncation);
+        self
+    }
+
+    /// Sets the maximum time in seconds to process the request.
+    pub fn with_timeout(mut self, timeout: u64) -> Self {
+        self.req.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the id of the chat completion, which is the key to cancel the request with.
+    pub fn with_chat_id(mut self, chat_id: impl Into<String>) -> Self {
+        self.req.chat_id = Some(chat_id.into());
+        self
+    }
+
     /// Sets the Qdrant settings, which are only used in RAG chat completions.
     ///
     /// # Arguments
@@ -387,6 +422,19 @@ pub struct ChatCompletionRequest {
     /// Defaults to None.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub logit_bias: Option<HashMap<String, f64>>,
+    /// Whether to return log probabilities of the output tokens or not. If true, returns the log probabilities of each output token returned in the `content` of `message`.
+    ///
+    /// Requires a backend which outputs the log probabilities. The WASI-NN ggml plugin does not, and the request is rejected.
+    /// Defaults to false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    /// An integer between 0 and 20 specifying the number of most likely tokens to return at each token position, each with an associated log probability. `logprobs` must be set to `true` if this parameter is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
+    /// If specified, the sampling is deterministic, such that repeated requests with the same `seed` and parameters return the same result. Determinism is not guaranteed across different `system_fingerprint` values.
+    /// Defaults to None.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     /// A unique identifier representing your end-user.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
@@ -416,6 +464,18 @@ pub struct ChatCompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub context_window: Option<u64>,
 
+    /// Controls how the chat history is truncated when the prompt exceeds the budget of prompt tokens. Defaults to the settings of the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_truncation: Option<ContextTruncation>,
+
+    /// The maximum time in seconds to process the request. If exceeded, the generation stops with the `stop` finish reason, and the choice is marked `cancelled`. Defaults to no limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+
+    /// The id of the chat completion, which is the `id` of the chat completion object and chunks, and the key to cancel the request with. A unique id is generated for the request if not given. It is never read from the request body, so that the concurrent requests do not share it.
+    #[serde(skip)]
+    pub chat_id: Option<String>,
+
     /// The URL of the VectorDB server.
     #[cfg(feature = "rag")]
     #[serde(rename = "url_vdb_server", skip_serializing_if = "Option::is_none")]
@@ -464,6 +524,9 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
                 let mut presence_penalty = None;
                 let mut frequency_penalty = None;
                 let mut logit_bias = None;
+                let mut logprobs = None;
+                let mut top_logprobs = None;
+                let mut seed = None;
                 let mut user = None;
                 let mut functions = None;
                 let mut function_call = None;
@@ -471,6 +534,8 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
                 let mut tools = None;
                 let mut tool_choice = None;
                 let mut context_window = None;
+                let mut context_truncation = None;
+                let mut timeout = None;
                 #[cfg(feature = "rag")]
                 let mut qdrant_url = None;
                 #[cfg(feature = "rag")]
@@ -494,6 +559,9 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
                         "presence_penalty" => presence_penalty = map.next_value()?,
                         "frequency_penalty" => frequency_penalty = map.next_value()?,
                         "logit_bias" => logit_bias = map.next_value()?,
+                        "logprobs" => logprobs = map.next_value()?,
+                        "top_logprobs" => top_logprobs = map.next_value()?,
+                        "seed" => seed = map.next_value()?,
                         "user" => user = map.next_value()?,
                         "functions" => functions = map.next_value()?,
                         "function_call" => function_call = map.next_value()?,
@@ -501,6 +569,8 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
                         "tools" => tools = map.next_value()?,
                         "tool_choice" => tool_choice = map.next_value()?,
                         "context_window" => context_window = map.next_value()?,
+                        "context_truncation" => context_truncation = map.next_value()?,
+                        "timeout" => timeout = map.next_value()?,
                         #[cfg(feature = "rag")]
                         "url_vdb_server" => qdrant_url = map.next_value()?,
                         #[cfg(feature = "rag")]
@@ -558,6 +628,9 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
                     presence_penalty,
                     frequency_penalty,
                     logit_bias,
+                    logprobs,
+                    top_logprobs,
+                    seed,
                     user,
                     functions,
                     function_call,
@@ -565,6 +638,9 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
                     tools,
                     tool_choice,
                     context_window,
+                    context_truncation,
+                    timeout,
+                    chat_id: None,
                     #[cfg(feature = "rag")]
                     qdrant_url,
                     #[cfg(feature = "rag")]
@@ -590,6 +666,9 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
             "presence_penalty",
             "frequency_penalty",
             "logit_bias",
+            "logprobs",
+            "top_logprobs",
+            "seed",
             "user",
             "functions",
             "function_call",
@@ -597,6 +676,8 @@ impl<'de> Deserialize<'de> for ChatCompletionRequest {
             "tools",
             "tool_choice",
             "context_window",
+            "context_truncation",
+            "timeout",
             #[cfg(feature = "rag")]
             "url_vdb_server",
             #[cfg(feature = "rag")]
@@ -628,6 +709,9 @@ impl Default for ChatCompletionRequest {
             presence_penalty: Some(0.0),
             frequency_penalty: Some(0.0),
             logit_bias: None,
+            logprobs: None,
+            top_logprobs: None,
+            seed: None,
             user: None,
             functions: None,
             function_call: None,
@@ -635,6 +719,9 @@ impl Default for ChatCompletionRequest {
             tools: None,
             tool_choice: None,
             context_window: Some(1),
+            context_truncation: None,
+            timeout: None,
+            chat_id: None,
             #[cfg(feature = "rag")]
             qdrant_url: None,
             #[cfg(feature = "rag")]
@@ -946,6 +1033,20 @@ fn test_chat_deserialize_chat_request() {
         assert_eq!(request.tool_choice, Some(ToolChoice::None));
     }
 
+    {
+        let json = r#"{"model":"model-id","messages":[{"role":"user","content":"Hello, world!"}],"logprobs":true,"top_logprobs":3}"#;
+        let request: ChatCompletionRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.logprobs, Some(true));
+        assert_eq!(request.top_logprobs, Some(3));
+        assert_eq!(request.seed, None);
+    }
+
+    {
+        let json = r#"{"model":"model-id","messages":[{"role":"user","content":"Hello, world!"}],"seed":1234}"#;
+        let request: ChatCompletionRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.seed, Some(1234));
+    }
+
     {
         let json = r#"{"model":"model-id","messages":[{"role":"system","content":"Hello, world!"},{"role":"user","content":"Hello, world!"},{"role":"assistant","content":"Hello, world!"}],"temperature":0.8,"top_p":1.0,"n":3,"stream":true,"stop":["stop1","stop2"],"max_tokens":100,"presence_penalty":0.5,"frequency_penalty":0.5,"response_format":{"type":"text"},"tool_choice":"auto"}"#;
         let request: ChatCompletionRequest = serde_json::from_str(json).unwrap();
@@ -1072,14 +1173,18 @@ fn test_chat_deserialize_chat_request() {
 /// An object specifying the format that the model must output.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ChatResponseFormat {
-    /// Must be one of `text`` or `json_object`. Defaults to `text`.
+    /// Must be one of `text`, `json_object` or `json_schema`. Defaults to `text`.
     #[serde(rename = "type")]
     pub ty: String,
+    /// The JSON schema the output must conform to. Required if `type` is `json_schema`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<ChatResponseJsonSchema>,
 }
 impl Default for ChatResponseFormat {
     fn default() -> Self {
         Self {
             ty: "text".to_string(),
+            json_schema: None,
         }
     }
 }
@@ -1088,15 +1193,189 @@ impl Default for ChatResponseFormat {
 fn test_chat_serialize_response_format() {
     let response_format = ChatResponseFormat {
         ty: "text".to_string(),
+        json_schema: None,
     };
     let json = serde_json::to_string(&response_format).unwrap();
     assert_eq!(json, r#"{"type":"text"}"#);
 
     let response_format = ChatResponseFormat {
         ty: "json_object".to_string(),
+        json_schema: None,
     };
     let json = serde_json::to_string(&response_format).unwrap();
     assert_eq!(json, r#"{"type":"json_object"}"#);
+
+    let response_format = ChatResponseFormat {
+        ty: "json_schema".to_string(),
+        json_schema: Some(ChatResponseJsonSchema {
+            name: "person".to_string(),
+            description: None,
+            schema: Some(serde_json::json!({
+                "type": "object",
+                "properties": {"name": {"type": "string"}}
+            })),
+            strict: Some(true),
+        }),
+    };
+    let json = serde_json::to_string(&response_format).unwrap();
+    assert_eq!(
+        json,
+        r#"{"type":"json_schema","json_schema":{"name":"person","schema":{"properties":{"name":{"type":"string"}},"type":"object"},"strict":true}}"#
+    );
+}
+
+#[test]
+fn test_chat_deserialize_response_format() {
+    let json = r#"{"type":"json_object"}"#;
+    let response_format: ChatResponseFormat = serde_json::from_str(json).unwrap();
+    assert_eq!(response_format.ty, "json_object");
+    assert!(response_format.json_schema.is_none());
+
+    let json = r#"{"type":"json_schema","json_schema":{"name":"person","description":"A person","schema":{"type":"object","properties":{"age":{"type":"integer"}},"required":["age"]}}}"#;
+    let response_format: ChatResponseFormat = serde_json::from_str(json).unwrap();
+    assert_eq!(response_format.ty, "json_schema");
+    let json_schema = response_format.json_schema.unwrap();
+    assert_eq!(json_schema.name, "person");
+    assert_eq!(json_schema.description, Some("A person".to_string()));
+    assert!(json_schema.strict.is_none());
+    let schema = json_schema.schema.unwrap();
+    assert_eq!(schema["properties"]["age"]["type"], "integer");
+}
+
+/// Describes the JSON schema of the `json_schema` response format.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChatResponseJsonSchema {
+    /// The name of the response format.
+    pub name: String,
+    /// A description of what the response format is for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The schema for the response format, described as a JSON Schema object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+    /// Whether to enable strict schema adherence when generating the output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// Controls how the chat history is truncated when the prompt exceeds the budget of prompt tokens.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ContextTruncation {
+    /// The strategy to truncate the chat history. Defaults to `drop_oldest`.
+    #[serde(default)]
+    pub strategy: TruncationStrategy,
+    /// Number of the latest turns kept by the `keep_last_turns` strategy. A turn starts with a user message. Defaults to 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_turns: Option<usize>,
+    /// Indices of the messages which are never dropped or truncated, for example, few-shot examples. The system message at the beginning and the last turn are always kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_messages: Vec<usize>,
+    /// Ratio of the context size for the prompt, between 0.0 and 1.0. The rest is left for the completion. Defaults to 0.8.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_ratio: Option<f64>,
+}
+
+/// Defines the strategies to truncate the chat history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Drops the oldest turns until the prompt fits.
+    #[default]
+    DropOldest,
+    /// Keeps the system message and the last `last_turns` turns only, and drops the oldest turns if the prompt still does not fit.
+    KeepLastTurns,
+    /// Truncates the middle of the longest message until the prompt fits.
+    TruncateMiddle,
+    /// Drops the oldest turns until the prompt fits, and summarizes them into the system message with the same model.
+    Summarize,
+}
+impl fmt::Display for TruncationStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TruncationStrategy::DropOldest => write!(f, "drop-oldest"),
+            TruncationStrategy::KeepLastTurns => write!(f, "keep-last-turns"),
+            TruncationStrategy::TruncateMiddle => write!(f, "truncate-middle"),
+            TruncationStrategy::Summarize => write!(f, "summarize"),
+        }
+    }
+}
+impl std::str::FromStr for TruncationStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.replace('_', "-").as_str() {
+            "drop-oldest" => Ok(TruncationStrategy::DropOldest),
+            "keep-last-turns" => Ok(TruncationStrategy::KeepLastTurns),
+            "truncate-middle" => Ok(TruncationStrategy::TruncateMiddle),
+            "summarize" => Ok(TruncationStrategy::Summarize),
+            _ => Err(format!(
+                "Invalid truncation strategy: {}. Possible values: drop-oldest, keep-last-turns, truncate-middle, summarize.",
+                s
+            )),
+        }
+    }
+}
+
+/// Reports how the chat history was truncated to fit the prompt into the context.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ContextTruncationReport {
+    /// The strategy used to truncate the chat history.
+    pub strategy: TruncationStrategy,
+    /// Indices of the messages in the request which were dropped from the prompt.
+    pub dropped_messages: Vec<usize>,
+    /// Indices of the messages in the request whose contents were shortened.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub truncated_messages: Vec<usize>,
+    /// Whether the dropped messages were summarized into the system message.
+    #[serde(default)]
+    pub summarized: bool,
+}
+
+#[test]
+fn test_chat_deserialize_timeout() {
+    let json = r#"{"messages":[{"role":"user","content":"Hello!"}],"timeout":30}"#;
+    let request: ChatCompletionRequest = serde_json::from_str(json).unwrap();
+    assert_eq!(request.timeout, Some(30));
+
+    let json = r#"{"messages":[{"role":"user","content":"Hello!"}]}"#;
+    let request: ChatCompletionRequest = serde_json::from_str(json).unwrap();
+    assert_eq!(request.timeout, None);
+}
+
+#[test]
+fn test_chat_deserialize_context_truncation() {
+    let json = r#"{"strategy":"keep_last_turns","last_turns":2,"pinned_messages":[1,2],"prompt_ratio":0.6}"#;
+    let truncation: ContextTruncation = serde_json::from_str(json).unwrap();
+    assert_eq!(truncation.strategy, TruncationStrategy::KeepLastTurns);
+    assert_eq!(truncation.last_turns, Some(2));
+    assert_eq!(truncation.pinned_messages, vec![1, 2]);
+    assert_eq!(truncation.prompt_ratio, Some(0.6));
+
+    let truncation: ContextTruncation = serde_json::from_str("{}").unwrap();
+    assert_eq!(truncation, ContextTruncation::default());
+
+    assert!(serde_json::from_str::<ContextTruncation>(r#"{"strategy":"unknown"}"#).is_err());
+
+    assert_eq!(
+        "truncate_middle".parse::<TruncationStrategy>(),
+        Ok(TruncationStrategy::TruncateMiddle)
+    );
+    assert_eq!(
+        "summarize".parse::<TruncationStrategy>(),
+        Ok(TruncationStrategy::Summarize)
+    );
+    assert!("unknown".parse::<TruncationStrategy>().is_err());
+
+    let report = ContextTruncationReport {
+        strategy: TruncationStrategy::DropOldest,
+        dropped_messages: vec![1, 2],
+        ..Default::default()
+    };
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(
+        json,
+        r#"{"strategy":"drop_oldest","dropped_messages":[1,2],"summarized":false}"#
+    );
 }
 
 /// Options for streaming response. Only set this when you set stream: `true``.
@@ -1911,6 +2190,10 @@ impl ChatCompletionUserMessage {
         &self.content
     }
 
+    pub fn content_mut(&mut self) -> &mut ChatCompletionUserMessageContent {
+        &mut self.content
+    }
+
     pub fn name(&self) -> Option<&String> {
         self.name.as_ref()
     }
@@ -2099,17 +2382,19 @@ fn test_deserialize_tool_call() {
     );
 }
 
-/// Represents a tool call generated by the model.
+/// Represents a delta of a tool call generated by the model in the stream mode.
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ToolCallForChunk {
+    /// The index of the tool call in the list of tool calls of the message.
     pub index: usize,
-    /// The ID of the tool call.
-    pub id: String,
-    /// The type of the tool. Currently, only function is supported.
-    #[serde(rename = "type")]
-    pub ty: String,
+    /// The ID of the tool call. Only present in the first delta of the tool call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The type of the tool. Currently, only function is supported. Only present in the first delta of the tool call.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub ty: Option<String>,
     /// The function that the model called.
-    pub function: Function,
+    pub function: FunctionForChunk,
 }
 
 #[test]
@@ -2117,15 +2402,67 @@ fn test_deserialize_tool_call_for_chunk() {
     let json = r#"{"index":0, "id":"tool-call-id","type":"function","function":{"name":"my_function","arguments":"{\"location\":\"San Francisco, CA\"}"}}"#;
     let tool_call: ToolCallForChunk = serde_json::from_str(json).unwrap();
     assert_eq!(tool_call.index, 0);
-    assert_eq!(tool_call.id, "tool-call-id");
-    assert_eq!(tool_call.ty, "function");
+    assert_eq!(tool_call.id, Some("tool-call-id".to_string()));
+    assert_eq!(tool_call.ty, Some("function".to_string()));
     assert_eq!(
         tool_call.function,
-        Function {
-            name: "my_function".to_string(),
+        FunctionForChunk {
+            name: Some("my_function".to_string()),
             arguments: r#"{"location":"San Francisco, CA"}"#.to_string()
         }
     );
+
+    let json = r#"{"index":1,"function":{"arguments":"{\"location\":"}}"#;
+    let tool_call: ToolCallForChunk = serde_json::from_str(json).unwrap();
+    assert_eq!(tool_call.index, 1);
+    assert_eq!(tool_call.id, None);
+    assert_eq!(tool_call.ty, None);
+    assert_eq!(tool_call.function.name, None);
+    assert_eq!(tool_call.function.arguments, r#"{"location":"#);
+}
+
+#[test]
+fn test_serialize_tool_call_for_chunk() {
+    let tool_call = ToolCallForChunk {
+        index: 0,
+        id: Some("call_abc123".to_string()),
+        ty: Some("function".to_string()),
+        function: FunctionForChunk {
+            name: Some("my_function".to_string()),
+            arguments: String::new(),
+        },
+    };
+    let json = serde_json::to_string(&tool_call).unwrap();
+    assert_eq!(
+        json,
+        r#"{"index":0,"id":"call_abc123","type":"function","function":{"name":"my_function","arguments":""}}"#
+    );
+
+    let tool_call = ToolCallForChunk {
+        index: 0,
+        id: None,
+        ty: None,
+        function: FunctionForChunk {
+            name: None,
+            arguments: r#"{"location":"#.to_string(),
+        },
+    };
+    let json = serde_json::to_string(&tool_call).unwrap();
+    assert_eq!(
+        json,
+        r#"{"index":0,"function":{"arguments":"{\"location\":"}}"#
+    );
+}
+
+/// A delta of the function that the model called in the stream mode.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FunctionForChunk {
+    /// The name of the function that the model called. Only present in the first delta of the tool call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// A fragment of the arguments that the model called the function with. The fragments of all the deltas of a tool call make up the arguments in JSON format.
+    #[serde(default)]
+    pub arguments: String,
 }
 
 /// The function that the model called.
@@ -2283,6 +2620,10 @@ impl ImageContentPart {
     pub fn image(&self) -> &Image {
         &self.image
     }
+
+    pub fn image_mut(&mut self) -> &mut Image {
+        &mut self.image
+    }
 }
 
 #[test]
@@ -2347,15 +2688,36 @@ fn test_chat_deserialize_image_content_part() {
 /// PNM (PPM and PGM binary only)
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct Image {
-    /// Either a URL of the image or the base64 encoded image data.
+    /// Either a URL of the image, a data URI, the id of a file uploaded via the `/v1/files` endpoint, or the base64 encoded image data.
     pub url: String,
     /// Specifies the detail level of the image. Defaults to auto.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detail: Option<String>,
 }
 impl Image {
+    /// Whether the image is referenced by a URL. Data URIs are not counted as URLs.
     pub fn is_url(&self) -> bool {
-        url::Url::parse(&self.url).is_ok()
+        match url::Url::parse(&self.url) {
+            Ok(url) => url.scheme() != "data",
+            Err(_) => false,
+        }
+    }
+
+    /// Whether the image is given as a data URI, e.g. `data:image/png;base64,...`.
+    pub fn is_data_uri(&self) -> bool {
+        self.url.starts_with("data:")
+    }
+
+    /// Whether the image refers to a file uploaded via the `/v1/files` endpoint.
+    pub fn is_file_id(&self) -> bool {
+        self.url.starts_with("file_")
+    }
+
+    /// Returns the media type and the base64 encoded data of the image if it is given as a base64 data URI.
+    pub fn data_uri(&self) -> Option<(&str, &str)> {
+        let (header, data) = self.url.strip_prefix("data:")?.split_once(',')?;
+        let media_type = header.strip_suffix(";base64")?;
+        Some((media_type, data))
     }
 }
 
@@ -2393,6 +2755,41 @@ fn test_chat_serialize_image() {
     assert_eq!(json, r#"{"url":"base64"}"#);
 }
 
+#[test]
+fn test_chat_image_source() {
+    let image = Image {
+        url: "https://example.com/image.png".to_string(),
+        detail: None,
+    };
+    assert!(image.is_url());
+    assert!(!image.is_data_uri());
+    assert!(!image.is_file_id());
+
+    let image = Image {
+        url: "data:image/png;base64,iVBORw0KGgo=".to_string(),
+        detail: None,
+    };
+    assert!(!image.is_url());
+    assert!(image.is_data_uri());
+    assert_eq!(image.data_uri(), Some(("image/png", "iVBORw0KGgo=")));
+
+    let image = Image {
+        url: "file_4bc1a0a4-f4b6-4b9b-8a54-3c9a4b6e2c11".to_string(),
+        detail: None,
+    };
+    assert!(!image.is_url());
+    assert!(image.is_file_id());
+    assert_eq!(image.data_uri(), None);
+
+    let image = Image {
+        url: "iVBORw0KGgo=".to_string(),
+        detail: None,
+    };
+    assert!(!image.is_url());
+    assert!(!image.is_data_uri());
+    assert!(!image.is_file_id());
+}
+
 #[test]
 fn test_chat_deserialize_image() {
     let json = r#"{"url":"https://example.com/image.png","detail":"auto"}"#;
@@ -2526,6 +2923,12 @@ pub struct ChatCompletionObject {
     pub choices: Vec<ChatCompletionObjectChoice>,
     /// Usage statistics for the completion request.
     pub usage: Usage,
+    /// This fingerprint represents the backend configuration that the model runs with. Can be used in conjunction with the `seed` request parameter to understand when backend changes have been made that might impact determinism.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+    /// How the chat history was truncated to fit the prompt into the context. Not present if the chat history was not truncated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_truncation: Option<ContextTruncationReport>,
 }
 
 #[test]
@@ -2607,10 +3010,13 @@ pub struct ChatCompletionObjectChoice {
     pub index: u32,
     /// A chat completion message generated by the model.
     pub message: ChatCompletionObjectMessage,
-    /// The reason the model stopped generating tokens. This will be `stop` if the model hit a natural stop point or a provided stop sequence, `length` if the maximum number of tokens specified in the request was reached, or `function_call` if the model called a function.
+    /// The reason the model stopped generating tokens. This will be `stop` if the model hit a natural stop point or a provided stop sequence, or if the generation was cancelled, `length` if the maximum number of tokens specified in the request was reached, or `function_call` if the model called a function.
     pub finish_reason: FinishReason,
     /// Log probability information for the choice.
     pub logprobs: Option<LogProbs>,
+    /// Whether the generation was cancelled or exceeded the `timeout` of the request. Not part of the OpenAI API, and only present if the generation was cancelled.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
 }
 
 #[test]
@@ -2634,6 +3040,7 @@ fn test_serialize_chat_completion_object_choice() {
         message,
         finish_reason: FinishReason::tool_calls,
         logprobs: None,
+        cancelled: false,
     };
     let json = serde_json::to_string(&choice).unwrap();
     assert_eq!(
@@ -2643,8 +3050,75 @@ fn test_serialize_chat_completion_object_choice() {
 }
 
 /// Log probability information for the choice.
-#[derive(Debug, Deserialize, Serialize)]
-pub struct LogProbs;
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct LogProbs {
+    /// A list of message content tokens with log probability information.
+    pub content: Option<Vec<TokenLogProb>>,
+}
+
+/// Log probability information of a token.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenLogProb {
+    /// The token.
+    pub token: String,
+    /// The log probability of this token.
+    pub logprob: f64,
+    /// A list of integers representing the UTF-8 bytes representation of the token. Useful in instances where characters are represented by multiple tokens and their byte representations must be combined to generate the correct text representation. Can be `None` if there is no bytes representation for the token.
+    pub bytes: Option<Vec<u8>>,
+    /// List of the most likely tokens and their log probability, at this token position. In rare cases, there may be fewer than the number of requested `top_logprobs` returned.
+    pub top_logprobs: Vec<TopLogProb>,
+}
+
+/// One of the most likely tokens and its log probability at a token position.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TopLogProb {
+    /// The token.
+    pub token: String,
+    /// The log probability of this token.
+    pub logprob: f64,
+    /// A list of integers representing the UTF-8 bytes representation of the token. Can be `None` if there is no bytes representation for the token.
+    pub bytes: Option<Vec<u8>>,
+}
+
+#[test]
+fn test_serialize_logprobs() {
+    let logprobs = LogProbs {
+        content: Some(vec![TokenLogProb {
+            token: "Hi".to_string(),
+            logprob: -0.25,
+            bytes: Some(vec![72, 105]),
+            top_logprobs: vec![
+                TopLogProb {
+                    token: "Hi".to_string(),
+                    logprob: -0.25,
+                    bytes: Some(vec![72, 105]),
+                },
+                TopLogProb {
+                    token: "Hello".to_string(),
+                    logprob: -1.5,
+                    bytes: None,
+                },
+            ],
+        }]),
+    };
+    let json = serde_json::to_string(&logprobs).unwrap();
+    assert_eq!(
+        json,
+        r#"{"content":[{"token":"Hi","logprob":-0.25,"bytes":[72,105],"top_logprobs":[{"token":"Hi","logprob":-0.25,"bytes":[72,105]},{"token":"Hello","logprob":-1.5,"bytes":null}]}]}"#
+    );
+}
+
+#[test]
+fn test_deserialize_logprobs() {
+    let json = r#"{"content":[{"token":"Hi","logprob":-0.25,"bytes":[72,105],"top_logprobs":[]}]}"#;
+    let logprobs: LogProbs = serde_json::from_str(json).unwrap();
+    let content = logprobs.content.unwrap();
+    assert_eq!(content.len(), 1);
+    assert_eq!(content[0].token, "Hi");
+    assert_eq!(content[0].logprob, -0.25);
+    assert_eq!(content[0].bytes, Some(vec![72, 105]));
+    assert!(content[0].top_logprobs.is_empty());
+}
 
 /// Represents a chat completion message generated by the model.
 #[derive(Debug, Serialize)]
@@ -2768,6 +3242,17 @@ pub struct ChatMessageFunctionCall {
     pub arguments: String,
 }
 
+/// Represents the status of a chat completion cancellation operation.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChatCompletionCancelStatus {
+    /// The identifier of the chat completion.
+    pub id: String,
+    /// The object type, which is always `chat.completion`.
+    pub object: String,
+    /// Whether the chat completion in progress was cancelled.
+    pub cancelled: bool,
+}
+
 /// Represents a streamed chunk of a chat completion response returned by model, based on the provided input.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ChatCompletionChunk {
@@ -2788,6 +3273,9 @@ pub struct ChatCompletionChunk {
     /// An optional field that will only be present when you set stream_options: {"include_usage": true} in your request. When present, it contains a null value except for the last chunk which contains the token usage statistics for the entire request.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<Usage>,
+    /// How the chat history was truncated to fit the prompt into the context. Only present in the first chunk if the chat history was truncated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_truncation: Option<ContextTruncationReport>,
 }
 
 #[test]
@@ -2803,12 +3291,14 @@ fn test_serialize_chat_completion_chunk() {
             },
             logprobs: None,
             finish_reason: None,
+            cancelled: false,
         }],
         created: 1722433423,
         model: "default".to_string(),
         system_fingerprint: "fp_44709d6fcb".to_string(),
         object: "chat.completion.chunk".to_string(),
         usage: None,
+        context_truncation: None,
     };
 
     let json = serde_json::to_string(&chunk).unwrap();
@@ -2830,6 +3320,7 @@ fn test_deserialize_chat_completion_chunk() {
         assert_eq!(chunk.choices[0].delta.content, Some(".".to_owned()));
         assert!(chunk.choices[0].delta.tool_calls.is_empty());
         assert_eq!(chunk.choices[0].delta.role, ChatCompletionRole::Assistant);
+        assert!(!chunk.choices[0].cancelled);
         assert_eq!(chunk.created, 1722433423);
         assert_eq!(chunk.model, "default");
         assert_eq!(chunk.system_fingerprint, "fp_44709d6fcb");
@@ -2846,8 +3337,32 @@ pub struct ChatCompletionChunkChoice {
     pub delta: ChatCompletionChunkChoiceDelta,
     /// Log probability information for the choice.
     pub logprobs: Option<LogProbs>,
-    /// The reason the model stopped generating tokens. This will be `stop` if the model hit a natural stop point or a provided stop sequence, `length` if the maximum number of tokens specified in the request was reached, or `function_call` if the model called a function.
+    /// The reason the model stopped generating tokens. This will be `stop` if the model hit a natural stop point or a provided stop sequence, or if the generation was cancelled, `length` if the maximum number of tokens specified in the request was reached, or `function_call` if the model called a function.
     pub finish_reason: Option<FinishReason>,
+    /// Whether the generation was cancelled or exceeded the `timeout` of the request. Not part of the OpenAI API, and only present in the last chunk if the generation was cancelled.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+}
+
+#[test]
+fn test_serialize_chat_completion_chunk_choice_cancelled() {
+    let choice = ChatCompletionChunkChoice {
+        index: 0,
+        delta: ChatCompletionChunkChoiceDelta {
+            content: None,
+            tool_calls: vec![],
+            role: ChatCompletionRole::Assistant,
+        },
+        logprobs: None,
+        finish_reason: Some(FinishReason::stop),
+        cancelled: true,
+    };
+
+    let json = serde_json::to_string(&choice).unwrap();
+    assert_eq!(
+        json,
+        r#"{"index":0,"delta":{"content":null,"role":"assistant"},"logprobs":null,"finish_reason":"stop","cancelled":true}"#
+    );
 }
 
 /// Represents a chat completion delta generated by streamed model responses.
@@ -1,15 +1,27 @@
 //! Define common types used by other types.
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize)]
+/// How the keys of `logit_bias` identify the tokens, in the same way as the `logit_bias` of [llama.cpp server](https://github.com/ggerganov/llama.cpp/tree/master/examples/server).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 #[allow(non_camel_case_types)]
 pub enum LlamaCppLogitBiasType {
+    /// The key is a token id, e.g. `"15043"`.
     input_ids,
+    /// The key is a token string to convert to token ids, e.g. `"Hello"`.
     tokens,
 }
+impl LlamaCppLogitBiasType {
+    /// Returns the type of the given key of `logit_bias`.
+    pub fn of_key(key: &str) -> Self {
+        match key.trim().parse::<u32>() {
+            Ok(_) => LlamaCppLogitBiasType::input_ids,
+            Err(_) => LlamaCppLogitBiasType::tokens,
+        }
+    }
+}
 
 /// Token usage
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Usage {
     /// Number of tokens in the prompt.
     pub prompt_tokens: u64,
@@ -32,7 +32,7 @@ pub struct CompletionRequest {
     pub logit_bias: Option<HashMap<String, f32>>,
     /// Include the log probabilities on the logprobs most likely tokens, as well the chosen tokens. For example, if logprobs is 5, the API will return a list of the 5 most likely tokens. The API will always return the logprob of the sampled token, so there may be up to logprobs+1 elements in the response.
     ///
-    /// The maximum value for logprobs is 5.
+    /// The maximum value for logprobs is 5. Requires a backend which outputs the log probabilities. The WASI-NN ggml plugin does not, and the request is rejected.
     /// Defaults to None.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub logprobs: Option<u32>,
@@ -50,6 +50,10 @@ pub struct CompletionRequest {
     /// Defaults to 0.0.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub presence_penalty: Option<f32>,
+    /// If specified, the sampling is deterministic, such that repeated requests with the same `seed` and parameters return the same result. Determinism is not guaranteed across different `system_fingerprint` values.
+    /// Defaults to None.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     /// Up to 4 sequences where the API will stop generating further tokens. The returned text will not contain the stop sequence.
     /// Defaults to None.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -97,6 +101,7 @@ fn test_serialize_completion_request() {
             max_tokens: Some(16),
             n: Some(1),
             presence_penalty: Some(0.0),
+            seed: Some(42),
             stop: Some(vec!["\n".to_string()]),
             stream: Some(false),
             suffix: Some("".to_string()),
@@ -106,7 +111,7 @@ fn test_serialize_completion_request() {
         };
 
         let actual = serde_json::to_string(&request).unwrap();
-        let expected = r#"{"model":"text-davinci-003","prompt":"Once upon a time","best_of":1,"echo":false,"frequency_penalty":0.0,"logit_bias":{},"logprobs":5,"max_tokens":16,"n":1,"presence_penalty":0.0,"stop":["\n"],"stream":false,"suffix":"","temperature":1.0,"top_p":1.0,"user":"user-123"}"#;
+        let expected = r#"{"model":"text-davinci-003","prompt":"Once upon a time","best_of":1,"echo":false,"frequency_penalty":0.0,"logit_bias":{},"logprobs":5,"max_tokens":16,"n":1,"presence_penalty":0.0,"seed":42,"stop":["\n"],"stream":false,"suffix":"","temperature":1.0,"top_p":1.0,"user":"user-123"}"#;
         assert_eq!(actual, expected);
     }
 
@@ -125,6 +130,7 @@ fn test_serialize_completion_request() {
             max_tokens: None,
             n: None,
             presence_penalty: None,
+            seed: None,
             stop: None,
             stream: None,
             suffix: None,
@@ -142,7 +148,7 @@ fn test_serialize_completion_request() {
 #[test]
 fn test_deserialize_completion_request() {
     {
-        let json = r#"{"model":"text-davinci-003","prompt":"Once upon a time","best_of":1,"echo":false,"frequency_penalty":0.0,"logit_bias":{},"logprobs":5,"max_tokens":16,"n":1,"presence_penalty":0.0,"stop":["\n"],"stream":false,"suffix":"","temperature":1.0,"top_p":1.0,"user":"user-123"}"#;
+        let json = r#"{"model":"text-davinci-003","prompt":"Once upon a time","best_of":1,"echo":false,"frequency_penalty":0.0,"logit_bias":{},"logprobs":5,"max_tokens":16,"n":1,"presence_penalty":0.0,"seed":42,"stop":["\n"],"stream":false,"suffix":"","temperature":1.0,"top_p":1.0,"user":"user-123"}"#;
         let request: CompletionRequest = serde_json::from_str(json).unwrap();
         assert_eq!(request.model, Some("text-davinci-003".to_string()));
         assert_eq!(
@@ -157,6 +163,7 @@ fn test_deserialize_completion_request() {
         assert_eq!(request.max_tokens, Some(16));
         assert_eq!(request.n, Some(1));
         assert_eq!(request.presence_penalty, Some(0.0));
+        assert_eq!(request.seed, Some(42));
         assert_eq!(request.stop, Some(vec!["\n".to_string()]));
         assert_eq!(request.stream, Some(false));
         assert_eq!(request.suffix, Some("".to_string()));
@@ -184,6 +191,7 @@ fn test_deserialize_completion_request() {
         assert_eq!(request.max_tokens, None);
         assert_eq!(request.n, None);
         assert_eq!(request.presence_penalty, None);
+        assert_eq!(request.seed, None);
         assert_eq!(request.stop, None);
         assert_eq!(request.stream, None);
         assert_eq!(request.suffix, None);
@@ -205,7 +213,7 @@ pub enum CompletionPrompt {
 
 /// Represents a completion response from the API.
 ///
-/// Note: both the streamed and non-streamed response objects share the same shape (unlike the chat endpoint).
+/// Note: the streamed response is a sequence of [`CompletionChunk`] objects, which differ from this object only in the optional `finish_reason` of the choices and the missing `usage`.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CompletionObject {
     /// A unique identifier for the completion.
@@ -218,6 +226,9 @@ pub struct CompletionObject {
     pub model: String,
     /// The object type, which is always "text_completion".
     pub object: String,
+    /// This fingerprint represents the backend configuration that the model runs with. Can be used in conjunction with the `seed` request parameter to understand when backend changes have been made that might impact determinism.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
     /// Usage statistics for the completion request.
     pub usage: Usage,
 }
@@ -233,10 +244,63 @@ pub struct CompletionChoice {
     pub text: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct LogprobResult {
     pub tokens: Vec<String>,
     pub token_logprobs: Vec<f32>,
     pub top_logprobs: Vec<HashMap<String, f32>>,
     pub text_offset: Vec<i32>,
 }
+
+/// Represents a streamed chunk of a completion response, which is sent as a server-sent event if `stream` is `true`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CompletionChunk {
+    /// A unique identifier for the completion. Each chunk has the same id.
+    pub id: String,
+    /// The list of the completion choices, which contain the text generated since the previous chunk.
+    pub choices: Vec<CompletionChunkChoice>,
+    /// The Unix timestamp (in seconds) of when the completion was created. Each chunk has the same timestamp.
+    pub created: u64,
+    /// The model used for completion.
+    pub model: String,
+    /// The object type, which is always "text_completion".
+    pub object: String,
+    /// This fingerprint represents the backend configuration that the model runs with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+}
+
+/// A choice in a streamed chunk of a completion response.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CompletionChunkChoice {
+    /// The reason the model stopped generating tokens, which is only set in the last chunk of the choice.
+    pub finish_reason: Option<FinishReason>,
+    /// The index of the choice in the list of choices.
+    pub index: u32,
+    /// The log probabilities of the tokens in `text`.
+    pub logprobs: Option<LogprobResult>,
+    /// The text generated since the previous chunk of the choice.
+    pub text: String,
+}
+
+#[test]
+fn test_serialize_completion_chunk() {
+    let chunk = CompletionChunk {
+        id: "cmpl-1".to_string(),
+        choices: vec![CompletionChunkChoice {
+            finish_reason: None,
+            index: 0,
+            logprobs: None,
+            text: "Hello".to_string(),
+        }],
+        created: 1700000000,
+        model: "llama-3-8b".to_string(),
+        object: "text_completion".to_string(),
+        system_fingerprint: None,
+    };
+    let json = serde_json::to_string(&chunk).unwrap();
+    assert_eq!(
+        json,
+        r#"{"id":"cmpl-1","choices":[{"finish_reason":null,"index":0,"logprobs":null,"text":"Hello"}],"created":1700000000,"model":"llama-3-8b","object":"text_completion"}"#
+    );
+}
@@ -0,0 +1,93 @@
+//! Define types for the `infill` endpoint, which are compatible with the `/infill` endpoint of [llama.cpp server](https://github.com/ggerganov/llama.cpp/tree/master/examples/server).
+
+use serde::{Deserialize, Serialize};
+
+/// Fills in the middle of the code between the given prefix and suffix.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct InfillRequest {
+    /// ID of the model to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The code before the cursor.
+    #[serde(default)]
+    pub input_prefix: String,
+    /// The code after the cursor.
+    #[serde(default)]
+    pub input_suffix: String,
+    /// The beginning of the middle part, which is already typed. It is added after the infill prompt, and is not included in the generated content.
+    /// Defaults to None.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// The maximum number of tokens to generate. A negative value means the `n_predict` setting of the model.
+    /// Defaults to the `n_predict` setting of the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_predict: Option<i32>,
+    /// Adjust the randomness of the generated text.
+    /// Defaults to the `temp` setting of the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Limit the next token selection to a subset of tokens with a cumulative probability above a threshold P.
+    /// Defaults to the `top_p` setting of the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Positive values penalize new tokens based on whether they appear in the text so far.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Positive values penalize new tokens based on their existing frequency in the text so far.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// If specified, the sampling is deterministic.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Sequences where the generation stops. The returned content will not contain the stop sequence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Whether to stream the content as it is generated.
+    /// Defaults to false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+/// The result of an infill request. In the stream mode, it is the last chunk of the stream.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InfillResponse {
+    /// The generated middle part. In the stream mode, the content not sent in the previous chunks.
+    pub content: String,
+    /// The model used for the infill.
+    pub model: String,
+    /// Whether the generation is finished. Always `true`, as the intermediate chunks in the stream mode are [`InfillChunk`]s.
+    pub stop: bool,
+    /// Whether the generation is stopped by the end of the sequence.
+    pub stopped_eos: bool,
+    /// Whether the generation is stopped by one of the stop sequences.
+    pub stopped_word: bool,
+    /// Whether the generation is stopped by the limit of the tokens.
+    pub stopped_limit: bool,
+    /// The stop sequence which stops the generation, or an empty string.
+    pub stopping_word: String,
+    /// The number of the generated tokens.
+    pub tokens_predicted: u64,
+    /// The number of the tokens in the prompt.
+    pub tokens_evaluated: u64,
+}
+
+/// An intermediate chunk of the infill in the stream mode.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InfillChunk {
+    /// The content generated since the previous chunk.
+    pub content: String,
+    /// Always `false`, as the last chunk is an [`InfillResponse`].
+    pub stop: bool,
+}
+
+#[test]
+fn test_deserialize_infill_request() {
+    let json = r#"{"input_prefix":"def add(a, b):\n    ","input_suffix":"\n\nprint(add(1, 2))","input_extra":[],"n_predict":-1,"stream":true}"#;
+    let request: InfillRequest = serde_json::from_str(json).unwrap();
+    assert_eq!(request.model, None);
+    assert_eq!(request.input_prefix, "def add(a, b):\n    ");
+    assert_eq!(request.input_suffix, "\n\nprint(add(1, 2))");
+    assert_eq!(request.prompt, None);
+    assert_eq!(request.n_predict, Some(-1));
+    assert_eq!(request.stream, Some(true));
+}
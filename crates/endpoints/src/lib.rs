@@ -2,14 +2,18 @@
 
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+pub mod assistants;
 pub mod audio;
+pub mod batches;
 pub mod chat;
 pub mod common;
 pub mod completions;
 pub mod embeddings;
 pub mod files;
 pub mod images;
+pub mod infill;
 pub mod models;
 #[cfg(feature = "rag")]
 #[cfg_attr(docsrs, doc(cfg(feature = "rag")))]
 pub mod rag;
+pub mod tokenize;
@@ -21,3 +21,63 @@ pub struct Model {
     /// The organization that owns the model.
     pub owned_by: String,
 }
+
+/// Request body of the `POST /v1/models` endpoint, which loads a model at runtime, or replaces the loaded model with the same id.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LoadModelRequest {
+    /// The model identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The path to the model file. If not provided, the model preloaded with the given `alias` is loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The alias of the preloaded model. Defaults to `default` for the chat models, and `embedding` for the embedding models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Whether the model is an embedding model. Defaults to false.
+    #[serde(default)]
+    pub embeddings: bool,
+    /// The name of the prompt template of the chat model, e.g., `chatml`. Defaults to `embedding` for the embedding models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_template: Option<String>,
+    /// The size of the prompt context.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ctx_size: Option<u64>,
+    /// The batch size for prompt processing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<u64>,
+    /// The number of tokens to predict.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_predict: Option<u64>,
+    /// The number of layers to run on the GPU.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n_gpu_layers: Option<u64>,
+    /// The number of threads to use during computation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u64>,
+}
+
+/// Represents the status of a model unloading operation.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteModelStatus {
+    /// The model identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always `model`.
+    pub object: String,
+    /// The status of the unloading operation.
+    pub deleted: bool,
+}
+
+#[test]
+fn test_models_deserialize_load_model_request() {
+    let json = r#"{"id":"Qwen2-1.5B-Instruct","path":"Qwen2-1.5B-Instruct-Q5_K_M.gguf","prompt_template":"chatml","ctx_size":4096}"#;
+    let request: LoadModelRequest = serde_json::from_str(json).unwrap();
+    assert_eq!(request.id, "Qwen2-1.5B-Instruct");
+    assert_eq!(
+        request.path.as_deref(),
+        Some("Qwen2-1.5B-Instruct-Q5_K_M.gguf")
+    );
+    assert!(!request.embeddings);
+    assert_eq!(request.prompt_template.as_deref(), Some("chatml"));
+    assert_eq!(request.ctx_size, Some(4096));
+    assert!(request.n_gpu_layers.is_none());
+}
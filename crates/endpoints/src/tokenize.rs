@@ -0,0 +1,77 @@
+//! Define types for the `tokenize`, `detokenize` and `count_tokens` endpoints, which count the tokens with the vocabulary of the model.
+
+use crate::chat::ContextTruncationReport;
+use serde::{Deserialize, Serialize};
+
+/// Converts the text to the token ids of the model.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TokenizeRequest {
+    /// ID of the model to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The text to tokenize.
+    pub content: String,
+}
+
+/// The token ids of the text.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenizeResponse {
+    /// The model used to tokenize the text.
+    pub model: String,
+    /// The ids of the tokens. Absent if the backend of the model does not expose the vocabulary of the model, e.g., the WASI-NN ggml plugin, which only counts the tokens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<u32>>,
+    /// The number of the tokens.
+    pub count: u64,
+}
+
+/// Converts the token ids of the model to text.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DetokenizeRequest {
+    /// ID of the model to use.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The ids of the tokens.
+    pub tokens: Vec<u32>,
+}
+
+/// The text of the token ids.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DetokenizeResponse {
+    /// The model used to detokenize the token ids.
+    pub model: String,
+    /// The text of the tokens.
+    pub content: String,
+}
+
+/// The number of the tokens in the prompt built from a chat completion request.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CountTokensResponse {
+    /// The model used to build the prompt.
+    pub model: String,
+    /// The number of the tokens in the prompt, after the chat history is truncated if it does not fit.
+    pub prompt_tokens: u64,
+    /// The context size of the model.
+    pub context_size: u64,
+    /// The maximum number of the tokens of the completion, which is the same `n_predict` as a chat completion of the request would get, capped by the context left after the prompt.
+    pub remaining_tokens: u64,
+    /// How the chat history would be truncated to fit the prompt into the context. Absent if the chat history fits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_truncation: Option<ContextTruncationReport>,
+}
+
+#[test]
+fn test_serialize_count_tokens_response() {
+    let response = CountTokensResponse {
+        model: "llama-3-8b".to_string(),
+        prompt_tokens: 20,
+        context_size: 4096,
+        remaining_tokens: 4076,
+        context_truncation: None,
+    };
+    let json = serde_json::to_string(&response).unwrap();
+    assert_eq!(
+        json,
+        r#"{"model":"llama-3-8b","prompt_tokens":20,"context_size":4096,"remaining_tokens":4076}"#
+    );
+}
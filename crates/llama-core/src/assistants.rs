@@ -0,0 +1,1093 @@
+//! Define APIs for the assistants and the threads, which keep the conversations on the server.
+//!
+//! An assistant bundles a model with a system prompt, tools and attached files. A thread keeps the messages of a conversation, so that the clients can resume it across devices without resending the history. A run generates the reply of an assistant to a thread through [`crate::chat::chat`], and the messages of the thread are truncated by the context truncation of the run or the model if they do not fit. If the model calls the tools, the run waits for the outputs of the tools submitted by [`submit_tool_outputs`]. The assistants and the threads are stored as JSON files in [`ASSISTANTS_DIR`].
+
+use crate::{cancellation, error::LlamaCoreError, files, utils::current_timestamp, ASSISTANTS_DIR};
+use either::Either;
+use endpoints::{
+    assistants::{
+        AssistantObject, AssistantRequest, DeletionStatus, ListAssistantsResponse,
+        ListMessagesResponse, ListRunsResponse, MessageContent, MessageObject, MessageRequest,
+        MessageText, RequiredAction, RunError, RunObject, RunRequest, RunStatus, SubmitToolOutputs,
+        SubmitToolOutputsRequest, ThreadObject, ThreadRequest,
+    },
+    chat::{
+        ChatCompletionObject, ChatCompletionRequestBuilder, ChatCompletionRequestMessage,
+        ChatCompletionRequestSampling, ChatCompletionRole, ChatCompletionUserMessageContent,
+        ContentPart, Image, ImageContentPart, TextContentPart, ToolChoice,
+    },
+    common::Usage,
+};
+use once_cell::sync::OnceCell;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+/// The extensions of the attached files which are passed to the model as images.
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "gif", "bmp", "tga", "hdr", "pnm"];
+
+// serializes the reads and the writes of the stored assistants and threads
+static STORE: OnceCell<Mutex<()>> = OnceCell::new();
+
+// the ids of the runs queued or in progress in this process
+static PENDING_RUNS: OnceCell<Mutex<HashSet<String>>> = OnceCell::new();
+
+/// A thread with its messages and runs, which is stored in a JSON file.
+#[derive(Debug, Deserialize, Serialize)]
+struct ThreadRecord {
+    thread: ThreadObject,
+    messages: Vec<MessageObject>,
+    runs: Vec<RunRecord>,
+}
+impl ThreadRecord {
+    fn run_mut(&mut self, run_id: &str) -> Option<&mut RunRecord> {
+        self.runs.iter_mut().find(|record| record.run.id == run_id)
+    }
+
+    fn has_active_run(&self) -> bool {
+        self.runs.iter().any(|record| record.run.status.is_active())
+    }
+
+    /// Marks the runs left queued or in progress by a previous process of the server as failed, as nothing processes them anymore. Returns whether any run is marked.
+    fn fail_interrupted_runs(&mut self) -> Result<bool, LlamaCoreError> {
+        let pending = lock_pending_runs()?;
+
+        let mut interrupted = false;
+        for record in self.runs.iter_mut() {
+            let run = &mut record.run;
+            if !matches!(
+                run.status,
+                RunStatus::Queued | RunStatus::InProgress | RunStatus::Cancelling
+            ) || pending.contains(&run.id)
+            {
+                continue;
+            }
+
+            #[cfg(feature = "logging")]
+            warn!(target: "stdout", "The run {} was interrupted, so it is marked as failed.", &run.id);
+
+            run.status = RunStatus::Failed;
+            run.failed_at = Some(current_timestamp()?);
+            run.last_error = Some(RunError {
+                code: "server_error".to_string(),
+                message: "The run was interrupted by a restart of the server.".to_string(),
+            });
+            interrupted = true;
+        }
+
+        Ok(interrupted)
+    }
+
+    /// Returns the ids of the runs which may have a chat request in progress.
+    fn active_run_ids(&self) -> Vec<String> {
+        self.runs
+            .iter()
+            .filter(|record| record.run.status.is_active())
+            .map(|record| record.run.id.clone())
+            .collect()
+    }
+}
+
+/// A run with the tool calls of the model and the outputs of the tools, which are appended to the messages of the thread when the run continues.
+#[derive(Debug, Deserialize, Serialize)]
+struct RunRecord {
+    run: RunObject,
+    #[serde(default)]
+    steps: Vec<ChatCompletionRequestMessage>,
+}
+
+/// Create an assistant.
+///
+/// # Arguments
+///
+/// * `request`: The request body of the `POST /v1/assistants` endpoint.
+///
+/// # Returns
+///
+/// An `AssistantObject` instance.
+pub fn create_assistant(request: AssistantRequest) -> Result<AssistantObject, LlamaCoreError> {
+    // the attached files must exist
+    for file_id in &request.file_ids {
+        files::retrieve_file(file_id)?;
+    }
+
+    let assistant = AssistantObject {
+        id: format!("asst_{}", uuid::Uuid::new_v4()),
+        object: "assistant".to_string(),
+        created_at: current_timestamp()?,
+        model: request.model,
+        name: request.name,
+        instructions: request.instructions,
+        tools: request.tools,
+        file_ids: request.file_ids,
+        metadata: request.metadata,
+    };
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Create the assistant {}", &assistant.id);
+
+    let _store = lock_store()?;
+    write_json(&assistant_path(&assistant.id), &assistant)?;
+
+    Ok(assistant)
+}
+
+/// Retrieve an assistant by id.
+///
+/// # Arguments
+///
+/// * `id`: The id of the assistant.
+///
+/// # Returns
+///
+/// An `AssistantObject` instance, or `None` if the assistant does not exist.
+pub fn retrieve_assistant(id: impl AsRef<str>) -> Result<Option<AssistantObject>, LlamaCoreError> {
+    if !is_valid_id(id.as_ref(), "asst_") {
+        return Ok(None);
+    }
+
+    let _store = lock_store()?;
+    read_json(&assistant_path(id.as_ref()))
+}
+
+/// List all the assistants, the newest first.
+///
+/// # Returns
+///
+/// A `ListAssistantsResponse` instance.
+pub fn list_assistants() -> Result<ListAssistantsResponse, LlamaCoreError> {
+    let _store = lock_store()?;
+
+    let mut data: Vec<AssistantObject> = vec![];
+    if let Ok(entries) = fs::read_dir(ASSISTANTS_DIR) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                data.extend(read_json::<AssistantObject>(&path)?);
+            }
+        }
+    }
+    data.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+
+    Ok(ListAssistantsResponse {
+        object: "list".to_string(),
+        data,
+    })
+}
+
+/// Delete an assistant by id. The threads run by the assistant are kept.
+///
+/// # Arguments
+///
+/// * `id`: The id of the assistant.
+///
+/// # Returns
+///
+/// A `DeletionStatus` instance, or `None` if the assistant does not exist.
+pub fn delete_assistant(id: impl AsRef<str>) -> Result<Option<DeletionStatus>, LlamaCoreError> {
+    if !is_valid_id(id.as_ref(), "asst_") {
+        return Ok(None);
+    }
+
+    let _store = lock_store()?;
+    let deleted = remove_json(&assistant_path(id.as_ref()))?;
+
+    Ok(deleted.then(|| DeletionStatus {
+        id: id.as_ref().to_string(),
+        object: "assistant.deleted".to_string(),
+        deleted: true,
+    }))
+}
+
+/// Create a thread, optionally with the messages to start with.
+///
+/// # Arguments
+///
+/// * `request`: The request body of the `POST /v1/threads` endpoint.
+///
+/// # Returns
+///
+/// A `ThreadObject` instance.
+pub fn create_thread(request: ThreadRequest) -> Result<ThreadObject, LlamaCoreError> {
+    let thread = ThreadObject {
+        id: format!("thread_{}", uuid::Uuid::new_v4()),
+        object: "thread".to_string(),
+        created_at: current_timestamp()?,
+        metadata: request.metadata,
+    };
+
+    let messages = request
+        .messages
+        .into_iter()
+        .map(|message| new_message(&thread.id, message))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Create the thread {} with {} messages", &thread.id, messages.len());
+
+    let _store = lock_store()?;
+    write_json(
+        &thread_path(&thread.id),
+        &ThreadRecord {
+            thread: thread.clone(),
+            messages,
+            runs: vec![],
+        },
+    )?;
+
+    Ok(thread)
+}
+
+/// Retrieve a thread by id.
+///
+/// # Arguments
+///
+/// * `id`: The id of the thread.
+///
+/// # Returns
+///
+/// A `ThreadObject` instance, or `None` if the thread does not exist.
+pub fn retrieve_thread(id: impl AsRef<str>) -> Result<Option<ThreadObject>, LlamaCoreError> {
+    Ok(read_thread(id.as_ref())?.map(|record| record.thread))
+}
+
+/// Delete a thread with its messages and runs by id.
+///
+/// # Arguments
+///
+/// * `id`: The id of the thread.
+///
+/// # Returns
+///
+/// A `DeletionStatus` instance, or `None` if the thread does not exist.
+pub fn delete_thread(id: impl AsRef<str>) -> Result<Option<DeletionStatus>, LlamaCoreError> {
+    if !is_valid_id(id.as_ref(), "thread_") {
+        return Ok(None);
+    }
+
+    let (deleted, run_ids) = {
+        let _store = lock_store()?;
+        let path = thread_path(id.as_ref());
+        let run_ids = match read_json::<ThreadRecord>(&path)? {
+            Some(record) => record.active_run_ids(),
+            None => vec![],
+        };
+
+        (remove_json(&path)?, run_ids)
+    };
+
+    if deleted {
+        // stop the run in progress, if any
+        for run_id in run_ids {
+            cancellation::cancel(run_id)?;
+        }
+    }
+
+    Ok(deleted.then(|| DeletionStatus {
+        id: id.as_ref().to_string(),
+        object: "thread.deleted".to_string(),
+        deleted: true,
+    }))
+}
+
+/// Add a message to a thread. A thread with an active run does not accept new messages.
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// * `request`: The request body of the `POST /v1/threads/{thread_id}/messages` endpoint.
+///
+/// # Returns
+///
+/// A `MessageObject` instance, or `None` if the thread does not exist.
+pub fn create_message(
+    thread_id: impl AsRef<str>,
+    request: MessageRequest,
+) -> Result<Option<MessageObject>, LlamaCoreError> {
+    let message = new_message(thread_id.as_ref(), request)?;
+
+    update_thread(thread_id.as_ref(), |record| {
+        if record.has_active_run() {
+            return Err(active_run_error(&record.thread.id));
+        }
+
+        record.messages.push(message.clone());
+
+        Ok(message)
+    })
+}
+
+/// List the messages of a thread in the order they were created.
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// # Returns
+///
+/// A `ListMessagesResponse` instance, or `None` if the thread does not exist.
+pub fn list_messages(
+    thread_id: impl AsRef<str>,
+) -> Result<Option<ListMessagesResponse>, LlamaCoreError> {
+    Ok(
+        read_thread(thread_id.as_ref())?.map(|record| ListMessagesResponse {
+            object: "list".to_string(),
+            first_id: record.messages.first().map(|message| message.id.clone()),
+            last_id: record.messages.last().map(|message| message.id.clone()),
+            data: record.messages,
+            has_more: false,
+        }),
+    )
+}
+
+/// Create a run of a thread with an assistant. The run is queued, and is processed by [`execute_run`]. A thread has at most one active run at a time.
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// * `request`: The request body of the `POST /v1/threads/{thread_id}/runs` endpoint.
+///
+/// # Returns
+///
+/// A `RunObject` instance, or `None` if the thread does not exist.
+pub fn create_run(
+    thread_id: impl AsRef<str>,
+    request: RunRequest,
+) -> Result<Option<RunObject>, LlamaCoreError> {
+    let assistant = match retrieve_assistant(&request.assistant_id)? {
+        Some(assistant) => assistant,
+        None => return Err(assistant_not_found(&request.assistant_id)),
+    };
+
+    let mut instructions = request
+        .instructions
+        .or(assistant.instructions)
+        .unwrap_or_default();
+    if let Some(additional_instructions) = request.additional_instructions {
+        if !instructions.is_empty() {
+            instructions.push_str("\n\n");
+        }
+        instructions.push_str(&additional_instructions);
+    }
+
+    let run = RunObject {
+        id: format!("run_{}", uuid::Uuid::new_v4()),
+        object: "thread.run".to_string(),
+        created_at: current_timestamp()?,
+        thread_id: thread_id.as_ref().to_string(),
+        assistant_id: assistant.id,
+        status: RunStatus::Queued,
+        required_action: None,
+        last_error: None,
+        started_at: None,
+        cancelled_at: None,
+        failed_at: None,
+        completed_at: None,
+        model: request.model.unwrap_or(assistant.model),
+        instructions,
+        tools: request.tools.unwrap_or(assistant.tools),
+        temperature: request.temperature,
+        max_completion_tokens: request.max_completion_tokens,
+        context_truncation: request.context_truncation,
+        usage: None,
+        metadata: request.metadata,
+    };
+
+    update_thread(thread_id.as_ref(), |record| {
+        if record.has_active_run() {
+            return Err(active_run_error(&record.thread.id));
+        }
+
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Create the run {} of the thread {}", &run.id, &record.thread.id);
+
+        record.runs.push(RunRecord {
+            run: run.clone(),
+            steps: vec![],
+        });
+        lock_pending_runs()?.insert(run.id.clone());
+
+        Ok(run)
+    })
+}
+
+/// Process a queued run, which adds the reply of the model to the thread, or waits for the outputs of the tools called by the model.
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// * `run_id`: The id of the run.
+pub async fn execute_run(
+    thread_id: impl AsRef<str>,
+    run_id: impl AsRef<str>,
+) -> Result<(), LlamaCoreError> {
+    let (thread_id, run_id) = (thread_id.as_ref(), run_id.as_ref());
+
+    // the run is not pending anymore once it is processed, even if the processing fails
+    let _pending = PendingRunGuard {
+        run_id: run_id.to_string(),
+    };
+
+    // take the queued run
+    let prepared = update_thread(thread_id, |record| {
+        let messages = record.messages.clone();
+        let Some(record) = record.run_mut(run_id) else {
+            return Ok(None);
+        };
+
+        match record.run.status {
+            RunStatus::Queued => {
+                record.run.status = RunStatus::InProgress;
+                if record.run.started_at.is_none() {
+                    record.run.started_at = Some(current_timestamp()?);
+                }
+
+                Ok(Some((record.run.clone(), record.steps.clone(), messages)))
+            }
+            RunStatus::Cancelling => {
+                record.run.status = RunStatus::Cancelled;
+                record.run.cancelled_at = Some(current_timestamp()?);
+
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    })?;
+    let Some(Some((run, steps, messages))) = prepared else {
+        return Ok(());
+    };
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Execute the run {} of the thread {}", run_id, thread_id);
+
+    let result = generate(&run, steps, &messages).await;
+
+    let reply = update_thread(thread_id, |record| {
+        let Some(record) = record.run_mut(run_id) else {
+            return Ok(None);
+        };
+
+        let object = match result {
+            Ok(object) => object,
+            Err(e) => {
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "The run {} failed. {}", run_id, &e);
+
+                record.run.status = match record.run.status {
+                    RunStatus::Cancelling => RunStatus::Cancelled,
+                    _ => RunStatus::Failed,
+                };
+                match record.run.status {
+                    RunStatus::Cancelled => record.run.cancelled_at = Some(current_timestamp()?),
+                    _ => {
+                        record.run.failed_at = Some(current_timestamp()?);
+                        record.run.last_error = Some(RunError {
+                            code: e.code().to_string(),
+                            message: e.to_string(),
+                        });
+                    }
+                }
+
+                return Ok(None);
+            }
+        };
+
+        record.run.usage = Some(add_usage(record.run.usage.take(), object.usage));
+
+        let Some(choice) = object.choices.into_iter().next() else {
+            return Ok(None);
+        };
+        if record.run.status == RunStatus::Cancelling || choice.cancelled {
+            record.run.status = RunStatus::Cancelled;
+            record.run.cancelled_at = Some(current_timestamp()?);
+
+            return Ok(None);
+        }
+
+        // wait for the outputs of the tools called by the model
+        if !choice.message.tool_calls.is_empty() {
+            record
+                .steps
+                .push(ChatCompletionRequestMessage::new_assistant_message(
+                    choice.message.content,
+                    None,
+                    Some(choice.message.tool_calls.clone()),
+                ));
+            record.run.status = RunStatus::RequiresAction;
+            record.run.required_action = Some(RequiredAction {
+                ty: "submit_tool_outputs".to_string(),
+                submit_tool_outputs: SubmitToolOutputs {
+                    tool_calls: choice.message.tool_calls,
+                },
+            });
+
+            return Ok(None);
+        }
+
+        record.run.status = RunStatus::Completed;
+        record.run.completed_at = Some(current_timestamp()?);
+
+        Ok(Some(MessageObject {
+            id: format!("msg_{}", uuid::Uuid::new_v4()),
+            object: "thread.message".to_string(),
+            created_at: current_timestamp()?,
+            thread_id: record.run.thread_id.clone(),
+            role: ChatCompletionRole::Assistant,
+            content: text_content(choice.message.content.unwrap_or_default()),
+            assistant_id: Some(record.run.assistant_id.clone()),
+            run_id: Some(record.run.id.clone()),
+            file_ids: vec![],
+            metadata: None,
+        }))
+    })?;
+
+    // add the reply of the model to the thread
+    if let Some(Some(reply)) = reply {
+        update_thread(thread_id, |record| {
+            record.messages.push(reply);
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Retrieve a run of a thread by id.
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// * `run_id`: The id of the run.
+///
+/// # Returns
+///
+/// A `RunObject` instance, or `None` if the thread or the run does not exist.
+pub fn retrieve_run(
+    thread_id: impl AsRef<str>,
+    run_id: impl AsRef<str>,
+) -> Result<Option<RunObject>, LlamaCoreError> {
+    Ok(read_thread(thread_id.as_ref())?.and_then(|record| {
+        record
+            .runs
+            .into_iter()
+            .find(|record| record.run.id == run_id.as_ref())
+            .map(|record| record.run)
+    }))
+}
+
+/// List the runs of a thread in the order they were created.
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// # Returns
+///
+/// A `ListRunsResponse` instance, or `None` if the thread does not exist.
+pub fn list_runs(thread_id: impl AsRef<str>) -> Result<Option<ListRunsResponse>, LlamaCoreError> {
+    Ok(read_thread(thread_id.as_ref())?.map(|record| {
+        let data: Vec<RunObject> = record.runs.into_iter().map(|record| record.run).collect();
+        ListRunsResponse {
+            object: "list".to_string(),
+            first_id: data.first().map(|run| run.id.clone()),
+            last_id: data.last().map(|run| run.id.clone()),
+            data,
+            has_more: false,
+        }
+    }))
+}
+
+/// Cancel a run. The generation in progress stops at the next token.
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// * `run_id`: The id of the run.
+///
+/// # Returns
+///
+/// A `RunObject` instance, or `None` if the thread or the run does not exist.
+pub fn cancel_run(
+    thread_id: impl AsRef<str>,
+    run_id: impl AsRef<str>,
+) -> Result<Option<RunObject>, LlamaCoreError> {
+    let run = update_thread(thread_id.as_ref(), |record| {
+        let Some(record) = record.run_mut(run_id.as_ref()) else {
+            return Ok(None);
+        };
+
+        match record.run.status {
+            RunStatus::Queued | RunStatus::InProgress => {
+                record.run.status = RunStatus::Cancelling;
+            }
+            RunStatus::RequiresAction => {
+                record.run.status = RunStatus::Cancelled;
+                record.run.cancelled_at = Some(current_timestamp()?);
+                record.run.required_action = None;
+            }
+            _ => {}
+        }
+
+        Ok(Some(record.run.clone()))
+    })?
+    .flatten();
+
+    if run
+        .as_ref()
+        .is_some_and(|run| run.status == RunStatus::Cancelling)
+    {
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Cancel the run {}", run_id.as_ref());
+
+        // the chat request of the run is registered by the run id
+        cancellation::cancel(run_id.as_ref())?;
+    }
+
+    Ok(run)
+}
+
+/// Continue a run waiting for the outputs of the tools called by the model. The run is queued again, and is processed by [`execute_run`].
+///
+/// # Arguments
+///
+/// * `thread_id`: The id of the thread.
+///
+/// * `run_id`: The id of the run.
+///
+/// * `request`: The request body of the `POST /v1/threads/{thread_id}/runs/{run_id}/submit_tool_outputs` endpoint.
+///
+/// # Returns
+///
+/// A `RunObject` instance, or `None` if the thread or the run does not exist.
+pub fn submit_tool_outputs(
+    thread_id: impl AsRef<str>,
+    run_id: impl AsRef<str>,
+    request: SubmitToolOutputsRequest,
+) -> Result<Option<RunObject>, LlamaCoreError> {
+    Ok(update_thread(thread_id.as_ref(), |record| {
+        let Some(record) = record.run_mut(run_id.as_ref()) else {
+            return Ok(None);
+        };
+
+        let tool_calls = match (&record.run.status, &record.run.required_action) {
+            (RunStatus::RequiresAction, Some(action)) => &action.submit_tool_outputs.tool_calls,
+            _ => {
+                let err_msg = format!(
+                    "The run {} is not waiting for the outputs of the tools.",
+                    run_id.as_ref()
+                );
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                return Err(LlamaCoreError::invalid_request(err_msg, None));
+            }
+        };
+
+        let mut tool_messages = vec![];
+        for tool_call in tool_calls {
+            match request
+                .tool_outputs
+                .iter()
+                .find(|output| output.tool_call_id == tool_call.id)
+            {
+                Some(output) => tool_messages.push(ChatCompletionRequestMessage::new_tool_message(
+                    &output.output,
+                    Some(tool_call.id.clone()),
+                )),
+                None => {
+                    let err_msg =
+                        format!("The output of the tool call {} is missing.", tool_call.id);
+
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", &err_msg);
+
+                    return Err(LlamaCoreError::invalid_request(
+                        err_msg,
+                        Some("tool_outputs"),
+                    ));
+                }
+            }
+        }
+
+        record.steps.extend(tool_messages);
+        record.run.status = RunStatus::Queued;
+        record.run.required_action = None;
+        lock_pending_runs()?.insert(record.run.id.clone());
+
+        Ok(Some(record.run.clone()))
+    })?
+    .flatten())
+}
+
+/// Generates the reply of the assistant to the messages of the thread.
+async fn generate(
+    run: &RunObject,
+    steps: Vec<ChatCompletionRequestMessage>,
+    messages: &[MessageObject],
+) -> Result<ChatCompletionObject, LlamaCoreError> {
+    let assistant = match retrieve_assistant(&run.assistant_id)? {
+        Some(assistant) => assistant,
+        None => return Err(assistant_not_found(&run.assistant_id)),
+    };
+
+    let mut instructions = run.instructions.clone();
+    for file_id in &assistant.file_ids {
+        instructions.push_str(&file_text(file_id)?);
+    }
+
+    let mut chat_messages = vec![];
+    if !instructions.trim().is_empty() {
+        chat_messages.push(ChatCompletionRequestMessage::new_system_message(
+            instructions.trim(),
+            None,
+        ));
+    }
+    for message in messages {
+        chat_messages.push(chat_message(message)?);
+    }
+    chat_messages.extend(steps);
+
+    // the chat request is cancelled by the id of the run
+    let mut builder = ChatCompletionRequestBuilder::new(&run.model, chat_messages)
+        .with_chat_id(&run.id)
+        .enable_stream(false);
+    if !run.tools.is_empty() {
+        builder = builder
+            .with_tools(run.tools.clone())
+            .with_tool_choice(ToolChoice::Auto);
+    }
+    if let Some(temperature) = run.temperature {
+        builder = builder.with_sampling(ChatCompletionRequestSampling::Temperature(temperature));
+    }
+    if let Some(max_completion_tokens) = run.max_completion_tokens {
+        builder = builder.with_max_tokens(max_completion_tokens);
+    }
+    if let Some(context_truncation) = &run.context_truncation {
+        builder = builder.with_context_truncation(context_truncation.clone());
+    }
+    let mut request = builder.build();
+
+    match crate::chat::chat(&mut request).await? {
+        Either::Right(object) => Ok(object),
+        Either::Left(_) => unreachable!("the stream mode is disabled"),
+    }
+}
+
+/// Converts a message of the thread to a message of the chat request. The attached images are passed as image parts, and the contents of the other attached files are appended to the text.
+fn chat_message(message: &MessageObject) -> Result<ChatCompletionRequestMessage, LlamaCoreError> {
+    let mut text = message.text();
+    if message.role == ChatCompletionRole::Assistant {
+        return Ok(ChatCompletionRequestMessage::new_assistant_message(
+            Some(text),
+            None,
+            None,
+        ));
+    }
+
+    let mut images = vec![];
+    for file_id in &message.file_ids {
+        let file = files::retrieve_file(file_id)?;
+        match is_image(&file.filename) {
+            true => images.push(ContentPart::Image(ImageContentPart::new(Image {
+                url: file.id,
+                detail: None,
+            }))),
+            false => text.push_str(&file_text(file_id)?),
+        }
+    }
+
+    let content = match images.is_empty() {
+        true => ChatCompletionUserMessageContent::Text(text),
+        false => {
+            let mut parts = vec![ContentPart::Text(TextContentPart::new(text))];
+            parts.extend(images);
+            ChatCompletionUserMessageContent::Parts(parts)
+        }
+    };
+
+    Ok(ChatCompletionRequestMessage::new_user_message(
+        content, None,
+    ))
+}
+
+/// Returns the contents of an attached file, which are appended to a message.
+fn file_text(file_id: &str) -> Result<String, LlamaCoreError> {
+    let (filename, content) = files::download_file(file_id)?;
+
+    Ok(format!(
+        "\n\nThe content of the file `{}`:\n{}",
+        filename,
+        String::from_utf8_lossy(&content)
+    ))
+}
+
+fn is_image(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn new_message(thread_id: &str, request: MessageRequest) -> Result<MessageObject, LlamaCoreError> {
+    if !matches!(
+        request.role,
+        ChatCompletionRole::User | ChatCompletionRole::Assistant
+    ) {
+        let err_msg = format!(
+            "Invalid role: {}. Must be `user` or `assistant`.",
+            request.role
+        );
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("role")));
+    }
+
+    // the attached files must exist
+    for file_id in &request.file_ids {
+        files::retrieve_file(file_id)?;
+    }
+
+    Ok(MessageObject {
+        id: format!("msg_{}", uuid::Uuid::new_v4()),
+        object: "thread.message".to_string(),
+        created_at: current_timestamp()?,
+        thread_id: thread_id.to_string(),
+        role: request.role,
+        content: text_content(request.content),
+        assistant_id: None,
+        run_id: None,
+        file_ids: request.file_ids,
+        metadata: request.metadata,
+    })
+}
+
+fn text_content(value: String) -> Vec<MessageContent> {
+    vec![MessageContent::Text {
+        text: MessageText {
+            value,
+            annotations: vec![],
+        },
+    }]
+}
+
+fn add_usage(usage: Option<Usage>, other: Usage) -> Usage {
+    match usage {
+        Some(usage) => Usage {
+            prompt_tokens: usage.prompt_tokens + other.prompt_tokens,
+            completion_tokens: usage.completion_tokens + other.completion_tokens,
+            total_tokens: usage.total_tokens + other.total_tokens,
+        },
+        None => other,
+    }
+}
+
+fn assistant_not_found(id: &str) -> LlamaCoreError {
+    let err_msg = format!("The assistant {} does not exist.", id);
+
+    #[cfg(feature = "logging")]
+    error!(target: "stdout", "{}", &err_msg);
+
+    LlamaCoreError::invalid_request(err_msg, Some("assistant_id"))
+}
+
+fn active_run_error(thread_id: &str) -> LlamaCoreError {
+    let err_msg = format!(
+        "The thread {} has an active run. Please wait for the run to finish or cancel it.",
+        thread_id
+    );
+
+    #[cfg(feature = "logging")]
+    error!(target: "stdout", "{}", &err_msg);
+
+    LlamaCoreError::invalid_request(err_msg, None)
+}
+
+/// Whether the id is generated by this module, so it is safe to be used as a file name.
+fn is_valid_id(id: &str, prefix: &str) -> bool {
+    id.starts_with(prefix)
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn assistant_path(id: &str) -> PathBuf {
+    Path::new(ASSISTANTS_DIR).join(format!("{}.json", id))
+}
+
+fn thread_path(id: &str) -> PathBuf {
+    Path::new(ASSISTANTS_DIR)
+        .join("threads")
+        .join(format!("{}.json", id))
+}
+
+fn read_thread(id: &str) -> Result<Option<ThreadRecord>, LlamaCoreError> {
+    if !is_valid_id(id, "thread_") {
+        return Ok(None);
+    }
+
+    let _store = lock_store()?;
+    let path = thread_path(id);
+    let mut record: ThreadRecord = match read_json(&path)? {
+        Some(record) => record,
+        None => return Ok(None),
+    };
+
+    if record.fail_interrupted_runs()? {
+        write_json(&path, &record)?;
+    }
+
+    Ok(Some(record))
+}
+
+/// Updates the stored thread, and returns the result of the update, or `None` if the thread does not exist. The thread is not saved if the update fails.
+fn update_thread<T>(
+    id: &str,
+    update: impl FnOnce(&mut ThreadRecord) -> Result<T, LlamaCoreError>,
+) -> Result<Option<T>, LlamaCoreError> {
+    if !is_valid_id(id, "thread_") {
+        return Ok(None);
+    }
+
+    let _store = lock_store()?;
+    let path = thread_path(id);
+    let mut record: ThreadRecord = match read_json(&path)? {
+        Some(record) => record,
+        None => return Ok(None),
+    };
+    record.fail_interrupted_runs()?;
+
+    let result = update(&mut record)?;
+    write_json(&path, &record)?;
+
+    Ok(Some(result))
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, LlamaCoreError> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            let err_msg = format!("Failed to read {}. {}", path.display(), e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::Operation(err_msg));
+        }
+    };
+
+    serde_json::from_slice(&content).map(Some).map_err(|e| {
+        let err_msg = format!("Failed to deserialize {}. {}", path.display(), e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), LlamaCoreError> {
+    let content = serde_json::to_vec_pretty(value).map_err(|e| {
+        let err_msg = format!("Failed to serialize {}. {}", path.display(), e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })?;
+
+    // write to a temporary file first, so a crash never leaves a partially written file behind
+    let tmp_path = path.with_extension("json.tmp");
+    let result = match path.parent() {
+        Some(dir) => fs::create_dir_all(dir),
+        None => Ok(()),
+    }
+    .and_then(|_| fs::write(&tmp_path, content))
+    .and_then(|_| fs::rename(&tmp_path, path));
+
+    result.map_err(|e| {
+        let err_msg = format!("Failed to write {}. {}", path.display(), e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })
+}
+
+fn remove_json(path: &Path) -> Result<bool, LlamaCoreError> {
+    match fs::remove_file(path) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => {
+            let err_msg = format!("Failed to remove {}. {}", path.display(), e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            Err(LlamaCoreError::Operation(err_msg))
+        }
+    }
+}
+
+/// Removes the run from the pending runs when dropped.
+struct PendingRunGuard {
+    run_id: String,
+}
+impl Drop for PendingRunGuard {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = lock_pending_runs() {
+            pending.remove(&self.run_id);
+        }
+    }
+}
+
+fn lock_pending_runs() -> Result<MutexGuard<'static, HashSet<String>>, LlamaCoreError> {
+    PENDING_RUNS
+        .get_or_init(|| Mutex::new(HashSet::new()))
+        .lock()
+        .map_err(|e| {
+            let err_msg = format!("Fail to acquire the lock of `PENDING_RUNS`. {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })
+}
+
+fn lock_store() -> Result<MutexGuard<'static, ()>, LlamaCoreError> {
+    STORE.get_or_init(|| Mutex::new(())).lock().map_err(|e| {
+        let err_msg = format!("Fail to acquire the lock of `STORE`. {}", e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })
+}
+
+#[test]
+fn test_assistants_valid_id() {
+    assert!(is_valid_id(
+        "thread_0a5f3c1e-6a4b-4bde-9d4c-1f2e3d4c5b6a",
+        "thread_"
+    ));
+    assert!(!is_valid_id("thread_../../etc/passwd", "thread_"));
+    assert!(!is_valid_id("asst_abc", "thread_"));
+
+    assert!(is_image("photo.JPG"));
+    assert!(!is_image("notes.md"));
+}
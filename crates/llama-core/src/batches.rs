@@ -0,0 +1,588 @@
+//! Define APIs for the batches, which run the requests of an uploaded JSONL file in the background.
+//!
+//! A batch is created by [`create_batch`] from an uploaded file, each line of which is a [`BatchRequestInput`]. [`run_batch`] then processes the requests one by one through the same pipelines as the online requests, i.e., [`crate::chat::chat`] and [`crate::embeddings::embeddings`], so the requests of a batch wait in the same queue of the model as the others. Once the batch is finished, the outputs and the errors are written to the archives directory as new files. The batches are kept in memory, and are lost when the server restarts.
+
+use crate::{error::LlamaCoreError, files, utils::current_timestamp};
+use either::Either;
+use endpoints::{
+    batches::{
+        BatchError, BatchErrors, BatchObject, BatchRequest, BatchRequestCounts, BatchRequestInput,
+        BatchRequestOutput, BatchResponse, BatchStatus, ListBatchesResponse,
+    },
+    chat::ChatCompletionRequest,
+    embeddings::EmbeddingRequest,
+};
+use once_cell::sync::OnceCell;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// The endpoints that the requests of a batch can target.
+const BATCH_ENDPOINTS: [&str; 2] = ["/v1/chat/completions", "/v1/embeddings"];
+/// The only supported completion window of a batch, i.e., 24 hours.
+const COMPLETION_WINDOW: u64 = 24 * 60 * 60;
+
+// key: batch id, value: the batch
+static BATCHES: OnceCell<Mutex<HashMap<String, Batch>>> = OnceCell::new();
+
+/// A batch with the requests which are not processed yet.
+#[derive(Debug)]
+struct Batch {
+    object: BatchObject,
+    /// The requests of the batch, which are taken by [`run_batch`]
+    inputs: Option<Vec<BatchRequestInput>>,
+}
+
+/// Create a batch from the uploaded file of requests. The batch fails at once if the file has invalid lines, and the errors are reported in [`BatchObject::errors`]. Otherwise, the requests are processed by [`run_batch`].
+///
+/// # Arguments
+///
+/// * `request`: The request body of the `POST /v1/batches` endpoint.
+///
+/// # Returns
+///
+/// A `BatchObject` instance.
+pub fn create_batch(request: BatchRequest) -> Result<BatchObject, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Create a batch from the file {}", &request.input_file_id);
+
+    if !BATCH_ENDPOINTS.contains(&request.endpoint.as_str()) {
+        let err_msg = format!(
+            "Unsupported endpoint: {}. Must be one of `{}`.",
+            request.endpoint,
+            BATCH_ENDPOINTS.join("`, `")
+        );
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("endpoint")));
+    }
+
+    if request.completion_window != "24h" {
+        let err_msg = format!(
+            "Unsupported completion window: {}. Must be `24h`.",
+            request.completion_window
+        );
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        return Err(LlamaCoreError::invalid_request(
+            err_msg,
+            Some("completion_window"),
+        ));
+    }
+
+    let (_, content) = files::download_file(&request.input_file_id)?;
+    let content = String::from_utf8_lossy(&content);
+
+    let created_at = current_timestamp()?;
+    let mut object = BatchObject {
+        id: format!("batch_{}", uuid::Uuid::new_v4()),
+        object: "batch".to_string(),
+        endpoint: request.endpoint,
+        errors: None,
+        input_file_id: request.input_file_id,
+        completion_window: request.completion_window,
+        status: BatchStatus::Validating,
+        output_file_id: None,
+        error_file_id: None,
+        created_at,
+        in_progress_at: None,
+        expires_at: Some(created_at + COMPLETION_WINDOW),
+        finalizing_at: None,
+        completed_at: None,
+        failed_at: None,
+        expired_at: None,
+        cancelling_at: None,
+        cancelled_at: None,
+        request_counts: BatchRequestCounts::default(),
+        metadata: request.metadata,
+    };
+
+    let inputs = match parse_inputs(&content, &object.endpoint) {
+        Ok(inputs) => {
+            object.request_counts.total = inputs.len() as u64;
+            Some(inputs)
+        }
+        Err(errors) => {
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "The input file of the batch {} has {} invalid lines.", &object.id, errors.len());
+
+            object.status = BatchStatus::Failed;
+            object.failed_at = Some(created_at);
+            object.errors = Some(BatchErrors {
+                object: "list".to_string(),
+                data: errors,
+            });
+            None
+        }
+    };
+
+    lock_batches()?.insert(
+        object.id.clone(),
+        Batch {
+            object: object.clone(),
+            inputs,
+        },
+    );
+
+    Ok(object)
+}
+
+/// Process the requests of the batch one by one, and write the outputs and the errors to the archives directory. The batch stops early if it is cancelled by [`cancel_batch`] or exceeds the completion window.
+///
+/// # Arguments
+///
+/// * `id`: The id of the batch.
+pub async fn run_batch(id: impl AsRef<str>) -> Result<(), LlamaCoreError> {
+    let id = id.as_ref();
+
+    let (endpoint, inputs) = {
+        let mut batches = lock_batches()?;
+        let batch = match batches.get_mut(id) {
+            Some(batch) => batch,
+            None => {
+                let err_msg = format!("The batch {} does not exist.", id);
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                return Err(LlamaCoreError::Operation(err_msg));
+            }
+        };
+
+        match batch.inputs.take() {
+            Some(inputs) => {
+                if batch.object.status == BatchStatus::Validating {
+                    batch.object.status = BatchStatus::InProgress;
+                    batch.object.in_progress_at = Some(current_timestamp()?);
+                }
+                (batch.object.endpoint.clone(), inputs)
+            }
+            // the batch failed the validation or is run by another task
+            None => return Ok(()),
+        }
+    };
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Run the batch {} with {} requests.", id, inputs.len());
+
+    let result = process_batch(id, &endpoint, inputs).await;
+    if let Err(e) = &result {
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "The batch {} failed. {}", id, e);
+
+        // mark the batch as failed, otherwise it is stuck in progress
+        let failed_at = current_timestamp().ok();
+        if let Some(batch) = lock_batches()?.get_mut(id) {
+            batch.object.status = BatchStatus::Failed;
+            batch.object.failed_at = failed_at;
+            batch.object.errors = Some(BatchErrors {
+                object: "list".to_string(),
+                data: vec![batch_error(e)],
+            });
+        }
+    }
+
+    result
+}
+
+/// Processes the requests of the batch, and writes the outputs and the errors to the archives directory.
+async fn process_batch(
+    id: &str,
+    endpoint: &str,
+    inputs: Vec<BatchRequestInput>,
+) -> Result<(), LlamaCoreError> {
+    let mut outputs = String::new();
+    let mut errors = String::new();
+    let mut status = BatchStatus::Completed;
+    for input in inputs {
+        if let Some(stopped) = stopped_status(id)? {
+            status = stopped;
+            break;
+        }
+
+        let output = match run_request(id, endpoint, &input).await {
+            Ok(body) => BatchRequestOutput {
+                id: format!("batch_req_{}", uuid::Uuid::new_v4()),
+                custom_id: input.custom_id,
+                response: Some(BatchResponse {
+                    status_code: 200,
+                    request_id: uuid::Uuid::new_v4().to_string(),
+                    body,
+                }),
+                error: None,
+            },
+            Err(e) => BatchRequestOutput {
+                id: format!("batch_req_{}", uuid::Uuid::new_v4()),
+                custom_id: input.custom_id,
+                response: None,
+                error: Some(batch_error(&e)),
+            },
+        };
+
+        let line = serde_json::to_string(&output).map_err(|e| {
+            let err_msg = format!("Failed to serialize the output of the batch. Reason: {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })?;
+
+        let failed = output.error.is_some();
+        match failed {
+            true => errors.extend([line, "\n".to_string()]),
+            false => outputs.extend([line, "\n".to_string()]),
+        }
+
+        if let Some(batch) = lock_batches()?.get_mut(id) {
+            match failed {
+                true => batch.object.request_counts.failed += 1,
+                false => batch.object.request_counts.completed += 1,
+            }
+        }
+    }
+
+    update_batch(id, |object| {
+        object.status = BatchStatus::Finalizing;
+        object.finalizing_at = Some(current_timestamp()?);
+        Ok(())
+    })?;
+
+    let output_file = match outputs.is_empty() {
+        true => None,
+        false => Some(files::create_file(
+            format!("{}_output.jsonl", id),
+            outputs.as_bytes(),
+            "batch_output",
+        )?),
+    };
+    let error_file = match errors.is_empty() {
+        true => None,
+        false => Some(files::create_file(
+            format!("{}_error.jsonl", id),
+            errors.as_bytes(),
+            "batch_output",
+        )?),
+    };
+
+    update_batch(id, |object| {
+        object.output_file_id = output_file.map(|file| file.id);
+        object.error_file_id = error_file.map(|file| file.id);
+        object.status = status;
+        match status {
+            BatchStatus::Cancelled => object.cancelled_at = Some(current_timestamp()?),
+            BatchStatus::Expired => object.expired_at = Some(current_timestamp()?),
+            _ => object.completed_at = Some(current_timestamp()?),
+        }
+        Ok(())
+    })?;
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "The batch {} is {:?}.", id, status);
+
+    Ok(())
+}
+
+/// Retrieve a batch by id.
+///
+/// # Arguments
+///
+/// * `id`: The id of the batch.
+///
+/// # Returns
+///
+/// A `BatchObject` instance, or `None` if the batch does not exist.
+pub fn retrieve_batch(id: impl AsRef<str>) -> Result<Option<BatchObject>, LlamaCoreError> {
+    Ok(lock_batches()?
+        .get(id.as_ref())
+        .map(|batch| batch.object.clone()))
+}
+
+/// List all the batches, the newest first.
+///
+/// # Returns
+///
+/// A `ListBatchesResponse` instance.
+pub fn list_batches() -> Result<ListBatchesResponse, LlamaCoreError> {
+    let mut data: Vec<BatchObject> = lock_batches()?
+        .values()
+        .map(|batch| batch.object.clone())
+        .collect();
+    data.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(a.id.cmp(&b.id)));
+
+    Ok(ListBatchesResponse {
+        object: "list".to_string(),
+        data,
+    })
+}
+
+/// Cancel a batch. The batch stops after the request in progress, and the outputs of the processed requests are still written to the archives directory.
+///
+/// # Arguments
+///
+/// * `id`: The id of the batch.
+///
+/// # Returns
+///
+/// A `BatchObject` instance, or `None` if the batch does not exist.
+pub fn cancel_batch(id: impl AsRef<str>) -> Result<Option<BatchObject>, LlamaCoreError> {
+    let mut batches = lock_batches()?;
+    let batch = match batches.get_mut(id.as_ref()) {
+        Some(batch) => batch,
+        None => return Ok(None),
+    };
+
+    if !batch.object.status.is_terminal() && batch.object.status != BatchStatus::Finalizing {
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Cancel the batch {}", id.as_ref());
+
+        batch.object.status = BatchStatus::Cancelling;
+        batch.object.cancelling_at = Some(current_timestamp()?);
+    }
+
+    Ok(Some(batch.object.clone()))
+}
+
+/// Runs a request of the batch. The request is retried after the suggested seconds if the model is busy.
+async fn run_request(
+    id: &str,
+    endpoint: &str,
+    input: &BatchRequestInput,
+) -> Result<serde_json::Value, LlamaCoreError> {
+    loop {
+        let result = match endpoint {
+            "/v1/chat/completions" => {
+                let mut request: ChatCompletionRequest = parse_body(input)?;
+                request.stream = Some(false);
+                crate::chat::chat(&mut request)
+                    .await
+                    .map(|response| match response {
+                        Either::Right(object) => serde_json::to_value(object),
+                        Either::Left(_) => unreachable!("the stream mode is disabled"),
+                    })
+            }
+            _ => {
+                let request: EmbeddingRequest = parse_body(input)?;
+                crate::embeddings::embeddings(&request)
+                    .await
+                    .map(serde_json::to_value)
+            }
+        };
+
+        match result {
+            Ok(body) => {
+                return body.map_err(|e| {
+                    let err_msg = format!("Failed to serialize the response. Reason: {}", e);
+
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", &err_msg);
+
+                    LlamaCoreError::Operation(err_msg)
+                })
+            }
+            Err(LlamaCoreError::Scheduler(e)) if stopped_status(id)?.is_none() => {
+                #[cfg(feature = "logging")]
+                warn!(target: "stdout", "Retry the request {} of the batch {} after {} seconds. {}", &input.custom_id, id, e.retry_after(), e);
+
+                tokio::time::sleep(Duration::from_secs(e.retry_after())).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Converts the error of a request to the error reported in the batch.
+fn batch_error(e: &LlamaCoreError) -> BatchError {
+    let param = match e {
+        LlamaCoreError::InvalidRequest { param, .. } => param.clone(),
+        _ => None,
+    };
+
+    BatchError {
+        code: e.code().to_string(),
+        message: e.to_string(),
+        param,
+        line: None,
+    }
+}
+
+fn parse_body<T: serde::de::DeserializeOwned>(
+    input: &BatchRequestInput,
+) -> Result<T, LlamaCoreError> {
+    serde_json::from_value(input.body.clone()).map_err(|e| {
+        let err_msg = format!("Invalid request body. Reason: {}", e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::invalid_request(err_msg, Some("body"))
+    })
+}
+
+/// Parses the lines of the input file, and returns the errors of all the invalid lines if any.
+fn parse_inputs(content: &str, endpoint: &str) -> Result<Vec<BatchRequestInput>, Vec<BatchError>> {
+    let mut inputs = vec![];
+    let mut errors = vec![];
+    let mut custom_ids = HashSet::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let line_error = |code: &str, message: String, param: Option<&str>| BatchError {
+            code: code.to_string(),
+            message,
+            param: param.map(Into::into),
+            line: Some(index as u64 + 1),
+        };
+
+        let input: BatchRequestInput = match serde_json::from_str(line) {
+            Ok(input) => input,
+            Err(e) => {
+                errors.push(line_error(
+                    "invalid_json_line",
+                    format!("The line is not a valid request. Reason: {}", e),
+                    None,
+                ));
+                continue;
+            }
+        };
+
+        if input.method != "POST" {
+            errors.push(line_error(
+                "invalid_method",
+                format!("Unsupported method: {}. Must be `POST`.", input.method),
+                Some("method"),
+            ));
+        } else if input.url != endpoint {
+            errors.push(line_error(
+                "mismatched_endpoint",
+                format!(
+                    "The url {} of the request does not match the endpoint {} of the batch.",
+                    input.url, endpoint
+                ),
+                Some("url"),
+            ));
+        } else if !custom_ids.insert(input.custom_id.clone()) {
+            errors.push(line_error(
+                "duplicate_custom_id",
+                format!("The custom_id {} is not unique.", input.custom_id),
+                Some("custom_id"),
+            ));
+        } else {
+            inputs.push(input);
+        }
+    }
+
+    if inputs.is_empty() && errors.is_empty() {
+        errors.push(BatchError {
+            code: "empty_file".to_string(),
+            message: "The input file has no requests.".to_string(),
+            param: None,
+            line: None,
+        });
+    }
+
+    match errors.is_empty() {
+        true => Ok(inputs),
+        false => Err(errors),
+    }
+}
+
+/// Returns the status that the batch should stop with, i.e., cancelled or expired, if any.
+fn stopped_status(id: &str) -> Result<Option<BatchStatus>, LlamaCoreError> {
+    let batches = lock_batches()?;
+    let object = match batches.get(id) {
+        Some(batch) => &batch.object,
+        None => return Ok(None),
+    };
+
+    if object.status == BatchStatus::Cancelling {
+        return Ok(Some(BatchStatus::Cancelled));
+    }
+    if object
+        .expires_at
+        .is_some_and(|expires_at| current_timestamp().is_ok_and(|now| now >= expires_at))
+    {
+        return Ok(Some(BatchStatus::Expired));
+    }
+
+    Ok(None)
+}
+
+fn update_batch(
+    id: &str,
+    update: impl FnOnce(&mut BatchObject) -> Result<(), LlamaCoreError>,
+) -> Result<(), LlamaCoreError> {
+    match lock_batches()?.get_mut(id) {
+        Some(batch) => update(&mut batch.object),
+        None => Ok(()),
+    }
+}
+
+fn lock_batches() -> Result<MutexGuard<'static, HashMap<String, Batch>>, LlamaCoreError> {
+    BATCHES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|e| {
+            let err_msg = format!("Fail to acquire the lock of `BATCHES`. {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })
+}
+
+#[test]
+fn test_batches_parse_inputs() {
+    let content = r#"{"custom_id":"request-1","method":"POST","url":"/v1/embeddings","body":{"model":"all-minilm","input":"Hello"}}
+
+{"custom_id":"request-2","method":"POST","url":"/v1/embeddings","body":{"model":"all-minilm","input":"World"}}
+"#;
+    let inputs = parse_inputs(content, "/v1/embeddings").unwrap();
+    assert_eq!(inputs.len(), 2);
+    assert_eq!(inputs[1].custom_id, "request-2");
+
+    // the errors of all the invalid lines are reported with the line numbers
+    let content = r#"{"custom_id":"request-1","method":"POST","url":"/v1/chat/completions","body":{}}
+not a json line
+{"custom_id":"request-1","method":"POST","url":"/v1/embeddings","body":{}}
+{"custom_id":"request-1","method":"GET","url":"/v1/embeddings","body":{}}"#;
+    let errors = parse_inputs(content, "/v1/embeddings").unwrap_err();
+    let codes: Vec<(&str, Option<u64>)> = errors
+        .iter()
+        .map(|error| (error.code.as_str(), error.line))
+        .collect();
+    assert_eq!(
+        codes,
+        [
+            ("mismatched_endpoint", Some(1)),
+            ("invalid_json_line", Some(2)),
+            ("invalid_method", Some(4)),
+        ]
+    );
+
+    let errors = parse_inputs("\n", "/v1/embeddings").unwrap_err();
+    assert_eq!(errors[0].code, "empty_file");
+}
+
+#[test]
+fn test_batches_batch_error() {
+    let error = batch_error(&LlamaCoreError::invalid_request(
+        "Invalid request body.",
+        Some("body"),
+    ));
+    assert_eq!(error.code, "invalid_request");
+    assert_eq!(error.param.as_deref(), Some("body"));
+
+    let error = batch_error(&LlamaCoreError::Operation("Failed to write.".to_string()));
+    assert_eq!(error.code, "server_error");
+    assert_eq!(error.param, None);
+}
@@ -0,0 +1,148 @@
+//! Define APIs for cancelling the chat requests in progress.
+//!
+//! Each chat request registers a [`CancellationToken`] under its chat id, which is checked between the generated tokens. The generation stops once the token is cancelled by [`cancel`] or the deadline of the request is exceeded.
+
+use crate::error::LlamaCoreError;
+use once_cell::sync::OnceCell;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+// key: chat id, value: the cancellation token of the chat request
+static CANCELLATION_TOKENS: OnceCell<Mutex<HashMap<String, CancellationToken>>> = OnceCell::new();
+
+/// Token checked between the generated tokens to stop the generation cooperatively.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+impl CancellationToken {
+    /// Creates a token, which is cancelled automatically once the deadline, if any, is exceeded.
+    pub fn new(deadline: Option<Instant>) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline,
+        }
+    }
+
+    /// Cancels the token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the token is cancelled or the deadline is exceeded.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Registration of a chat request that can be cancelled by its chat id. The registration is removed when the guard is dropped.
+#[derive(Debug)]
+pub(crate) struct CancellationGuard {
+    id: String,
+    token: CancellationToken,
+}
+impl CancellationGuard {
+    pub(crate) fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+impl Drop for CancellationGuard {
+    fn drop(&mut self) {
+        if let Ok(mut tokens) = lock_tokens() {
+            // the id may have been registered again by a newer request
+            if tokens
+                .get(&self.id)
+                .is_some_and(|token| Arc::ptr_eq(&token.cancelled, &self.token.cancelled))
+            {
+                tokens.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Registers the chat request with the given chat id, so that it can be cancelled by [`cancel`]. The generation also stops after the given timeout.
+pub(crate) fn register(
+    id: impl Into<String>,
+    timeout: Option<Duration>,
+) -> Result<CancellationGuard, LlamaCoreError> {
+    let id = id.into();
+    let token = CancellationToken::new(timeout.map(|timeout| Instant::now() + timeout));
+
+    lock_tokens()?.insert(id.clone(), token.clone());
+
+    Ok(CancellationGuard { id, token })
+}
+
+/// Cancels the chat request in progress with the given chat id.
+///
+/// # Arguments
+///
+/// * `id`: The chat id, which is the `id` of the chat completion object and chunks.
+///
+/// # Returns
+///
+/// `true` if a chat request with the given id is in progress and cancelled, `false` otherwise.
+pub fn cancel(id: impl AsRef<str>) -> Result<bool, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Cancel the chat request: {}", id.as_ref());
+
+    match lock_tokens()?.get(id.as_ref()) {
+        Some(token) => {
+            token.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+fn lock_tokens() -> Result<MutexGuard<'static, HashMap<String, CancellationToken>>, LlamaCoreError>
+{
+    CANCELLATION_TOKENS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|e| {
+            let err_msg = format!("Fail to acquire the lock of `CANCELLATION_TOKENS`. {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })
+}
+
+#[test]
+fn test_cancellation_cancel_by_id() {
+    let guard = register("chatcmpl-test-cancel", None).unwrap();
+    assert!(!guard.token().is_cancelled());
+
+    assert!(cancel("chatcmpl-test-cancel").unwrap());
+    assert!(guard.token().is_cancelled());
+
+    // the registration is removed with the guard
+    drop(guard);
+    assert!(!cancel("chatcmpl-test-cancel").unwrap());
+}
+
+#[test]
+fn test_cancellation_reregister_and_deadline() {
+    let old = register("chatcmpl-test-reregister", None).unwrap();
+    let new = register("chatcmpl-test-reregister", Some(Duration::from_secs(60))).unwrap();
+
+    // dropping the old registration keeps the new one
+    drop(old);
+    assert!(cancel("chatcmpl-test-reregister").unwrap());
+    assert!(new.token().is_cancelled());
+
+    let expired = CancellationToken::new(Some(Instant::now()));
+    assert!(expired.is_cancelled());
+}
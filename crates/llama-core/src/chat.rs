@@ -1,17 +1,24 @@
 //! Define APIs for chat completion.
 
 use crate::{
+    cancellation::{self, CancellationGuard, CancellationToken},
     error,
+    grammar::response_format_to_grammar,
+    graph::InferenceError,
     metadata::ggml::GgmlMetadata,
-    running_mode,
+    models, running_mode,
+    scheduler::{self, SchedulerPermit},
     utils::{
-        gen_chat_id, get_output_buffer, get_output_buffer_single, get_token_info_by_graph,
-        get_token_info_by_graph_name, set_tensor_data_u8,
+        check_logprobs_support, gen_chat_id, gen_system_fingerprint, gen_tool_call_id,
+        get_logprobs_by_graph_single, get_output_buffer_single, get_token_info_by_graph,
+        get_token_info_by_graph_name, parse_logit_bias, set_tensor_data_u8,
     },
     Graph, RunningMode, CACHED_UTF8_ENCODINGS, CHAT_GRAPHS, OUTPUT_TENSOR,
 };
+use base64::{engine::general_purpose, Engine as _};
 use chat_prompts::{
     chat::{BuildChatPrompt, ChatPrompt},
+    tool::{tool_call_parser, PartialToolCalls, ToolCallParser},
     PromptTemplateType,
 };
 use either::{Either, Left, Right};
@@ -20,21 +27,29 @@ use endpoints::{
         ChatCompletionChunk, ChatCompletionChunkChoice, ChatCompletionChunkChoiceDelta,
         ChatCompletionObject, ChatCompletionObjectChoice, ChatCompletionObjectMessage,
         ChatCompletionRequest, ChatCompletionRequestMessage, ChatCompletionRole,
-        ChatCompletionUserMessageContent, ContentPart, Function, ToolCall, ToolCallForChunk,
-        ToolChoice,
+        ChatCompletionUserMessageContent, ContentPart, ContextTruncationReport, FunctionForChunk,
+        Image, LogProbs, TokenLogProb, ToolCall, ToolCallForChunk, ToolChoice, TruncationStrategy,
     },
     common::{FinishReason, Usage},
+    tokenize::CountTokensResponse,
 };
 use error::{BackendError, LlamaCoreError};
 use futures::StreamExt;
 use std::{
     collections::VecDeque,
+    fmt,
     pin::Pin,
-    sync::Mutex,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
+/// The maximum size in bytes of an image in the chat messages.
+const MAX_IMAGE_SIZE: usize = 20 * 1024 * 1024;
+
+/// The maximum number of tokens of the summary of the dropped messages.
+const MAX_SUMMARY_TOKENS: u64 = 256;
+
 /// Processes a chat-completion request and returns either a stream of ChatCompletionChunk instances or a ChatCompletionObject instance.
 pub async fn chat(
     chat_request: &mut ChatCompletionRequest,
@@ -77,6 +92,74 @@ pub async fn chat_completions(
     chat_once(chat_request).await
 }
 
+/// Counts the tokens of the prompt built from the chat request for the model, without generating the completion. The chat history is truncated in the same way as [`chat`] does, except that the `summarize` strategy drops the oldest turns without summarizing them.
+pub async fn count_tokens(
+    chat_request: &mut ChatCompletionRequest,
+) -> Result<CountTokensResponse, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Count the tokens of the chat prompt.");
+
+    let running_mode = running_mode()?;
+    if running_mode == RunningMode::Embeddings {
+        let err_msg = format!(
+            "Counting the tokens of the chat prompt is not supported in the {} mode.",
+            running_mode
+        );
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("model")));
+    }
+
+    // resolve the images in the user messages
+    resolve_images(chat_request).await?;
+
+    // wait for the turn to use the model, as building the prompt replaces the prompt of the model
+    let _permit = scheduler::acquire(chat_request.model.as_ref()).await?;
+
+    let model_name = chat_request.model.clone();
+    let metadata = get_model_metadata(model_name.as_ref())?;
+
+    // the summary needs a generation, so the dropped turns are not summarized
+    let mut truncation = chat_request
+        .context_truncation
+        .clone()
+        .unwrap_or_else(|| metadata.context_truncation.clone());
+    if truncation.strategy == TruncationStrategy::Summarize {
+        truncation.strategy = TruncationStrategy::DropOldest;
+    }
+    chat_request.context_truncation = Some(truncation);
+
+    let (_prompt, available_completion_tokens, _, context_truncation) = build_prompt(
+        model_name.as_ref(),
+        chat_request,
+        &metadata,
+        &CancellationToken::default(),
+    )?;
+    let token_info = get_token_info_by_graph_name(model_name.as_ref())?;
+
+    // the completion is limited by `n_predict` set for the request, and by the context left after the prompt
+    let n_predict = completion_n_predict(
+        chat_request,
+        metadata.n_predict,
+        available_completion_tokens,
+    );
+    let remaining_tokens =
+        n_predict.min(metadata.ctx_size.saturating_sub(token_info.prompt_tokens));
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Prompt tokens: {}, remaining tokens: {}", token_info.prompt_tokens, remaining_tokens);
+
+    Ok(CountTokensResponse {
+        model: metadata.model_name,
+        prompt_tokens: token_info.prompt_tokens,
+        context_size: metadata.ctx_size,
+        remaining_tokens,
+        context_truncation,
+    })
+}
+
 async fn chat_stream(
     chat_request: &mut ChatCompletionRequest,
 ) -> Result<impl futures::TryStream<Ok = String, Error = LlamaCoreError>, LlamaCoreError> {
@@ -93,17 +176,14 @@ async fn chat_stream(
         #[cfg(feature = "logging")]
         error!(target: "stdout", "{}", &err_msg);
 
-        return Err(LlamaCoreError::Operation(err_msg));
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("model")));
     }
 
     let model_name = chat_request.model.clone();
-    let id = match &chat_request.user {
-        Some(id) => id.clone(),
-        None => gen_chat_id(),
-    };
+    let id = chat_request.chat_id.get_or_insert_with(gen_chat_id).clone();
 
     #[cfg(feature = "logging")]
-    info!(target: "stdout", "user: {}", &id);
+    info!(target: "stdout", "chat id: {}", &id);
 
     // parse the `include_usage` option
     let include_usage = match chat_request.stream_options {
@@ -114,12 +194,29 @@ async fn chat_stream(
     #[cfg(feature = "logging")]
     info!(target: "stdout", "include_usage: {}", include_usage);
 
+    // make the request cancellable by its chat id
+    let cancellation = cancellation::register(&id, chat_request.timeout.map(Duration::from_secs))?;
+
+    // resolve the images in the user messages
+    resolve_images(chat_request).await?;
+
+    // wait for the turn to use the model
+    let permit = scheduler::acquire(chat_request.model.as_ref()).await?;
+
     // update metadata
     let mut metadata = check_model_metadata(chat_request).await?;
 
+    // restore the metadata of the model if it is changed only for the request, even if the request fails or is abandoned
+    let metadata_guard =
+        MetadataGuard::new(model_name.as_ref(), should_restore_metadata(chat_request));
+
     // build prompt
-    let (prompt, avaible_completion_tokens, tool_use) =
-        build_prompt(model_name.as_ref(), chat_request)?;
+    let (prompt, avaible_completion_tokens, tool_use, context_truncation) = build_prompt(
+        model_name.as_ref(),
+        chat_request,
+        &metadata,
+        cancellation.token(),
+    )?;
 
     #[cfg(feature = "logging")]
     {
@@ -131,1585 +228,497 @@ async fn chat_stream(
     // update metadata n_predict
     update_n_predict(chat_request, &mut metadata, avaible_completion_tokens).await?;
 
+    // parse the tool calls out of the output if the request uses tools
+    let tool_calls = match tool_use {
+        true => Some(ToolCallStream::new(get_tool_call_parser(&metadata)?)),
+        false => None,
+    };
+
+    // the end-of-turn markers of the prompt template stop the generation as the stop sequences do
+    let mut stop = chat_request.stop.clone().unwrap_or_default();
+    stop.extend(
+        ChatPrompt::from(metadata.prompt_template)
+            .end_of_turn_markers()
+            .iter()
+            .map(|marker| marker.to_string()),
+    );
+
     // set prompt
     set_prompt(chat_request.model.as_ref(), &prompt)?;
 
-    let stream = match tool_use {
-        false => ChatStream::new(model_name, id, include_usage, None),
-        true => {
-            let chat_graphs = match CHAT_GRAPHS.get() {
-                Some(chat_graphs) => chat_graphs,
-                None => {
-                    let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
+    let token = cancellation.token().clone();
+    let stream = ChatStream::new(
+        model_name,
+        id,
+        include_usage,
+        None,
+        permit,
+        metadata_guard,
+        cancellation,
+        ChoiceState {
+            n_choice: chat_request.n_choice.unwrap_or(1),
+            prompt,
+            logprobs: chat_request.logprobs.unwrap_or_default(),
+            stop: Some(stop),
+            prompt_template: Some(metadata.prompt_template),
+            tool_calls,
+            context_truncation,
+            cancellation: token,
+            ..Default::default()
+        },
+    );
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "End of the chat completion stream.");
 
-                    return Err(LlamaCoreError::Operation(err_msg.into()));
-                }
-            };
+    Ok(stream)
+}
 
-            let mut chat_graphs = chat_graphs.lock().map_err(|e| {
-                let err_msg = format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
+async fn chat_once(
+    chat_request: &mut ChatCompletionRequest,
+) -> Result<ChatCompletionObject, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Processing chat completion request in non-stream mode.");
 
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
+    let running_mode = running_mode()?;
+    if running_mode == RunningMode::Embeddings {
+        let err_msg = format!(
+            "The chat completion is not supported in the {} mode.",
+            running_mode
+        );
 
-                LlamaCoreError::Operation(err_msg)
-            })?;
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
 
-            match model_name {
-                Some(model_name) => match chat_graphs.contains_key(&model_name) {
-                    true => {
-                        let graph = chat_graphs.get_mut(&model_name).unwrap();
-                        chat_stream_by_graph(graph, id, include_usage)?
-                    }
-                    false => match chat_graphs.iter_mut().next() {
-                        Some((_, graph)) => chat_stream_by_graph(graph, id, include_usage)?,
-                        None => {
-                            let err_msg = "There is no model available in the chat graphs.";
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("model")));
+    }
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+    let model_name = chat_request.model.clone();
+    let id = chat_request.chat_id.get_or_insert_with(gen_chat_id).clone();
 
-                            return Err(LlamaCoreError::Operation(err_msg.into()));
-                        }
-                    },
-                },
-                None => match chat_graphs.iter_mut().next() {
-                    Some((_, graph)) => chat_stream_by_graph(graph, id, include_usage)?,
-                    None => {
-                        let err_msg = "There is no model available in the chat graphs.";
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "chat id: {}", &id);
 
-                        #[cfg(feature = "logging")]
-                        error!(target: "stdout", "{}", &err_msg);
+    // make the request cancellable by its chat id
+    let cancellation = cancellation::register(&id, chat_request.timeout.map(Duration::from_secs))?;
 
-                        return Err(LlamaCoreError::Operation(err_msg.into()));
-                    }
-                },
-            }
-        }
-    };
+    // resolve the images in the user messages
+    resolve_images(chat_request).await?;
+
+    // wait for the turn to use the model
+    let _permit = scheduler::acquire(chat_request.model.as_ref()).await?;
+
+    // update metadata
+    let mut metadata = check_model_metadata(chat_request).await?;
+
+    // restore the metadata of the model if it is changed only for the request, even if the request fails or is abandoned
+    let metadata_guard =
+        MetadataGuard::new(model_name.as_ref(), should_restore_metadata(chat_request));
+
+    // build prompt
+    let (prompt, avaible_completion_tokens, tool_use, context_truncation) = build_prompt(
+        model_name.as_ref(),
+        chat_request,
+        &metadata,
+        cancellation.token(),
+    )?;
 
     #[cfg(feature = "logging")]
-    info!(target: "stdout", "End of the chat completion stream.");
+    {
+        info!(target: "stdout", "prompt:\n{}", &prompt);
+        info!(target: "stdout", "available_completion_tokens: {}", avaible_completion_tokens);
+        info!(target: "stdout", "tool_use: {}", tool_use);
+    }
 
-    Ok(stream)
-}
+    // update metadata n_predict
+    update_n_predict(chat_request, &mut metadata, avaible_completion_tokens).await?;
+
+    // feed the prompt to the model
+    set_prompt(model_name.as_ref(), &prompt)?;
+
+    // compute
+    let res = compute_choices(
+        model_name.as_ref(),
+        &id,
+        tool_use,
+        &prompt,
+        chat_request.n_choice.unwrap_or(1),
+        chat_request.stop.as_deref(),
+        chat_request.logprobs.unwrap_or_default(),
+        cancellation.token(),
+    )
+    .await
+    .map(|mut object| {
+        object.context_truncation = context_truncation;
+        object
+    });
+
+    // restore the metadata of the model if it is changed only for the request
+    drop(metadata_guard);
 
-fn chat_stream_by_graph(
-    graph: &mut Graph<GgmlMetadata>,
-    id: impl Into<String>,
-    include_usage: bool,
-) -> Result<ChatStream, LlamaCoreError> {
     #[cfg(feature = "logging")]
-    info!(target: "stdout", "Handle chat request with available tools by the model named {}.", graph.name());
+    info!(target: "stdout", "End of the chat completion.");
 
-    let id = id.into();
+    res
+}
 
-    match graph.compute() {
-        Ok(_) => {
-            // Retrieve the output.
-            let output_buffer = get_output_buffer(graph, OUTPUT_TENSOR)?;
-            let output = std::str::from_utf8(&output_buffer[..]).map_err(|e| {
-                let err_msg = format!(
-                    "Failed to decode the buffer of the inference result to a utf-8 string. {}",
-                    e
-                );
+/// Generates `n_choice` choices from the same prompt.
+#[allow(clippy::too_many_arguments)]
+async fn compute_choices(
+    model_name: Option<&String>,
+    id: &str,
+    tool_use: bool,
+    prompt: &str,
+    n_choice: u64,
+    stop: Option<&[String]>,
+    logprobs: bool,
+    cancellation: &CancellationToken,
+) -> Result<ChatCompletionObject, LlamaCoreError> {
+    let mut res = generate(model_name, id, tool_use, stop, logprobs, cancellation).await?;
 
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
+    // generate the rest choices from the same prompt
+    for index in 1..n_choice {
+        if cancellation.is_cancelled() {
+            break;
+        }
 
-                LlamaCoreError::Operation(err_msg)
-            })?;
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Generate the choice with index {}.", index);
 
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw generation:\n{}", output);
+        // feed the prompt to the model again
+        set_prompt(model_name, prompt)?;
 
-            // post-process
-            let message = post_process(output, &graph.metadata.prompt_template).map_err(|e| {
-                LlamaCoreError::Operation(format!("Failed to post-process the output. {}", e))
-            })?;
+        let object = generate(model_name, id, tool_use, stop, logprobs, cancellation).await?;
 
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "post-processed generation:\n{}", &message);
+        // the prompt tokens are shared by all the choices
+        res.usage.completion_tokens += object.usage.completion_tokens;
+        res.usage.total_tokens = res.usage.prompt_tokens + res.usage.completion_tokens;
 
-            // retrieve the number of prompt and completion tokens
-            let token_info = get_token_info_by_graph(graph)?;
+        res.choices
+            .extend(object.choices.into_iter().map(|mut choice| {
+                choice.index = index as u32;
+                choice
+            }));
+    }
+
+    Ok(res)
+}
+
+/// Generates a choice token by token, so that the generation can be cancelled between the tokens.
+async fn generate(
+    model_name: Option<&String>,
+    id: &str,
+    tool_use: bool,
+    stop: Option<&[String]>,
+    logprobs: bool,
+    cancellation: &CancellationToken,
+) -> Result<ChatCompletionObject, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Generate chat completion token by token.");
+
+    // clean up the context once the generation finishes or is abandoned, e.g., the client disconnects
+    let _context = SingleContextGuard {
+        model_name: model_name.cloned(),
+    };
 
+    let mut output = Vec::new();
+    let mut output_logprobs = Vec::new();
+    // the length of the output searched for the stop sequences
+    let mut searched = 0;
+    let end = loop {
+        if cancellation.is_cancelled() {
             #[cfg(feature = "logging")]
-            info!(target: "stdout", "prompt tokens: {}, completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
+            info!(target: "stdout", "The chat request is cancelled. Stop the generation.");
 
-            let usage = Some(Usage {
-                prompt_tokens: token_info.prompt_tokens,
-                completion_tokens: token_info.completion_tokens,
-                total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
-            });
+            break GenerationEnd::Cancelled;
+        }
 
-            let created = SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| {
-                    let err_msg = format!("Failed to get the current time. Reason: {}", e);
+        let end = with_chat_graph(model_name, |graph| match graph.compute_single() {
+            Ok(_) => {
+                output.extend(get_output_buffer_single(graph, OUTPUT_TENSOR)?);
+                if logprobs {
+                    output_logprobs.extend(get_logprobs_by_graph_single(graph)?);
+                }
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+                // stop the generation as soon as it reaches one of the stop sequences, which are
+                // searched in the new token and the tail of the output they may start in
+                let found = stop.and_then(|stop| find_stop_sequence_after(&output, searched, stop));
+                searched = output.len();
+                match found {
+                    Some(pos) => {
+                        #[cfg(feature = "logging")]
+                        info!(target: "stdout", "Stop the generation at the stop sequence.");
 
-                    LlamaCoreError::Operation(err_msg)
-                })?;
+                        truncate_logprobs(&mut output_logprobs, pos);
+                        output.truncate(pos);
+
+                        Ok(Some(GenerationEnd::Stopped))
+                    }
+                    None => Ok(None),
+                }
+            }
+            Err(InferenceError::EndOfSequence) => Ok(Some(GenerationEnd::EndOfSequence)),
+            Err(InferenceError::ContextFull) => Ok(Some(GenerationEnd::ContextFull)),
+            Err(InferenceError::PromptTooLong) => {
+                #[cfg(feature = "logging")]
+                warn!(target: "stdout", "The prompt is too long. Please reduce the length of your input and try again.");
 
-            if graph.metadata.prompt_template != PromptTemplateType::MistralTool
-                && graph.metadata.prompt_template != PromptTemplateType::ChatMLTool
-                && graph.metadata.prompt_template != PromptTemplateType::GroqLlama3Tool
-                && graph.metadata.prompt_template != PromptTemplateType::Llama3Tool
-                && graph.metadata.prompt_template != PromptTemplateType::InternLM2Tool
-            {
-                let err_msg = "The tool use is only supported for 'mistral-chat' and 'chatml' prompt templates.";
+                Ok(Some(GenerationEnd::PromptTooLong))
+            }
+            Err(e) => {
+                let err_msg = format!("Failed to compute the chat completion. Reason: {}", e);
 
                 #[cfg(feature = "logging")]
                 error!(target: "stdout", "{}", &err_msg);
 
-                return Err(LlamaCoreError::Operation(err_msg.into()));
+                Err(LlamaCoreError::Backend(BackendError::ComputeSingle(
+                    err_msg,
+                )))
             }
+        })?;
 
-            let parsed_result = parse_tool_calls(&message, graph.metadata.prompt_template)?;
-
-            let content = match parsed_result.content {
-                Some(content) => Some(content),
-                None => Some(parsed_result.raw),
-            };
+        if let Some(end) = end {
+            break end;
+        }
 
-            let tool_calls: Vec<ToolCallForChunk> = parsed_result
-                .tool_calls
-                .into_iter()
-                .enumerate()
-                .map(|(index, tool_call)| ToolCallForChunk {
-                    index,
-                    id: tool_call.id,
-                    ty: tool_call.ty,
-                    function: tool_call.function,
-                })
-                .collect();
+        // let the other tasks run between the tokens, e.g., the requests to cancel the generation
+        tokio::task::yield_now().await;
+    };
 
-            // tool_calls chunk
-            let tool_call_chunk = {
-                let chat_completion_chunk = ChatCompletionChunk {
-                    id: id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: created.as_secs(),
-                    model: graph.name().to_owned(),
-                    system_fingerprint: "fp_44709d6fcb".to_string(),
-                    choices: vec![ChatCompletionChunkChoice {
-                        index: 0,
-                        delta: ChatCompletionChunkChoiceDelta {
-                            role: ChatCompletionRole::Assistant,
-                            content,
-                            tool_calls,
-                        },
-                        logprobs: None,
-                        finish_reason: None,
-                    }],
-                    usage: None,
-                };
-                let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                    let err_msg =
-                        format!("Failed to serialize chat completion chunk. Reason: {}", e);
+    let output = String::from_utf8_lossy(&output);
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "raw generation: {}", output);
 
-                    LlamaCoreError::Operation(err_msg)
-                })?;
+    with_chat_graph(model_name, |graph| {
+        completion_object(
+            graph,
+            id,
+            &output,
+            end,
+            tool_use,
+            stop,
+            logprobs.then_some(output_logprobs),
+        )
+    })
+}
 
-                format!("data: {}\n\n", chunk_str)
-            };
+/// How the generation of a choice ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GenerationEnd {
+    /// The model generated the end-of-sequence token or `n_predict` tokens.
+    EndOfSequence,
+    /// The context of the model is full.
+    ContextFull,
+    /// The prompt is too long for the context of the model.
+    PromptTooLong,
+    /// The chat request is cancelled or exceeds its deadline.
+    Cancelled,
+    /// The generation reached one of the stop sequences.
+    Stopped,
+}
 
-            // uage chunk
-            let usage_chunk = {
-                let chat_completion_chunk = ChatCompletionChunk {
-                    id: id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: created.as_secs(),
-                    model: graph.name().to_owned(),
-                    system_fingerprint: "fp_44709d6fcb".to_string(),
-                    choices: vec![],
-                    usage,
-                };
-                let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                    let err_msg =
-                        format!("Failed to serialize chat completion chunk. Reason: {}", e);
+/// Drops the log probabilities of the tokens after the first `len` bytes of the generation.
+fn truncate_logprobs(logprobs: &mut Vec<TokenLogProb>, len: usize) {
+    let mut offset = 0;
+    let kept = logprobs
+        .iter()
+        .take_while(|logprob| {
+            let start = offset;
+            offset += logprob.bytes.as_ref().map_or(logprob.token.len(), Vec::len);
+            start < len
+        })
+        .count();
+    logprobs.truncate(kept);
+}
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+/// Creates the chat completion object from the raw output of the model.
+fn completion_object(
+    graph: &Graph<GgmlMetadata>,
+    id: impl Into<String>,
+    output: &str,
+    end: GenerationEnd,
+    tool_use: bool,
+    stop: Option<&[String]>,
+    logprobs: Option<Vec<TokenLogProb>>,
+) -> Result<ChatCompletionObject, LlamaCoreError> {
+    // post-process
+    let mut message = ChatPrompt::from(graph.metadata.prompt_template).post_process(output);
 
-                    LlamaCoreError::Operation(err_msg)
-                })?;
+    // truncate the generation at the stop sequences
+    let stopped = truncate_at_stop_sequence(&mut message, stop);
 
-                format!("data: {}\n\n", chunk_str)
-            };
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "post-processed generation:\n{}", &message);
 
-            // ending chunk
-            let ending_chunk = "data: [DONE]\n\n".to_string();
+    // retrieve the number of prompt and completion tokens
+    let token_info = get_token_info_by_graph(graph)?;
 
-            let chunks = vec![tool_call_chunk, usage_chunk, ending_chunk];
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "prompt tokens: {}, completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
 
-            Ok(ChatStream::new(
-                Some(graph.name().to_owned()),
-                id,
-                include_usage,
-                Some(chunks),
-            ))
+    let finish_reason = match (end, stopped) {
+        (GenerationEnd::EndOfSequence | GenerationEnd::Stopped, _) | (_, true) => {
+            FinishReason::stop
         }
-        Err(wasmedge_wasi_nn::Error::BackendError(wasmedge_wasi_nn::BackendError::ContextFull)) => {
-            // Retrieve the output.
-            let output_buffer = get_output_buffer(graph, OUTPUT_TENSOR)?;
-            let output = std::str::from_utf8(&output_buffer[..]).map_err(|e| {
-                let err_msg = format!(
-                    "Failed to decode the buffer of the inference result to a utf-8 string. {}",
-                    e
-                );
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
+        (GenerationEnd::ContextFull | GenerationEnd::PromptTooLong, false) => FinishReason::length,
+        // the finish reasons of OpenAI have no `cancelled`, so a cancelled generation is marked by the `cancelled` field of the choice instead
+        (GenerationEnd::Cancelled, false) => FinishReason::stop,
+    };
+    let cancelled = matches!(end, GenerationEnd::Cancelled) && !stopped;
 
-                LlamaCoreError::Operation(err_msg)
-            })?;
+    // parse the tool calls out of the complete generation
+    let parse_tool_calls =
+        tool_use && matches!(end, GenerationEnd::EndOfSequence | GenerationEnd::Stopped);
+    let (content, tool_calls, finish_reason) = match parse_tool_calls {
+        true => {
+            let parser = get_tool_call_parser(&graph.metadata)?;
+            let parsed = parser.parse(&message);
 
-            // post-process
-            let message = post_process(output, &graph.metadata.prompt_template).map_err(|e| {
-                let err_msg = format!("Failed to post-process the output. {}", e);
+            let finish_reason = match parsed.tool_calls.is_empty() {
+                true => finish_reason,
+                false => FinishReason::tool_calls,
+            };
 
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
+            let tool_calls: Vec<ToolCall> = parsed
+                .tool_calls
+                .into_iter()
+                .map(|function| ToolCall {
+                    id: gen_tool_call_id(),
+                    ty: "function".to_string(),
+                    function,
+                })
+                .collect();
 
-                LlamaCoreError::Operation(err_msg)
-            })?;
+            (parsed.content, tool_calls, finish_reason)
+        }
+        false => (Some(message), vec![], finish_reason),
+    };
 
-            // retrieve the number of prompt and completion tokens
-            let token_info = get_token_info_by_graph(graph)?;
+    let created = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| {
+            let err_msg = format!("Failed to get the current time. Reason: {}", e);
 
             #[cfg(feature = "logging")]
-            info!(target: "stdout", "prompt tokens: {}, completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
+            error!(target: "stdout", "{}", &err_msg);
 
-            let usage = Some(Usage {
-                prompt_tokens: token_info.prompt_tokens,
-                completion_tokens: token_info.completion_tokens,
-                total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
-            });
+            LlamaCoreError::Operation(err_msg)
+        })?;
 
-            let created = SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| {
-                    let err_msg = format!("Failed to get the current time. Reason: {}", e);
+    // create ChatCompletionResponse
+    Ok(ChatCompletionObject {
+        id: id.into(),
+        object: String::from("chat.completion"),
+        created: created.as_secs(),
+        model: graph.name().to_owned(),
+        choices: vec![ChatCompletionObjectChoice {
+            index: 0,
+            message: ChatCompletionObjectMessage {
+                role: ChatCompletionRole::Assistant,
+                content,
+                tool_calls,
+                function_call: None,
+            },
+            finish_reason,
+            logprobs: logprobs.map(|content| LogProbs {
+                content: Some(content),
+            }),
+            cancelled,
+        }],
+        usage: Usage {
+            prompt_tokens: token_info.prompt_tokens,
+            completion_tokens: token_info.completion_tokens,
+            total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
+        },
+        system_fingerprint: Some(gen_system_fingerprint(graph)?),
+        context_truncation: None,
+    })
+}
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+/// Restores the metadata of the model to the one the model is loaded with when dropped, if the request changes it only for its own duration.
+#[derive(Debug)]
+pub(crate) struct MetadataGuard {
+    model_name: Option<String>,
+    restore: bool,
+}
+impl MetadataGuard {
+    pub(crate) fn new(model_name: Option<&String>, restore: bool) -> Self {
+        Self {
+            model_name: model_name.cloned(),
+            restore,
+        }
+    }
+}
+impl Drop for MetadataGuard {
+    fn drop(&mut self) {
+        if !self.restore {
+            return;
+        }
 
-                    LlamaCoreError::Operation(err_msg)
-                })?;
+        if let Err(e) = restore_model_metadata(self.model_name.as_ref()) {
+            let err_msg = format!("Failed to restore the metadata. Reason: {}", e);
 
-            // context full chunk
-            let context_full_chunk = {
-                let chat_completion_chunk = ChatCompletionChunk {
-                    id: id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: created.as_secs(),
-                    model: graph.name().to_owned(),
-                    system_fingerprint: "fp_44709d6fcb".to_string(),
-                    choices: vec![ChatCompletionChunkChoice {
-                        index: 0,
-                        delta: ChatCompletionChunkChoiceDelta {
-                            role: ChatCompletionRole::Assistant,
-                            content: Some(message),
-                            tool_calls: vec![],
-                        },
-                        logprobs: None,
-                        finish_reason: Some(FinishReason::length),
-                    }],
-                    usage: None,
-                };
-
-                // serialize chat completion chunk
-                let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                    let err_msg =
-                        format!("Failed to serialize chat completion chunk. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-                format!("data: {}\n\n", chunk_str)
-            };
-
-            // usage chunk
-            let usage_chunk = {
-                let chat_completion_chunk = ChatCompletionChunk {
-                    id: id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: created.as_secs(),
-                    model: graph.name().to_owned(),
-                    system_fingerprint: "fp_44709d6fcb".to_string(),
-                    choices: vec![],
-                    usage,
-                };
-
-                // serialize chat completion chunk
-                let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                    let err_msg =
-                        format!("Failed to serialize chat completion chunk. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-                format!("data: {}\n\n", chunk_str)
-            };
-
-            // ending chunk
-            let ending_chunk = "data: [DONE]\n\n".to_string();
-
-            let chunks = vec![context_full_chunk, usage_chunk, ending_chunk];
-
-            Ok(ChatStream::new(
-                Some(graph.name().to_owned()),
-                id,
-                include_usage,
-                Some(chunks),
-            ))
-        }
-        Err(wasmedge_wasi_nn::Error::BackendError(
-            wasmedge_wasi_nn::BackendError::PromptTooLong,
-        )) => {
-            #[cfg(feature = "logging")]
-            warn!(target: "stdout", "The prompt is too long. Please reduce the length of your input and try again.");
-
-            // Retrieve the output.
-            let output_buffer = get_output_buffer(graph, OUTPUT_TENSOR)?;
-            let output = std::str::from_utf8(&output_buffer[..]).map_err(|e| {
-                let err_msg = format!(
-                    "Failed to decode the buffer of the inference result to a utf-8 string. {}",
-                    e
-                );
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                LlamaCoreError::Operation(err_msg)
-            })?;
-
-            // post-process
-            let message = post_process(output, &graph.metadata.prompt_template).map_err(|e| {
-                let err_msg = format!("Failed to post-process the output. {}", e);
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                LlamaCoreError::Operation(err_msg)
-            })?;
-
-            // retrieve the number of prompt and completion token
-            let token_info = get_token_info_by_graph(graph)?;
-
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "prompt tokens: {}, completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
-
-            let usage = Some(Usage {
-                prompt_tokens: token_info.prompt_tokens,
-                completion_tokens: token_info.completion_tokens,
-                total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
-            });
-
-            let created = SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| {
-                    let err_msg = format!("Failed to get the current time. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-            // prompt too long chunk
-            let prompt_too_long_chunk = {
-                let chat_completion_chunk = ChatCompletionChunk {
-                    id: id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: created.as_secs(),
-                    model: graph.name().to_owned(),
-                    system_fingerprint: "fp_44709d6fcb".to_string(),
-                    choices: vec![ChatCompletionChunkChoice {
-                        index: 0,
-                        delta: ChatCompletionChunkChoiceDelta {
-                            role: ChatCompletionRole::Assistant,
-                            content: Some(message),
-                            tool_calls: vec![],
-                        },
-                        logprobs: None,
-                        finish_reason: Some(FinishReason::length),
-                    }],
-                    usage: None,
-                };
-
-                // serialize chat completion chunk
-                let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                    let err_msg =
-                        format!("Failed to serialize chat completion chunk. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-                format!("data: {}\n\n", chunk_str)
-            };
-
-            // usage chunk
-            let usage_chunk = {
-                let chat_completion_chunk = ChatCompletionChunk {
-                    id: id.clone(),
-                    object: "chat.completion.chunk".to_string(),
-                    created: created.as_secs(),
-                    model: graph.name().to_owned(),
-                    system_fingerprint: "fp_44709d6fcb".to_string(),
-                    choices: vec![],
-                    usage,
-                };
-
-                // serialize chat completion chunk
-                let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                    let err_msg =
-                        format!("Failed to serialize chat completion chunk. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-                format!("data: {}\n\n", chunk_str)
-            };
-
-            // ending chunk
-            let ending_chunk = "data: [DONE]\n\n".to_string();
-
-            let chunks = vec![prompt_too_long_chunk, usage_chunk, ending_chunk];
-
-            Ok(ChatStream::new(
-                Some(graph.name().to_owned()),
-                id,
-                include_usage,
-                Some(chunks),
-            ))
-        }
-        Err(e) => {
-            let err_msg = format!("Failed to compute the chat completion. Reason: {}", e);
-
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", &err_msg);
-
-            Err(LlamaCoreError::Backend(BackendError::Compute(err_msg)))
-        }
-    }
-}
-
-async fn chat_once(
-    chat_request: &mut ChatCompletionRequest,
-) -> Result<ChatCompletionObject, LlamaCoreError> {
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "Processing chat completion request in non-stream mode.");
-
-    let running_mode = running_mode()?;
-    if running_mode == RunningMode::Embeddings {
-        let err_msg = format!(
-            "The chat completion is not supported in the {} mode.",
-            running_mode
-        );
-
-        #[cfg(feature = "logging")]
-        error!(target: "stdout", "{}", &err_msg);
-
-        return Err(LlamaCoreError::Operation(err_msg));
-    }
-
-    let model_name = chat_request.model.clone();
-    let id = match &chat_request.user {
-        Some(id) => id.clone(),
-        None => gen_chat_id(),
-    };
-
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "user: {}", &id);
-
-    // update metadata
-    let mut metadata = check_model_metadata(chat_request).await?;
-
-    // build prompt
-    let (prompt, avaible_completion_tokens, tool_use) =
-        build_prompt(model_name.as_ref(), chat_request)?;
-
-    #[cfg(feature = "logging")]
-    {
-        info!(target: "stdout", "prompt:\n{}", &prompt);
-        info!(target: "stdout", "available_completion_tokens: {}", avaible_completion_tokens);
-        info!(target: "stdout", "tool_use: {}", tool_use);
-    }
-
-    // update metadata n_predict
-    update_n_predict(chat_request, &mut metadata, avaible_completion_tokens).await?;
-
-    // feed the prompt to the model
-    set_prompt(model_name.as_ref(), &prompt)?;
-
-    // compute
-    let res = compute(model_name.as_ref(), id, tool_use);
-
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "End of the chat completion.");
-
-    res
-}
-
-fn compute(
-    model_name: Option<&String>,
-    id: impl Into<String>,
-    tool_use: bool,
-) -> Result<ChatCompletionObject, LlamaCoreError> {
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "Compute chat completion.");
-
-    let chat_graphs = match CHAT_GRAPHS.get() {
-        Some(chat_graphs) => chat_graphs,
-        None => {
-            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
-
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", &err_msg);
-
-            return Err(LlamaCoreError::Operation(err_msg.into()));
-        }
-    };
-
-    let mut chat_graphs = chat_graphs.lock().map_err(|e| {
-        let err_msg = format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
-
-        #[cfg(feature = "logging")]
-        error!(target: "stdout", "{}", &err_msg);
-
-        LlamaCoreError::Operation(err_msg)
-    })?;
-
-    match model_name {
-        Some(model_name) => match chat_graphs.contains_key(model_name) {
-            true => {
-                let graph = chat_graphs.get_mut(model_name).unwrap();
-                compute_by_graph(graph, id, tool_use)
-            }
-            false => match chat_graphs.iter_mut().next() {
-                Some((_, graph)) => compute_by_graph(graph, id, tool_use),
-                None => {
-                    let err_msg = "There is no model available in the chat graphs.";
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            },
-        },
-        None => match chat_graphs.iter_mut().next() {
-            Some((_, graph)) => compute_by_graph(graph, id, tool_use),
-            None => {
-                let err_msg = "There is no model available in the chat graphs.";
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                Err(LlamaCoreError::Operation(err_msg.into()))
-            }
-        },
-    }
-}
-
-fn compute_by_graph(
-    graph: &mut Graph<GgmlMetadata>,
-    id: impl Into<String>,
-    tool_use: bool,
-) -> Result<ChatCompletionObject, LlamaCoreError> {
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "Compute chat completion by the model named {}.", graph.name());
-
-    match graph.compute() {
-        Ok(_) => {
-            // Retrieve the output.
-            let output_buffer = get_output_buffer(graph, OUTPUT_TENSOR)?;
-            let output = std::str::from_utf8(&output_buffer[..]).map_err(|e| {
-                let err_msg = format!(
-                    "Failed to decode the buffer of the inference result to a utf-8 string. {}",
-                    e
-                );
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                LlamaCoreError::Operation(err_msg)
-            })?;
-
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw generation: {}", output);
-
-            // post-process
-            let message = post_process(output, &graph.metadata.prompt_template).map_err(|e| {
-                LlamaCoreError::Operation(format!("Failed to post-process the output. {}", e))
-            })?;
-
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "post-processed generation:\n{}", &message);
-
-            // retrieve the number of prompt and completion tokens
-            let token_info = get_token_info_by_graph(graph)?;
-
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "prompt tokens: {}, completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
-
-            let created = SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| {
-                    let err_msg = format!("Failed to get the current time. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-            match tool_use {
-                true => {
-                    if graph.metadata.prompt_template != PromptTemplateType::MistralTool
-                        && graph.metadata.prompt_template != PromptTemplateType::ChatMLTool
-                        && graph.metadata.prompt_template != PromptTemplateType::GroqLlama3Tool
-                        && graph.metadata.prompt_template != PromptTemplateType::Llama3Tool
-                        && graph.metadata.prompt_template != PromptTemplateType::InternLM2Tool
-                        && graph.metadata.prompt_template != PromptTemplateType::NemotronTool
-                        && graph.metadata.prompt_template != PromptTemplateType::FunctionaryV32
-                        && graph.metadata.prompt_template != PromptTemplateType::FunctionaryV31
-                    {
-                        let err_msg = "The tool use is only supported for 'mistral-tool', 'chatml', 'groq-llama3-tool', 'llama-3-tool', 'internlm-2-tool', 'nemotron-tool', 'functionary-31', and 'functionary-32' prompt templates.";
-
-                        #[cfg(feature = "logging")]
-                        error!(target: "stdout", "{}", &err_msg);
-
-                        return Err(LlamaCoreError::Operation(err_msg.into()));
-                    }
-
-                    let parsed_result = parse_tool_calls(&message, graph.metadata.prompt_template)?;
-
-                    let finish_reason = if parsed_result.tool_calls.is_empty() {
-                        FinishReason::stop
-                    } else {
-                        FinishReason::tool_calls
-                    };
-
-                    let content = match parsed_result.content {
-                        Some(content) => Some(content),
-                        None => Some(parsed_result.raw),
-                    };
-
-                    // create ChatCompletionResponse
-                    Ok(ChatCompletionObject {
-                        id: id.into(),
-                        object: String::from("chat.completion"),
-                        created: created.as_secs(),
-                        model: graph.name().to_owned(),
-                        choices: vec![ChatCompletionObjectChoice {
-                            index: 0,
-                            message: ChatCompletionObjectMessage {
-                                role: ChatCompletionRole::Assistant,
-                                content,
-                                tool_calls: parsed_result.tool_calls,
-                                function_call: None,
-                            },
-                            finish_reason,
-                            logprobs: None,
-                        }],
-                        usage: Usage {
-                            prompt_tokens: token_info.prompt_tokens,
-                            completion_tokens: token_info.completion_tokens,
-                            total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
-                        },
-                    })
-                }
-                false => {
-                    // create ChatCompletionResponse
-                    Ok(ChatCompletionObject {
-                        id: id.into(),
-                        object: String::from("chat.completion"),
-                        created: created.as_secs(),
-                        model: graph.name().to_owned(),
-                        choices: vec![ChatCompletionObjectChoice {
-                            index: 0,
-                            message: ChatCompletionObjectMessage {
-                                role: ChatCompletionRole::Assistant,
-                                content: Some(message),
-                                tool_calls: vec![],
-                                function_call: None,
-                            },
-                            finish_reason: FinishReason::stop,
-                            logprobs: None,
-                        }],
-                        usage: Usage {
-                            prompt_tokens: token_info.prompt_tokens,
-                            completion_tokens: token_info.completion_tokens,
-                            total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
-                        },
-                    })
-                }
-            }
-        }
-        Err(wasmedge_wasi_nn::Error::BackendError(wasmedge_wasi_nn::BackendError::ContextFull)) => {
-            // Retrieve the output.
-            let output_buffer = get_output_buffer(graph, OUTPUT_TENSOR)?;
-            let output = std::str::from_utf8(&output_buffer[..]).map_err(|e| {
-                let err_msg = format!(
-                    "Failed to decode the buffer of the inference result to a utf-8 string. {}",
-                    e
-                );
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                LlamaCoreError::Operation(err_msg)
-            })?;
-
-            // post-process
-            let message = post_process(output, &graph.metadata.prompt_template).map_err(|e| {
-                let err_msg = format!("Failed to post-process the output. {}", e);
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                LlamaCoreError::Operation(err_msg)
-            })?;
-
-            // retrieve the number of prompt and completion tokens
-            let token_info = get_token_info_by_graph(graph)?;
-
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "prompt tokens: {}, completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
-
-            let created = SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| {
-                    let err_msg = format!("Failed to get the current time. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-            // create ChatCompletionResponse
-            Ok(ChatCompletionObject {
-                id: id.into(),
-                object: String::from("chat.completion"),
-                created: created.as_secs(),
-                model: graph.name().to_owned(),
-                choices: vec![ChatCompletionObjectChoice {
-                    index: 0,
-                    message: ChatCompletionObjectMessage {
-                        role: ChatCompletionRole::Assistant,
-                        content: Some(message),
-                        tool_calls: vec![],
-                        function_call: None,
-                    },
-                    finish_reason: FinishReason::length,
-                    logprobs: None,
-                }],
-                usage: Usage {
-                    prompt_tokens: token_info.prompt_tokens,
-                    completion_tokens: token_info.completion_tokens,
-                    total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
-                },
-            })
-        }
-        Err(wasmedge_wasi_nn::Error::BackendError(
-            wasmedge_wasi_nn::BackendError::PromptTooLong,
-        )) => {
-            #[cfg(feature = "logging")]
-            warn!(target: "stdout", "The prompt is too long. Please reduce the length of your input and try again.");
-
-            // Retrieve the output.
-            let output_buffer = get_output_buffer(graph, OUTPUT_TENSOR)?;
-            let output = std::str::from_utf8(&output_buffer[..]).map_err(|e| {
-                let err_msg = format!(
-                    "Failed to decode the buffer of the inference result to a utf-8 string. {}",
-                    e
-                );
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                LlamaCoreError::Operation(err_msg)
-            })?;
-
-            // post-process
-            let message = post_process(output, &graph.metadata.prompt_template).map_err(|e| {
-                let err_msg = format!("Failed to post-process the output. {}", e);
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                LlamaCoreError::Operation(err_msg)
-            })?;
-
-            // retrieve the number of prompt and completion token
-            let token_info = get_token_info_by_graph(graph)?;
-
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "prompt tokens: {}, completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
-
-            let created = SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| {
-                    let err_msg = format!("Failed to get the current time. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    LlamaCoreError::Operation(err_msg)
-                })?;
-
-            // create ChatCompletionResponse
-            Ok(ChatCompletionObject {
-                id: id.into(),
-                object: String::from("chat.completion"),
-                created: created.as_secs(),
-                model: graph.name().to_owned(),
-                choices: vec![ChatCompletionObjectChoice {
-                    index: 0,
-                    message: ChatCompletionObjectMessage {
-                        role: ChatCompletionRole::Assistant,
-                        content: Some(message),
-                        tool_calls: vec![],
-                        function_call: None,
-                    },
-                    finish_reason: FinishReason::length,
-                    logprobs: None,
-                }],
-                usage: Usage {
-                    prompt_tokens: token_info.prompt_tokens,
-                    completion_tokens: token_info.completion_tokens,
-                    total_tokens: token_info.completion_tokens + token_info.completion_tokens,
-                },
-            })
-        }
-        Err(e) => {
-            let err_msg = format!("Failed to compute the chat completion. Reason: {}", e);
-
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", &err_msg);
-
-            Err(LlamaCoreError::Backend(BackendError::Compute(err_msg)))
-        }
-    }
-}
-
-fn parse_tool_calls(
-    input: &str,
-    prompt_template: PromptTemplateType,
-) -> Result<ParseResult, LlamaCoreError> {
-    match prompt_template {
-        PromptTemplateType::MistralTool => match regex::Regex::new(r"\[\{.*?\}\]") {
-            Ok(re) => {
-                let mut values: Vec<serde_json::Value> = vec![];
-                for cap in re.captures_iter(input) {
-                    let matched = &cap[0];
-
-                    #[cfg(feature = "logging")]
-                    info!(target: "stdout", "captured: {}", matched);
-
-                    match serde_json::from_str::<Vec<serde_json::Value>>(matched) {
-                        Ok(group) => values.extend(group),
-                        Err(e) => {
-                            let err_msg = format!(
-                                "Failed to deserialize generated tool calls. Reason: {}",
-                                e
-                            );
-
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
-
-                            return Err(LlamaCoreError::Operation(err_msg));
-                        }
-                    }
-                }
-
-                let mut tool_calls: Vec<ToolCall> = vec![];
-                for value in values.iter() {
-                    let name = match value.get("name") {
-                        Some(name) => name.to_string().replace("\"", ""),
-                        None => {
-                            let err_msg = format!(
-                                "Failed to get the name of the function. Tool call: {:?}",
-                                value
-                            );
-
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
-
-                            return Err(LlamaCoreError::Operation(err_msg));
-                        }
-                    };
-
-                    let arguments = match value.get("arguments") {
-                        Some(arguments) => arguments.to_string(),
-                        None => {
-                            let err_msg = format!(
-                                "Failed to get the arguments of the function. Tool call: {:?}",
-                                value
-                            );
-
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
-
-                            return Err(LlamaCoreError::Operation(err_msg));
-                        }
-                    };
-
-                    let function = Function { name, arguments };
-
-                    let tool_call = ToolCall {
-                        id: "call_abc123".to_string(),
-                        ty: "function".to_string(),
-                        function,
-                    };
-
-                    tool_calls.push(tool_call);
-                }
-
-                let parsed = ParseResult {
-                    raw: input.to_owned(),
-                    content: None,
-                    tool_calls,
-                };
-
-                #[cfg(feature = "logging")]
-                info!(target: "stdout", "parsed result: {:?}", parsed);
-
-                Ok(parsed)
-            }
-            Err(e) => {
-                let err_msg = format!("Failed to create a regex pattern. Reason: {}", e);
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                Err(LlamaCoreError::Operation(err_msg))
-            }
-        },
-        PromptTemplateType::ChatMLTool => {
-            match regex::Regex::new(r"<tool_call>(.*?)</tool_call>") {
-                Ok(re) => {
-                    let mut values: Vec<serde_json::Value> = vec![];
-                    for cap in re.captures_iter(input) {
-                        let matched = cap[1].replace("\\n", ""); // Remove "\\n" from the captured group
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "captured: {}", &matched);
-
-                        match serde_json::from_str::<serde_json::Value>(&matched) {
-                            Ok(value) => values.push(value),
-                            Err(e) => {
-                                let err_msg = format!(
-                                    "Failed to deserialize generated tool calls. Reason: {}",
-                                    e
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        }
-                    }
-
-                    let mut tool_calls: Vec<ToolCall> = vec![];
-                    for value in values.iter() {
-                        let name = match value.get("name") {
-                            Some(name) => name.to_string().replace("\"", ""),
-                            None => {
-                                let err_msg = format!(
-                                    "Failed to get the name of the function. Tool call: {:?}",
-                                    value
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        };
-
-                        let arguments = match value.get("arguments") {
-                            Some(arguments) => arguments.to_string(),
-                            None => {
-                                let err_msg = format!(
-                                    "Failed to get the arguments of the function. Tool call: {:?}",
-                                    value
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        };
-
-                        let function = Function { name, arguments };
-
-                        let tool_call = ToolCall {
-                            id: "call_abc123".to_string(),
-                            ty: "function".to_string(),
-                            function,
-                        };
-
-                        tool_calls.push(tool_call);
-                    }
-
-                    let parsed = ParseResult {
-                        raw: input.to_owned(),
-                        content: None,
-                        tool_calls,
-                    };
-
-                    #[cfg(feature = "logging")]
-                    info!(target: "stdout", "parsed result: {:?}", parsed);
-
-                    Ok(parsed)
-                }
-                Err(e) => {
-                    let err_msg = format!("Failed to create a regex pattern. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    Err(LlamaCoreError::Operation(err_msg))
-                }
-            }
-        }
-        PromptTemplateType::GroqLlama3Tool => {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw input: {}", input);
-
-            match regex::Regex::new(r"(?s)<tool_call>((.|\r|\n)*?)</tool_call>") {
-                Ok(re) => {
-                    let mut values: Vec<serde_json::Value> = vec![];
-                    for cap in re.captures_iter(input) {
-                        let matched = cap[1].trim();
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "captured: {}", matched);
-
-                        match serde_json::from_str::<serde_json::Value>(matched) {
-                            Ok(value) => values.push(value),
-                            Err(e) => {
-                                let err_msg = format!(
-                                    "Failed to deserialize generated tool calls. Reason: {}",
-                                    e
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        }
-                    }
-
-                    let mut tool_calls: Vec<ToolCall> = vec![];
-                    for value in values.iter() {
-                        let name = match value.get("name") {
-                            Some(name) => name.to_string().replace("\"", ""),
-                            None => {
-                                let err_msg = format!(
-                                    "Failed to get the name of the function. Tool call: {:?}",
-                                    value
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        };
-
-                        let arguments = match value.get("arguments") {
-                            Some(arguments) => arguments.to_string(),
-                            None => {
-                                let err_msg = format!(
-                                    "Failed to get the arguments of the function. Tool call: {:?}",
-                                    value
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        };
-
-                        let function = Function { name, arguments };
-
-                        let tool_call = ToolCall {
-                            id: "call_abc123".to_string(),
-                            ty: "function".to_string(),
-                            function,
-                        };
-
-                        tool_calls.push(tool_call);
-                    }
-
-                    let parsed = ParseResult {
-                        raw: input.to_owned(),
-                        content: None,
-                        tool_calls,
-                    };
-
-                    #[cfg(feature = "logging")]
-                    info!(target: "stdout", "parsed result: {:?}", parsed);
-
-                    Ok(parsed)
-                }
-                Err(e) => {
-                    let err_msg = format!("Failed to create a regex pattern. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    Err(LlamaCoreError::Operation(err_msg))
-                }
-            }
-        }
-        PromptTemplateType::Llama3Tool => {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw input: {}", input);
-
-            let re = match regex::Regex::new(r"^\{(.|\r|\n)*\}$") {
-                Ok(re) => re,
-                Err(e) => {
-                    let err_msg = format!("Failed to create a regex pattern. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    return Err(LlamaCoreError::Operation(err_msg));
-                }
-            };
-
-            if re.is_match(input) {
-                match serde_json::from_str::<serde_json::Value>(input) {
-                    Ok(value) => {
-                        let values: Vec<serde_json::Value> = vec![value];
-
-                        let mut tool_calls: Vec<ToolCall> = vec![];
-                        for value in values.iter() {
-                            let name = match value.get("name") {
-                                Some(name) => name.to_string().replace("\"", ""),
-                                None => {
-                                    let err_msg = format!(
-                                        "Failed to get the name of the function. Tool call: {:?}",
-                                        value
-                                    );
-
-                                    #[cfg(feature = "logging")]
-                                    error!(target: "stdout", "{}", &err_msg);
-
-                                    return Err(LlamaCoreError::Operation(err_msg));
-                                }
-                            };
-
-                            let arguments = match value.get("parameters") {
-                                Some(arguments) => arguments.to_string(),
-                                None => {
-                                    let err_msg = format!(
-                                        "Failed to get the arguments of the function. Tool call: {:?}",
-                                        value
-                                    );
-
-                                    #[cfg(feature = "logging")]
-                                    error!(target: "stdout", "{}", &err_msg);
-
-                                    return Err(LlamaCoreError::Operation(err_msg));
-                                }
-                            };
-
-                            let function = Function { name, arguments };
-
-                            let tool_call = ToolCall {
-                                id: "call_abc123".to_string(),
-                                ty: "function".to_string(),
-                                function,
-                            };
-
-                            tool_calls.push(tool_call);
-                        }
-
-                        let parsed = ParseResult {
-                            raw: input.to_owned(),
-                            content: None,
-                            tool_calls,
-                        };
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "parsed result: {:?}", parsed);
-
-                        Ok(parsed)
-                    }
-                    Err(e) => {
-                        let err_msg =
-                            format!("Failed to deserialize generated tool calls. Reason: {}", e);
-
-                        #[cfg(feature = "logging")]
-                        error!(target: "stdout", "{}", &err_msg);
-
-                        Err(LlamaCoreError::Operation(err_msg))
-                    }
-                }
-            } else {
-                let parsed = ParseResult {
-                    raw: input.to_owned(),
-                    content: None,
-                    tool_calls: vec![],
-                };
-
-                #[cfg(feature = "logging")]
-                info!(target: "stdout", "parsed result: {:?}", parsed);
-
-                Ok(parsed)
-            }
-        }
-        PromptTemplateType::InternLM2Tool => {
             #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw input: {}", input);
-
-            let blocks: Vec<&str> = input.trim().split("<|action_start|><|plugin|>").collect();
-
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "blocks: {:?}", blocks);
-
-            let mut tool_calls: Vec<ToolCall> = vec![];
-            let mut content = String::new();
-            for block in blocks {
-                let block = block.trim();
-                if !block.is_empty() {
-                    if block.ends_with("<|action_end|>") {
-                        let value = block.trim().trim_end_matches("<|action_end|>");
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "tool call: {}", value);
-
-                        match serde_json::from_str::<serde_json::Value>(value) {
-                            Ok(value) => {
-                                let name = match value.get("name") {
-                                    Some(name) => name.to_string().replace("\"", ""),
-                                    None => {
-                                        let err_msg = format!(
-                                            "Failed to get the name of the function. Tool call: {:?}",
-                                            value
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        return Err(LlamaCoreError::Operation(err_msg));
-                                    }
-                                };
-
-                                let arguments = match value.get("parameters") {
-                                    Some(arguments) => arguments.to_string(),
-                                    None => {
-                                        let err_msg = format!(
-                                            "Failed to get the arguments of the function. Tool call: {:?}",
-                                            value
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        return Err(LlamaCoreError::Operation(err_msg));
-                                    }
-                                };
-
-                                let function = Function { name, arguments };
-
-                                let tool_call = ToolCall {
-                                    id: "call_abc123".to_string(),
-                                    ty: "function".to_string(),
-                                    function,
-                                };
-
-                                tool_calls.push(tool_call);
-                            }
-                            Err(e) => {
-                                let err_msg = format!(
-                                    "Failed to deserialize generated tool calls. Reason: {}",
-                                    e
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
+            error!(target: "stdout", "{}", &err_msg);
 
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        }
-                    } else {
-                        content.push_str(block);
-                        content.push('\n');
-                    }
-                }
-            }
+            #[cfg(not(feature = "logging"))]
+            println!("[ERROR][llama_core] {}", &err_msg);
+        }
+    }
+}
 
-            let parsed = match content.is_empty() {
-                true => ParseResult {
-                    raw: input.to_owned(),
-                    content: None,
-                    tool_calls,
-                },
-                false => ParseResult {
-                    raw: input.to_owned(),
-                    content: Some(content.trim().to_owned()),
-                    tool_calls,
-                },
-            };
+/// Cleans up the context of the model after the token-by-token generation when dropped.
+struct SingleContextGuard {
+    model_name: Option<String>,
+}
+impl Drop for SingleContextGuard {
+    fn drop(&mut self) {
+        if let Err(e) = with_chat_graph(self.model_name.as_ref(), |graph| {
+            graph
+                .finish_single()
+                .map_err(|e| LlamaCoreError::Backend(BackendError::FinishSingle(e.to_string())))
+        }) {
+            let err_msg = format!("Failed to clean up the context. Reason: {}", e);
 
             #[cfg(feature = "logging")]
-            info!(target: "stdout", "parsed result: {:?}", parsed);
+            error!(target: "stdout", "{}", &err_msg);
 
-            Ok(parsed)
+            #[cfg(not(feature = "logging"))]
+            println!("[ERROR][llama_core] {}", &err_msg);
         }
-        PromptTemplateType::NemotronTool => {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw input: {}", input);
-
-            match regex::Regex::new(r"(?s)<toolcall>\s*(.*?)\s*</toolcall>") {
-                Ok(re) => {
-                    let mut values: Vec<serde_json::Value> = vec![];
-                    for cap in re.captures_iter(input) {
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "captured: {}", &cap[0]);
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "extracted: {}", &cap[1]);
-
-                        let matched = cap[1].trim();
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "captured: {}", matched);
-
-                        match serde_json::from_str::<serde_json::Value>(matched) {
-                            Ok(value) => values.push(value),
-                            Err(e) => {
-                                let err_msg = format!(
-                                    "Failed to deserialize generated tool calls. Reason: {}",
-                                    e
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        }
-                    }
-
-                    let mut tool_calls: Vec<ToolCall> = vec![];
-                    for value in values.iter() {
-                        let name = match value.get("name") {
-                            Some(name) => name.to_string().replace("\"", ""),
-                            None => {
-                                let err_msg = format!(
-                                    "Failed to get the name of the function. Tool call: {:?}",
-                                    value
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        };
-
-                        let arguments = match value.get("arguments") {
-                            Some(arguments) => arguments.to_string(),
-                            None => {
-                                let err_msg = format!(
-                                    "Failed to get the arguments of the function. Tool call: {:?}",
-                                    value
-                                );
-
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
-
-                                return Err(LlamaCoreError::Operation(err_msg));
-                            }
-                        };
-
-                        let function = Function { name, arguments };
-
-                        let tool_call = ToolCall {
-                            id: "call_abc123".to_string(),
-                            ty: "function".to_string(),
-                            function,
-                        };
-
-                        tool_calls.push(tool_call);
-                    }
-
-                    let parsed = ParseResult {
-                        raw: input.to_owned(),
-                        content: None,
-                        tool_calls,
-                    };
-
-                    #[cfg(feature = "logging")]
-                    info!(target: "stdout", "parsed result: {:?}", parsed);
-
-                    Ok(parsed)
-                }
-                Err(e) => {
-                    let err_msg = format!("Failed to create a regex pattern. Reason: {}", e);
+    }
+}
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+/// Runs the function with the chat model resolved from the given name by [`models::resolve_model_name`].
+pub(crate) fn with_chat_graph<T>(
+    model_name: Option<&String>,
+    f: impl FnOnce(&mut Graph<GgmlMetadata>) -> Result<T, LlamaCoreError>,
+) -> Result<T, LlamaCoreError> {
+    let chat_graphs = match CHAT_GRAPHS.get() {
+        Some(chat_graphs) => chat_graphs,
+        None => {
+            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
 
-                    Err(LlamaCoreError::Operation(err_msg))
-                }
-            }
-        }
-        PromptTemplateType::FunctionaryV32 => {
             #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw input: {}", input);
-
-            match regex::Regex::new(r">>>\s*(\w+)\s*\{(.*)\}<\|eot_id\|>") {
-                Ok(re) => {
-                    let mut tool_calls: Vec<ToolCall> = vec![];
-                    for cap in re.captures_iter(input) {
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "func_name: {}", &cap[1]);
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "arguments: {}", &cap[2]);
-
-                        let tool_call = ToolCall {
-                            id: "call_abc123".to_string(),
-                            ty: "function".to_string(),
-                            function: Function {
-                                name: cap[1].to_string(),
-                                arguments: cap[2].to_string(),
-                            },
-                        };
-
-                        tool_calls.push(tool_call);
-                    }
-
-                    let parsed = ParseResult {
-                        raw: input.to_owned(),
-                        content: None,
-                        tool_calls,
-                    };
-
-                    #[cfg(feature = "logging")]
-                    info!(target: "stdout", "parsed result: {:?}", parsed);
-
-                    Ok(parsed)
-                }
-                Err(e) => {
-                    let warn_msg = format!("Failed to create a regex pattern. Reason: {}", e);
-
-                    #[cfg(feature = "logging")]
-                    warn!(target: "stdout", "{}", &warn_msg);
+            error!(target: "stdout", "{}", &err_msg);
 
-                    Ok(ParseResult {
-                        raw: input.to_owned(),
-                        content: None,
-                        tool_calls: vec![],
-                    })
-                }
-            }
+            return Err(LlamaCoreError::Operation(err_msg.into()));
         }
-        PromptTemplateType::FunctionaryV31 => {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "raw input: {}", input);
-
-            match regex::Regex::new(r"<function=(\w+)>\s*(\{.*?\})</function>") {
-                Ok(re) => {
-                    let mut tool_calls: Vec<ToolCall> = vec![];
-                    for cap in re.captures_iter(input) {
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "func_name: {}", &cap[1]);
-
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "arguments: {}", &cap[2]);
-
-                        let tool_call = ToolCall {
-                            id: "call_abc123".to_string(),
-                            ty: "function".to_string(),
-                            function: Function {
-                                name: cap[1].to_string(),
-                                arguments: cap[2].to_string(),
-                            },
-                        };
-
-                        tool_calls.push(tool_call);
-                    }
-
-                    let parsed = ParseResult {
-                        raw: input.to_owned(),
-                        content: None,
-                        tool_calls,
-                    };
-
-                    #[cfg(feature = "logging")]
-                    info!(target: "stdout", "parsed result: {:?}", parsed);
+    };
 
-                    Ok(parsed)
-                }
-                Err(e) => {
-                    let warn_msg = format!("Failed to create a regex pattern. Reason: {}", e);
+    let mut chat_graphs = chat_graphs.lock().map_err(|e| {
+        let err_msg = format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
 
-                    #[cfg(feature = "logging")]
-                    warn!(target: "stdout", "{}", &warn_msg);
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
 
-                    Ok(ParseResult {
-                        raw: input.to_owned(),
-                        content: None,
-                        tool_calls: vec![],
-                    })
-                }
-            }
-        }
-        _ => {
-            let err_msg = format!(
-                "The tool use is only supported for prompt templates: {}, {}, {}, {}, {}, {}, and {}.",
-                PromptTemplateType::MistralTool,
-                PromptTemplateType::ChatMLTool,
-                PromptTemplateType::GroqLlama3Tool,
-                PromptTemplateType::Llama3Tool,
-                PromptTemplateType::InternLM2Tool,
-                PromptTemplateType::NemotronTool,
-                PromptTemplateType::FunctionaryV32,
-            );
+        LlamaCoreError::Operation(err_msg)
+    })?;
 
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", &err_msg);
+    let graph = models::get_graph_mut(&mut chat_graphs, model_name.map(String::as_str))?;
 
-            Err(LlamaCoreError::Operation(err_msg))
-        }
-    }
+    f(graph)
 }
 
 async fn check_model_metadata(
@@ -1721,28 +730,12 @@ async fn check_model_metadata(
     let mut should_update = false;
     let mut metadata = get_model_metadata(chat_request.model.as_ref())?;
 
-    // check if necessary to update `image`
-    if let Some(ChatCompletionRequestMessage::User(user_message)) = chat_request.messages.last() {
-        if let ChatCompletionUserMessageContent::Parts(parts) = user_message.content() {
-            for part in parts {
-                if let ContentPart::Image(image) = part {
-                    let image = image.image();
-
-                    if image.is_url() {
-                        // update metadata image
-                        let img = download_image(&image.url).await?;
-
-                        metadata.image = Some(img);
+    // the images are embedded in the prompt, so no image file is passed to the backend
+    if metadata.image.is_some() {
+        metadata.image = None;
 
-                        if !should_update {
-                            should_update = true;
-                        }
-
-                        // todo: now only support a single image
-                        break;
-                    }
-                }
-            }
+        if !should_update {
+            should_update = true;
         }
     }
 
@@ -1794,57 +787,78 @@ async fn check_model_metadata(
         }
     }
 
-    // check if the `embedding` option is disabled
-    if metadata.embeddings {
-        metadata.embeddings = false;
+    // check if necessary to update the grammar with the response format
+    if let Some(response_format) = &chat_request.response_format {
+        if let Some(grammar) = response_format_to_grammar(response_format)? {
+            #[cfg(feature = "logging")]
+            info!(target: "stdout", "Constrain the output with the `{}` response format.", &response_format.ty);
 
-        if !should_update {
-            should_update = true;
-        }
-    }
+            // the grammar takes effect only if no json schema is set
+            metadata.grammar = grammar;
+            metadata.json_schema = None;
 
-    if should_update {
-        // update the target graph with the new metadata
-        update_model_metadata(chat_request.model.as_ref(), &metadata)?;
+            if !should_update {
+                should_update = true;
+            }
+        }
     }
 
-    Ok(metadata)
-}
-
-async fn update_n_predict(
-    chat_request: &ChatCompletionRequest,
-    metadata: &mut GgmlMetadata,
-    available_completion_tokens: u64,
-) -> Result<(), LlamaCoreError> {
-    let mut should_update = false;
+    // check if necessary to output the log probabilities
+    match chat_request.logprobs {
+        Some(true) => {
+            let top_logprobs = chat_request.top_logprobs.unwrap_or_default();
+            if top_logprobs > 20 {
+                let err_msg = format!(
+                    "Invalid `top_logprobs`: {}. It must be an integer between 0 and 20.",
+                    top_logprobs
+                );
 
-    // check if necessary to update n_predict with max_tokens
-    if let Some(max_tokens) = chat_request.max_tokens {
-        #[cfg(feature = "logging")]
-        info!(target: "stdout", "available_completion_tokens: {}, max_tokens from request: {}, n_predict: {}", available_completion_tokens, max_tokens, metadata.n_predict);
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
 
-        let max_completion_tokens = match available_completion_tokens < max_tokens {
-            true => available_completion_tokens,
-            false => max_tokens,
-        };
+                return Err(LlamaCoreError::invalid_request(
+                    err_msg,
+                    Some("top_logprobs"),
+                ));
+            }
 
-        // update n_predict
-        if metadata.n_predict != max_completion_tokens {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "update n_predict from {} to {}", metadata.n_predict, max_completion_tokens);
+            with_chat_graph(chat_request.model.as_ref(), |graph| {
+                check_logprobs_support(graph, "logprobs")
+            })?;
 
-            metadata.n_predict = max_completion_tokens;
+            metadata.n_probs = Some(top_logprobs as u64);
 
             if !should_update {
                 should_update = true;
             }
         }
-        if metadata.n_predict < available_completion_tokens {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "Update n_predict from {} to {}", metadata.n_predict, available_completion_tokens);
+        _ => {
+            if chat_request.top_logprobs.is_some() {
+                let err_msg = "`logprobs` must be set to `true` if `top_logprobs` is used.";
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", err_msg);
+
+                return Err(LlamaCoreError::invalid_request(err_msg, Some("logprobs")));
+            }
+        }
+    }
+
+    // check if necessary to update logit_bias
+    if let Some(logit_bias) = &chat_request.logit_bias {
+        metadata.logit_bias = Some(with_chat_graph(chat_request.model.as_ref(), |graph| {
+            parse_logit_bias(graph, logit_bias)
+        })?);
+
+        if !should_update {
+            should_update = true;
+        }
+    }
 
-            // update n_predict
-            metadata.n_predict = available_completion_tokens;
+    // check if necessary to update the sampling seed
+    if let Some(seed) = chat_request.seed {
+        if metadata.seed != Some(seed) {
+            metadata.seed = Some(seed);
 
             if !should_update {
                 should_update = true;
@@ -1852,179 +866,72 @@ async fn update_n_predict(
         }
     }
 
+    // check if the `embedding` option is disabled
+    if metadata.embeddings {
+        metadata.embeddings = false;
+
+        if !should_update {
+            should_update = true;
+        }
+    }
+
     if should_update {
         // update the target graph with the new metadata
-        update_model_metadata(chat_request.model.as_ref(), metadata)?;
+        update_model_metadata(chat_request.model.as_ref(), &metadata)?;
     }
 
-    Ok(())
+    Ok(metadata)
 }
 
-fn post_process(
-    output: impl AsRef<str>,
-    template_ty: &PromptTemplateType,
-) -> Result<String, String> {
+async fn update_n_predict(
+    chat_request: &ChatCompletionRequest,
+    metadata: &mut GgmlMetadata,
+    available_completion_tokens: u64,
+) -> Result<(), LlamaCoreError> {
     #[cfg(feature = "logging")]
-    info!(target: "stdout", "Post-process the generated output.");
+    info!(target: "stdout", "available_completion_tokens: {}, max_tokens from request: {:?}, n_predict: {}", available_completion_tokens, chat_request.max_tokens, metadata.n_predict);
 
-    let output = if *template_ty == PromptTemplateType::Baichuan2 {
-        if output.as_ref().contains("用户:") {
-            output.as_ref().trim_end_matches("用户:").trim().to_owned()
-        } else {
-            output.as_ref().trim().to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::OpenChat {
-        if output.as_ref().contains("<|end_of_turn|>") {
-            output
-                .as_ref()
-                .trim_end_matches("<|end_of_turn|>")
-                .trim()
-                .to_owned()
-        } else {
-            output.as_ref().trim().to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::GemmaInstruct {
-        let s = output.as_ref().trim();
-        if s.ends_with("<end_of_turn>") {
-            s.trim_end_matches("<end_of_turn>").trim().to_owned()
-        } else {
-            s.to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::ChatML
-        || *template_ty == PromptTemplateType::ChatMLTool
-        || *template_ty == PromptTemplateType::InternLM2Tool
-        || *template_ty == PromptTemplateType::MiniCPMV
-    {
-        if output.as_ref().contains("<|im_start|>") && output.as_ref().contains("<|im_end|>") {
-            let idx_start = output.as_ref().find("<|im_start|>").unwrap();
-            let idx_end = output.as_ref().find("<|im_end|>").unwrap();
-
-            match idx_start <= idx_end {
-                true => output.as_ref().split("<|im_start|>").collect::<Vec<_>>()[0]
-                    .trim()
-                    .to_owned(),
-                false => output.as_ref().split("<|im_end|>").collect::<Vec<_>>()[0]
-                    .trim()
-                    .to_owned(),
-            }
-        } else if output.as_ref().contains("<|im_start|>") {
-            output.as_ref().split("<|im_start|>").collect::<Vec<_>>()[0]
-                .trim()
-                .to_owned()
-        } else if output.as_ref().contains("<|im_end|>") {
-            let output = output.as_ref().trim_end_matches("<|im_end|>").trim();
-            if output.starts_with(": ") {
-                output.trim_start_matches(": ").to_owned()
-            } else {
-                output.to_owned()
-            }
-        } else {
-            output.as_ref().trim().to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::Zephyr
-        || *template_ty == PromptTemplateType::MistralLite
-        || *template_ty == PromptTemplateType::MistralTool
-        || *template_ty == PromptTemplateType::MistralInstruct
-        || *template_ty == PromptTemplateType::BreezeInstruct
-    {
-        if output.as_ref().contains("</s><") {
-            output.as_ref().trim_end_matches("</s><").trim().to_owned()
-        } else if output.as_ref().contains("</s>") {
-            output
-                .as_ref()
-                .strip_suffix("</s>")
-                .unwrap()
-                .trim()
-                .to_owned()
-        } else {
-            output.as_ref().trim().to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::DeepseekChat {
-        if output.as_ref().contains("<|end_of_sentence|>") {
-            output
-                .as_ref()
-                .trim_end_matches("<|end_of_sentence|>")
-                .trim()
-                .replace("<|end_of_sentence|>", " ")
-                .trim()
-                .to_owned()
-        } else {
-            output.as_ref().trim().to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::HumanAssistant {
-        if output.as_ref().contains("Human:") {
-            output.as_ref().trim_end_matches("Human:").trim().to_owned()
-        } else {
-            output.as_ref().trim().to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::SolarInstruct {
-        let s = output.as_ref().trim();
+    let n_predict = completion_n_predict(
+        chat_request,
+        metadata.n_predict,
+        available_completion_tokens,
+    );
 
-        if s.starts_with("### Answer") {
-            let s = s.trim_start_matches("###").trim();
+    if metadata.n_predict != n_predict {
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Update n_predict from {} to {}", metadata.n_predict, n_predict);
 
-            if s.starts_with("Answer:\n") {
-                s.replace("Answer:\n", "Answer: ")
-            } else {
-                s.to_owned()
-            }
-        } else {
-            s.to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::Llama2Chat {
-        let s = output.as_ref().trim();
-        if s.ends_with("</s>") {
-            s.trim_end_matches("</s>").trim().to_owned()
-        } else {
-            s.to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::Llama3Chat
-        || *template_ty == PromptTemplateType::GroqLlama3Tool
-        || *template_ty == PromptTemplateType::Llama3Tool
-        || *template_ty == PromptTemplateType::FunctionaryV32
-    {
-        let s = output.as_ref().trim();
-        if s.ends_with("<|eot_id|>") {
-            s.trim_end_matches("<|eot_id|>").trim().to_owned()
-        } else {
-            s.to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::Phi3Chat {
-        let s = output.as_ref().trim();
-        if s.ends_with("<|end|>") {
-            s.trim_end_matches("<|end|>").trim().to_owned()
-        } else {
-            s.to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::NemotronTool
-        || *template_ty == PromptTemplateType::NemotronChat
-    {
-        let s = output.as_ref().trim();
-        if s.ends_with("</s>") {
-            s.trim_end_matches("</s>").trim().to_owned()
-        } else {
-            s.to_owned()
-        }
-    } else if *template_ty == PromptTemplateType::FunctionaryV31 {
-        let mut s = output.as_ref().trim();
-        if s.ends_with("<|eot_id|>") {
-            s = s.trim_end_matches("<|eot_id|>").trim();
-        }
-        if s.ends_with("<|eom_id|>") {
-            s = s.trim_end_matches("<|eom_id|>").trim();
-        }
-        s.to_owned()
-    } else {
-        output.as_ref().trim().to_owned()
-    };
+        metadata.n_predict = n_predict;
+
+        // update the target graph with the new metadata
+        update_model_metadata(chat_request.model.as_ref(), metadata)?;
+    }
+
+    Ok(())
+}
 
-    Ok(output)
+/// Returns the `n_predict` of the model for the request, given the `n_predict` of the model and the number of the tokens available for the completion.
+fn completion_n_predict(
+    chat_request: &ChatCompletionRequest,
+    n_predict: u64,
+    available_completion_tokens: u64,
+) -> u64 {
+    match chat_request.max_tokens {
+        // `max_tokens` is capped at the tokens available for the completion, and then raised back to them
+        Some(_) => available_completion_tokens,
+        None => n_predict,
+    }
 }
 
+/// Builds the prompt from the messages of the request, and truncates the chat history if the prompt exceeds the budget of prompt tokens. Returns the prompt, the number of tokens available for the completion, whether the request uses tools, and how the chat history was truncated if it was.
+///
+/// The `request_metadata` is sent to the model again after the dropped messages are summarized, and the summary stops once the `cancellation` token is cancelled.
 fn build_prompt(
     model_name: Option<&String>,
     chat_request: &mut ChatCompletionRequest,
-) -> Result<(String, u64, bool), LlamaCoreError> {
+    request_metadata: &GgmlMetadata,
+    cancellation: &CancellationToken,
+) -> Result<(String, u64, bool, Option<ContextTruncationReport>), LlamaCoreError> {
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Build the chat prompt from the chat messages.");
 
@@ -2032,10 +939,57 @@ fn build_prompt(
     let ctx_size = metadata.ctx_size as u64;
     let chat_prompt = ChatPrompt::from(metadata.prompt_template);
 
-    // compute max prompt tokens, which is 80% of the context size
-    let max_prompt_tokens = ctx_size * 4 / 5;
+    // the truncation settings of the request override the ones of the model
+    let truncation = match chat_request.context_truncation.clone() {
+        Some(mut truncation) => {
+            if truncation.prompt_ratio.is_none() {
+                truncation.prompt_ratio = metadata.context_truncation.prompt_ratio;
+            }
+            truncation
+        }
+        None => metadata.context_truncation.clone(),
+    };
+
+    // compute max prompt tokens, which is 80% of the context size by default
+    let prompt_ratio = truncation.prompt_ratio.unwrap_or(0.8);
+    if !(prompt_ratio > 0.0 && prompt_ratio <= 1.0) {
+        let err_msg = format!(
+            "Invalid prompt ratio: {}. The value should be greater than 0.0 and no more than 1.0.",
+            prompt_ratio
+        );
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        return Err(LlamaCoreError::invalid_request(
+            err_msg,
+            Some("context_truncation"),
+        ));
+    }
+    let max_prompt_tokens = (ctx_size as f64 * prompt_ratio) as u64;
+
+    if chat_request.messages.is_empty() {
+        let err_msg = "The messages in the chat request are empty.";
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", err_msg);
+
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
+    }
+
+    let mut history = ChatHistory::new(&chat_request.messages, &truncation.pinned_messages);
+    if truncation.strategy == TruncationStrategy::KeepLastTurns {
+        history.keep_last_turns(truncation.last_turns.unwrap_or(1));
+    }
+
+    // the summary is not generated if the output is constrained by a grammar
+    let mut summarized = truncation.strategy != TruncationStrategy::Summarize
+        || !metadata.grammar.is_empty()
+        || metadata.json_schema.is_some();
 
     loop {
+        chat_request.messages = history.messages();
+
         // ! DO NOT REMOVE
         // build prompt
         // let prompt = match chat_prompt.build(&mut chat_request.messages) {
@@ -2050,15 +1004,6 @@ fn build_prompt(
         //     }
         // };
 
-        if chat_request.messages.is_empty() {
-            let err_msg = "The messages in the chat request are empty.";
-
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", err_msg);
-
-            return Err(LlamaCoreError::Operation(err_msg.to_owned()));
-        }
-
         let (prompt, tool_use) = match chat_request.tool_choice.as_ref() {
             Some(tool_choice) => match tool_choice {
                 ToolChoice::None => {
@@ -2070,7 +1015,7 @@ fn build_prompt(
                             #[cfg(feature = "logging")]
                             error!(target: "stdout", "{}", &err_msg);
 
-                            return Err(LlamaCoreError::Operation(err_msg));
+                            return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
                         }
                     }
                 }
@@ -2085,7 +1030,7 @@ fn build_prompt(
                             #[cfg(feature = "logging")]
                             error!(target: "stdout", "{}", &err_msg);
 
-                            return Err(LlamaCoreError::Operation(err_msg));
+                            return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
                         }
                     },
                     None => {
@@ -2100,7 +1045,10 @@ fn build_prompt(
                                 #[cfg(feature = "logging")]
                                 error!(target: "stdout", "{}", &err_msg);
 
-                                return Err(LlamaCoreError::Operation(err_msg));
+                                return Err(LlamaCoreError::invalid_request(
+                                    err_msg,
+                                    Some("messages"),
+                                ));
                             }
                         }
                     }
@@ -2114,98 +1062,689 @@ fn build_prompt(
                     #[cfg(feature = "logging")]
                     error!(target: "stdout", "{}", &err_msg);
 
-                    return Err(LlamaCoreError::Operation(err_msg));
+                    return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
+                }
+            },
+        };
+
+        // set prompt
+        set_prompt(model_name, &prompt)?;
+
+        // Retrieve the number of prompt tokens.
+        let token_info = get_token_info_by_graph_name(model_name)?;
+
+        if token_info.prompt_tokens <= max_prompt_tokens {
+            // summarize the dropped messages once, and build the prompt again with the summary
+            if !summarized && history.has_dropped() {
+                summarized = true;
+
+                let max_chars = prompt.chars().count() * max_prompt_tokens as usize
+                    / 2
+                    / token_info.prompt_tokens.max(1) as usize;
+                let summary = summarize_messages(
+                    model_name,
+                    &chat_prompt,
+                    history.dropped(),
+                    max_chars,
+                    cancellation,
+                );
+
+                // send the metadata of the request again, as the summary is generated with the metadata of the model
+                update_model_metadata(model_name, request_metadata)?;
+
+                match summary {
+                    Ok(summary) => history.set_summary(summary),
+                    Err(_e) => {
+                        #[cfg(feature = "logging")]
+                        warn!(target: "stdout", "Fail to summarize the dropped messages, so they are dropped without a summary. Reason: {}", _e);
+                    }
+                }
+
+                continue;
+            }
+
+            return Ok((
+                prompt,
+                ctx_size - max_prompt_tokens,
+                tool_use,
+                history.report(truncation.strategy),
+            ));
+        }
+
+        let shrunk = match truncation.strategy {
+            TruncationStrategy::TruncateMiddle => {
+                let excess_chars = (token_info.prompt_tokens - max_prompt_tokens) as usize
+                    * prompt.chars().count()
+                    / token_info.prompt_tokens as usize;
+
+                // leave some margin as the number of tokens is estimated
+                history.truncate_middle(excess_chars + excess_chars / 10 + 1)
+                    || history.drop_oldest_turn()
+            }
+            _ => history.drop_oldest_turn(),
+        };
+
+        if !shrunk {
+            if token_info.prompt_tokens > ctx_size {
+                let err = LlamaCoreError::ContextLengthExceeded {
+                    prompt_tokens: token_info.prompt_tokens,
+                    max_tokens: ctx_size,
+                };
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err);
+
+                return Err(err);
+            }
+
+            return Ok((
+                prompt,
+                ctx_size - token_info.prompt_tokens,
+                tool_use,
+                history.report(truncation.strategy),
+            ));
+        }
+    }
+}
+
+/// Messages of a chat request being truncated to fit the prompt into the context.
+#[derive(Debug, Default)]
+struct ChatHistory {
+    /// Messages kept in the prompt, each with its index in the request
+    messages: Vec<(usize, ChatCompletionRequestMessage)>,
+    /// Messages dropped from the prompt, each with its index in the request
+    dropped: Vec<(usize, ChatCompletionRequestMessage)>,
+    /// Indices of the messages which are never dropped or truncated
+    pinned: Vec<usize>,
+    /// Indices of the messages whose contents are shortened
+    truncated: Vec<usize>,
+    /// Summary of the dropped messages
+    summary: Option<String>,
+}
+impl ChatHistory {
+    /// Characters kept at both ends of a message whose middle is truncated
+    const MIN_KEPT_CHARS: usize = 32;
+    /// Text which replaces the truncated middle of a message
+    const ELLIPSIS: &'static str = " ... ";
+
+    fn new(messages: &[ChatCompletionRequestMessage], pinned: &[usize]) -> Self {
+        Self {
+            messages: messages.iter().cloned().enumerate().collect(),
+            pinned: pinned.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the messages to build the prompt, with the summary of the dropped messages in the system message.
+    fn messages(&self) -> Vec<ChatCompletionRequestMessage> {
+        let mut messages: Vec<ChatCompletionRequestMessage> = self
+            .messages
+            .iter()
+            .map(|(_, message)| message.clone())
+            .collect();
+
+        if let Some(summary) = self.summary.as_ref() {
+            let note = format!("Summary of the earlier conversation:\n{}", summary);
+
+            match messages.first() {
+                Some(ChatCompletionRequestMessage::System(message)) => {
+                    messages[0] = ChatCompletionRequestMessage::new_system_message(
+                        format!("{}\n\n{}", message.content(), note),
+                        message.name().cloned(),
+                    );
+                }
+                _ => messages.insert(
+                    0,
+                    ChatCompletionRequestMessage::new_system_message(note, None),
+                ),
+            }
+        }
+
+        messages
+    }
+
+    /// Returns whether the message at the given position is never dropped, i.e. the system message at the beginning, the pinned messages, and the messages from the last user message on.
+    fn is_protected(&self, pos: usize) -> bool {
+        let last_turn = self
+            .messages
+            .iter()
+            .rposition(|(_, message)| message.role() == ChatCompletionRole::User)
+            .unwrap_or(self.messages.len().saturating_sub(1));
+
+        let (index, message) = &self.messages[pos];
+        (pos == 0 && message.role() == ChatCompletionRole::System)
+            || self.pinned.contains(index)
+            || pos >= last_turn
+    }
+
+    /// Drops the oldest turn which is not protected, i.e. a user message and the messages following it until the next user message. Returns false if there is nothing to drop.
+    fn drop_oldest_turn(&mut self) -> bool {
+        let mut positions = vec![];
+        for pos in 0..self.messages.len() {
+            let is_user = self.messages[pos].1.role() == ChatCompletionRole::User;
+            if !positions.is_empty() && is_user {
+                break;
+            }
+            if !self.is_protected(pos) {
+                positions.push(pos);
+            }
+        }
+
+        self.drop_at(&positions)
+    }
+
+    /// Drops the messages which are not protected, except the last `turns` turns. Returns false if there is nothing to drop.
+    fn keep_last_turns(&mut self, turns: usize) -> bool {
+        let mut users = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, message))| message.role() == ChatCompletionRole::User)
+            .map(|(pos, _)| pos)
+            .rev();
+
+        let start = match turns {
+            0 => self.messages.len(),
+            _ => match users.nth(turns - 1) {
+                Some(pos) => pos,
+                None => return false,
+            },
+        };
+
+        let positions: Vec<usize> = (0..start).filter(|pos| !self.is_protected(*pos)).collect();
+        self.drop_at(&positions)
+    }
+
+    fn drop_at(&mut self, positions: &[usize]) -> bool {
+        for pos in positions.iter().rev() {
+            let message = self.messages.remove(*pos);
+
+            #[cfg(feature = "logging")]
+            info!(target: "stdout", "remove a {} message from the message queue", message.1.role());
+
+            // a dropped message is reported as dropped only, even if it was truncated before
+            self.truncated.retain(|index| *index != message.0);
+            self.dropped.push(message);
+        }
+        self.dropped.sort_by_key(|(index, _)| *index);
+
+        !positions.is_empty()
+    }
+
+    /// Removes about the given number of characters from the middle of the longest message which is not pinned. Returns false if no message is long enough.
+    fn truncate_middle(&mut self, chars: usize) -> bool {
+        let longest = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, (index, _))| !self.pinned.contains(index))
+            .filter_map(|(pos, (_, message))| {
+                message_text(message).map(|text| (pos, text.chars().count()))
+            })
+            .max_by_key(|(_, len)| *len);
+
+        let (pos, len) = match longest {
+            Some((pos, len)) if len > 2 * Self::MIN_KEPT_CHARS + Self::ELLIPSIS.len() => (pos, len),
+            _ => return false,
+        };
+
+        let (index, message) = &self.messages[pos];
+        let text = message_text(message).unwrap_or_default();
+        let kept = len.saturating_sub(chars).max(2 * Self::MIN_KEPT_CHARS);
+        let text = truncate_middle_text(text, kept, Self::ELLIPSIS);
+
+        let message = match message {
+            ChatCompletionRequestMessage::System(message) => {
+                ChatCompletionRequestMessage::new_system_message(text, message.name().cloned())
+            }
+            ChatCompletionRequestMessage::User(message) => {
+                ChatCompletionRequestMessage::new_user_message(
+                    ChatCompletionUserMessageContent::Text(text),
+                    message.name().cloned(),
+                )
+            }
+            ChatCompletionRequestMessage::Assistant(message) => {
+                ChatCompletionRequestMessage::new_assistant_message(
+                    Some(text),
+                    message.name().cloned(),
+                    None,
+                )
+            }
+            ChatCompletionRequestMessage::Tool(message) => {
+                ChatCompletionRequestMessage::new_tool_message(text, message.tool_call_id())
+            }
+        };
+
+        if !self.truncated.contains(index) {
+            self.truncated.push(*index);
+            self.truncated.sort();
+        }
+        self.messages[pos].1 = message;
+
+        true
+    }
+
+    fn has_dropped(&self) -> bool {
+        !self.dropped.is_empty()
+    }
+
+    fn dropped(&self) -> Vec<&ChatCompletionRequestMessage> {
+        self.dropped.iter().map(|(_, message)| message).collect()
+    }
+
+    fn set_summary(&mut self, summary: String) {
+        self.summary = Some(summary);
+    }
+
+    /// Returns how the chat history was truncated, or None if it was not.
+    fn report(&self, strategy: TruncationStrategy) -> Option<ContextTruncationReport> {
+        match self.dropped.is_empty() && self.truncated.is_empty() {
+            true => None,
+            false => Some(ContextTruncationReport {
+                strategy,
+                dropped_messages: self.dropped.iter().map(|(index, _)| *index).collect(),
+                truncated_messages: self.truncated.clone(),
+                summarized: self.summary.is_some(),
+            }),
+        }
+    }
+}
+
+/// Returns the text contents of the message, or None if the message has no text contents or has other contents as well, e.g. images or tool calls.
+fn message_text(message: &ChatCompletionRequestMessage) -> Option<&str> {
+    match message {
+        ChatCompletionRequestMessage::System(message) => Some(message.content()),
+        ChatCompletionRequestMessage::User(message) => match message.content() {
+            ChatCompletionUserMessageContent::Text(text) => Some(text),
+            ChatCompletionUserMessageContent::Parts(_) => None,
+        },
+        ChatCompletionRequestMessage::Assistant(message) => match message.tool_calls() {
+            Some(_) => None,
+            None => message.content().map(|content| content.as_str()),
+        },
+        ChatCompletionRequestMessage::Tool(message) => Some(message.content()),
+    }
+}
+
+/// Keeps the given number of characters at both ends of the text in total, and replaces the middle with the ellipsis.
+fn truncate_middle_text(text: &str, kept: usize, ellipsis: &str) -> String {
+    let len = text.chars().count();
+    if len <= kept {
+        return text.to_owned();
+    }
+
+    let head: String = text.chars().take(kept - kept / 2).collect();
+    let tail: String = text.chars().skip(len - kept / 2).collect();
+
+    format!("{}{}{}", head, ellipsis, tail)
+}
+
+#[cfg(test)]
+fn chat_history_roles(history: &ChatHistory) -> Vec<(usize, ChatCompletionRole)> {
+    history
+        .messages
+        .iter()
+        .map(|(index, message)| (*index, message.role()))
+        .collect()
+}
+
+#[cfg(test)]
+fn user_message(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::new_user_message(
+        ChatCompletionUserMessageContent::Text(text.to_string()),
+        None,
+    )
+}
+
+#[cfg(test)]
+fn assistant_message(text: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestMessage::new_assistant_message(Some(text.to_string()), None, None)
+}
+
+#[test]
+fn test_chat_history_drop_oldest_turn() {
+    let messages = vec![
+        ChatCompletionRequestMessage::new_system_message("system", None),
+        user_message("user_1"),
+        assistant_message("assistant_1"),
+        user_message("user_2"),
+        assistant_message("assistant_2"),
+        user_message("user_3"),
+    ];
+
+    let mut history = ChatHistory::new(&messages, &[]);
+    assert!(history.drop_oldest_turn());
+    assert_eq!(
+        chat_history_roles(&history),
+        vec![
+            (0, ChatCompletionRole::System),
+            (3, ChatCompletionRole::User),
+            (4, ChatCompletionRole::Assistant),
+            (5, ChatCompletionRole::User),
+        ]
+    );
+    assert!(history.drop_oldest_turn());
+    // the system message and the last turn are kept
+    assert!(!history.drop_oldest_turn());
+    assert_eq!(history.messages().len(), 2);
+
+    let report = history.report(TruncationStrategy::DropOldest).unwrap();
+    assert_eq!(report.dropped_messages, vec![1, 2, 3, 4]);
+    assert!(report.truncated_messages.is_empty());
+    assert!(!report.summarized);
+
+    // the pinned messages are kept
+    let mut history = ChatHistory::new(&messages, &[1]);
+    assert!(history.drop_oldest_turn());
+    assert_eq!(
+        chat_history_roles(&history),
+        vec![
+            (0, ChatCompletionRole::System),
+            (1, ChatCompletionRole::User),
+            (3, ChatCompletionRole::User),
+            (4, ChatCompletionRole::Assistant),
+            (5, ChatCompletionRole::User),
+        ]
+    );
+
+    // nothing is truncated
+    let history = ChatHistory::new(&messages, &[]);
+    assert_eq!(history.report(TruncationStrategy::DropOldest), None);
+}
+
+#[test]
+fn test_chat_history_keep_last_turns() {
+    let messages = vec![
+        ChatCompletionRequestMessage::new_system_message("system", None),
+        user_message("user_1"),
+        assistant_message("assistant_1"),
+        user_message("user_2"),
+        assistant_message("assistant_2"),
+        user_message("user_3"),
+    ];
+
+    let mut history = ChatHistory::new(&messages, &[]);
+    assert!(history.keep_last_turns(2));
+    assert_eq!(
+        chat_history_roles(&history),
+        vec![
+            (0, ChatCompletionRole::System),
+            (3, ChatCompletionRole::User),
+            (4, ChatCompletionRole::Assistant),
+            (5, ChatCompletionRole::User),
+        ]
+    );
+
+    let mut history = ChatHistory::new(&messages, &[]);
+    assert!(!history.keep_last_turns(3));
+    assert!(history.keep_last_turns(1));
+    assert_eq!(history.messages().len(), 2);
+}
+
+#[test]
+fn test_chat_history_truncate_middle() {
+    let long = format!("{}{}{}", "a".repeat(100), "b".repeat(200), "c".repeat(100));
+    let messages = vec![
+        user_message("user_1"),
+        assistant_message("assistant_1"),
+        user_message(&long),
+    ];
+
+    let mut history = ChatHistory::new(&messages, &[]);
+    assert!(history.truncate_middle(200));
+    match &history.messages()[2] {
+        ChatCompletionRequestMessage::User(message) => match message.content() {
+            ChatCompletionUserMessageContent::Text(text) => {
+                assert_eq!(
+                    text,
+                    &format!(
+                        "{}{}{}",
+                        "a".repeat(100),
+                        ChatHistory::ELLIPSIS,
+                        "c".repeat(100)
+                    )
+                );
+            }
+            _ => panic!("unexpected content"),
+        },
+        _ => panic!("unexpected message"),
+    }
+
+    // at least some characters are kept at both ends
+    assert!(history.truncate_middle(1000));
+    assert!(!history.truncate_middle(1000));
+
+    let report = history.report(TruncationStrategy::TruncateMiddle).unwrap();
+    assert!(report.dropped_messages.is_empty());
+    assert_eq!(report.truncated_messages, vec![2]);
+
+    // a message truncated and then dropped is reported as dropped only
+    let messages = vec![
+        user_message(&long),
+        assistant_message("assistant_1"),
+        user_message("user_2"),
+    ];
+    let mut truncated_history = ChatHistory::new(&messages, &[]);
+    assert!(truncated_history.truncate_middle(200));
+    assert!(truncated_history.drop_oldest_turn());
+    let report = truncated_history
+        .report(TruncationStrategy::TruncateMiddle)
+        .unwrap();
+    assert_eq!(report.dropped_messages, vec![0, 1]);
+    assert!(report.truncated_messages.is_empty());
+
+    // the summary goes into the system message
+    history.set_summary("summary".to_string());
+    match &history.messages()[0] {
+        ChatCompletionRequestMessage::System(message) => {
+            assert_eq!(
+                message.content(),
+                "Summary of the earlier conversation:\nsummary"
+            );
+        }
+        _ => panic!("unexpected message"),
+    }
+}
+
+/// Summarizes the messages with the model, keeping the transcript of the messages within the given number of characters.
+///
+/// The summary is generated with the metadata the model is loaded with instead of the sampling settings of the request, so the metadata of the request has to be sent to the model again afterwards.
+fn summarize_messages(
+    model_name: Option<&String>,
+    chat_prompt: &ChatPrompt,
+    messages: Vec<&ChatCompletionRequestMessage>,
+    max_chars: usize,
+    cancellation: &CancellationToken,
+) -> Result<String, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Summarize {} dropped messages.", messages.len());
+
+    let transcript = messages
+        .iter()
+        .filter_map(|message| {
+            message_text(message).map(|text| format!("{}: {}", message.role(), text))
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let transcript = truncate_middle_text(&transcript, max_chars, "\n...\n");
+
+    let mut messages = vec![
+        ChatCompletionRequestMessage::new_system_message(
+            "Summarize the following conversation between a user and an assistant in a few sentences. Keep the facts and decisions which are needed to continue the conversation.",
+            None,
+        ),
+        ChatCompletionRequestMessage::new_user_message(
+            ChatCompletionUserMessageContent::Text(transcript),
+            None,
+        ),
+    ];
+    let prompt = chat_prompt.build(&mut messages).map_err(|e| {
+        let err_msg = format!("Fail to build the prompt for the summary. Reason: {}", e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })?;
+
+    let mut metadata = get_model_metadata(model_name)?;
+    metadata.n_predict = MAX_SUMMARY_TOKENS;
+    metadata.temperature = 0.0;
+    metadata.grammar = String::new();
+    metadata.json_schema = None;
+    metadata.logit_bias = None;
+    metadata.n_probs = None;
+    metadata.seed = None;
+    metadata.embeddings = false;
+    metadata.image = None;
+    update_model_metadata(model_name, &metadata)?;
+
+    set_prompt(model_name, &prompt)?;
+
+    // clean up the context once the summary is generated or the request is cancelled
+    let _context = SingleContextGuard {
+        model_name: model_name.cloned(),
+    };
+
+    let mut output = Vec::new();
+    for _ in 0..MAX_SUMMARY_TOKENS {
+        if cancellation.is_cancelled() {
+            let err_msg = "The chat request is cancelled while summarizing the dropped messages.";
+
+            #[cfg(feature = "logging")]
+            info!(target: "stdout", "{}", err_msg);
+
+            return Err(LlamaCoreError::Operation(err_msg.into()));
+        }
+
+        let finished = with_chat_graph(model_name, |graph| match graph.compute_single() {
+            Ok(_) => {
+                output.extend(get_output_buffer_single(graph, OUTPUT_TENSOR)?);
+
+                Ok(false)
+            }
+            Err(InferenceError::EndOfSequence | InferenceError::ContextFull) => Ok(true),
+            Err(e) => {
+                let err_msg = format!("Failed to compute the summary. Reason: {}", e);
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                Err(LlamaCoreError::Backend(BackendError::ComputeSingle(
+                    err_msg,
+                )))
+            }
+        })?;
+
+        if finished {
+            break;
+        }
+    }
+
+    let summary = chat_prompt.post_process(&String::from_utf8_lossy(&output));
+    match summary.trim() {
+        summary if !summary.is_empty() => Ok(summary.to_owned()),
+        _ => {
+            let err_msg = "The summary generated by the model is empty.";
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            Err(LlamaCoreError::Operation(err_msg.into()))
+        }
+    }
+}
+
+/// Resolves the images in the user messages into base64 encoded data, so that the prompt templates can embed them in the order of the content parts. The images can be given as URLs, data URIs, ids of the files uploaded via the `/v1/files` endpoint, or base64 encoded data.
+async fn resolve_images(chat_request: &mut ChatCompletionRequest) -> Result<(), LlamaCoreError> {
+    for message in chat_request.messages.iter_mut() {
+        let user_message = match message {
+            ChatCompletionRequestMessage::User(user_message) => user_message,
+            _ => continue,
+        };
+
+        let parts = match user_message.content_mut() {
+            ChatCompletionUserMessageContent::Parts(parts) => parts,
+            _ => continue,
+        };
+
+        for part in parts.iter_mut() {
+            if let ContentPart::Image(image_part) = part {
+                let image = image_part.image_mut();
+                let data = load_image(image).await?;
+
+                // validate the image
+                if data.len() > MAX_IMAGE_SIZE {
+                    let err_msg = format!(
+                        "The image is too large: {} bytes. The maximum size is {} bytes.",
+                        data.len(),
+                        MAX_IMAGE_SIZE
+                    );
+
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", &err_msg);
+
+                    return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
                 }
-            },
-        };
+                if chat_prompts::chat::image_format(&data).is_none() {
+                    let err_msg = "Unsupported image format. The supported formats are png, jpeg, gif, bmp, tga, hdr and pnm.";
 
-        // set prompt
-        set_prompt(model_name, &prompt)?;
-
-        // Retrieve the number of prompt tokens.
-        let token_info = get_token_info_by_graph_name(model_name)?;
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", &err_msg);
 
-        match token_info.prompt_tokens > max_prompt_tokens {
-            true => {
-                match chat_request.messages[0].role() {
-                    ChatCompletionRole::System => {
-                        if chat_request.messages.len() >= 3 {
-                            // remove user_1 if it exists
-                            // For example, `system -> user_1 -> ... -> user_2 -> ... -> user_latest` will be converted to `system -> ... -> user_2 -> ... -> user_latest`
-                            if chat_request.messages[1].role() == ChatCompletionRole::User {
-                                chat_request.messages.remove(1);
-                            }
+                    return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
+                }
 
-                            // remove all messages until the message is of `user`
-                            // For example, `system -> ... -> user_2 -> ... -> user_latest` will be converted to `system -> user_2 -> ... -> user_latest`
-                            while chat_request.messages[1].role() != ChatCompletionRole::User {
-                                chat_request.messages.remove(1);
-                            }
-                        } else if token_info.prompt_tokens > ctx_size {
-                            let err_msg = format!(
-                                    "The number of prompt tokens is greater than the context size: {} > {}",
-                                    token_info.prompt_tokens, ctx_size
-                                );
+                image.url = general_purpose::STANDARD.encode(&data);
+            }
+        }
+    }
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+    Ok(())
+}
 
-                            return Err(LlamaCoreError::Operation(err_msg));
-                        } else {
-                            return Ok((prompt, ctx_size - token_info.prompt_tokens, tool_use));
-                        }
-                    }
-                    ChatCompletionRole::User => {
-                        if chat_request.messages.len() >= 3 {
-                            // user_1 -> ... -> user_2 -> ... -> user_latest
-
-                            // remove user_1 if it exists
-                            // For example, `user_1 -> ... -> user_2 -> ... -> user_latest` will be converted to `... -> user_2 -> ... -> user_latest`
-                            if chat_request.messages[0].role() == ChatCompletionRole::User {
-                                chat_request.messages.remove(0);
-                            }
+/// Loads the raw data of the image.
+async fn load_image(image: &Image) -> Result<Vec<u8>, LlamaCoreError> {
+    if image.is_url() {
+        return download_image(&image.url).await;
+    }
 
-                            // remove all messages until the message is of `user`
-                            // For example, `... -> user_2 -> ... -> user_latest` will be converted to `user_2 -> ... -> user_latest`
-                            while chat_request.messages[1].role() != ChatCompletionRole::User {
-                                chat_request.messages.remove(1);
-                            }
-                        } else if chat_request.messages.len() == 2
-                            && chat_request.messages[0].role() == ChatCompletionRole::User
-                        {
-                            // deal with "user_1 -> user_latest"
-                            chat_request.messages.remove(0);
-                        } else if token_info.prompt_tokens > ctx_size {
-                            let err_msg = format!(
-                                    "The number of prompt tokens is greater than the context size: {} > {}",
-                                    token_info.prompt_tokens, ctx_size
-                                );
+    if image.is_file_id() {
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Load the image from the uploaded file: {}", &image.url);
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+        let (_, data) = crate::files::download_file(&image.url)?;
+        return Ok(data);
+    }
 
-                            return Err(LlamaCoreError::Operation(err_msg));
-                        } else {
-                            return Ok((prompt, ctx_size - token_info.prompt_tokens, tool_use));
-                        }
-                    }
-                    _ => {
-                        #[cfg(feature = "logging")]
-                        info!(target: "stdout", "remove a {} message from the message queue", chat_request.messages[0].role());
+    let base64_str = match image.is_data_uri() {
+        true => match image.data_uri() {
+            Some((media_type, data)) if media_type.starts_with("image/") => data,
+            _ => {
+                let err_msg =
+                    "Invalid data URI of the image. Expected `data:image/<format>;base64,<data>`.";
 
-                        chat_request.messages.remove(0);
-                    }
-                }
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
 
-                continue;
+                return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
             }
-            false => return Ok((prompt, ctx_size - max_prompt_tokens, tool_use)),
-        }
-    }
+        },
+        false => image.url.as_str(),
+    };
+
+    general_purpose::STANDARD.decode(base64_str).map_err(|e| {
+        let err_msg = format!("Fail to decode the base64 encoded image. Reason: {}", e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::invalid_request(err_msg, Some("messages"))
+    })
 }
 
-/// Downloads an image from the given URL and returns the file name.
-async fn download_image(image_url: impl AsRef<str>) -> Result<String, LlamaCoreError> {
+/// Downloads an image from the given URL and returns the image data. The image is kept in memory, so no temporary file is left behind.
+async fn download_image(image_url: impl AsRef<str>) -> Result<Vec<u8>, LlamaCoreError> {
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Download image from the URL.");
 
@@ -2216,7 +1755,7 @@ async fn download_image(image_url: impl AsRef<str>) -> Result<String, LlamaCoreE
         #[cfg(feature = "logging")]
         error!(target: "stdout", "{}", &err_msg);
 
-        LlamaCoreError::Operation(err_msg)
+        LlamaCoreError::invalid_request(err_msg, Some("messages"))
     })?;
 
     let response = reqwest::get(url).await.map_err(|e| {
@@ -2228,51 +1767,44 @@ async fn download_image(image_url: impl AsRef<str>) -> Result<String, LlamaCoreE
         #[cfg(feature = "logging")]
         error!(target: "stdout", "{}", &err_msg);
 
-        LlamaCoreError::Operation(err_msg)
+        LlamaCoreError::invalid_request(err_msg, Some("messages"))
     })?;
 
-    let fname = response
-        .url()
-        .path_segments()
-        .and_then(|segments| segments.last())
-        .and_then(|name| if name.is_empty() { None } else { Some(name) })
-        .ok_or(LlamaCoreError::Operation(format!(
-            "Fail to get the file name: {}",
-            image_url
-        )))?
-        .to_string();
-
-    let mut dest = std::fs::File::create(&fname).map_err(|e| {
-        let err_msg = format!(
-            "Fail to create the file to save the image: {}. Reason: {}",
-            &fname, e
-        );
+    let mut data = Vec::new();
+    let mut content = response.bytes_stream();
+    while let Some(item) = content.next().await {
+        let item = item.map_err(|e| {
+            let err_msg = format!(
+                "Fail to download the image from the URL: {}. Reason: {}",
+                image_url, e
+            );
 
-        #[cfg(feature = "logging")]
-        error!(target: "stdout", "{}", &err_msg);
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
 
-        LlamaCoreError::Operation(err_msg)
-    })?;
+            LlamaCoreError::invalid_request(err_msg, Some("messages"))
+        })?;
 
-    let mut content = response.bytes_stream();
-    while let Some(Ok(item)) = content.next().await {
-        std::io::copy(&mut item.as_ref(), &mut dest).map_err(|e| {
+        // stop downloading as soon as the image exceeds the size limit
+        if data.len() + item.len() > MAX_IMAGE_SIZE {
             let err_msg = format!(
-                "Fail to write the image content to the file: {}. Reason: {}",
-                &fname, e
+                "The image from the URL {} exceeds the maximum size of {} bytes.",
+                image_url, MAX_IMAGE_SIZE
             );
 
             #[cfg(feature = "logging")]
             error!(target: "stdout", "{}", &err_msg);
 
-            LlamaCoreError::Operation(err_msg)
-        })?;
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("messages")));
+        }
+
+        data.extend_from_slice(&item);
     }
 
     #[cfg(feature = "logging")]
     info!(target: "stdout", "The image is downloaded successfully.");
 
-    Ok(fname)
+    Ok(data)
 }
 
 fn set_prompt(model_name: Option<&String>, prompt: impl AsRef<str>) -> Result<(), LlamaCoreError> {
@@ -2297,53 +1829,13 @@ fn set_prompt(model_name: Option<&String>, prompt: impl AsRef<str>) -> Result<()
         LlamaCoreError::Operation(err_msg)
     })?;
 
-    match model_name {
-        Some(model_name) => {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "Set prompt to the chat model named {}.", model_name);
-
-            match chat_graphs.contains_key(model_name) {
-                true => {
-                    let graph = chat_graphs.get_mut(model_name).unwrap();
-                    let tensor_data = prompt.as_ref().as_bytes().to_vec();
-                    set_tensor_data_u8(graph, 0, &tensor_data)
-                }
-                false => match chat_graphs.iter_mut().next() {
-                    Some((_, graph)) => {
-                        let tensor_data = prompt.as_ref().as_bytes().to_vec();
-                        set_tensor_data_u8(graph, 0, &tensor_data)
-                    }
-                    None => {
-                        let err_msg = "There is no model available in the chat graphs.";
-
-                        #[cfg(feature = "logging")]
-                        error!(target: "stdout", "{}", &err_msg);
-
-                        Err(LlamaCoreError::Operation(err_msg.into()))
-                    }
-                },
-            }
-        }
-        None => {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "Set prompt to the default chat model.");
-
-            match chat_graphs.iter_mut().next() {
-                Some((_, graph)) => {
-                    let tensor_data = prompt.as_ref().as_bytes().to_vec();
-                    set_tensor_data_u8(graph, 0, &tensor_data)
-                }
-                None => {
-                    let err_msg = "There is no model available in the chat graphs while trying to set prompt to the default model.";
+    let graph = models::get_graph_mut(&mut chat_graphs, model_name.map(String::as_str))?;
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", err_msg);
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Set prompt to the chat model named {}.", graph.name());
 
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            }
-        }
-    }
+    let tensor_data = prompt.as_ref().as_bytes().to_vec();
+    set_tensor_data_u8(graph, 0, &tensor_data)
 }
 
 // fn set_tensor_data_u8(
@@ -2364,7 +1856,9 @@ fn set_prompt(model_name: Option<&String>, prompt: impl AsRef<str>) -> Result<()
 // }
 
 /// Get a copy of the metadata of the model.
-fn get_model_metadata(model_name: Option<&String>) -> Result<GgmlMetadata, LlamaCoreError> {
+pub(crate) fn get_model_metadata(
+    model_name: Option<&String>,
+) -> Result<GgmlMetadata, LlamaCoreError> {
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Get the model metadata.");
 
@@ -2389,39 +1883,13 @@ fn get_model_metadata(model_name: Option<&String>) -> Result<GgmlMetadata, Llama
         LlamaCoreError::Operation(err_msg)
     })?;
 
-    match model_name {
-        Some(model_name) => match chat_graphs.contains_key(model_name) {
-            true => {
-                let graph = chat_graphs.get(model_name).unwrap();
-                Ok(graph.metadata.clone())
-            }
-            false => match chat_graphs.iter().next() {
-                Some((_, graph)) => Ok(graph.metadata.clone()),
-                None => {
-                    let err_msg = "There is no model available in the chat graphs.";
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            },
-        },
-        None => match chat_graphs.iter().next() {
-            Some((_, graph)) => Ok(graph.metadata.clone()),
-            None => {
-                let err_msg = "There is no model available in the chat graphs.";
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", err_msg);
+    let graph = models::get_graph(&chat_graphs, model_name.map(String::as_str))?;
 
-                Err(LlamaCoreError::Operation(err_msg.into()))
-            }
-        },
-    }
+    Ok(graph.metadata.clone())
 }
 
-fn update_model_metadata(
+/// Send the metadata to the model, which takes effect until the metadata is updated again.
+pub(crate) fn update_model_metadata(
     model_name: Option<&String>,
     metadata: &GgmlMetadata,
 ) -> Result<(), LlamaCoreError> {
@@ -2461,47 +1929,55 @@ fn update_model_metadata(
         LlamaCoreError::Operation(err_msg)
     })?;
 
-    match model_name {
-        Some(model_name) => {
-            match chat_graphs.contains_key(model_name) {
-                true => {
-                    let graph = chat_graphs.get_mut(model_name).unwrap();
-                    // update metadata
-                    set_tensor_data_u8(graph, 1, config.as_bytes())
-                }
-                false => match chat_graphs.iter_mut().next() {
-                    Some((_, graph)) => {
-                        // update metadata
-                        set_tensor_data_u8(graph, 1, config.as_bytes())
-                    }
-                    None => {
-                        let err_msg = "There is no model available in the chat graphs.";
+    let graph = models::get_graph_mut(&mut chat_graphs, model_name.map(String::as_str))?;
 
-                        #[cfg(feature = "logging")]
-                        error!(target: "stdout", "{}", &err_msg);
+    // update metadata
+    set_tensor_data_u8(graph, 1, config.as_bytes())
+}
 
-                        Err(LlamaCoreError::Operation(err_msg.into()))
-                    }
-                },
-            }
-        }
-        None => {
-            match chat_graphs.iter_mut().next() {
-                Some((_, graph)) => {
-                    // update metadata
-                    set_tensor_data_u8(graph, 1, config.as_bytes())
-                }
-                None => {
-                    let err_msg = "There is no model available in the chat graphs.";
+/// Restore the metadata of the model to the one the model is loaded with.
+fn restore_model_metadata(model_name: Option<&String>) -> Result<(), LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Restore the model metadata.");
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", err_msg);
+    let metadata = get_model_metadata(model_name)?;
 
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            }
-        }
-    }
+    update_model_metadata(model_name, &metadata)
+}
+
+/// Check if the request changes the metadata of the model only for its own duration, i.e. by the response format, the logit bias, the log probabilities or the seed.
+fn should_restore_metadata(chat_request: &ChatCompletionRequest) -> bool {
+    let has_response_format = match &chat_request.response_format {
+        Some(response_format) => response_format.ty != "text",
+        None => false,
+    };
+
+    has_response_format
+        || chat_request.logit_bias.is_some()
+        || chat_request.logprobs == Some(true)
+        || chat_request.seed.is_some()
+}
+
+/// Returns the parser of the tool calls generated by the model, which is the one selected by the metadata of the model, or the one of its prompt template.
+fn get_tool_call_parser(
+    metadata: &GgmlMetadata,
+) -> Result<Arc<dyn ToolCallParser>, LlamaCoreError> {
+    let name = match &metadata.tool_call_parser {
+        Some(name) => name.clone(),
+        None => metadata.prompt_template.to_string(),
+    };
+
+    tool_call_parser(&name).ok_or_else(|| {
+        let err_msg = format!(
+            "The tool use is not supported for the model, as no parser of the tool calls is named '{}'.",
+            name
+        );
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::invalid_request(err_msg, Some("tools"))
+    })
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -2527,192 +2003,376 @@ enum PromptTooLongState {
     EndOfSequence,
 }
 
-struct ChatStream {
-    id: String,
-    model: Option<String>,
-    include_usage: bool,
-    context_full_state: ContextFullState,
-    prompt_too_long_state: PromptTooLongState,
-    stream_state: StreamState,
-    cache: Option<VecDeque<String>>,
+/// The length in bytes of the beginning of the output held back in the stream mode before the post-processing of the prompt template applies.
+const POST_PROCESS_PREFIX_LEN: usize = 16;
+
+#[derive(Debug, Default)]
+struct ChoiceState {
+    /// Number of choices to generate
+    n_choice: u64,
+    /// Index of the choice being generated
+    index: u32,
+    /// Number of completion tokens of the finished choices
+    completion_tokens: u64,
+    /// Prompt fed to the model again before generating the next choice
+    prompt: String,
+    /// Whether to output the log probabilities of the output tokens
+    logprobs: bool,
+    /// Log probabilities of the tokens not sent to the client yet
+    pending_logprobs: Vec<TokenLogProb>,
+    /// Stop sequences of the request and the end-of-turn markers of the prompt template
+    stop: Option<Vec<String>>,
+    /// Text held back from the client as it may be the beginning of a stop sequence
+    pending: String,
+    /// Whether the generation is finished, either by a stop sequence or by flushing the held-back text
+    finished: bool,
+    /// Prompt template whose post-processing cleans up the output, as in the non-stream mode
+    prompt_template: Option<PromptTemplateType>,
+    /// Text released from the hold-back for the stop sequences, before the post-processing
+    output: String,
+    /// Length in bytes of the post-processed output sent to the client
+    processed_len: usize,
+    /// Parser of the tool calls in the output if the request uses tools
+    tool_calls: Option<ToolCallStream>,
+    /// How the chat history was truncated, which is sent in the first chunk
+    context_truncation: Option<ContextTruncationReport>,
+    /// Token checked before generating each token to stop the generation
+    cancellation: CancellationToken,
 }
-impl ChatStream {
-    fn new(
-        model: Option<String>,
-        id: String,
-        include_usage: bool,
-        cache: Option<Vec<String>>,
-    ) -> Self {
-        let stream_state = if include_usage {
-            StreamState::Usage
-        } else {
-            StreamState::Done
+impl ChoiceState {
+    /// Cleans up the text released from the hold-back for the stop sequences with the post-processing of the prompt template, and returns the cleaned text not sent to the client yet. The beginning of the output is held back until it is long enough to tell the prefix the post-processing strips, e.g. `: `, unless the generation is finished.
+    fn post_process(&mut self, text: &str, finished: bool) -> String {
+        let Some(prompt_template) = self.prompt_template else {
+            return text.to_string();
         };
 
-        ChatStream {
-            id,
-            model,
-            include_usage,
-            context_full_state: ContextFullState::Message,
-            prompt_too_long_state: PromptTooLongState::Message,
-            stream_state,
-            cache: cache.map(VecDeque::from),
+        self.output.push_str(text);
+        if !finished && self.output.trim_start().len() < POST_PROCESS_PREFIX_LEN {
+            return String::new();
+        }
+
+        // the trailing whitespaces are trimmed until more text follows them
+        let processed = ChatPrompt::from(prompt_template).post_process(&self.output);
+        let delta = processed
+            .get(self.processed_len..)
+            .unwrap_or_default()
+            .to_string();
+        self.processed_len = self.processed_len.max(processed.len());
+
+        delta
+    }
+
+    /// Take the log probabilities of the tokens not sent to the client yet.
+    fn take_logprobs(&mut self) -> Option<LogProbs> {
+        match self.logprobs {
+            true => Some(LogProbs {
+                content: Some(std::mem::take(&mut self.pending_logprobs)),
+            }),
+            false => None,
         }
     }
 }
-impl Drop for ChatStream {
-    fn drop(&mut self) {
-        if self.cache.is_none() {
-            #[cfg(feature = "logging")]
-            info!(target: "stdout", "Clean up the context of the stream work environment.");
 
-            match &self.model {
-                Some(model_name) => {
-                    match CHAT_GRAPHS.get() {
-                        Some(chat_graphs) => match chat_graphs.lock() {
-                            Ok(mut chat_graphs) => match chat_graphs.contains_key(model_name) {
-                                true => {
-                                    let graph = chat_graphs.get_mut(model_name).unwrap();
-
-                                    if let Err(e) = graph.finish_single() {
-                                        let err_msg = format!(
-                                            "Failed to clean up the context. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        #[cfg(not(feature = "logging"))]
-                                        println!(
-                                            "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                            &err_msg
-                                        );
-                                    }
-                                }
-                                false => match chat_graphs.iter_mut().next() {
-                                    Some((_, graph)) => {
-                                        if let Err(e) = graph.finish_single() {
-                                            let err_msg = format!(
-                                                "Failed to clean up the context. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            #[cfg(not(feature = "logging"))]
-                                            println!(
-                                                "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                                &err_msg
-                                            );
-                                        }
-                                    }
-                                    None => {
-                                        let err_msg =
-                                            "There is no model available in the chat graphs.";
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        #[cfg(not(feature = "logging"))]
-                                        println!(
-                                            "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                            &err_msg
-                                        );
-                                    }
+/// Incremental parser of the tool calls in the output of the model in the stream mode. It turns the output into the content and the tool call deltas to send to the client, with the name of a function sent first and then the fragments of its arguments.
+struct ToolCallStream {
+    parser: Arc<dyn ToolCallParser>,
+    /// Output of the model so far
+    text: String,
+    /// Length in bytes of the content sent to the client
+    content_len: usize,
+    /// ID of the tool calls sent to the client and the length in bytes of their arguments sent
+    calls: Vec<(String, usize)>,
+}
+impl fmt::Debug for ToolCallStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToolCallStream")
+            .field("text", &self.text)
+            .field("content_len", &self.content_len)
+            .field("calls", &self.calls)
+            .finish_non_exhaustive()
+    }
+}
+impl ToolCallStream {
+    fn new(parser: Arc<dyn ToolCallParser>) -> Self {
+        Self {
+            parser,
+            text: String::new(),
+            content_len: 0,
+            calls: vec![],
+        }
+    }
+
+    /// Appends the newly generated text, and returns the content and the tool call deltas which are ready to send. The text held back as it may be the beginning of a tool call is released if `finished` is true.
+    fn push(&mut self, text: &str, finished: bool) -> (Option<String>, Vec<ToolCallForChunk>) {
+        self.text.push_str(text);
+
+        let PartialToolCalls {
+            content,
+            tool_calls: calls,
+        } = self.parser.parse_partial(&self.text, finished);
+
+        // the whitespaces around the tool calls are not sent as content on their own
+        let content = match content.trim().is_empty() {
+            true => None,
+            false => match content.get(self.content_len..) {
+                Some(delta) if !delta.is_empty() => {
+                    self.content_len = content.len();
+                    Some(delta.to_owned())
+                }
+                _ => None,
+            },
+        };
+
+        let mut deltas = vec![];
+        for (index, call) in calls.into_iter().enumerate() {
+            match self.calls.get_mut(index) {
+                Some((_, sent)) => {
+                    if let Some(arguments) = call.arguments.get(*sent..) {
+                        if !arguments.is_empty() {
+                            *sent = call.arguments.len();
+
+                            deltas.push(ToolCallForChunk {
+                                index,
+                                id: None,
+                                ty: None,
+                                function: FunctionForChunk {
+                                    name: None,
+                                    arguments: arguments.to_owned(),
                                 },
+                            });
+                        }
+                    }
+                }
+                None => match call.name {
+                    Some(name) => {
+                        let id = gen_tool_call_id();
+                        self.calls.push((id.clone(), call.arguments.len()));
+
+                        deltas.push(ToolCallForChunk {
+                            index,
+                            id: Some(id),
+                            ty: Some("function".to_string()),
+                            function: FunctionForChunk {
+                                name: Some(name),
+                                arguments: call.arguments,
                             },
-                            Err(e) => {
-                                let err_msg =
-                                    format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
+                        });
+                    }
+                    // the tool calls are sent in order, so wait for the name of the function
+                    None => break,
+                },
+            }
+        }
 
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
+        (content, deltas)
+    }
 
-                                #[cfg(not(feature = "logging"))]
-                                println!(
-                                "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                &err_msg
-                            );
-                            }
-                        },
-                        None => {
-                            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
+    /// Whether any tool call has been sent to the client.
+    fn has_tool_calls(&self) -> bool {
+        !self.calls.is_empty()
+    }
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+    /// Clears the state for the next choice.
+    fn reset(&mut self) {
+        self.text.clear();
+        self.content_len = 0;
+        self.calls.clear();
+    }
+}
 
-                            #[cfg(not(feature = "logging"))]
-                            println!(
-                                "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                &err_msg
-                            );
-                        }
-                    };
+/// Feeds the output to the parser in pieces of the given size in bytes, and collects the content and the tool calls sent to the client.
+#[cfg(test)]
+fn stream_tool_calls(parser: &str, output: &str, size: usize) -> (String, Vec<(String, String)>) {
+    let mut parser = ToolCallStream::new(tool_call_parser(parser).unwrap());
+
+    let chars: Vec<char> = output.chars().collect();
+    let pieces: Vec<String> = chars.chunks(size).map(|c| c.iter().collect()).collect();
+
+    let mut content = String::new();
+    let mut calls: Vec<(String, String)> = vec![];
+    for (idx, piece) in pieces.iter().enumerate() {
+        let (delta, deltas) = parser.push(piece, idx + 1 == pieces.len());
+        content.push_str(&delta.unwrap_or_default());
+        for delta in deltas {
+            match delta.id {
+                Some(id) => {
+                    assert!(id.starts_with("call_"));
+                    assert_eq!(delta.index, calls.len());
+                    assert_eq!(delta.ty.as_deref(), Some("function"));
+                    calls.push((delta.function.name.unwrap(), delta.function.arguments));
                 }
                 None => {
-                    match CHAT_GRAPHS.get() {
-                        Some(chat_graphs) => match chat_graphs.lock() {
-                            Ok(mut chat_graphs) => match chat_graphs.iter_mut().next() {
-                                Some((_, graph)) => {
-                                    if let Err(e) = graph.finish_single() {
-                                        let err_msg = format!(
-                                            "Failed to clean up the context. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        #[cfg(not(feature = "logging"))]
-                                        println!(
-                                        "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                        &err_msg
-                                    );
-                                    }
-                                }
-                                None => {
-                                    let err_msg = "There is no model available in the chat graphs.";
+                    assert!(delta.function.name.is_none());
+                    calls[delta.index].1.push_str(&delta.function.arguments);
+                }
+            }
+        }
+    }
 
-                                    #[cfg(feature = "logging")]
-                                    error!(target: "stdout", "{}", err_msg);
+    (content, calls)
+}
 
-                                    #[cfg(not(feature = "logging"))]
-                                    println!(
-                                    "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                    err_msg
-                                );
-                                }
-                            },
-                            Err(e) => {
-                                let err_msg =
-                                    format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
+#[test]
+fn test_stream_tool_calls_chatml() {
+    let output = "<tool_call>\n{\"arguments\": {\"location\": \"Paris\", \"unit\": \"celsius\"}, \"name\": \"get_current_weather\"}\n</tool_call>\n<tool_call>\n{\"name\": \"get_time\", \"arguments\": {\"city\": \"Paris\"}}\n</tool_call>";
+
+    for size in [1, 3, 7, output.len()] {
+        let (content, calls) = stream_tool_calls("chatml-tool", output, size);
+        assert_eq!(content, "");
+        assert_eq!(
+            calls,
+            vec![
+                (
+                    "get_current_weather".to_string(),
+                    r#"{"location": "Paris", "unit": "celsius"}"#.to_string()
+                ),
+                ("get_time".to_string(), r#"{"city": "Paris"}"#.to_string()),
+            ]
+        );
+    }
+
+    // no tool call
+    let output = "The weather in <b>Paris</b> is sunny.";
+    let (content, calls) = stream_tool_calls("chatml-tool", output, 2);
+    assert_eq!(content, output);
+    assert!(calls.is_empty());
+}
+
+#[test]
+fn test_stream_post_process() {
+    let mut state = ChoiceState {
+        prompt_template: Some(PromptTemplateType::ChatML),
+        ..Default::default()
+    };
+
+    // the prefix stripped by the post-processing is never sent
+    assert_eq!(state.post_process(": Hel", false), "");
+    assert_eq!(
+        state.post_process("lo, how are you doing", false),
+        "Hello, how are you doing"
+    );
+    // the trailing whitespaces are held back
+    assert_eq!(state.post_process(" today? ", false), " today?");
+    assert_eq!(state.post_process("\n", true), "");
+    assert_eq!(
+        ChatPrompt::from(PromptTemplateType::ChatML).post_process(&state.output),
+        "Hello, how are you doing today?"
+    );
+}
+
+#[test]
+fn test_stream_tool_calls_incrementally() {
+    let mut parser = ToolCallStream::new(tool_call_parser("chatml-tool").unwrap());
+
+    // the text which may be the beginning of a tool call is held back
+    let (content, deltas) = parser.push("<tool", false);
+    assert_eq!(content, None);
+    assert!(deltas.is_empty());
+
+    // the name is sent first
+    let (content, deltas) =
+        parser.push("_call>{\"name\": \"get_time\", \"arguments\": {\"ci", false);
+    assert_eq!(content, None);
+    assert_eq!(deltas.len(), 1);
+    assert_eq!(deltas[0].function.name.as_deref(), Some("get_time"));
+    assert_eq!(deltas[0].function.arguments, r#"{"ci"#);
+
+    // then the fragments of the arguments
+    let (content, deltas) = parser.push("ty\": \"Paris\"}}</tool_call>", false);
+    assert_eq!(content, None);
+    assert_eq!(deltas.len(), 1);
+    assert_eq!(deltas[0].id, None);
+    assert_eq!(deltas[0].function.name, None);
+    assert_eq!(deltas[0].function.arguments, r#"ty": "Paris"}"#);
+    assert!(parser.has_tool_calls());
+
+    let (content, deltas) = parser.push("", true);
+    assert_eq!(content, None);
+    assert!(deltas.is_empty());
+
+    // unique ids
+    parser.reset();
+    let (_, first) = parser.push(
+        "<tool_call>{\"name\": \"a\", \"arguments\": {}}</tool_call>",
+        false,
+    );
+    let (_, second) = parser.push(
+        "<tool_call>{\"name\": \"b\", \"arguments\": {}}</tool_call>",
+        true,
+    );
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 1);
+    assert_eq!(second[0].index, 1);
+    assert_ne!(first[0].id, second[0].id);
+}
+
+struct ChatStream {
+    id: String,
+    model: Option<String>,
+    include_usage: bool,
+    context_full_state: ContextFullState,
+    prompt_too_long_state: PromptTooLongState,
+    stream_state: StreamState,
+    cache: Option<VecDeque<String>>,
+    choice_state: ChoiceState,
+    /// The guard restoring the metadata of the model after the stream is dropped
+    _metadata: MetadataGuard,
+    /// The permit to use the model, which is released after the stream is dropped
+    _permit: SchedulerPermit,
+    /// The registration of the request to cancel it by its chat id, which is removed after the stream is dropped
+    _cancellation: CancellationGuard,
+}
+impl ChatStream {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        model: Option<String>,
+        id: String,
+        include_usage: bool,
+        cache: Option<Vec<String>>,
+        permit: SchedulerPermit,
+        metadata: MetadataGuard,
+        cancellation: CancellationGuard,
+        choice_state: ChoiceState,
+    ) -> Self {
+        let stream_state = if include_usage {
+            StreamState::Usage
+        } else {
+            StreamState::Done
+        };
 
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
+        ChatStream {
+            id,
+            model,
+            include_usage,
+            context_full_state: ContextFullState::Message,
+            prompt_too_long_state: PromptTooLongState::Message,
+            stream_state,
+            cache: cache.map(VecDeque::from),
+            choice_state,
+            _metadata: metadata,
+            _permit: permit,
+            _cancellation: cancellation,
+        }
+    }
+}
+impl Drop for ChatStream {
+    fn drop(&mut self) {
+        if self.cache.is_none() {
+            #[cfg(feature = "logging")]
+            info!(target: "stdout", "Clean up the context of the stream work environment.");
 
-                                #[cfg(not(feature = "logging"))]
-                                println!(
-                                "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                &err_msg
-                            );
-                            }
-                        },
-                        None => {
-                            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
+            if let Err(e) = with_chat_graph(self.model.as_ref(), |graph| {
+                graph
+                    .finish_single()
+                    .map_err(|e| LlamaCoreError::Backend(BackendError::FinishSingle(e.to_string())))
+            }) {
+                let err_msg = format!("Failed to clean up the context. Reason: {}", e);
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
 
-                            #[cfg(not(feature = "logging"))]
-                            println!(
-                                "[ERROR][llama_core] Failed to clean up the context. Reason: {}",
-                                &err_msg
-                            );
-                        }
-                    };
-                }
+                #[cfg(not(feature = "logging"))]
+                println!("[ERROR][llama_core] {}", &err_msg);
             }
 
             #[cfg(feature = "logging")]
@@ -2733,6 +2393,7 @@ impl futures::Stream for ChatStream {
                 &mut this.prompt_too_long_state,
                 &mut this.context_full_state,
                 &mut this.stream_state,
+                &mut this.choice_state,
             );
 
             match x {
@@ -2772,116 +2433,548 @@ fn compute_stream(
     prompt_too_long_state: &mut PromptTooLongState,
     context_full_state: &mut ContextFullState,
     stream_state: &mut StreamState,
+    choice_state: &mut ChoiceState,
 ) -> Result<String, LlamaCoreError> {
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Compute the chat stream chunk.");
 
-    if *prompt_too_long_state == PromptTooLongState::EndOfSequence
-        || *context_full_state == ContextFullState::EndOfSequence
-        || *stream_state == StreamState::EndOfSequence
-    {
-        #[cfg(feature = "logging")]
-        info!(target: "stdout", "Return the chat stream chunk!");
+    if *prompt_too_long_state == PromptTooLongState::EndOfSequence
+        || *context_full_state == ContextFullState::EndOfSequence
+        || *stream_state == StreamState::EndOfSequence
+    {
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Return the chat stream chunk!");
+
+        return Ok("[GGML] End of sequence".to_string());
+    }
+
+    let chat_graphs = match CHAT_GRAPHS.get() {
+        Some(chat_graphs) => chat_graphs,
+        None => {
+            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::Operation(err_msg.into()));
+        }
+    };
+
+    let mut chat_graphs = chat_graphs.lock().map_err(|e| {
+        let err_msg = format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })?;
+
+    // get graph
+    let graph = models::get_graph_mut(&mut chat_graphs, model_name.as_deref())?;
+
+    // the current choice is finished, so move on to the next choice or close the stream
+    if choice_state.finished {
+        match (choice_state.index as u64) + 1 < choice_state.n_choice {
+            true => next_choice(graph, choice_state)?,
+            false => return end_of_stream(graph, id, stream_state, choice_state.completion_tokens),
+        }
+    }
+
+    let res = loop {
+        // stop the generation if the request is cancelled or exceeds its deadline
+        if choice_state.cancellation.is_cancelled() {
+            break cancel_choice(graph, id, choice_state);
+        }
+
+        match graph.compute_single() {
+            Ok(_) => {
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "Compute the chat stream chunk successfully.");
+
+                // Retrieve the output
+                let output_buffer = get_output_buffer_single(graph, OUTPUT_TENSOR)?;
+
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "retrieved the output buffer");
+
+                // decode the output buffer to a utf8 string
+                let output = match String::from_utf8(output_buffer.clone()) {
+                    Ok(token) => token,
+                    Err(_) => {
+                        let mutex = CACHED_UTF8_ENCODINGS.get_or_init(|| Mutex::new(Vec::new()));
+                        let mut cached_encodings = mutex.lock().map_err(|e| {
+                            let err_msg = format!(
+                                "Fail to acquire the lock of `UTF8_ENCODINGS`. Reason: {}",
+                                e
+                            );
+
+                            #[cfg(feature = "logging")]
+                            error!(target: "stdout", "{}", &err_msg);
+
+                            LlamaCoreError::Operation(err_msg)
+                        })?;
+
+                        // cache the bytes for future decoding
+                        cached_encodings.extend_from_slice(&output_buffer[..]);
+
+                        match String::from_utf8(cached_encodings.to_vec()) {
+                            Ok(token) => {
+                                // clear encodings
+                                cached_encodings.clear();
+
+                                token
+                            }
+                            Err(e) => {
+                                // TODO This is a temp check. In case, infinite cached encodings happen.
+                                if cached_encodings.len() > 4 {
+                                    let err_msg = format!("Fail to convert a vector of bytes to string. The length of the utf8 bytes exceeds 4. {}", e);
+
+                                    #[cfg(feature = "logging")]
+                                    error!(target: "stdout", "{}", &err_msg);
+
+                                    return Err(LlamaCoreError::Operation(err_msg));
+                                } else {
+                                    let warn_msg = format!(
+                                        "Fail to convert a vector of bytes to string. {}",
+                                        e
+                                    );
+
+                                    #[cfg(feature = "logging")]
+                                    warn!(target: "stdout", "{}", &warn_msg);
+
+                                    String::from(" ")
+                                }
+                            }
+                        }
+                    }
+                };
+
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "decoded the output buffer");
+
+                // collect the log probabilities of the output token
+                if choice_state.logprobs {
+                    let logprobs = get_logprobs_by_graph_single(graph)?;
+                    choice_state.pending_logprobs.extend(logprobs);
+                }
+
+                // hold back the text which may be the beginning of a stop sequence
+                let (content, finish_reason) = match choice_state.stop.as_deref() {
+                    Some(stop) if !stop.is_empty() => {
+                        choice_state.pending.push_str(&output);
+
+                        match find_stop_sequence(&choice_state.pending, stop) {
+                            Some(pos) => {
+                                #[cfg(feature = "logging")]
+                                info!(target: "stdout", "Hit a stop sequence. Stop the generation.");
+
+                                choice_state.finished = true;
+
+                                let content = choice_state.pending[..pos].to_string();
+                                choice_state.pending.clear();
+
+                                (content, Some(FinishReason::stop))
+                            }
+                            None => {
+                                let len = choice_state.pending.len()
+                                    - partial_stop_sequence_len(&choice_state.pending, stop);
+
+                                // all the pending text may be the beginning of a stop sequence
+                                if len == 0 {
+                                    continue;
+                                }
+
+                                let content: String = choice_state.pending.drain(..len).collect();
+
+                                (content, None)
+                            }
+                        }
+                    }
+                    _ => (output, None),
+                };
+
+                // clean up the text with the prompt template
+                let content = choice_state.post_process(&content, finish_reason.is_some());
+                if finish_reason.is_none() && content.is_empty() {
+                    continue;
+                }
+
+                // parse the tool calls out of the text
+                let (content, tool_calls, finish_reason) = match choice_state.tool_calls.as_mut() {
+                    Some(tool_calls) => {
+                        let (content, deltas) = tool_calls.push(&content, finish_reason.is_some());
+
+                        // the text may be the beginning of a tool call
+                        if finish_reason.is_none() && content.is_none() && deltas.is_empty() {
+                            continue;
+                        }
+
+                        let finish_reason = match tool_calls.has_tool_calls() {
+                            true => finish_reason.map(|_| FinishReason::tool_calls),
+                            false => finish_reason,
+                        };
+
+                        (content, deltas, finish_reason)
+                    }
+                    None => (Some(content), vec![], finish_reason),
+                };
+
+                let created = SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_err(|e| {
+                        let err_msg = format!("Failed to get the current time. Reason: {}", e);
+
+                        #[cfg(feature = "logging")]
+                        error!(target: "stdout", "{}", &err_msg);
+
+                        LlamaCoreError::Operation(err_msg)
+                    })?;
+
+                let chat_completion_chunk = ChatCompletionChunk {
+                    id,
+                    object: "chat.completion.chunk".to_string(),
+                    created: created.as_secs(),
+                    model: graph.name().to_owned(),
+                    system_fingerprint: gen_system_fingerprint(graph)?,
+                    choices: vec![ChatCompletionChunkChoice {
+                        index: choice_state.index,
+                        delta: ChatCompletionChunkChoiceDelta {
+                            role: ChatCompletionRole::Assistant,
+                            content,
+                            tool_calls,
+                        },
+                        logprobs: choice_state.take_logprobs(),
+                        finish_reason,
+                        cancelled: false,
+                    }],
+                    usage: None,
+                    context_truncation: choice_state.context_truncation.take(),
+                };
+
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "created chat completion chunk");
+
+                // serialize chat completion chunk
+                let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+                    let err_msg =
+                        format!("Failed to serialize chat completion chunk. Reason: {}", e);
+
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", &err_msg);
+
+                    LlamaCoreError::Operation(err_msg)
+                })?;
+
+                break Ok(format!("data: {}\n\n", chunk_str));
+            }
+            Err(InferenceError::EndOfSequence) => {
+                // finish the current choice by flushing the text held back for the stop
+                // sequences, the post-processing and the tool calls, or by notifying the
+                // client if more choices are coming
+                let pending = std::mem::take(&mut choice_state.pending);
+                let pending = choice_state.post_process(&pending, true);
+                if !pending.is_empty()
+                    || choice_state.tool_calls.is_some()
+                    || (choice_state.index as u64) + 1 < choice_state.n_choice
+                {
+                    choice_state.finished = true;
+
+                    let (content, tool_calls, finish_reason) =
+                        match choice_state.tool_calls.as_mut() {
+                            Some(tool_calls) => {
+                                let (content, deltas) = tool_calls.push(&pending, true);
+
+                                let finish_reason = match tool_calls.has_tool_calls() {
+                                    true => FinishReason::tool_calls,
+                                    false => FinishReason::stop,
+                                };
+
+                                (content, deltas, finish_reason)
+                            }
+                            None => {
+                                let content = match pending.is_empty() {
+                                    true => None,
+                                    false => Some(pending),
+                                };
+
+                                (content, vec![], FinishReason::stop)
+                            }
+                        };
+
+                    let created = SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_err(|e| {
+                            let err_msg = format!("Failed to get the current time. Reason: {}", e);
+
+                            #[cfg(feature = "logging")]
+                            error!(target: "stdout", "{}", &err_msg);
+
+                            LlamaCoreError::Operation(err_msg)
+                        })?;
+
+                    let chat_completion_chunk = ChatCompletionChunk {
+                        id,
+                        object: "chat.completion.chunk".to_string(),
+                        created: created.as_secs(),
+                        model: graph.name().to_owned(),
+                        system_fingerprint: gen_system_fingerprint(graph)?,
+                        choices: vec![ChatCompletionChunkChoice {
+                            index: choice_state.index,
+                            delta: ChatCompletionChunkChoiceDelta {
+                                role: ChatCompletionRole::Assistant,
+                                content,
+                                tool_calls,
+                            },
+                            logprobs: choice_state.take_logprobs(),
+                            finish_reason: Some(finish_reason),
+                            cancelled: false,
+                        }],
+                        usage: None,
+                        context_truncation: choice_state.context_truncation.take(),
+                    };
+
+                    // serialize chat completion chunk
+                    let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+                        let err_msg =
+                            format!("Failed to serialize chat completion chunk. Reason: {}", e);
+
+                        #[cfg(feature = "logging")]
+                        error!(target: "stdout", "{}", &err_msg);
+
+                        LlamaCoreError::Operation(err_msg)
+                    })?;
+
+                    break Ok(format!("data: {}\n\n", chunk_str));
+                }
+
+                break end_of_stream(graph, id, stream_state, choice_state.completion_tokens);
+            }
+            Err(InferenceError::ContextFull) => {
+                break match context_full_state {
+                    ContextFullState::Message => {
+                        match include_usage {
+                            true => *context_full_state = ContextFullState::Usage,
+                            false => *context_full_state = ContextFullState::Done,
+                        }
+
+                        // flush the text held back for the stop sequences, the post-processing and the tool calls
+                        let pending = std::mem::take(&mut choice_state.pending);
+                        let pending = choice_state.post_process(&pending, true);
+                        let (content, tool_calls) = match choice_state.tool_calls.as_mut() {
+                            Some(tool_calls) => tool_calls.push(&pending, true),
+                            None => (Some(pending), vec![]),
+                        };
+                        let content = format!(
+                            "{}<|WASMEDGE-GGML-CONTEXT-FULL|>",
+                            content.unwrap_or_default()
+                        );
+
+                        // the current choice and the rest choices are all finished as the context is full
+                        let mut choices = vec![ChatCompletionChunkChoice {
+                            index: choice_state.index,
+                            delta: ChatCompletionChunkChoiceDelta {
+                                role: ChatCompletionRole::Assistant,
+                                content: Some(content),
+                                tool_calls,
+                            },
+                            logprobs: choice_state.take_logprobs(),
+                            finish_reason: Some(FinishReason::length),
+                            cancelled: false,
+                        }];
+                        choices.extend(
+                            ((choice_state.index + 1)..choice_state.n_choice as u32).map(|index| {
+                                ChatCompletionChunkChoice {
+                                    index,
+                                    delta: ChatCompletionChunkChoiceDelta {
+                                        role: ChatCompletionRole::Assistant,
+                                        content: None,
+                                        tool_calls: vec![],
+                                    },
+                                    logprobs: None,
+                                    finish_reason: Some(FinishReason::length),
+                                    cancelled: false,
+                                }
+                            }),
+                        );
+
+                        let created = SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_err(|e| {
+                                let err_msg =
+                                    format!("Failed to get the current time. Reason: {}", e);
+
+                                #[cfg(feature = "logging")]
+                                error!(target: "stdout", "{}", &err_msg);
+
+                                LlamaCoreError::Operation(err_msg)
+                            })?;
+
+                        let chat_completion_chunk = ChatCompletionChunk {
+                            id,
+                            object: "chat.completion.chunk".to_string(),
+                            created: created.as_secs(),
+                            model: graph.name().to_owned(),
+                            system_fingerprint: gen_system_fingerprint(graph)?,
+                            choices,
+                            usage: None,
+                            context_truncation: choice_state.context_truncation.take(),
+                        };
+
+                        // serialize chat completion chunk
+                        let chunk_str =
+                            serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+                                let err_msg = format!(
+                                    "Failed to serialize chat completion chunk. Reason: {}",
+                                    e
+                                );
+
+                                #[cfg(feature = "logging")]
+                                error!(target: "stdout", "{}", &err_msg);
+
+                                LlamaCoreError::Operation(err_msg)
+                            })?;
+
+                        Ok(format!("data: {}\n\n", chunk_str))
+                    }
+                    ContextFullState::Usage => {
+                        *context_full_state = ContextFullState::Done;
+
+                        // retrieve the number of prompt and completion tokens
+                        let token_info = get_token_info_by_graph(graph)?;
+
+                        // count in the completion tokens of the finished choices
+                        let completion_tokens =
+                            token_info.completion_tokens + choice_state.completion_tokens;
+
+                        let usage = Some(Usage {
+                            prompt_tokens: token_info.prompt_tokens,
+                            completion_tokens,
+                            total_tokens: token_info.prompt_tokens + completion_tokens,
+                        });
+
+                        let created = SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_err(|e| {
+                                let err_msg =
+                                    format!("Failed to get the current time. Reason: {}", e);
+
+                                #[cfg(feature = "logging")]
+                                error!(target: "stdout", "{}", &err_msg);
+
+                                LlamaCoreError::Operation(err_msg)
+                            })?;
+
+                        let chat_completion_chunk = ChatCompletionChunk {
+                            id,
+                            object: "chat.completion.chunk".to_string(),
+                            created: created.as_secs(),
+                            model: graph.name().to_owned(),
+                            system_fingerprint: gen_system_fingerprint(graph)?,
+                            choices: vec![],
+                            usage,
+                            context_truncation: choice_state.context_truncation.take(),
+                        };
+
+                        // serialize chat completion chunk
+                        let chunk_str =
+                            serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+                                let err_msg = format!(
+                                    "Failed to serialize chat completion chunk. Reason: {}",
+                                    e
+                                );
+
+                                #[cfg(feature = "logging")]
+                                error!(target: "stdout", "{}", &err_msg);
+
+                                LlamaCoreError::Operation(err_msg)
+                            })?;
 
-        return Ok("[GGML] End of sequence".to_string());
-    }
+                        Ok(format!("data: {}\n\n", chunk_str))
+                    }
+                    ContextFullState::Done => {
+                        *context_full_state = ContextFullState::EndOfSequence;
 
-    let chat_graphs = match CHAT_GRAPHS.get() {
-        Some(chat_graphs) => chat_graphs,
-        None => {
-            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
+                        Ok("data: [DONE]\n\n".to_string())
+                    }
+                    ContextFullState::EndOfSequence => Ok("[GGML] End of sequence".to_string()),
+                };
+            }
+            Err(InferenceError::PromptTooLong) => {
+                break match prompt_too_long_state {
+                    PromptTooLongState::Message => {
+                        match include_usage {
+                            true => *prompt_too_long_state = PromptTooLongState::Usage,
+                            false => *prompt_too_long_state = PromptTooLongState::Done,
+                        }
 
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", &err_msg);
+                        let created = SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_err(|e| {
+                                let err_msg =
+                                    format!("Failed to get the current time. Reason: {}", e);
 
-            return Err(LlamaCoreError::Operation(err_msg.into()));
-        }
-    };
+                                #[cfg(feature = "logging")]
+                                error!(target: "stdout", "{}", &err_msg);
 
-    let mut chat_graphs = chat_graphs.lock().map_err(|e| {
-        let err_msg = format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
+                                LlamaCoreError::Operation(err_msg)
+                            })?;
 
-        #[cfg(feature = "logging")]
-        error!(target: "stdout", "{}", &err_msg);
+                        let chat_completion_chunk = ChatCompletionChunk {
+                            id,
+                            object: "chat.completion.chunk".to_string(),
+                            created: created.as_secs(),
+                            model: graph.name().to_owned(),
+                            system_fingerprint: gen_system_fingerprint(graph)?,
+                            choices: vec![ChatCompletionChunkChoice {
+                                index: choice_state.index,
+                                delta: ChatCompletionChunkChoiceDelta {
+                                    role: ChatCompletionRole::Assistant,
+                                    content: None,
+                                    tool_calls: vec![],
+                                },
+                                logprobs: None,
+                                finish_reason: Some(FinishReason::length),
+                                cancelled: false,
+                            }],
+                            usage: None,
+                            context_truncation: choice_state.context_truncation.take(),
+                        };
 
-        LlamaCoreError::Operation(err_msg)
-    })?;
+                        // serialize chat completion chunk
+                        let chunk_str =
+                            serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+                                let err_msg = format!(
+                                    "Failed to serialize chat completion chunk. Reason: {}",
+                                    e
+                                );
 
-    // get graph
-    let res = match &model_name {
-        Some(model_name) => {
-            match chat_graphs.contains_key(model_name) {
-                true => {
-                    let graph = chat_graphs.get_mut(model_name).unwrap();
-                    // compute
-                    match graph.compute_single() {
-                        Ok(_) => {
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "Compute the chat stream chunk successfully.");
+                                #[cfg(feature = "logging")]
+                                error!(target: "stdout", "{}", &err_msg);
+
+                                LlamaCoreError::Operation(err_msg)
+                            })?;
 
-                            // Retrieve the output
-                            let output_buffer = get_output_buffer_single(graph, OUTPUT_TENSOR)?;
+                        Ok(format!("data: {}\n\n", chunk_str))
+                    }
+                    PromptTooLongState::Usage => {
+                        *prompt_too_long_state = PromptTooLongState::Done;
 
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "retrieved the output buffer");
-
-                            // decode the output buffer to a utf8 string
-                            let output = match String::from_utf8(output_buffer.clone()) {
-                                Ok(token) => token,
-                                Err(_) => {
-                                    let mutex = CACHED_UTF8_ENCODINGS
-                                        .get_or_init(|| Mutex::new(Vec::new()));
-                                    let mut cached_encodings = mutex.lock().map_err(|e| {
-                                            let err_msg = format!(
-                                                "Fail to acquire the lock of `UTF8_ENCODINGS`. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    // cache the bytes for future decoding
-                                    cached_encodings.extend_from_slice(&output_buffer[..]);
-
-                                    match String::from_utf8(cached_encodings.to_vec()) {
-                                        Ok(token) => {
-                                            // clear encodings
-                                            cached_encodings.clear();
-
-                                            token
-                                        }
-                                        Err(e) => {
-                                            // TODO This is a temp check. In case, infinite cached encodings happen.
-                                            if cached_encodings.len() > 4 {
-                                                let err_msg = format!("Fail to convert a vector of bytes to string. The length of the utf8 bytes exceeds 4. {}", e);
-
-                                                #[cfg(feature = "logging")]
-                                                error!(target: "stdout", "{}", &err_msg);
-
-                                                return Err(LlamaCoreError::Operation(err_msg));
-                                            } else {
-                                                let warn_msg = format!("Fail to convert a vector of bytes to string. {}", e);
-
-                                                #[cfg(feature = "logging")]
-                                                warn!(target: "stdout", "{}", &warn_msg);
-
-                                                String::from(" ")
-                                            }
-                                        }
-                                    }
-                                }
-                            };
+                        // retrieve the number of prompt and completion tokens
+                        let token_info = get_token_info_by_graph(graph)?;
 
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "decoded the output buffer");
+                        // count in the completion tokens of the finished choices
+                        let completion_tokens =
+                            token_info.completion_tokens + choice_state.completion_tokens;
+
+                        let usage = Some(Usage {
+                            prompt_tokens: token_info.prompt_tokens,
+                            completion_tokens,
+                            total_tokens: token_info.prompt_tokens + completion_tokens,
+                        });
 
-                            let created = SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .map_err(|e| {
+                        let created = SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_err(|e| {
                                 let err_msg =
                                     format!("Failed to get the current time. Reason: {}", e);
 
@@ -2891,1324 +2984,295 @@ fn compute_stream(
                                 LlamaCoreError::Operation(err_msg)
                             })?;
 
-                            let chat_completion_chunk = ChatCompletionChunk {
-                                id,
-                                object: "chat.completion.chunk".to_string(),
-                                created: created.as_secs(),
-                                model: graph.name().to_owned(),
-                                system_fingerprint: "fp_44709d6fcb".to_string(),
-                                choices: vec![ChatCompletionChunkChoice {
-                                    index: 0,
-                                    delta: ChatCompletionChunkChoiceDelta {
-                                        role: ChatCompletionRole::Assistant,
-                                        content: Some(output),
-                                        tool_calls: vec![],
-                                    },
-                                    logprobs: None,
-                                    finish_reason: None,
-                                }],
-                                usage: None,
-                            };
+                        let chat_completion_chunk = ChatCompletionChunk {
+                            id,
+                            object: "chat.completion.chunk".to_string(),
+                            created: created.as_secs(),
+                            model: graph.name().to_owned(),
+                            system_fingerprint: gen_system_fingerprint(graph)?,
+                            choices: vec![],
+                            usage,
+                            context_truncation: choice_state.context_truncation.take(),
+                        };
 
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "created chat completion chunk");
+                        // serialize chat completion chunk
+                        let chunk_str =
+                            serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+                                let err_msg = format!(
+                                    "Failed to serialize chat completion chunk. Reason: {}",
+                                    e
+                                );
 
-                            // serialize chat completion chunk
-                            let chunk_str =
-                                serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                                    let err_msg = format!(
-                                        "Failed to serialize chat completion chunk. Reason: {}",
-                                        e
-                                    );
+                                #[cfg(feature = "logging")]
+                                error!(target: "stdout", "{}", &err_msg);
 
-                                    #[cfg(feature = "logging")]
-                                    error!(target: "stdout", "{}", &err_msg);
+                                LlamaCoreError::Operation(err_msg)
+                            })?;
 
-                                    LlamaCoreError::Operation(err_msg)
-                                })?;
+                        Ok(format!("data: {}\n\n", chunk_str))
+                    }
+                    PromptTooLongState::Done => {
+                        *prompt_too_long_state = PromptTooLongState::EndOfSequence;
 
-                            Ok(format!("data: {}\n\n", chunk_str))
-                        }
-                        Err(wasmedge_wasi_nn::Error::BackendError(
-                            wasmedge_wasi_nn::BackendError::EndOfSequence,
-                        )) => {
-                            match stream_state {
-                                StreamState::Usage => {
-                                    *stream_state = StreamState::Done;
-
-                                    // retrieve the number of prompt and completion tokens
-                                    let token_info = get_token_info_by_graph(graph)?;
-
-                                    let usage = Some(Usage {
-                                        prompt_tokens: token_info.prompt_tokens,
-                                        completion_tokens: token_info.completion_tokens,
-                                        total_tokens: token_info.prompt_tokens
-                                            + token_info.completion_tokens,
-                                    });
+                        Ok("data: [DONE]\n\n".to_string())
+                    }
+                    PromptTooLongState::EndOfSequence => Ok("[GGML] End of sequence".to_string()),
+                };
+            }
+            Err(e) => {
+                let err_msg = format!("Failed to compute the chat completion. Reason: {}", e);
 
-                                    #[cfg(feature = "logging")]
-                                    info!(target: "stdout", "token_info: {} prompt tokens, {} completion tokens", token_info.prompt_tokens, token_info.completion_tokens);
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![],
-                                        usage,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                StreamState::Done => {
-                                    *stream_state = StreamState::EndOfSequence;
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
 
-                                    Ok("data: [DONE]\n\n".to_string())
-                                }
-                                StreamState::EndOfSequence => {
-                                    Ok("[GGML] End of sequence".to_string())
-                                }
-                            }
-                        }
-                        Err(wasmedge_wasi_nn::Error::BackendError(
-                            wasmedge_wasi_nn::BackendError::ContextFull,
-                        )) => {
-                            match context_full_state {
-                                ContextFullState::Message => {
-                                    match include_usage {
-                                        true => *context_full_state = ContextFullState::Usage,
-                                        false => *context_full_state = ContextFullState::Done,
-                                    }
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![ChatCompletionChunkChoice {
-                                            index: 0,
-                                            delta: ChatCompletionChunkChoiceDelta {
-                                                role: ChatCompletionRole::Assistant,
-                                                content: Some(
-                                                    "<|WASMEDGE-GGML-CONTEXT-FULL|>".to_string(),
-                                                ),
-                                                tool_calls: vec![],
-                                            },
-                                            logprobs: None,
-                                            finish_reason: Some(FinishReason::length),
-                                        }],
-                                        usage: None,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                ContextFullState::Usage => {
-                                    *context_full_state = ContextFullState::Done;
-
-                                    // retrieve the number of prompt and completion tokens
-                                    let token_info = get_token_info_by_graph(graph)?;
-
-                                    let usage = Some(Usage {
-                                        prompt_tokens: token_info.prompt_tokens,
-                                        completion_tokens: token_info.completion_tokens,
-                                        total_tokens: token_info.prompt_tokens
-                                            + token_info.completion_tokens,
-                                    });
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![],
-                                        usage,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                ContextFullState::Done => {
-                                    *context_full_state = ContextFullState::EndOfSequence;
+                break Err(LlamaCoreError::Backend(BackendError::ComputeSingle(
+                    err_msg,
+                )));
+            }
+        }
+    };
 
-                                    Ok("data: [DONE]\n\n".to_string())
-                                }
-                                ContextFullState::EndOfSequence => {
-                                    Ok("[GGML] End of sequence".to_string())
-                                }
-                            }
-                        }
-                        Err(wasmedge_wasi_nn::Error::BackendError(
-                            wasmedge_wasi_nn::BackendError::PromptTooLong,
-                        )) => {
-                            match prompt_too_long_state {
-                                PromptTooLongState::Message => {
-                                    match include_usage {
-                                        true => *prompt_too_long_state = PromptTooLongState::Usage,
-                                        false => *prompt_too_long_state = PromptTooLongState::Done,
-                                    }
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![ChatCompletionChunkChoice {
-                                            index: 0,
-                                            delta: ChatCompletionChunkChoiceDelta {
-                                                role: ChatCompletionRole::Assistant,
-                                                content: None,
-                                                tool_calls: vec![],
-                                            },
-                                            logprobs: None,
-                                            finish_reason: Some(FinishReason::length),
-                                        }],
-                                        usage: None,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                PromptTooLongState::Usage => {
-                                    *prompt_too_long_state = PromptTooLongState::Done;
-
-                                    // retrieve the number of prompt and completion tokens
-                                    let token_info = get_token_info_by_graph(graph)?;
-
-                                    let usage = Some(Usage {
-                                        prompt_tokens: token_info.prompt_tokens,
-                                        completion_tokens: token_info.completion_tokens,
-                                        total_tokens: token_info.prompt_tokens
-                                            + token_info.completion_tokens,
-                                    });
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![],
-                                        usage,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                PromptTooLongState::Done => {
-                                    *prompt_too_long_state = PromptTooLongState::EndOfSequence;
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Return the chat stream chunk!");
 
-                                    Ok("data: [DONE]\n\n".to_string())
-                                }
-                                PromptTooLongState::EndOfSequence => {
-                                    Ok("[GGML] End of sequence".to_string())
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let err_msg =
-                                format!("Failed to compute the chat completion. Reason: {}", e);
+    res
+}
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+/// Returns the usage chunk, the `[DONE]` chunk and the end-of-sequence flag in turn when the stream is finished.
+fn end_of_stream(
+    graph: &mut Graph<GgmlMetadata>,
+    id: String,
+    stream_state: &mut StreamState,
+    completion_tokens: u64,
+) -> Result<String, LlamaCoreError> {
+    match stream_state {
+        StreamState::Usage => {
+            *stream_state = StreamState::Done;
 
-                            Err(LlamaCoreError::Backend(BackendError::ComputeSingle(
-                                err_msg,
-                            )))
-                        }
-                    }
-                }
-                false => {
-                    match chat_graphs.iter_mut().next() {
-                        Some((_, graph)) => {
-                            // compute
-                            match graph.compute_single() {
-                                Ok(_) => {
-                                    #[cfg(feature = "logging")]
-                                    info!(target: "stdout", "Compute the chat stream chunk successfully.");
+            // retrieve the number of prompt and completion tokens
+            let token_info = get_token_info_by_graph(graph)?;
 
-                                    // Retrieve the output
-                                    let output_buffer =
-                                        get_output_buffer_single(graph, OUTPUT_TENSOR)?;
+            // count in the completion tokens of the finished choices
+            let completion_tokens = token_info.completion_tokens + completion_tokens;
 
-                                    #[cfg(feature = "logging")]
-                                    info!(target: "stdout", "retrieved the output buffer");
-
-                                    // decode the output buffer to a utf8 string
-                                    let output = match String::from_utf8(output_buffer.clone()) {
-                                        Ok(token) => token,
-                                        Err(_) => {
-                                            let mutex = CACHED_UTF8_ENCODINGS
-                                                .get_or_init(|| Mutex::new(Vec::new()));
-                                            let mut cached_encodings = mutex.lock().map_err(|e| {
-                                            let err_msg = format!(
-                                                "Fail to acquire the lock of `UTF8_ENCODINGS`. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                            // cache the bytes for future decoding
-                                            cached_encodings.extend_from_slice(&output_buffer[..]);
-
-                                            match String::from_utf8(cached_encodings.to_vec()) {
-                                                Ok(token) => {
-                                                    // clear encodings
-                                                    cached_encodings.clear();
-
-                                                    token
-                                                }
-                                                Err(e) => {
-                                                    // TODO This is a temp check. In case, infinite cached encodings happen.
-                                                    if cached_encodings.len() > 4 {
-                                                        let err_msg = format!("Fail to convert a vector of bytes to string. The length of the utf8 bytes exceeds 4. {}", e);
-
-                                                        #[cfg(feature = "logging")]
-                                                        error!(target: "stdout", "{}", &err_msg);
-
-                                                        return Err(LlamaCoreError::Operation(
-                                                            err_msg,
-                                                        ));
-                                                    } else {
-                                                        let warn_msg = format!("Fail to convert a vector of bytes to string. {}", e);
-
-                                                        #[cfg(feature = "logging")]
-                                                        warn!(target: "stdout", "{}", &warn_msg);
-
-                                                        String::from(" ")
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    };
+            let usage = Some(Usage {
+                prompt_tokens: token_info.prompt_tokens,
+                completion_tokens,
+                total_tokens: token_info.prompt_tokens + completion_tokens,
+            });
 
-                                    #[cfg(feature = "logging")]
-                                    info!(target: "stdout", "decoded the output buffer");
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![ChatCompletionChunkChoice {
-                                            index: 0,
-                                            delta: ChatCompletionChunkChoiceDelta {
-                                                role: ChatCompletionRole::Assistant,
-                                                content: Some(output),
-                                                tool_calls: vec![],
-                                            },
-                                            logprobs: None,
-                                            finish_reason: None,
-                                        }],
-                                        usage: None,
-                                    };
+            #[cfg(feature = "logging")]
+            info!(target: "stdout", "token_info: {} prompt tokens, {} completion tokens", token_info.prompt_tokens, token_info.completion_tokens);
 
-                                    #[cfg(feature = "logging")]
-                                    info!(target: "stdout", "created chat completion chunk");
+            let created = SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_err(|e| {
+                    let err_msg = format!("Failed to get the current time. Reason: {}", e);
 
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", &err_msg);
 
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
+                    LlamaCoreError::Operation(err_msg)
+                })?;
 
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
+            let chat_completion_chunk = ChatCompletionChunk {
+                id,
+                object: "chat.completion.chunk".to_string(),
+                created: created.as_secs(),
+                model: graph.name().to_owned(),
+                system_fingerprint: gen_system_fingerprint(graph)?,
+                choices: vec![],
+                usage,
+                context_truncation: None,
+            };
 
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                Err(wasmedge_wasi_nn::Error::BackendError(
-                                    wasmedge_wasi_nn::BackendError::EndOfSequence,
-                                )) => {
-                                    match stream_state {
-                                        StreamState::Usage => {
-                                            *stream_state = StreamState::Done;
-
-                                            // retrieve the number of prompt and completion tokens
-                                            let token_info = get_token_info_by_graph(graph)?;
-
-                                            let usage = Some(Usage {
-                                                prompt_tokens: token_info.prompt_tokens,
-                                                completion_tokens: token_info.completion_tokens,
-                                                total_tokens: token_info.prompt_tokens
-                                                    + token_info.completion_tokens,
-                                            });
-
-                                            #[cfg(feature = "logging")]
-                                            info!(target: "stdout", "token_info: {} prompt tokens, {} completion tokens", token_info.prompt_tokens, token_info.completion_tokens);
-
-                                            let created = SystemTime::now()
-                                                .duration_since(std::time::UNIX_EPOCH)
-                                                .map_err(|e| {
-                                                    let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                                    #[cfg(feature = "logging")]
-                                                    error!(target: "stdout", "{}", &err_msg);
-
-                                                    LlamaCoreError::Operation(err_msg)
-                                                })?;
-
-                                            let chat_completion_chunk = ChatCompletionChunk {
-                                                id,
-                                                object: "chat.completion.chunk".to_string(),
-                                                created: created.as_secs(),
-                                                model: graph.name().to_owned(),
-                                                system_fingerprint: "fp_44709d6fcb".to_string(),
-                                                choices: vec![],
-                                                usage,
-                                            };
-
-                                            // serialize chat completion chunk
-                                            let chunk_str =
-                                                serde_json::to_string(&chat_completion_chunk)
-                                                    .map_err(|e| {
-                                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                                        #[cfg(feature = "logging")]
-                                                        error!(target: "stdout", "{}", &err_msg);
-
-                                                        LlamaCoreError::Operation(err_msg)
-                                                    })?;
-
-                                            Ok(format!("data: {}\n\n", chunk_str))
-                                        }
-                                        StreamState::Done => {
-                                            *stream_state = StreamState::EndOfSequence;
-
-                                            Ok("data: [DONE]\n\n".to_string())
-                                        }
-                                        StreamState::EndOfSequence => {
-                                            Ok("[GGML] End of sequence".to_string())
-                                        }
-                                    }
-                                }
-                                Err(wasmedge_wasi_nn::Error::BackendError(
-                                    wasmedge_wasi_nn::BackendError::ContextFull,
-                                )) => {
-                                    match context_full_state {
-                                        ContextFullState::Message => {
-                                            match include_usage {
-                                                true => {
-                                                    *context_full_state = ContextFullState::Usage
-                                                }
-                                                false => {
-                                                    *context_full_state = ContextFullState::Done
-                                                }
-                                            }
-
-                                            let created = SystemTime::now()
-                                                .duration_since(std::time::UNIX_EPOCH)
-                                                .map_err(|e| {
-                                                    let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                                    #[cfg(feature = "logging")]
-                                                    error!(target: "stdout", "{}", &err_msg);
-
-                                                    LlamaCoreError::Operation(err_msg)
-                                                })?;
-
-                                            let chat_completion_chunk = ChatCompletionChunk {
-                                                id,
-                                                object: "chat.completion.chunk".to_string(),
-                                                created: created.as_secs(),
-                                                model: graph.name().to_owned(),
-                                                system_fingerprint: "fp_44709d6fcb".to_string(),
-                                                choices: vec![ChatCompletionChunkChoice {
-                                                    index: 0,
-                                                    delta: ChatCompletionChunkChoiceDelta {
-                                                        role: ChatCompletionRole::Assistant,
-                                                        content: Some(
-                                                            "<|WASMEDGE-GGML-CONTEXT-FULL|>"
-                                                                .to_string(),
-                                                        ),
-                                                        tool_calls: vec![],
-                                                    },
-                                                    logprobs: None,
-                                                    finish_reason: Some(FinishReason::length),
-                                                }],
-                                                usage: None,
-                                            };
-
-                                            // serialize chat completion chunk
-                                            let chunk_str =
-                                                serde_json::to_string(&chat_completion_chunk)
-                                                    .map_err(|e| {
-                                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                                        #[cfg(feature = "logging")]
-                                                        error!(target: "stdout", "{}", &err_msg);
-
-                                                        LlamaCoreError::Operation(err_msg)
-                                                    })?;
-
-                                            Ok(format!("data: {}\n\n", chunk_str))
-                                        }
-                                        ContextFullState::Usage => {
-                                            *context_full_state = ContextFullState::Done;
-
-                                            // retrieve the number of prompt and completion tokens
-                                            let token_info = get_token_info_by_graph(graph)?;
-
-                                            let usage = Some(Usage {
-                                                prompt_tokens: token_info.prompt_tokens,
-                                                completion_tokens: token_info.completion_tokens,
-                                                total_tokens: token_info.prompt_tokens
-                                                    + token_info.completion_tokens,
-                                            });
-
-                                            let created = SystemTime::now()
-                                                .duration_since(std::time::UNIX_EPOCH)
-                                                .map_err(|e| {
-                                                    let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                                    #[cfg(feature = "logging")]
-                                                    error!(target: "stdout", "{}", &err_msg);
-
-                                                    LlamaCoreError::Operation(err_msg)
-                                                })?;
-
-                                            let chat_completion_chunk = ChatCompletionChunk {
-                                                id,
-                                                object: "chat.completion.chunk".to_string(),
-                                                created: created.as_secs(),
-                                                model: graph.name().to_owned(),
-                                                system_fingerprint: "fp_44709d6fcb".to_string(),
-                                                choices: vec![],
-                                                usage,
-                                            };
-
-                                            // serialize chat completion chunk
-                                            let chunk_str =
-                                                serde_json::to_string(&chat_completion_chunk)
-                                                    .map_err(|e| {
-                                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                                        #[cfg(feature = "logging")]
-                                                        error!(target: "stdout", "{}", &err_msg);
-
-                                                        LlamaCoreError::Operation(err_msg)
-                                                    })?;
-
-                                            Ok(format!("data: {}\n\n", chunk_str))
-                                        }
-                                        ContextFullState::Done => {
-                                            *context_full_state = ContextFullState::EndOfSequence;
-
-                                            Ok("data: [DONE]\n\n".to_string())
-                                        }
-                                        ContextFullState::EndOfSequence => {
-                                            Ok("[GGML] End of sequence".to_string())
-                                        }
-                                    }
-                                }
-                                Err(wasmedge_wasi_nn::Error::BackendError(
-                                    wasmedge_wasi_nn::BackendError::PromptTooLong,
-                                )) => {
-                                    match prompt_too_long_state {
-                                        PromptTooLongState::Message => {
-                                            match include_usage {
-                                                true => {
-                                                    *prompt_too_long_state =
-                                                        PromptTooLongState::Usage
-                                                }
-                                                false => {
-                                                    *prompt_too_long_state =
-                                                        PromptTooLongState::Done
-                                                }
-                                            }
-
-                                            let created = SystemTime::now()
-                                                .duration_since(std::time::UNIX_EPOCH)
-                                                .map_err(|e| {
-                                                    let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                                    #[cfg(feature = "logging")]
-                                                    error!(target: "stdout", "{}", &err_msg);
-
-                                                    LlamaCoreError::Operation(err_msg)
-                                                })?;
-
-                                            let chat_completion_chunk = ChatCompletionChunk {
-                                                id,
-                                                object: "chat.completion.chunk".to_string(),
-                                                created: created.as_secs(),
-                                                model: graph.name().to_owned(),
-                                                system_fingerprint: "fp_44709d6fcb".to_string(),
-                                                choices: vec![ChatCompletionChunkChoice {
-                                                    index: 0,
-                                                    delta: ChatCompletionChunkChoiceDelta {
-                                                        role: ChatCompletionRole::Assistant,
-                                                        content: None,
-                                                        tool_calls: vec![],
-                                                    },
-                                                    logprobs: None,
-                                                    finish_reason: Some(FinishReason::length),
-                                                }],
-                                                usage: None,
-                                            };
-
-                                            // serialize chat completion chunk
-                                            let chunk_str =
-                                                serde_json::to_string(&chat_completion_chunk)
-                                                    .map_err(|e| {
-                                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                                        #[cfg(feature = "logging")]
-                                                        error!(target: "stdout", "{}", &err_msg);
-
-                                                        LlamaCoreError::Operation(err_msg)
-                                                    })?;
-
-                                            Ok(format!("data: {}\n\n", chunk_str))
-                                        }
-                                        PromptTooLongState::Usage => {
-                                            *prompt_too_long_state = PromptTooLongState::Done;
-
-                                            // retrieve the number of prompt and completion tokens
-                                            let token_info = get_token_info_by_graph(graph)?;
-
-                                            let usage = Some(Usage {
-                                                prompt_tokens: token_info.prompt_tokens,
-                                                completion_tokens: token_info.completion_tokens,
-                                                total_tokens: token_info.prompt_tokens
-                                                    + token_info.completion_tokens,
-                                            });
-
-                                            let created = SystemTime::now()
-                                                .duration_since(std::time::UNIX_EPOCH)
-                                                .map_err(|e| {
-                                                    let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                                    #[cfg(feature = "logging")]
-                                                    error!(target: "stdout", "{}", &err_msg);
-
-                                                    LlamaCoreError::Operation(err_msg)
-                                                })?;
-
-                                            let chat_completion_chunk = ChatCompletionChunk {
-                                                id,
-                                                object: "chat.completion.chunk".to_string(),
-                                                created: created.as_secs(),
-                                                model: graph.name().to_owned(),
-                                                system_fingerprint: "fp_44709d6fcb".to_string(),
-                                                choices: vec![],
-                                                usage,
-                                            };
-
-                                            // serialize chat completion chunk
-                                            let chunk_str =
-                                                serde_json::to_string(&chat_completion_chunk)
-                                                    .map_err(|e| {
-                                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                                        #[cfg(feature = "logging")]
-                                                        error!(target: "stdout", "{}", &err_msg);
-
-                                                        LlamaCoreError::Operation(err_msg)
-                                                    })?;
-
-                                            Ok(format!("data: {}\n\n", chunk_str))
-                                        }
-                                        PromptTooLongState::Done => {
-                                            *prompt_too_long_state =
-                                                PromptTooLongState::EndOfSequence;
-
-                                            Ok("data: [DONE]\n\n".to_string())
-                                        }
-                                        PromptTooLongState::EndOfSequence => {
-                                            Ok("[GGML] End of sequence".to_string())
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    let err_msg = format!(
-                                        "Failed to compute the chat completion. Reason: {}",
-                                        e
-                                    );
+            // serialize chat completion chunk
+            let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+                let err_msg = format!("Failed to serialize chat completion chunk. Reason: {}", e);
 
-                                    #[cfg(feature = "logging")]
-                                    error!(target: "stdout", "{}", &err_msg);
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
 
-                                    Err(LlamaCoreError::Backend(BackendError::ComputeSingle(
-                                        err_msg,
-                                    )))
-                                }
-                            }
-                        }
-                        None => {
-                            let err_msg = "There is no model available in the chat graphs.";
+                LlamaCoreError::Operation(err_msg)
+            })?;
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+            Ok(format!("data: {}\n\n", chunk_str))
+        }
+        StreamState::Done => {
+            *stream_state = StreamState::EndOfSequence;
 
-                            Err(LlamaCoreError::Operation(err_msg.into()))
-                        }
-                    }
-                }
-            }
+            Ok("data: [DONE]\n\n".to_string())
         }
-        None => {
-            match chat_graphs.iter_mut().next() {
-                Some((_, graph)) => {
-                    // compute
-                    match graph.compute_single() {
-                        Ok(_) => {
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "Compute the chat stream chunk successfully.");
+        StreamState::EndOfSequence => Ok("[GGML] End of sequence".to_string()),
+    }
+}
 
-                            // Retrieve the output
-                            let output_buffer = get_output_buffer_single(graph, OUTPUT_TENSOR)?;
+/// Resets the context and feeds the prompt to the model again to generate the next choice.
+fn next_choice(
+    graph: &mut Graph<GgmlMetadata>,
+    choice_state: &mut ChoiceState,
+) -> Result<(), LlamaCoreError> {
+    // count the completion tokens of the finished choice
+    let token_info = get_token_info_by_graph(graph)?;
+    choice_state.completion_tokens += token_info.completion_tokens;
 
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "retrieved the output buffer");
-
-                            // decode the output buffer to a utf8 string
-                            let output = match String::from_utf8(output_buffer.clone()) {
-                                Ok(token) => token,
-                                Err(_) => {
-                                    let mutex = CACHED_UTF8_ENCODINGS
-                                        .get_or_init(|| Mutex::new(Vec::new()));
-                                    let mut cached_encodings = mutex.lock().map_err(|e| {
-                                            let err_msg = format!(
-                                                "Fail to acquire the lock of `UTF8_ENCODINGS`. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    cached_encodings.extend_from_slice(&output_buffer[..]);
-
-                                    match String::from_utf8(cached_encodings.to_vec()) {
-                                        Ok(token) => {
-                                            // clear encodings
-                                            cached_encodings.clear();
-
-                                            token
-                                        }
-                                        Err(e) => {
-                                            // TODO This is a temp check. In case, infinite cached encodings happen.
-                                            if cached_encodings.len() > 4 {
-                                                let err_msg = format!("Fail to convert a vector of bytes to string. The length of the utf8 bytes exceeds 4. {}", e);
-
-                                                #[cfg(feature = "logging")]
-                                                error!(target: "stdout", "{}", &err_msg);
-
-                                                return Err(LlamaCoreError::Operation(err_msg));
-                                            } else {
-                                                let warn_msg = format!("Fail to convert a vector of bytes to string. {}", e);
-
-                                                #[cfg(feature = "logging")]
-                                                warn!(target: "stdout", "{}", &warn_msg);
-
-                                                String::from(" ")
-                                            }
-                                        }
-                                    }
-                                }
-                            };
+    // clean up the context of the finished choice
+    graph.finish_single().map_err(|e| {
+        let err_msg = format!("Failed to clean up the context. Reason: {}", e);
 
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "decoded the output buffer");
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
 
-                            let created = SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .map_err(|e| {
-                                let err_msg =
-                                    format!("Failed to get the current time. Reason: {}", e);
+        LlamaCoreError::Backend(BackendError::FinishSingle(err_msg))
+    })?;
 
-                                #[cfg(feature = "logging")]
-                                error!(target: "stdout", "{}", &err_msg);
+    // feed the prompt again
+    set_tensor_data_u8(graph, 0, choice_state.prompt.as_bytes())?;
+
+    choice_state.index += 1;
+    choice_state.pending.clear();
+    choice_state.pending_logprobs.clear();
+    choice_state.output.clear();
+    choice_state.processed_len = 0;
+    choice_state.finished = false;
+    if let Some(tool_calls) = choice_state.tool_calls.as_mut() {
+        tool_calls.reset();
+    }
 
-                                LlamaCoreError::Operation(err_msg)
-                            })?;
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Generate the choice with index {}.", choice_state.index);
 
-                            let chat_completion_chunk = ChatCompletionChunk {
-                                id,
-                                object: "chat.completion.chunk".to_string(),
-                                created: created.as_secs(),
-                                model: graph.name().to_owned(),
-                                system_fingerprint: "fp_44709d6fcb".to_string(),
-                                choices: vec![ChatCompletionChunkChoice {
-                                    index: 0,
-                                    delta: ChatCompletionChunkChoiceDelta {
-                                        role: ChatCompletionRole::Assistant,
-                                        content: Some(output),
-                                        tool_calls: vec![],
-                                    },
-                                    logprobs: None,
-                                    finish_reason: None,
-                                }],
-                                usage: None,
-                            };
+    Ok(())
+}
 
-                            #[cfg(feature = "logging")]
-                            info!(target: "stdout", "created chat completion chunk");
+/// Finishes the current choice as cancelled by flushing the text held back, and skips the rest choices.
+fn cancel_choice(
+    graph: &mut Graph<GgmlMetadata>,
+    id: String,
+    choice_state: &mut ChoiceState,
+) -> Result<String, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "The chat request is cancelled. Stop the generation.");
+
+    choice_state.finished = true;
+    choice_state.n_choice = choice_state.index as u64 + 1;
+
+    let pending = std::mem::take(&mut choice_state.pending);
+    let pending = choice_state.post_process(&pending, true);
+    let (content, tool_calls) = match choice_state.tool_calls.as_mut() {
+        Some(tool_calls) => tool_calls.push(&pending, true),
+        None => match pending.is_empty() {
+            true => (None, vec![]),
+            false => (Some(pending), vec![]),
+        },
+    };
 
-                            // serialize chat completion chunk
-                            let chunk_str =
-                                serde_json::to_string(&chat_completion_chunk).map_err(|e| {
-                                    let err_msg = format!(
-                                        "Failed to serialize chat completion chunk. Reason: {}",
-                                        e
-                                    );
+    let created = SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| {
+            let err_msg = format!("Failed to get the current time. Reason: {}", e);
 
-                                    #[cfg(feature = "logging")]
-                                    error!(target: "stdout", "{}", &err_msg);
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
 
-                                    LlamaCoreError::Operation(err_msg)
-                                })?;
+            LlamaCoreError::Operation(err_msg)
+        })?;
 
-                            Ok(format!("data: {}\n\n", chunk_str))
-                        }
-                        Err(wasmedge_wasi_nn::Error::BackendError(
-                            wasmedge_wasi_nn::BackendError::EndOfSequence,
-                        )) => {
-                            match stream_state {
-                                StreamState::Usage => {
-                                    *stream_state = StreamState::Done;
-
-                                    // retrieve the number of prompt and completion tokens
-                                    let token_info = get_token_info_by_graph(graph)?;
-
-                                    let usage = Some(Usage {
-                                        prompt_tokens: token_info.prompt_tokens,
-                                        completion_tokens: token_info.completion_tokens,
-                                        total_tokens: token_info.prompt_tokens
-                                            + token_info.completion_tokens,
-                                    });
+    let chat_completion_chunk = ChatCompletionChunk {
+        id,
+        object: "chat.completion.chunk".to_string(),
+        created: created.as_secs(),
+        model: graph.name().to_owned(),
+        system_fingerprint: gen_system_fingerprint(graph)?,
+        choices: vec![ChatCompletionChunkChoice {
+            index: choice_state.index,
+            delta: ChatCompletionChunkChoiceDelta {
+                role: ChatCompletionRole::Assistant,
+                content,
+                tool_calls,
+            },
+            logprobs: choice_state.take_logprobs(),
+            finish_reason: Some(FinishReason::stop),
+            cancelled: true,
+        }],
+        usage: None,
+        context_truncation: choice_state.context_truncation.take(),
+    };
 
-                                    #[cfg(feature = "logging")]
-                                    info!(target: "stdout", "token_info: {} prompt tokens, {} completion tokens", token_info.prompt_tokens, token_info.completion_tokens);
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![],
-                                        usage,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                StreamState::Done => {
-                                    *stream_state = StreamState::EndOfSequence;
+    // serialize chat completion chunk
+    let chunk_str = serde_json::to_string(&chat_completion_chunk).map_err(|e| {
+        let err_msg = format!("Failed to serialize chat completion chunk. Reason: {}", e);
 
-                                    Ok("data: [DONE]\n\n".to_string())
-                                }
-                                StreamState::EndOfSequence => {
-                                    Ok("[GGML] End of sequence".to_string())
-                                }
-                            }
-                        }
-                        Err(wasmedge_wasi_nn::Error::BackendError(
-                            wasmedge_wasi_nn::BackendError::ContextFull,
-                        )) => {
-                            match context_full_state {
-                                ContextFullState::Message => {
-                                    match include_usage {
-                                        true => *context_full_state = ContextFullState::Usage,
-                                        false => *context_full_state = ContextFullState::Done,
-                                    }
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![ChatCompletionChunkChoice {
-                                            index: 0,
-                                            delta: ChatCompletionChunkChoiceDelta {
-                                                role: ChatCompletionRole::Assistant,
-                                                content: Some(
-                                                    "<|WASMEDGE-GGML-CONTEXT-FULL|>".to_string(),
-                                                ),
-                                                tool_calls: vec![],
-                                            },
-                                            logprobs: None,
-                                            finish_reason: Some(FinishReason::length),
-                                        }],
-                                        usage: None,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                ContextFullState::Usage => {
-                                    *context_full_state = ContextFullState::Done;
-
-                                    // retrieve the number of prompt and completion tokens
-                                    let token_info = get_token_info_by_graph(graph)?;
-
-                                    let usage = Some(Usage {
-                                        prompt_tokens: token_info.prompt_tokens,
-                                        completion_tokens: token_info.completion_tokens,
-                                        total_tokens: token_info.prompt_tokens
-                                            + token_info.completion_tokens,
-                                    });
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![],
-                                        usage,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                ContextFullState::Done => {
-                                    *context_full_state = ContextFullState::EndOfSequence;
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
 
-                                    Ok("data: [DONE]\n\n".to_string())
-                                }
-                                ContextFullState::EndOfSequence => {
-                                    Ok("[GGML] End of sequence".to_string())
-                                }
-                            }
-                        }
-                        Err(wasmedge_wasi_nn::Error::BackendError(
-                            wasmedge_wasi_nn::BackendError::PromptTooLong,
-                        )) => {
-                            match prompt_too_long_state {
-                                PromptTooLongState::Message => {
-                                    match include_usage {
-                                        true => *prompt_too_long_state = PromptTooLongState::Usage,
-                                        false => *prompt_too_long_state = PromptTooLongState::Done,
-                                    }
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![ChatCompletionChunkChoice {
-                                            index: 0,
-                                            delta: ChatCompletionChunkChoiceDelta {
-                                                role: ChatCompletionRole::Assistant,
-                                                content: None,
-                                                tool_calls: vec![],
-                                            },
-                                            logprobs: None,
-                                            finish_reason: Some(FinishReason::length),
-                                        }],
-                                        usage: None,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                PromptTooLongState::Usage => {
-                                    *prompt_too_long_state = PromptTooLongState::Done;
-
-                                    // retrieve the number of prompt and completion tokens
-                                    let token_info = get_token_info_by_graph(graph)?;
-
-                                    let usage = Some(Usage {
-                                        prompt_tokens: token_info.prompt_tokens,
-                                        completion_tokens: token_info.completion_tokens,
-                                        total_tokens: token_info.prompt_tokens
-                                            + token_info.completion_tokens,
-                                    });
-
-                                    let created = SystemTime::now()
-                                        .duration_since(std::time::UNIX_EPOCH)
-                                        .map_err(|e| {
-                                            let err_msg = format!(
-                                                "Failed to get the current time. Reason: {}",
-                                                e
-                                            );
-
-                                            #[cfg(feature = "logging")]
-                                            error!(target: "stdout", "{}", &err_msg);
-
-                                            LlamaCoreError::Operation(err_msg)
-                                        })?;
-
-                                    let chat_completion_chunk = ChatCompletionChunk {
-                                        id,
-                                        object: "chat.completion.chunk".to_string(),
-                                        created: created.as_secs(),
-                                        model: graph.name().to_owned(),
-                                        system_fingerprint: "fp_44709d6fcb".to_string(),
-                                        choices: vec![],
-                                        usage,
-                                    };
-
-                                    // serialize chat completion chunk
-                                    let chunk_str = serde_json::to_string(&chat_completion_chunk)
-                                        .map_err(|e| {
-                                        let err_msg = format!(
-                                            "Failed to serialize chat completion chunk. Reason: {}",
-                                            e
-                                        );
-
-                                        #[cfg(feature = "logging")]
-                                        error!(target: "stdout", "{}", &err_msg);
-
-                                        LlamaCoreError::Operation(err_msg)
-                                    })?;
-
-                                    Ok(format!("data: {}\n\n", chunk_str))
-                                }
-                                PromptTooLongState::Done => {
-                                    *prompt_too_long_state = PromptTooLongState::EndOfSequence;
+        LlamaCoreError::Operation(err_msg)
+    })?;
 
-                                    Ok("data: [DONE]\n\n".to_string())
-                                }
-                                PromptTooLongState::EndOfSequence => {
-                                    Ok("[GGML] End of sequence".to_string())
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let err_msg =
-                                format!("Failed to compute the chat completion. Reason: {}", e);
+    Ok(format!("data: {}\n\n", chunk_str))
+}
 
-                            #[cfg(feature = "logging")]
-                            error!(target: "stdout", "{}", &err_msg);
+/// Returns the byte position of the earliest stop sequence in the text.
+pub(crate) fn find_stop_sequence(text: &str, stop: &[String]) -> Option<usize> {
+    stop.iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| text.find(s.as_str()))
+        .min()
+}
 
-                            Err(LlamaCoreError::Backend(BackendError::ComputeSingle(
-                                err_msg,
-                            )))
-                        }
-                    }
-                }
-                None => {
-                    let err_msg = "There is no model available in the chat graphs.";
+/// Returns the position in bytes of the earliest stop sequence in the output, given that the first `searched` bytes of the output contain no stop sequence. Only the bytes after them, and the tail before them a stop sequence may start in, are searched.
+fn find_stop_sequence_after(output: &[u8], searched: usize, stop: &[String]) -> Option<usize> {
+    stop.iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| {
+            let start = searched.saturating_sub(s.len() - 1);
+            output
+                .get(start..)?
+                .windows(s.len())
+                .position(|window| window == s.as_bytes())
+                .map(|pos| start + pos)
+        })
+        .min()
+}
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+/// Returns the length in bytes of the longest suffix of the text which is the beginning of a stop sequence.
+pub(crate) fn partial_stop_sequence_len(text: &str, stop: &[String]) -> usize {
+    stop.iter()
+        .flat_map(|s| s.char_indices().skip(1).map(move |(idx, _)| &s[..idx]))
+        .filter(|prefix| text.ends_with(prefix))
+        .map(|prefix| prefix.len())
+        .max()
+        .unwrap_or_default()
+}
 
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            }
-        }
-    };
+#[test]
+fn test_find_stop_sequence_after() {
+    let stop = ["</s>".to_string(), "\n\n".to_string()];
 
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "Return the chat stream chunk!");
+    // the stop sequence spans the searched output and the new token
+    let output = b"Hello</s> world";
+    assert_eq!(find_stop_sequence_after(output, 7, &stop), Some(5));
+    assert_eq!(find_stop_sequence_after(output, 5, &stop), Some(5));
 
-    res
+    // the earliest stop sequence in the new tail
+    let output = b"Hello\n\nworld</s>";
+    assert_eq!(find_stop_sequence_after(output, 3, &stop), Some(5));
+
+    // the searched output is not searched again
+    assert_eq!(find_stop_sequence_after(b"</s>abcd", 8, &stop), None);
+    assert_eq!(find_stop_sequence_after(b"Hello", 0, &stop), None);
 }
 
-#[derive(Debug)]
-struct ParseResult {
-    raw: String,
-    content: Option<String>,
-    tool_calls: Vec<ToolCall>,
+/// Truncates the text at the earliest stop sequence. Returns `true` if a stop sequence is found.
+fn truncate_at_stop_sequence(text: &mut String, stop: Option<&[String]>) -> bool {
+    match stop.and_then(|stop| find_stop_sequence(text, stop)) {
+        Some(pos) => {
+            #[cfg(feature = "logging")]
+            info!(target: "stdout", "Truncate the generation at the stop sequence.");
+
+            text.truncate(pos);
+            true
+        }
+        None => false,
+    }
 }
@@ -1,197 +1,1008 @@
 //! Define APIs for completions.
 
 use crate::{
+    chat::{
+        find_stop_sequence, get_model_metadata, partial_stop_sequence_len, update_model_metadata,
+        with_chat_graph, MetadataGuard,
+    },
     error::{BackendError, LlamaCoreError},
+    graph::InferenceError,
     metadata::ggml::GgmlMetadata,
     running_mode,
-    utils::{get_output_buffer, get_token_info_by_graph},
-    Graph, RunningMode, CHAT_GRAPHS, OUTPUT_TENSOR,
+    scheduler::{self, SchedulerPermit},
+    utils::{
+        check_logprobs_support, current_timestamp, gen_system_fingerprint,
+        get_logprobs_by_graph_single, get_output_buffer_single, get_token_info_by_graph,
+        parse_logit_bias, set_tensor_data_u8,
+    },
+    Graph, RunningMode, OUTPUT_TENSOR,
 };
+use chat_prompts::infill::{BuildInfillPrompt, InfillPrompt, InfillTemplateType};
 use endpoints::{
+    chat::TokenLogProb,
     common::{FinishReason, Usage},
-    completions::{CompletionChoice, CompletionObject, CompletionPrompt, CompletionRequest},
+    completions::{
+        CompletionChoice, CompletionChunk, CompletionChunkChoice, CompletionObject,
+        CompletionPrompt, CompletionRequest, LogprobResult,
+    },
+    infill::{InfillChunk, InfillRequest, InfillResponse},
+};
+use serde::Serialize;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
 };
-use std::time::SystemTime;
 
 /// Given a prompt, the model will return one or more predicted completions along with the probabilities of alternative tokens at each position.
+///
+/// If `suffix` is set and the model is trained to fill in the middle, the choices are the code between the prompt and the suffix, built with the infill template of the model. Otherwise, the suffix is appended to the choices.
+///
+/// If the prompt is a list of prompts, `n` choices are generated for each prompt, and the choices of the `i`-th prompt have the indexes from `i * n` to `i * n + n - 1`. If `best_of` is greater than `n`, `best_of` candidates are generated for each prompt, and the `n` candidates with the highest log probability per token are returned.
 pub async fn completions(request: &CompletionRequest) -> Result<CompletionObject, LlamaCoreError> {
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Generate completions");
 
-    let running_mode = running_mode()?;
-    if running_mode == RunningMode::Embeddings || running_mode == RunningMode::Rag {
-        let err_msg = format!(
-            "The completion is not supported in the {} mode.",
-            running_mode
-        );
+    let params = CompletionParams::new(request, false)?;
 
-        #[cfg(feature = "logging")]
-        error!(target: "stdout", "{}", &err_msg);
+    // wait for the turn to use the model
+    let _permit = scheduler::acquire(request.model.as_ref()).await?;
 
-        return Err(LlamaCoreError::Operation(err_msg));
-    }
+    // apply the sampling parameters for the duration of the request
+    let _metadata = apply_metadata(request, &params)?;
 
-    let prompt = match &request.prompt {
-        CompletionPrompt::SingleText(prompt) => prompt.to_owned(),
-        CompletionPrompt::MultiText(prompts) => prompts.join(" "),
+    let mut choices = vec![];
+    let mut usage = Usage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        total_tokens: 0,
     };
+    for (prompt_index, prompt) in params.prompts.iter().enumerate() {
+        let mut candidates = vec![];
+        for _ in 0..params.best_of {
+            let choice = generate(&params, &params.inputs[prompt_index]).await?;
+
+            // the prompt tokens are shared by all the choices of the prompt
+            if candidates.is_empty() {
+                usage.prompt_tokens += choice.prompt_tokens;
+            }
+            usage.completion_tokens += choice.state.tokens;
+
+            candidates.push(choice.state);
+        }
+
+        // keep the candidates with the highest log probability per token
+        if params.best_of > params.n {
+            candidates.sort_by(|a, b| b.mean_logprob().total_cmp(&a.mean_logprob()));
+            candidates.truncate(params.n as usize);
+        }
+
+        for (choice_index, state) in candidates.into_iter().enumerate() {
+            let offset = match params.echo {
+                true => prompt.len(),
+                false => 0,
+            };
+            let logprobs = params
+                .logprobs
+                .is_some()
+                .then(|| logprob_result(&state.logprobs, offset));
+
+            let mut text = match params.echo {
+                true => prompt.clone(),
+                false => String::new(),
+            };
+            text.push_str(&state.text);
+            if let Some(suffix) = &params.suffix {
+                text.push_str(suffix);
+            }
+
+            choices.push(CompletionChoice {
+                finish_reason: state.finish_reason.unwrap_or(FinishReason::stop),
+                index: (prompt_index * params.n as usize + choice_index) as u32,
+                logprobs,
+                text,
+            });
+        }
+    }
+    usage.total_tokens = usage.prompt_tokens + usage.completion_tokens;
 
-    compute(prompt.trim(), request.model.as_ref())
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Completions generated successfully.");
+
+    let (model, system_fingerprint) = with_chat_graph(params.model_name.as_ref(), |graph| {
+        Ok((graph.name().to_string(), gen_system_fingerprint(graph)?))
+    })?;
+
+    Ok(CompletionObject {
+        id: uuid::Uuid::new_v4().to_string(),
+        object: String::from("text_completion"),
+        created: current_timestamp()?,
+        model,
+        choices,
+        usage,
+        system_fingerprint: Some(system_fingerprint),
+    })
 }
 
-fn compute(
-    prompt: impl AsRef<str>,
-    model_name: Option<&String>,
-) -> std::result::Result<CompletionObject, LlamaCoreError> {
+/// Given a prompt, the model will stream the predicted completions as server-sent events, each of which is a [`CompletionChunk`] in the form of `data: {chunk}\n\n`. The stream ends with `data: [DONE]\n\n`.
+///
+/// The choices are generated one by one in the same order as [`completions`] returns them. `best_of` is not supported in the stream mode.
+pub async fn completions_stream(
+    request: &CompletionRequest,
+) -> Result<impl futures::TryStream<Ok = String, Error = LlamaCoreError>, LlamaCoreError> {
     #[cfg(feature = "logging")]
-    info!(target: "stdout", "Compute completions");
+    info!(target: "stdout", "Generate completions in the stream mode");
+
+    let params = CompletionParams::new(request, true)?;
+
+    // wait for the turn to use the model
+    let permit = scheduler::acquire(request.model.as_ref()).await?;
+
+    // apply the sampling parameters for the duration of the request
+    let metadata = apply_metadata(request, &params)?;
+
+    CompletionStream::new(params, StreamFormat::Completions, metadata, permit)
+}
+
+/// Fills in the middle of the code between the prefix and the suffix, in the same way as the `/infill` endpoint of llama.cpp. The model should be trained to fill in the middle, i.e. it has an infill template.
+pub async fn infill(request: &InfillRequest) -> Result<InfillResponse, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Fill in the middle");
+
+    let (completion_request, params) = infill_params(request, false)?;
+
+    // wait for the turn to use the model
+    let _permit = scheduler::acquire(request.model.as_ref()).await?;
+
+    // apply the sampling parameters for the duration of the request
+    let _metadata = apply_metadata(&completion_request, &params)?;
+
+    let choice = generate(&params, &params.inputs[0]).await?;
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Infill generated successfully.");
+
+    let model = with_chat_graph(params.model_name.as_ref(), |graph| {
+        Ok(graph.name().to_string())
+    })?;
+
+    Ok(infill_response(
+        &params,
+        &choice.state,
+        choice.state.text.clone(),
+        model,
+        choice.prompt_tokens,
+    ))
+}
+
+/// Fills in the middle of the code between the prefix and the suffix, and streams the content as server-sent events in the same way as the `/infill` endpoint of llama.cpp. Each event is an [`InfillChunk`] in the form of `data: {chunk}\n\n`, except the last one, which is an [`InfillResponse`].
+pub async fn infill_stream(
+    request: &InfillRequest,
+) -> Result<impl futures::TryStream<Ok = String, Error = LlamaCoreError>, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Fill in the middle in the stream mode");
+
+    let (completion_request, params) = infill_params(request, true)?;
+
+    // wait for the turn to use the model
+    let permit = scheduler::acquire(request.model.as_ref()).await?;
+
+    // apply the sampling parameters for the duration of the request
+    let metadata = apply_metadata(&completion_request, &params)?;
+
+    CompletionStream::new(params, StreamFormat::Infill, metadata, permit)
+}
+
+/// Converts the infill request to a completion request with the suffix, and checks if the model fills in the middle.
+fn infill_params(
+    request: &InfillRequest,
+    stream: bool,
+) -> Result<(CompletionRequest, CompletionParams), LlamaCoreError> {
+    let completion_request = CompletionRequest {
+        model: request.model.clone(),
+        prompt: CompletionPrompt::SingleText(request.input_prefix.clone()),
+        best_of: None,
+        echo: None,
+        frequency_penalty: request.frequency_penalty,
+        logit_bias: None,
+        logprobs: None,
+        max_tokens: request.n_predict.and_then(|n| u32::try_from(n).ok()),
+        n: None,
+        presence_penalty: request.presence_penalty,
+        seed: request.seed,
+        stop: request.stop.clone(),
+        stream: Some(stream),
+        suffix: Some(request.input_suffix.clone()),
+        temperature: request.temperature,
+        top_p: request.top_p,
+        user: None,
+    };
+
+    let mut params = CompletionParams::new(&completion_request, stream)?;
+    if params.infill.is_none() {
+        let err_msg = "The model is not trained to fill in the middle. Please set the infill template of the model.";
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", err_msg);
+
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("model")));
+    }
+
+    // the middle part typed already
+    if let Some(prompt) = &request.prompt {
+        params.inputs[0].push_str(prompt);
+    }
 
-    let chat_graphs = match CHAT_GRAPHS.get() {
-        Some(chat_graphs) => chat_graphs,
-        None => {
-            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
+    Ok((completion_request, params))
+}
+
+/// Creates the result of the infill from the finished choice.
+fn infill_response(
+    params: &CompletionParams,
+    state: &ChoiceState,
+    content: String,
+    model: String,
+    prompt_tokens: u64,
+) -> InfillResponse {
+    // the end-of-infill markers generated as text end the sequence as well
+    let stopping_word = state
+        .stopping_word
+        .clone()
+        .filter(|word| !params.end_markers().contains(&word.as_str()));
+
+    InfillResponse {
+        content,
+        model,
+        stop: true,
+        stopped_eos: state.finish_reason == Some(FinishReason::stop) && stopping_word.is_none(),
+        stopped_word: stopping_word.is_some(),
+        stopped_limit: state.finish_reason == Some(FinishReason::length),
+        stopping_word: stopping_word.unwrap_or_default(),
+        tokens_predicted: state.tokens,
+        tokens_evaluated: prompt_tokens,
+    }
+}
+
+/// The parameters of a completion request, which are checked before the generation.
+#[derive(Debug)]
+struct CompletionParams {
+    model_name: Option<String>,
+    prompts: Vec<String>,
+    /// The prompts fed to the model, which are built with the infill template if the model fills in the middle
+    inputs: Vec<String>,
+    /// The infill template, if the model fills in the middle between the prompts and the suffix
+    infill: Option<InfillTemplateType>,
+    /// Number of the choices returned for each prompt
+    n: u32,
+    /// Number of the candidates generated for each prompt
+    best_of: u32,
+    echo: bool,
+    /// The text appended to the choices, if the model does not fill in the middle
+    suffix: Option<String>,
+    /// The stop sequences, including the end-of-infill markers if the model fills in the middle
+    stop: Vec<String>,
+    logprobs: Option<u32>,
+    max_tokens: Option<u64>,
+}
+impl CompletionParams {
+    fn new(request: &CompletionRequest, stream: bool) -> Result<Self, LlamaCoreError> {
+        let running_mode = running_mode()?;
+        if running_mode == RunningMode::Embeddings || running_mode == RunningMode::Rag {
+            let err_msg = format!(
+                "The completion is not supported in the {} mode.",
+                running_mode
+            );
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("model")));
+        }
+
+        let prompts = match &request.prompt {
+            CompletionPrompt::SingleText(prompt) => vec![prompt.to_owned()],
+            CompletionPrompt::MultiText(prompts) => prompts.to_owned(),
+        };
+        if prompts.is_empty() {
+            let err_msg = "The list of prompts is empty.";
 
             #[cfg(feature = "logging")]
             error!(target: "stdout", "{}", err_msg);
 
-            return Err(LlamaCoreError::Operation(err_msg.into()));
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("prompt")));
         }
-    };
 
-    let mut chat_graphs = chat_graphs.lock().map_err(|e| {
-        let err_msg = format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
+        // check the number of the choices
+        let n = request.n.unwrap_or(1);
+        if n == 0 {
+            let err_msg = "Invalid `n`: 0. The minimum value for `n` is 1.";
 
-        #[cfg(feature = "logging")]
-        error!(target: "stdout", "{}", &err_msg);
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", err_msg);
 
-        LlamaCoreError::Operation(err_msg)
-    })?;
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("n")));
+        }
+        let best_of = request.best_of.unwrap_or(n);
+        if best_of < n {
+            let err_msg = format!(
+                "Invalid `best_of`: {}. `best_of` must be greater than or equal to `n`.",
+                best_of
+            );
 
-    match model_name {
-        Some(model_name) => match chat_graphs.contains_key(model_name) {
-            true => {
-                let graph = chat_graphs.get_mut(model_name).unwrap();
-                compute_by_graph(graph, prompt)
-            }
-            false => match chat_graphs.iter_mut().next() {
-                Some((_, graph)) => compute_by_graph(graph, prompt),
-                None => {
-                    let err_msg = "There is no model available in the chat graphs.";
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
 
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("best_of")));
+        }
+        if stream && best_of > n {
+            let err_msg = "`best_of` is not supported in the stream mode.";
 
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            },
-        },
-        None => match chat_graphs.iter_mut().next() {
-            Some((_, graph)) => compute_by_graph(graph, prompt),
-            None => {
-                let err_msg = "There is no model available in the chat graphs.";
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", err_msg);
+
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("best_of")));
+        }
+
+        // check the number of the log probabilities
+        if let Some(logprobs) = request.logprobs {
+            if logprobs > 5 {
+                let err_msg = format!(
+                    "Invalid `logprobs`: {}. The maximum value for `logprobs` is 5.",
+                    logprobs
+                );
 
                 #[cfg(feature = "logging")]
                 error!(target: "stdout", "{}", &err_msg);
 
-                Err(LlamaCoreError::Operation(err_msg.into()))
+                return Err(LlamaCoreError::invalid_request(err_msg, Some("logprobs")));
+            }
+        }
+
+        // the log probabilities are needed to return them or to choose the best candidates
+        if request.logprobs.is_some() || best_of > n {
+            let param = match request.logprobs {
+                Some(_) => "logprobs",
+                None => "best_of",
+            };
+            with_chat_graph(request.model.as_ref(), |graph| {
+                check_logprobs_support(graph, param)
+            })?;
+        }
+
+        // fill in the middle between the prompts and the suffix, if the model is trained to
+        let infill = match request.suffix {
+            Some(_) => with_chat_graph(request.model.as_ref(), |graph| {
+                Ok(graph.metadata.infill_template())
+            })?,
+            None => None,
+        };
+        let mut stop = request.stop.clone().unwrap_or_default();
+        let (inputs, suffix) = match (infill, &request.suffix) {
+            (Some(template), Some(suffix)) => {
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "Fill in the middle with the {} infill template.", template);
+
+                let infill_prompt = InfillPrompt::from(template);
+                stop.extend(
+                    infill_prompt
+                        .end_of_infill_markers()
+                        .iter()
+                        .map(|marker| marker.to_string()),
+                );
+                let inputs = prompts
+                    .iter()
+                    .map(|prompt| infill_prompt.build(prompt, suffix))
+                    .collect();
+
+                (inputs, None)
             }
-        },
+            _ => (prompts.clone(), request.suffix.clone()),
+        };
+
+        Ok(Self {
+            model_name: request.model.clone(),
+            prompts,
+            inputs,
+            infill,
+            n,
+            best_of,
+            echo: request.echo.unwrap_or_default(),
+            suffix,
+            stop,
+            logprobs: request.logprobs,
+            max_tokens: request.max_tokens.map(u64::from),
+        })
+    }
+
+    /// Whether the log probabilities of the output tokens are needed, either to return them or to choose the best candidates.
+    fn needs_logprobs(&self) -> bool {
+        self.logprobs.is_some() || self.best_of > self.n
+    }
+
+    /// The markers ending the middle part generated by the model, if the model fills in the middle.
+    fn end_markers(&self) -> &'static [&'static str] {
+        match self.infill {
+            Some(template) => InfillPrompt::from(template).end_of_infill_markers(),
+            None => &[],
+        }
     }
 }
 
-/// Runs inference on the model with the given name and returns the output.
-fn compute_by_graph(
-    graph: &mut Graph<GgmlMetadata>,
-    prompt: impl AsRef<str>,
-) -> std::result::Result<CompletionObject, LlamaCoreError> {
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "Compute completions by graph");
+/// Applies the sampling parameters of the request to the model for the duration of the request. The returned guard restores the metadata the model is loaded with when dropped.
+fn apply_metadata(
+    request: &CompletionRequest,
+    params: &CompletionParams,
+) -> Result<MetadataGuard, LlamaCoreError> {
+    let mut metadata = get_model_metadata(params.model_name.as_ref())?;
 
     // check if the `embedding` model is disabled or not
-    if graph.metadata.embeddings {
-        graph.metadata.embeddings = false;
+    let embeddings = metadata.embeddings;
+    if embeddings {
+        metadata.embeddings = false;
 
         #[cfg(feature = "logging")]
         info!(target: "stdout", "The `embedding` field of metadata sets to false.");
+    }
+
+    if let Some(temperature) = request.temperature {
+        metadata.temperature = temperature as f64;
+    }
+    if let Some(top_p) = request.top_p {
+        metadata.top_p = top_p as f64;
+    }
+    if let Some(frequency_penalty) = request.frequency_penalty {
+        metadata.frequency_penalty = frequency_penalty as f64;
+    }
+    if let Some(presence_penalty) = request.presence_penalty {
+        metadata.presence_penalty = presence_penalty as f64;
+    }
+    if let Some(max_tokens) = params.max_tokens {
+        metadata.n_predict = max_tokens;
+    }
+    if let Some(logit_bias) = &request.logit_bias {
+        metadata.logit_bias = Some(with_chat_graph(params.model_name.as_ref(), |graph| {
+            parse_logit_bias(graph, logit_bias)
+        })?);
+    }
+    if params.needs_logprobs() {
+        metadata.n_probs = Some(params.logprobs.unwrap_or_default() as u64);
+    }
+    if request.seed.is_some() {
+        metadata.seed = request.seed;
+    }
+
+    let changed = embeddings
+        || request.temperature.is_some()
+        || request.top_p.is_some()
+        || request.frequency_penalty.is_some()
+        || request.presence_penalty.is_some()
+        || params.max_tokens.is_some()
+        || request.logit_bias.is_some()
+        || params.needs_logprobs()
+        || request.seed.is_some();
+    if changed {
+        update_model_metadata(params.model_name.as_ref(), &metadata)?;
+    }
+
+    Ok(MetadataGuard::new(params.model_name.as_ref(), changed))
+}
 
-        graph.update_metadata()?;
+/// A choice generated from a prompt.
+#[derive(Debug)]
+struct GeneratedChoice {
+    state: ChoiceState,
+    prompt_tokens: u64,
+}
+
+/// Generates a choice from the prompt token by token.
+async fn generate(
+    params: &CompletionParams,
+    prompt: &str,
+) -> Result<GeneratedChoice, LlamaCoreError> {
+    // clean up the context once the generation finishes or is abandoned, e.g., the client disconnects
+    let context = SingleContextGuard::new(params.model_name.clone(), prompt)?;
+
+    let mut state = ChoiceState::default();
+    while state.finish_reason.is_none() {
+        with_chat_graph(params.model_name.as_ref(), |graph| {
+            state.next(graph, params)
+        })?;
+
+        // let the other tasks run between the tokens
+        tokio::task::yield_now().await;
     }
 
-    // set input
-    let tensor_data = prompt.as_ref().as_bytes().to_vec();
-    graph
-        .set_input(0, wasmedge_wasi_nn::TensorType::U8, &[1], &tensor_data)
-        .map_err(|e| {
-            let err_msg = format!("Failed to set the input tensor. {}", e);
+    let token_info = with_chat_graph(params.model_name.as_ref(), |graph| {
+        get_token_info_by_graph(graph)
+    })?;
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Prompt tokens: {}, Completion tokens: {}", token_info.prompt_tokens, state.tokens);
+
+    drop(context);
+
+    Ok(GeneratedChoice {
+        state,
+        prompt_tokens: token_info.prompt_tokens,
+    })
+}
+
+/// Feeds the prompt to the model, and cleans up the context of the model after the token-by-token generation when dropped.
+#[derive(Debug)]
+struct SingleContextGuard {
+    model_name: Option<String>,
+}
+impl SingleContextGuard {
+    fn new(model_name: Option<String>, prompt: &str) -> Result<Self, LlamaCoreError> {
+        with_chat_graph(model_name.as_ref(), |graph| {
+            set_tensor_data_u8(graph, 0, prompt.trim().as_bytes())
+        })?;
+
+        Ok(Self { model_name })
+    }
+}
+impl Drop for SingleContextGuard {
+    fn drop(&mut self) {
+        if let Err(e) = with_chat_graph(self.model_name.as_ref(), |graph| {
+            graph
+                .finish_single()
+                .map_err(|e| LlamaCoreError::Backend(BackendError::FinishSingle(e.to_string())))
+        }) {
+            let err_msg = format!("Failed to clean up the context. Reason: {}", e);
 
             #[cfg(feature = "logging")]
             error!(target: "stdout", "{}", &err_msg);
 
-            LlamaCoreError::Backend(BackendError::SetInput(err_msg))
-        })?;
+            #[cfg(not(feature = "logging"))]
+            println!("[ERROR][llama_core] {}", &err_msg);
+        }
+    }
+}
 
-    // execute the inference
-    graph.compute().map_err(|e| {
-        let err_msg = format!("Failed to execute the inference. {}", e);
+/// The state of the choice being generated.
+#[derive(Debug, Default)]
+struct ChoiceState {
+    /// The generated text, truncated at the stop sequence
+    text: String,
+    /// The bytes of an incomplete UTF-8 character at the end of the output
+    pending_bytes: Vec<u8>,
+    /// The log probabilities of the tokens in `text`
+    logprobs: Vec<TokenLogProb>,
+    /// The number of the generated tokens
+    tokens: u64,
+    /// The stop sequence which the text is truncated at
+    stopping_word: Option<String>,
+    /// The length in bytes of the text sent to the client in the stream mode
+    sent: usize,
+    /// The number of the log probabilities sent to the client in the stream mode
+    sent_logprobs: usize,
+    /// The reason the generation is finished, or `None` if the generation is in progress
+    finish_reason: Option<FinishReason>,
+}
+impl ChoiceState {
+    /// Generates the next token.
+    fn next(
+        &mut self,
+        graph: &mut Graph<GgmlMetadata>,
+        params: &CompletionParams,
+    ) -> Result<(), LlamaCoreError> {
+        match graph.compute_single() {
+            Ok(_) => {
+                let output = get_output_buffer_single(graph, OUTPUT_TENSOR)?;
+                if params.needs_logprobs() {
+                    self.logprobs.extend(get_logprobs_by_graph_single(graph)?);
+                }
+                self.tokens += 1;
+                self.push_bytes(&output);
 
-        #[cfg(feature = "logging")]
-        error!(target: "stdout", "{}", &err_msg);
+                // truncate the generation at the stop sequences
+                if let Some(pos) = find_stop_sequence(&self.text, &params.stop) {
+                    #[cfg(feature = "logging")]
+                    info!(target: "stdout", "Truncate the generation at the stop sequence.");
+
+                    self.stopping_word = params
+                        .stop
+                        .iter()
+                        .find(|stop| {
+                            !stop.is_empty() && self.text[pos..].starts_with(stop.as_str())
+                        })
+                        .cloned();
+                    self.text.truncate(pos);
+                    self.truncate_logprobs();
+                    self.finish_reason = Some(FinishReason::stop);
+                } else if params
+                    .max_tokens
+                    .is_some_and(|max_tokens| self.tokens >= max_tokens)
+                {
+                    self.finish(FinishReason::length);
+                }
+            }
+            Err(InferenceError::EndOfSequence) => self.finish(FinishReason::stop),
+            Err(InferenceError::ContextFull) => self.finish(FinishReason::length),
+            Err(InferenceError::PromptTooLong) => {
+                let token_info = get_token_info_by_graph(graph)?;
+                let err = LlamaCoreError::ContextLengthExceeded {
+                    prompt_tokens: token_info.prompt_tokens,
+                    max_tokens: graph.metadata.ctx_size,
+                };
 
-        LlamaCoreError::Backend(BackendError::Compute(err_msg))
-    })?;
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err);
+
+                return Err(err);
+            }
+            Err(e) => {
+                let err_msg = format!("Failed to compute the completion. Reason: {}", e);
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                return Err(LlamaCoreError::Backend(BackendError::ComputeSingle(
+                    err_msg,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Appends the output bytes to the text, and holds back the bytes of an incomplete UTF-8 character.
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.pending_bytes.extend_from_slice(bytes);
+
+        match std::str::from_utf8(&self.pending_bytes) {
+            Ok(text) => {
+                self.text.push_str(text);
+                self.pending_bytes.clear();
+            }
+            Err(e) => {
+                let valid_up_to = e.valid_up_to();
+                self.text
+                    .push_str(&String::from_utf8_lossy(&self.pending_bytes[..valid_up_to]));
+
+                // the bytes are invalid rather than incomplete
+                if e.error_len().is_some() || self.pending_bytes.len() - valid_up_to > 4 {
+                    self.text
+                        .push_str(&String::from_utf8_lossy(&self.pending_bytes[valid_up_to..]));
+                    self.pending_bytes.clear();
+                } else {
+                    self.pending_bytes.drain(..valid_up_to);
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, finish_reason: FinishReason) {
+        let pending_bytes = std::mem::take(&mut self.pending_bytes);
+        self.text.push_str(&String::from_utf8_lossy(&pending_bytes));
+        self.finish_reason = Some(finish_reason);
+    }
+
+    /// Drops the log probabilities of the tokens after the truncated text.
+    fn truncate_logprobs(&mut self) {
+        let mut offset = 0;
+        let kept = self
+            .logprobs
+            .iter()
+            .take_while(|logprob| {
+                let start = offset;
+                offset += logprob.token.len();
+                start < self.text.len()
+            })
+            .count();
+        self.logprobs.truncate(kept);
+    }
+
+    /// The mean of the log probabilities of the generated tokens.
+    fn mean_logprob(&self) -> f64 {
+        match self.logprobs.is_empty() {
+            true => f64::NEG_INFINITY,
+            false => {
+                self.logprobs
+                    .iter()
+                    .map(|logprob| logprob.logprob)
+                    .sum::<f64>()
+                    / self.logprobs.len() as f64
+            }
+        }
+    }
+
+    /// Takes the text not sent to the client yet, except the end of the text which may be the beginning of a stop sequence.
+    fn take_delta(&mut self, stop: &[String]) -> String {
+        let end = match self.finish_reason {
+            Some(_) => self.text.len(),
+            None => self.text.len() - partial_stop_sequence_len(&self.text, stop),
+        };
+        if end <= self.sent {
+            return String::new();
+        }
 
-    // Retrieve the output
-    let buffer = get_output_buffer(graph, OUTPUT_TENSOR)?;
+        let delta = self.text[self.sent..end].to_string();
+        self.sent = end;
 
-    // convert inference result to string
-    let model_answer = String::from_utf8(buffer).map_err(|e| {
-        let err_msg = format!(
-            "Failed to decode the buffer of the inference result to a utf-8 string. {}",
-            e
+        delta
+    }
+
+    /// Takes the log probabilities not sent to the client yet.
+    fn take_logprobs(&mut self, offset: usize) -> LogprobResult {
+        let offset = offset
+            + self.logprobs[..self.sent_logprobs]
+                .iter()
+                .map(|logprob| logprob.token.len())
+                .sum::<usize>();
+        let result = logprob_result(&self.logprobs[self.sent_logprobs..], offset);
+        self.sent_logprobs = self.logprobs.len();
+
+        result
+    }
+}
+
+/// Converts the log probabilities of the tokens to the legacy format of the completions, with the offsets of the tokens in the text starting from `offset`.
+fn logprob_result(logprobs: &[TokenLogProb], offset: usize) -> LogprobResult {
+    let mut result = LogprobResult::default();
+
+    let mut offset = offset;
+    for logprob in logprobs {
+        result.text_offset.push(offset as i32);
+        offset += logprob.token.len();
+
+        result.token_logprobs.push(logprob.logprob as f32);
+        result.top_logprobs.push(
+            logprob
+                .top_logprobs
+                .iter()
+                .map(|top| (top.token.clone(), top.logprob as f32))
+                .collect(),
         );
+        result.tokens.push(logprob.token.clone());
+    }
+
+    result
+}
+
+/// The format of the chunks in the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamFormat {
+    /// [`CompletionChunk`]s ending with `[DONE]`
+    Completions,
+    /// [`InfillChunk`]s ending with an [`InfillResponse`]
+    Infill,
+}
+
+/// Stream of the chunks of the completions, which generates the choices one by one.
+struct CompletionStream {
+    id: String,
+    created: u64,
+    model: String,
+    system_fingerprint: String,
+    params: CompletionParams,
+    format: StreamFormat,
+    /// The index of the prompt being completed
+    prompt_index: usize,
+    /// The index of the choice being generated for the prompt
+    choice_index: u32,
+    /// The state of the choice being generated, or `None` before the generation of the choice starts
+    state: Option<(ChoiceState, SingleContextGuard)>,
+    /// Whether `[DONE]` is sent
+    done: bool,
+    /// The metadata of the model is restored after the stream is dropped
+    _metadata: MetadataGuard,
+    /// The permit to use the model, which is released after the stream is dropped
+    _permit: SchedulerPermit,
+}
+impl CompletionStream {
+    fn new(
+        params: CompletionParams,
+        format: StreamFormat,
+        metadata: MetadataGuard,
+        permit: SchedulerPermit,
+    ) -> Result<Self, LlamaCoreError> {
+        let (model, system_fingerprint) = with_chat_graph(params.model_name.as_ref(), |graph| {
+            Ok((graph.name().to_string(), gen_system_fingerprint(graph)?))
+        })?;
+
+        Ok(Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            created: current_timestamp()?,
+            model,
+            system_fingerprint,
+            params,
+            format,
+            prompt_index: 0,
+            choice_index: 0,
+            state: None,
+            done: false,
+            _metadata: metadata,
+            _permit: permit,
+        })
+    }
+
+    /// Returns the next chunk, or `None` if the stream is finished.
+    fn next_chunk(&mut self) -> Result<Option<String>, LlamaCoreError> {
+        if self.done {
+            return Ok(None);
+        }
+
+        if self.prompt_index >= self.params.prompts.len() {
+            self.done = true;
+
+            return match self.format {
+                StreamFormat::Completions => Ok(Some("data: [DONE]\n\n".to_string())),
+                StreamFormat::Infill => Ok(None),
+            };
+        }
+
+        let prompt = self.params.prompts[self.prompt_index].clone();
+        let offset = match self.params.echo {
+            true => prompt.len(),
+            false => 0,
+        };
+
+        // start the generation of the choice, and echo the prompt first
+        if self.state.is_none() {
+            let context = SingleContextGuard::new(
+                self.params.model_name.clone(),
+                &self.params.inputs[self.prompt_index],
+            )?;
+            self.state = Some((ChoiceState::default(), context));
+
+            if self.params.echo {
+                return self.chunk(prompt, None, None).map(Some);
+            }
+        }
+
+        loop {
+            let Some((state, _)) = self.state.as_mut() else {
+                return Ok(None);
+            };
+
+            with_chat_graph(self.params.model_name.as_ref(), |graph| {
+                state.next(graph, &self.params)
+            })?;
+
+            let mut text = state.take_delta(&self.params.stop);
+            let logprobs = match self.params.logprobs.is_some() && !text.is_empty() {
+                true => Some(state.take_logprobs(offset)),
+                false => None,
+            };
+            let finish_reason = state.finish_reason;
+
+            if let Some(finish_reason) = finish_reason {
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "The choice {} of the prompt {} is finished: {:?}", self.choice_index, self.prompt_index, finish_reason);
+
+                let chunk = match self.format {
+                    StreamFormat::Completions => {
+                        if let Some(suffix) = &self.params.suffix {
+                            text.push_str(suffix);
+                        }
+                        self.chunk(text, logprobs, Some(finish_reason))
+                    }
+                    StreamFormat::Infill => {
+                        let token_info =
+                            with_chat_graph(self.params.model_name.as_ref(), |graph| {
+                                get_token_info_by_graph(graph)
+                            })?;
+                        event(&infill_response(
+                            &self.params,
+                            state,
+                            text,
+                            self.model.clone(),
+                            token_info.prompt_tokens,
+                        ))
+                    }
+                };
+
+                // clean up the context, and move on to the next choice
+                self.state = None;
+                self.choice_index += 1;
+                if self.choice_index >= self.params.n {
+                    self.choice_index = 0;
+                    self.prompt_index += 1;
+                }
+
+                return chunk.map(Some);
+            }
+
+            if !text.is_empty() {
+                return match self.format {
+                    StreamFormat::Completions => self.chunk(text, logprobs, None),
+                    StreamFormat::Infill => event(&InfillChunk {
+                        content: text,
+                        stop: false,
+                    }),
+                }
+                .map(Some);
+            }
+        }
+    }
+
+    /// Creates the chunk of the choice being generated.
+    fn chunk(
+        &self,
+        text: String,
+        logprobs: Option<LogprobResult>,
+        finish_reason: Option<FinishReason>,
+    ) -> Result<String, LlamaCoreError> {
+        let chunk = CompletionChunk {
+            id: self.id.clone(),
+            object: "text_completion".to_string(),
+            created: self.created,
+            model: self.model.clone(),
+            system_fingerprint: Some(self.system_fingerprint.clone()),
+            choices: vec![CompletionChunkChoice {
+                finish_reason,
+                index: self.prompt_index as u32 * self.params.n + self.choice_index,
+                logprobs,
+                text,
+            }],
+        };
+
+        event(&chunk)
+    }
+}
+
+/// Serializes the chunk to a server-sent event.
+fn event(chunk: &impl Serialize) -> Result<String, LlamaCoreError> {
+    let chunk_str = serde_json::to_string(chunk).map_err(|e| {
+        let err_msg = format!("Failed to serialize the chunk. Reason: {}", e);
 
         #[cfg(feature = "logging")]
         error!(target: "stdout", "{}", &err_msg);
 
         LlamaCoreError::Operation(err_msg)
     })?;
-    let answer = model_answer.trim();
 
-    // retrieve the number of prompt and completion tokens
-    let token_info = get_token_info_by_graph(graph)?;
-
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "Prompt tokens: {}, Completion tokens: {}", token_info.prompt_tokens, token_info.completion_tokens);
+    Ok(format!("data: {}\n\n", chunk_str))
+}
+impl futures::Stream for CompletionStream {
+    type Item = Result<String, LlamaCoreError>;
 
-    let created = SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map_err(|e| {
-            let err_msg = format!("Failed to get the current time. {}", e);
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
 
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", &err_msg);
+        match this.next_chunk() {
+            Ok(Some(chunk)) => Poll::Ready(Some(Ok(chunk))),
+            Ok(None) => Poll::Ready(None),
+            Err(e) => {
+                // stop the stream after the error
+                this.done = true;
+                this.state = None;
 
-            LlamaCoreError::Operation(err_msg)
-        })?;
+                Poll::Ready(Some(Err(e)))
+            }
+        }
+    }
+}
 
-    #[cfg(feature = "logging")]
-    info!(target: "stdout", "Completions generated successfully.");
+#[test]
+fn test_completions_choice_state() {
+    let params = CompletionParams {
+        model_name: None,
+        prompts: vec!["Once upon a time".to_string()],
+        inputs: vec!["Once upon a time".to_string()],
+        infill: None,
+        n: 1,
+        best_of: 1,
+        echo: false,
+        suffix: None,
+        stop: vec!["\n\n".to_string()],
+        logprobs: None,
+        max_tokens: None,
+    };
 
-    Ok(CompletionObject {
-        id: uuid::Uuid::new_v4().to_string(),
-        object: String::from("text_completion"),
-        created: created.as_secs(),
-        model: graph.name().to_string(),
-        choices: vec![CompletionChoice {
-            index: 0,
-            text: String::from(answer),
-            finish_reason: FinishReason::stop,
-            logprobs: None,
-        }],
-        usage: Usage {
-            prompt_tokens: token_info.prompt_tokens,
-            completion_tokens: token_info.completion_tokens,
-            total_tokens: token_info.prompt_tokens + token_info.completion_tokens,
-        },
-    })
+    // hold back the incomplete UTF-8 character
+    let mut state = ChoiceState::default();
+    let bytes = "é".as_bytes();
+    state.push_bytes(&bytes[..1]);
+    assert_eq!(state.text, "");
+    state.push_bytes(&bytes[1..]);
+    assert_eq!(state.text, "é");
+
+    // hold back the beginning of the stop sequence
+    state.push_bytes(b" there\n");
+    assert_eq!(state.take_delta(&params.stop), "é there");
+    state.push_bytes(b"was");
+    assert_eq!(state.take_delta(&params.stop), "\nwas");
+    state.finish(FinishReason::stop);
+    assert_eq!(state.take_delta(&params.stop), "");
 }
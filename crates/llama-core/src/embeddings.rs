@@ -2,8 +2,9 @@
 
 use crate::{
     error::{BackendError, LlamaCoreError},
+    graph::TensorType,
     metadata::ggml::GgmlMetadata,
-    running_mode,
+    models, running_mode,
     utils::{get_output_buffer, get_token_info_by_graph},
     Graph, RunningMode, CHAT_GRAPHS, EMBEDDING_GRAPHS, OUTPUT_TENSOR,
 };
@@ -40,7 +41,7 @@ pub async fn embeddings(
         #[cfg(feature = "logging")]
         error!(target: "stdout", "{}", &err_msg);
 
-        return Err(LlamaCoreError::Operation(err_msg));
+        return Err(LlamaCoreError::invalid_request(err_msg, Some("model")));
     }
 
     let model_name = &embedding_request.model;
@@ -71,22 +72,7 @@ pub async fn embeddings(
         LlamaCoreError::Operation(err_msg)
     })?;
 
-    let graph = match model_name {
-        Some(model_name) if embedding_graphs.contains_key(model_name) => {
-            embedding_graphs.get_mut(model_name).unwrap()
-        }
-        _ => match embedding_graphs.iter_mut().next() {
-            Some((_, graph)) => graph,
-            None => {
-                let err_msg = "Not found available model in the embedding graphs.";
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                return Err(LlamaCoreError::Operation(err_msg.into()));
-            }
-        },
-    };
+    let graph = models::get_graph_mut(&mut embedding_graphs, model_name.as_deref())?;
 
     // check if the `embedding` option of metadata is enabled
     if !graph.metadata.embeddings {
@@ -144,7 +130,7 @@ fn compute_embeddings(
         // set input
         let tensor_data = input.as_bytes().to_vec();
         graph
-            .set_input(0, wasmedge_wasi_nn::TensorType::U8, &[1], &tensor_data)
+            .set_input(0, TensorType::U8, &[1], &tensor_data)
             .map_err(|e| {
                 let err_msg = e.to_string();
 
@@ -222,7 +208,7 @@ fn compute_embeddings(
 ///
 /// # Arguments
 ///
-/// * `name` - The name of the embedding model. If `None`, the dimension of the first model will be returned.
+/// * `name` - The name or alias of the embedding model. If `None`, the dimension of the default model will be returned.
 ///
 /// # Returns
 ///
@@ -254,46 +240,9 @@ pub fn dimension(name: Option<&str>) -> Result<u64, LlamaCoreError> {
         LlamaCoreError::Operation(err_msg)
     })?;
 
-    match name {
-        Some(model_name) => match embedding_graphs.get(model_name) {
-            Some(graph) => Ok(graph.metadata.ctx_size),
-            None => {
-                let err_msg = format!(
-                    "The model `{}` does not exist in the embedding graphs.",
-                    model_name
-                );
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                Err(LlamaCoreError::Operation(err_msg))
-            }
-        },
-        None => {
-            if !embedding_graphs.is_empty() {
-                let graph = match embedding_graphs.values().next() {
-                    Some(graph) => graph,
-                    None => {
-                        let err_msg = "Fail to get the underlying value of `EMBEDDING_GRAPHS`.";
-
-                        #[cfg(feature = "logging")]
-                        error!(target: "stdout", "{}", err_msg);
-
-                        return Err(LlamaCoreError::Operation(err_msg.into()));
-                    }
-                };
-
-                Ok(graph.metadata.ctx_size)
-            } else {
-                let err_msg = "There is no model available in the embedding graphs.";
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
+    let graph = models::get_graph(&embedding_graphs, name)?;
 
-                Err(LlamaCoreError::Operation(err_msg.into()))
-            }
-        }
-    }
+    Ok(graph.metadata.ctx_size)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -332,7 +281,7 @@ pub fn chunk_text(
         #[cfg(feature = "logging")]
         error!(target: "stdout", "{}", err_msg);
 
-        return Err(LlamaCoreError::Operation(err_msg.into()));
+        return Err(LlamaCoreError::invalid_request(err_msg, None));
     }
 
     match ty.as_ref().to_lowercase().as_str() {
@@ -1,5 +1,6 @@
 //! Error types for the Llama Core library.
 
+use std::time::Duration;
 use thiserror::Error;
 
 /// Error types for the Llama Core library.
@@ -11,6 +12,16 @@ pub enum LlamaCoreError {
     /// Errors in Context initialization.
     #[error("Failed to initialize computation context. Reason: {0}")]
     InitContext(String),
+    /// Errors in the request, e.g., an empty message list or an invalid parameter.
+    #[error("{message}")]
+    InvalidRequest {
+        message: String,
+        /// The name of the invalid parameter of the request
+        param: Option<String>,
+    },
+    /// Errors in the prompt exceeding the context size of the model.
+    #[error("This model's maximum context length is {max_tokens} tokens. However, your messages resulted in {prompt_tokens} tokens. Please reduce the length of the messages.")]
+    ContextLengthExceeded { prompt_tokens: u64, max_tokens: u64 },
     /// Errors thrown by the wasi-nn-ggml plugin and runtime.
     #[error("{0}")]
     Backend(#[from] BackendError),
@@ -21,6 +32,35 @@ pub enum LlamaCoreError {
     /// Errors in file not found.
     #[error("File not found.")]
     FileNotFound,
+    /// Errors in routing the request to a model that is not loaded.
+    #[error("The model `{0}` does not exist.")]
+    ModelNotFound(String),
+    /// Errors in scheduling the request while the model is busy.
+    #[error("{0}")]
+    Scheduler(#[from] SchedulerError),
+}
+
+impl LlamaCoreError {
+    /// Creates an [`LlamaCoreError::InvalidRequest`] error for the given parameter of the request.
+    pub fn invalid_request(message: impl Into<String>, param: Option<&str>) -> Self {
+        LlamaCoreError::InvalidRequest {
+            message: message.into(),
+            param: param.map(Into::into),
+        }
+    }
+
+    /// The code of the error, which is the same as the `code` of the error object returned by the API server, e.g., in the error file of a batch.
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            LlamaCoreError::InvalidRequest { .. } => "invalid_request",
+            LlamaCoreError::ContextLengthExceeded { .. } => "context_length_exceeded",
+            LlamaCoreError::ModelNotFound(_) => "model_not_found",
+            LlamaCoreError::FileNotFound => "file_not_found",
+            LlamaCoreError::Scheduler(_) => "model_busy",
+            LlamaCoreError::Backend(_) => "backend_failure",
+            _ => "server_error",
+        }
+    }
 }
 
 /// Error types for wasi-nn errors.
@@ -45,3 +85,27 @@ pub enum BackendError {
     #[error("{0}")]
     FinishSingle(String),
 }
+
+/// Error types for the request scheduler.
+#[derive(Error, Debug)]
+pub enum SchedulerError {
+    /// The queue of the model is full.
+    #[error("The request queue of the model '{model}' is full. Please retry after {retry_after} seconds.")]
+    QueueFull { model: String, retry_after: u64 },
+    /// The request waits in the queue of the model for too long.
+    #[error("The request timed out after waiting {} seconds in the queue of the model '{model}'. Please retry after {retry_after} seconds.", waited.as_secs())]
+    Timeout {
+        model: String,
+        waited: Duration,
+        retry_after: u64,
+    },
+}
+impl SchedulerError {
+    /// The suggested seconds to wait before retrying the request.
+    pub fn retry_after(&self) -> u64 {
+        match self {
+            SchedulerError::QueueFull { retry_after, .. } => *retry_after,
+            SchedulerError::Timeout { retry_after, .. } => *retry_after,
+        }
+    }
+}
@@ -6,6 +6,7 @@ use std::{
     fs::{self, File},
     io::Read,
     path::Path,
+    time::SystemTime,
 };
 use walkdir::{DirEntry, WalkDir};
 
@@ -249,6 +250,64 @@ pub fn download_file(id: impl AsRef<str>) -> Result<(String, Vec<u8>), LlamaCore
     }
 }
 
+/// Create a file in the archives directory, e.g., the output of a batch.
+///
+/// # Arguments
+///
+/// * `filename`: The name of the file.
+///
+/// * `content`: The content of the file.
+///
+/// * `purpose`: The intended purpose of the file.
+///
+/// # Returns
+///
+/// A `FileObject` instance.
+pub(crate) fn create_file(
+    filename: impl Into<String>,
+    content: &[u8],
+    purpose: impl Into<String>,
+) -> Result<FileObject, LlamaCoreError> {
+    let id = format!("file_{}", uuid::Uuid::new_v4());
+    let filename = filename.into();
+
+    let dir = Path::new(ARCHIVES_DIR).join(&id);
+    if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::write(dir.join(&filename), content)) {
+        let err_msg = format!("Failed to create the archive file {}. {}", &filename, e);
+
+        // log
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        return Err(LlamaCoreError::Operation(err_msg));
+    }
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "file_id: {}, file_name: {}", &id, &filename);
+
+    let created_at = match SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(e) => {
+            let err_msg = format!("Failed to get the current time. {}", e);
+
+            // log
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::Operation(err_msg));
+        }
+    };
+
+    Ok(FileObject {
+        id,
+        bytes: content.len() as u64,
+        created_at,
+        filename,
+        object: "file".to_string(),
+        purpose: purpose.into(),
+    })
+}
+
 fn is_hidden(entry: &DirEntry) -> bool {
     entry
         .file_name()
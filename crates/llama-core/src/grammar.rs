@@ -0,0 +1,703 @@
+//! Define APIs for converting response formats and JSON schemas to GBNF grammars.
+
+use crate::error::LlamaCoreError;
+use endpoints::chat::ChatResponseFormat;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+const SPACE_RULE: &str = r#"" "?"#;
+
+/// Primitive rules, each of which is given as `(name, body, dependencies)`.
+const PRIMITIVE_RULES: &[(&str, &str, &[&str])] = &[
+    ("boolean", r#"("true" | "false") space"#, &[]),
+    (
+        "number",
+        r#"("-"? ([0-9] | [1-9] [0-9]*)) ("." [0-9]+)? ([eE] [-+]? [0-9]+)? space"#,
+        &[],
+    ),
+    ("integer", r#"("-"? ([0-9] | [1-9] [0-9]*)) space"#, &[]),
+    (
+        "char",
+        r#"[^"\\\x7F\x00-\x1F] | [\\] (["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F])"#,
+        &[],
+    ),
+    ("string", r#""\"" char* "\"" space"#, &["char"]),
+    ("null", r#""null" space"#, &[]),
+    (
+        "value",
+        "object | array | string | number | boolean | null",
+        &["object", "array", "string", "number", "boolean", "null"],
+    ),
+    (
+        "object",
+        r#""{" space ( string ":" space value ("," space string ":" space value)* )? "}" space"#,
+        &["string", "value"],
+    ),
+    (
+        "array",
+        r#""[" space ( value ("," space value)* )? "]" space"#,
+        &["value"],
+    ),
+];
+
+/// Returns the GBNF grammar for the given response format, or `None` if the output is not constrained.
+pub fn response_format_to_grammar(
+    response_format: &ChatResponseFormat,
+) -> Result<Option<String>, LlamaCoreError> {
+    match response_format.ty.as_str() {
+        "text" => Ok(None),
+        "json_object" => Ok(Some(json_object_grammar())),
+        "json_schema" => {
+            let schema = match &response_format.json_schema {
+                Some(json_schema) => json_schema.schema.clone().unwrap_or(Value::Bool(true)),
+                None => {
+                    let err_msg = "The `json_schema` field is required for the `json_schema` response format.";
+
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", err_msg);
+
+                    return Err(LlamaCoreError::invalid_request(
+                        err_msg,
+                        Some("response_format"),
+                    ));
+                }
+            };
+
+            json_schema_to_grammar(&schema).map(Some)
+        }
+        ty => {
+            let err_msg = format!(
+                "Unsupported response format: {}. Must be one of `text`, `json_object` or `json_schema`.",
+                ty
+            );
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            Err(LlamaCoreError::invalid_request(
+                err_msg,
+                Some("response_format"),
+            ))
+        }
+    }
+}
+
+/// Returns the GBNF grammar which constrains the output to any JSON object.
+pub fn json_object_grammar() -> String {
+    let mut converter = SchemaConverter::new(&Value::Null);
+    converter.add_primitive("object");
+    converter
+        .rules
+        .insert("root".to_string(), "object".to_string());
+    converter.format_grammar()
+}
+
+/// Converts a JSON schema to a GBNF grammar.
+///
+/// The local references, i.e. `#/$defs/...` and `#/definitions/...`, are supported. The keywords
+/// `pattern`, `allOf`, `not` and the conditional ones are not supported.
+pub fn json_schema_to_grammar(schema: &Value) -> Result<String, LlamaCoreError> {
+    let mut converter = SchemaConverter::new(schema);
+    converter.visit(schema, "")?;
+    Ok(converter.format_grammar())
+}
+
+struct SchemaConverter<'a> {
+    root_schema: &'a Value,
+    rules: BTreeMap<String, String>,
+    // key: reference, value: rule name
+    refs: HashMap<String, String>,
+}
+impl<'a> SchemaConverter<'a> {
+    fn new(root_schema: &'a Value) -> Self {
+        let mut rules = BTreeMap::new();
+        rules.insert("space".to_string(), SPACE_RULE.to_string());
+
+        Self {
+            root_schema,
+            rules,
+            refs: HashMap::new(),
+        }
+    }
+
+    /// Formats the rules as a grammar with the `root` rule in the first line.
+    fn format_grammar(&self) -> String {
+        let mut grammar = String::new();
+        if let Some(root) = self.rules.get("root") {
+            grammar.push_str(&format!("root ::= {}\n", root));
+        }
+        for (name, body) in self.rules.iter().filter(|(name, _)| *name != "root") {
+            grammar.push_str(&format!("{} ::= {}\n", name, body));
+        }
+        grammar
+    }
+
+    /// Adds a rule and returns its name, which is suffixed if the name is taken by a different rule.
+    ///
+    /// Note that no rule is added if the body is merely the name of another rule, except for the root rule.
+    fn add_rule(&mut self, name: &str, body: String) -> String {
+        if !name.is_empty() && self.rules.contains_key(&body) {
+            return body;
+        }
+
+        let name = match name.is_empty() {
+            true => "root".to_string(),
+            false => name
+                .chars()
+                .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+                    true => c,
+                    false => '-',
+                })
+                .collect(),
+        };
+
+        let mut key = name.clone();
+        let mut i = 0;
+        // the names of the primitive rules are reserved even if the primitive rules are not added yet
+        while let Some(existing) = self.rules.get(&key).map(String::as_str).or_else(|| {
+            PRIMITIVE_RULES
+                .iter()
+                .find(|(n, _, _)| *n == key)
+                .map(|(_, body, _)| *body)
+        }) {
+            if existing == body {
+                return key;
+            }
+            i += 1;
+            key = format!("{}{}", name, i);
+        }
+        self.rules.insert(key.clone(), body);
+
+        key
+    }
+
+    /// Adds a primitive rule along with its dependencies, and returns its name.
+    fn add_primitive(&mut self, name: &str) -> String {
+        if let Some((_, body, deps)) = PRIMITIVE_RULES.iter().find(|(n, _, _)| *n == name) {
+            if !self.rules.contains_key(name) {
+                self.rules.insert(name.to_string(), body.to_string());
+                for dep in deps.iter() {
+                    self.add_primitive(dep);
+                }
+            }
+        }
+
+        name.to_string()
+    }
+
+    /// Converts the schema to rules, and returns the name of the rule matching the schema.
+    fn visit(&mut self, schema: &Value, name: &str) -> Result<String, LlamaCoreError> {
+        let schema = match schema {
+            Value::Bool(true) => {
+                let rule = self.add_primitive("value");
+                return Ok(self.add_rule(name, rule));
+            }
+            Value::Object(schema) => schema,
+            _ => return Err(unsupported(format!("invalid schema `{}`", schema))),
+        };
+
+        for keyword in ["pattern", "allOf", "not", "if", "then", "else"] {
+            if schema.contains_key(keyword) {
+                return Err(unsupported(format!("the `{}` keyword", keyword)));
+            }
+        }
+
+        if let Some(reference) = schema.get("$ref") {
+            let reference = reference
+                .as_str()
+                .ok_or_else(|| unsupported("a non-string `$ref`".to_string()))?;
+            let rule = self.resolve_ref(reference)?;
+            return Ok(self.add_rule(name, rule));
+        }
+
+        if let Some(alternatives) = schema.get("anyOf").or_else(|| schema.get("oneOf")) {
+            let alternatives = alternatives
+                .as_array()
+                .ok_or_else(|| unsupported("a non-array `anyOf` or `oneOf`".to_string()))?;
+
+            let mut rules = vec![];
+            for (i, alternative) in alternatives.iter().enumerate() {
+                let rule = match name.is_empty() {
+                    true => self.visit(alternative, &format!("alternative-{}", i))?,
+                    false => self.visit(alternative, &format!("{}-{}", name, i))?,
+                };
+                rules.push(rule);
+            }
+
+            return Ok(self.add_rule(name, rules.join(" | ")));
+        }
+
+        if let Some(value) = schema.get("const") {
+            return Ok(self.add_rule(name, format!("{} space", literal(value))));
+        }
+
+        if let Some(values) = schema.get("enum") {
+            let values = values
+                .as_array()
+                .ok_or_else(|| unsupported("a non-array `enum`".to_string()))?;
+            let body = values.iter().map(literal).collect::<Vec<_>>().join(" | ");
+
+            return Ok(self.add_rule(name, format!("({}) space", body)));
+        }
+
+        match schema.get("type") {
+            Some(Value::String(ty)) => self.visit_type(schema, ty, name),
+            Some(Value::Array(types)) => {
+                let mut rules = vec![];
+                for ty in types {
+                    let ty = ty
+                        .as_str()
+                        .ok_or_else(|| unsupported(format!("the type `{}`", ty)))?;
+                    rules.push(self.visit_type(
+                        schema,
+                        ty,
+                        &format!("{}{}", sub_name(name), ty),
+                    )?);
+                }
+
+                Ok(self.add_rule(name, rules.join(" | ")))
+            }
+            Some(ty) => Err(unsupported(format!("the type `{}`", ty))),
+            None if schema.contains_key("properties") => self.visit_type(schema, "object", name),
+            None if schema.contains_key("items") || schema.contains_key("prefixItems") => {
+                self.visit_type(schema, "array", name)
+            }
+            None => {
+                let rule = self.add_primitive("value");
+                Ok(self.add_rule(name, rule))
+            }
+        }
+    }
+
+    fn visit_type(
+        &mut self,
+        schema: &Map<String, Value>,
+        ty: &str,
+        name: &str,
+    ) -> Result<String, LlamaCoreError> {
+        match ty {
+            "object" => self.visit_object(schema, name),
+            "array" => self.visit_array(schema, name),
+            "string" => {
+                let min_length = schema.get("minLength").and_then(Value::as_u64).unwrap_or(0);
+                let max_length = schema.get("maxLength").and_then(Value::as_u64);
+                if min_length == 0 && max_length.is_none() {
+                    let rule = self.add_primitive("string");
+                    return Ok(self.add_rule(name, rule));
+                }
+
+                let char_rule = self.add_primitive("char");
+                let body = repeat(&char_rule, min_length, max_length, "");
+
+                Ok(self.add_rule(name, format!(r#""\"" {} "\"" space"#, body)))
+            }
+            "number" | "integer" | "boolean" | "null" => {
+                let rule = self.add_primitive(ty);
+                Ok(self.add_rule(name, rule))
+            }
+            _ => Err(unsupported(format!("the type `{}`", ty))),
+        }
+    }
+
+    fn visit_object(
+        &mut self,
+        schema: &Map<String, Value>,
+        name: &str,
+    ) -> Result<String, LlamaCoreError> {
+        let properties = match schema.get("properties") {
+            Some(Value::Object(properties)) if !properties.is_empty() => properties,
+            _ => {
+                // an object with arbitrary keys
+                let value_rule = match schema.get("additionalProperties") {
+                    Some(Value::Object(additional)) => self.visit(
+                        &Value::Object(additional.clone()),
+                        &format!("{}additional", sub_name(name)),
+                    )?,
+                    _ => self.add_primitive("value"),
+                };
+                let string_rule = self.add_primitive("string");
+                let kv = format!(r#"{} ":" space {}"#, string_rule, value_rule);
+                let body = format!(r#""{{" space ( {} ("," space {})* )? "}}" space"#, kv, kv);
+
+                return Ok(self.add_rule(name, body));
+            }
+        };
+
+        let required: Vec<&str> = match schema.get("required") {
+            Some(Value::Array(required)) => required.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+
+        let mut required_kvs = vec![];
+        let mut optional_kvs = vec![];
+        for (prop_name, prop_schema) in properties {
+            let prop_rule = self.visit(prop_schema, &format!("{}{}", sub_name(name), prop_name))?;
+            let kv = format!(
+                "{} space \":\" space {}",
+                literal(&Value::String(prop_name.clone())),
+                prop_rule
+            );
+            let kv_rule = self.add_rule(&format!("{}{}-kv", sub_name(name), prop_name), kv);
+
+            match required.contains(&prop_name.as_str()) {
+                true => required_kvs.push(kv_rule),
+                false => optional_kvs.push(kv_rule),
+            }
+        }
+
+        let mut body = String::from(r#""{" space"#);
+        match required_kvs.is_empty() {
+            true => {
+                // any of the optional properties may come first
+                let alternatives: Vec<String> = (0..optional_kvs.len())
+                    .map(|i| {
+                        let mut alternative = optional_kvs[i].clone();
+                        for kv in &optional_kvs[i + 1..] {
+                            alternative.push_str(&format!(r#" ( "," space {} )?"#, kv));
+                        }
+                        alternative
+                    })
+                    .collect();
+                body.push_str(&format!(" ( {} )?", alternatives.join(" | ")));
+            }
+            false => {
+                body.push(' ');
+                body.push_str(&required_kvs.join(r#" "," space "#));
+                for kv in &optional_kvs {
+                    body.push_str(&format!(r#" ( "," space {} )?"#, kv));
+                }
+            }
+        }
+        body.push_str(r#" "}" space"#);
+
+        Ok(self.add_rule(name, body))
+    }
+
+    fn visit_array(
+        &mut self,
+        schema: &Map<String, Value>,
+        name: &str,
+    ) -> Result<String, LlamaCoreError> {
+        let body = match (schema.get("prefixItems"), schema.get("items")) {
+            (Some(Value::Array(prefix_items)), _) | (None, Some(Value::Array(prefix_items))) => {
+                // a tuple
+                let mut rules = vec![];
+                for (i, item) in prefix_items.iter().enumerate() {
+                    rules.push(self.visit(item, &format!("{}tuple-{}", sub_name(name), i))?);
+                }
+                rules.join(r#" "," space "#)
+            }
+            (_, Some(items)) => {
+                let item_rule = self.visit(items, &format!("{}item", sub_name(name)))?;
+                let min_items = schema.get("minItems").and_then(Value::as_u64).unwrap_or(0);
+                let max_items = schema.get("maxItems").and_then(Value::as_u64);
+                repeat(&item_rule, min_items, max_items, r#""," space"#)
+            }
+            (_, None) => {
+                let value_rule = self.add_primitive("value");
+                repeat(&value_rule, 0, None, r#""," space"#)
+            }
+        };
+
+        Ok(self.add_rule(name, format!(r#""[" space {} "]" space"#, body)))
+    }
+
+    fn resolve_ref(&mut self, reference: &str) -> Result<String, LlamaCoreError> {
+        if let Some(rule) = self.refs.get(reference) {
+            return Ok(rule.clone());
+        }
+
+        let target = match reference.strip_prefix('#') {
+            Some(pointer) => self.root_schema.pointer(pointer),
+            None => None,
+        };
+        let target = match target {
+            Some(target) => target,
+            None => return Err(unsupported(format!("the reference `{}`", reference))),
+        };
+
+        // reserve the rule name before visiting the target to support recursive references
+        let name = reference.rsplit('/').next().unwrap_or("ref").to_string();
+        let rule = self.add_rule(&format!("ref-{}", name), format!("({})", reference));
+        self.refs.insert(reference.to_string(), rule.clone());
+
+        let target_rule = self.visit(target, &format!("{}-def", rule))?;
+        self.rules.insert(rule.clone(), target_rule);
+
+        Ok(rule)
+    }
+}
+
+/// Returns the prefix of the names of the sub-rules.
+fn sub_name(name: &str) -> String {
+    match name.is_empty() {
+        true => String::new(),
+        false => format!("{}-", name),
+    }
+}
+
+/// Returns the GBNF literal matching the JSON representation of the value.
+fn literal(value: &Value) -> String {
+    let json = value.to_string();
+    let mut literal = String::from("\"");
+    for c in json.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            _ => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Returns the expression repeating the item between `min` and `max` times.
+fn repeat(item: &str, min: u64, max: Option<u64>, separator: &str) -> String {
+    if max == Some(0) {
+        return String::new();
+    }
+
+    let sep_item = match separator.is_empty() {
+        true => item.to_string(),
+        false => format!("{} {}", separator, item),
+    };
+
+    if min == 0 {
+        return format!("( {} )?", repeat(item, 1, max, separator));
+    }
+
+    let mut parts = vec![item.to_string()];
+    for _ in 1..min {
+        parts.push(sep_item.clone());
+    }
+    match max {
+        Some(max) => {
+            for _ in min..max {
+                parts.push(format!("( {} )?", sep_item));
+            }
+        }
+        None => parts.push(format!("( {} )*", sep_item)),
+    }
+
+    parts.join(" ")
+}
+
+fn unsupported(what: String) -> LlamaCoreError {
+    let err_msg = format!(
+        "Failed to convert the JSON schema to a grammar. Reason: Unsupported {}.",
+        what
+    );
+
+    #[cfg(feature = "logging")]
+    error!(target: "stdout", "{}", &err_msg);
+
+    LlamaCoreError::invalid_request(err_msg, Some("response_format"))
+}
+
+#[test]
+fn test_json_object_grammar() {
+    let grammar = json_object_grammar();
+    assert!(grammar.starts_with("root ::= object\n"));
+    for rule in [
+        "object", "array", "value", "string", "char", "number", "boolean", "null", "space",
+    ] {
+        assert!(grammar.contains(&format!("\n{} ::= ", rule)), "{}", rule);
+    }
+    // unused primitives are not included
+    assert!(!grammar.contains("integer ::="));
+}
+
+#[test]
+fn test_json_schema_to_grammar_primitives() {
+    let grammar = json_schema_to_grammar(&serde_json::json!({"type": "integer"})).unwrap();
+    assert_eq!(
+        grammar,
+        "root ::= integer\ninteger ::= (\"-\"? ([0-9] | [1-9] [0-9]*)) space\nspace ::= \" \"?\n"
+    );
+
+    let grammar = json_schema_to_grammar(&serde_json::json!({"type": "boolean"})).unwrap();
+    assert!(grammar.starts_with("root ::= boolean\n"));
+
+    let grammar = json_schema_to_grammar(&serde_json::json!({})).unwrap();
+    assert!(grammar.starts_with("root ::= value\n"));
+    assert!(grammar.contains("\nobject ::= "));
+}
+
+#[test]
+fn test_json_schema_to_grammar_object() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "age": {"type": "integer"},
+            "name": {"type": "string"},
+            "nickname": {"type": "string"}
+        },
+        "required": ["name", "age"]
+    });
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar.starts_with(
+        "root ::= \"{\" space age-kv \",\" space name-kv ( \",\" space nickname-kv )? \"}\" space\n"
+    ));
+    assert!(grammar.contains("\nage-kv ::= \"\\\"age\\\"\" space \":\" space integer\n"));
+    assert!(grammar.contains("\nname-kv ::= \"\\\"name\\\"\" space \":\" space string\n"));
+}
+
+#[test]
+fn test_json_schema_to_grammar_optional_properties() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "a": {"type": "number"},
+            "b": {"type": "number"}
+        }
+    });
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar
+        .starts_with("root ::= \"{\" space ( a-kv ( \",\" space b-kv )? | b-kv )? \"}\" space\n"));
+}
+
+#[test]
+fn test_json_schema_to_grammar_enum_and_const() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "unit": {"type": "string", "enum": ["celsius", "fahrenheit"]},
+            "version": {"const": 1}
+        },
+        "required": ["unit", "version"]
+    });
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar.contains("\nunit ::= (\"\\\"celsius\\\"\" | \"\\\"fahrenheit\\\"\") space\n"));
+    assert!(grammar.contains("\nversion ::= \"1\" space\n"));
+}
+
+#[test]
+fn test_json_schema_to_grammar_array() {
+    let schema = serde_json::json!({
+        "type": "array",
+        "items": {"type": "string"},
+        "minItems": 1,
+        "maxItems": 3
+    });
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar.starts_with(
+        "root ::= \"[\" space string ( \",\" space string )? ( \",\" space string )? \"]\" space\n"
+    ));
+
+    let schema = serde_json::json!({"type": "array", "items": {"type": "number"}});
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar
+        .starts_with("root ::= \"[\" space ( number ( \",\" space number )* )? \"]\" space\n"));
+}
+
+#[test]
+fn test_json_schema_to_grammar_any_of_and_nullable() {
+    let schema = serde_json::json!({
+        "anyOf": [{"type": "integer"}, {"type": "string", "maxLength": 1}]
+    });
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar.starts_with("root ::= integer | alternative-1\n"));
+    assert!(grammar.contains("\nalternative-1 ::= \"\\\"\" ( char )? \"\\\"\" space\n"));
+
+    let schema = serde_json::json!({"type": ["string", "null"]});
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar.starts_with("root ::= string | null\n"));
+}
+
+#[test]
+fn test_json_schema_to_grammar_refs() {
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "children": {"type": "array", "items": {"$ref": "#/$defs/node"}}
+        },
+        "required": ["children"],
+        "$defs": {
+            "node": {
+                "type": "object",
+                "properties": {
+                    "children": {"type": "array", "items": {"$ref": "#/$defs/node"}}
+                }
+            }
+        }
+    });
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar.contains(
+        "\nchildren ::= \"[\" space ( ref-node ( \",\" space ref-node )* )? \"]\" space\n"
+    ));
+    assert!(grammar.contains("\nref-node ::= ref-node-def\n"));
+    assert!(grammar
+        .contains("\nref-node-def ::= \"{\" space ( ref-node-def-children-kv )? \"}\" space\n"));
+
+    let schema = serde_json::json!({"$ref": "#/$defs/missing"});
+    assert!(json_schema_to_grammar(&schema).is_err());
+}
+
+#[test]
+fn test_json_schema_to_grammar_reserved_names() {
+    // the property named `value` must not shadow the primitive rule `value`
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {"value": {"type": "string", "maxLength": 2}}
+    });
+    let grammar = json_schema_to_grammar(&schema).unwrap();
+    assert!(grammar.contains("\nvalue-kv ::= \"\\\"value\\\"\" space \":\" space value1\n"));
+    assert!(!grammar.contains("\nvalue ::= "));
+}
+
+#[test]
+fn test_json_schema_to_grammar_unsupported() {
+    let schema = serde_json::json!({"type": "string", "pattern": "^[a-z]+$"});
+    assert!(json_schema_to_grammar(&schema).is_err());
+
+    let schema = serde_json::json!({"type": "date"});
+    assert!(json_schema_to_grammar(&schema).is_err());
+}
+
+#[test]
+fn test_response_format_to_grammar() {
+    use endpoints::chat::ChatResponseJsonSchema;
+
+    let response_format = ChatResponseFormat::default();
+    assert!(response_format_to_grammar(&response_format)
+        .unwrap()
+        .is_none());
+
+    let response_format = ChatResponseFormat {
+        ty: "json_object".to_string(),
+        json_schema: None,
+    };
+    assert_eq!(
+        response_format_to_grammar(&response_format).unwrap(),
+        Some(json_object_grammar())
+    );
+
+    let response_format = ChatResponseFormat {
+        ty: "json_schema".to_string(),
+        json_schema: Some(ChatResponseJsonSchema {
+            name: "answer".to_string(),
+            description: None,
+            schema: Some(serde_json::json!({"type": "number"})),
+            strict: None,
+        }),
+    };
+    let grammar = response_format_to_grammar(&response_format)
+        .unwrap()
+        .unwrap();
+    assert!(grammar.starts_with("root ::= number\n"));
+
+    let response_format = ChatResponseFormat {
+        ty: "json_schema".to_string(),
+        json_schema: None,
+    };
+    assert!(response_format_to_grammar(&response_format).is_err());
+
+    let response_format = ChatResponseFormat {
+        ty: "xml".to_string(),
+        json_schema: None,
+    };
+    assert!(response_format_to_grammar(&response_format).is_err());
+}
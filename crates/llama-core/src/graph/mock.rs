@@ -0,0 +1,282 @@
+//! Define the mock inference backend, which generates the canned tokens and embeddings without loading a model.
+//!
+//! The mock backend lets the chat, completion and embedding pipelines run on plain Linux without WasmEdge and a GGUF model, e.g., in the integration tests or a dry-run server for frontend development:
+//!
+//! ```
+//! use llama_core::{graph::mock::MockBackend, GgmlMetadata, Graph, InferenceBackend};
+//!
+//! let backend = MockBackend::default().with_tokens(["Hello", ",", " world", "!"]);
+//! let graph: Graph<GgmlMetadata> =
+//!     Graph::with_backend(GgmlMetadata::default(), Box::new(backend) as Box<dyn InferenceBackend>)
+//!         .unwrap();
+//! ```
+
+use super::{InferenceBackend, InferenceError, TensorType};
+use crate::OUTPUT_TENSOR;
+
+/// The index of the input tensor for the prompt.
+const PROMPT_TENSOR: usize = 0;
+/// The index of the input tensor for the metadata of the model.
+const METADATA_TENSOR: usize = 1;
+/// The index of the output tensor for the token information and the plugin information.
+const INFO_TENSOR: usize = 1;
+
+/// Inference backend which generates the same canned tokens for every prompt, or returns the same canned embedding for every input.
+///
+/// The tokens of the text are the whitespace-separated words in the text, whose ids are assigned in the order they are first seen. The log probability of each generated token is `0.0`.
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    /// Tokens generated for every prompt
+    tokens: Vec<String>,
+    /// Embedding returned for every input
+    embedding: Option<Vec<f64>>,
+    /// Prompt set by the last input
+    prompt: String,
+    /// Words seen in the tokenized texts, indexed by the token ids
+    vocab: Vec<String>,
+    /// Number of the tokens generated from the prompt
+    generated: usize,
+}
+impl MockBackend {
+    /// Set the tokens generated for every prompt.
+    pub fn with_tokens(mut self, tokens: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tokens = tokens.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the embedding returned for every input, which turns the backend into an embedding model.
+    pub fn with_embedding(mut self, embedding: Vec<f64>) -> Self {
+        self.embedding = Some(embedding);
+        self
+    }
+
+    fn generation(&self) -> String {
+        self.tokens[..self.generated].concat()
+    }
+
+    fn info(&self) -> String {
+        serde_json::json!({
+            "input_tokens": self.prompt.split_whitespace().count(),
+            "output_tokens": self.generated,
+            "llama_build_number": 0,
+            "llama_commit": "mock",
+        })
+        .to_string()
+    }
+
+    fn logprobs(tokens: &[String]) -> String {
+        let logprobs: Vec<serde_json::Value> = tokens
+            .iter()
+            .map(|token| {
+                serde_json::json!({
+                    "token": token,
+                    "bytes": token.as_bytes(),
+                    "logprob": 0.0,
+                    "top_logprobs": [],
+                })
+            })
+            .collect();
+
+        serde_json::Value::from(logprobs).to_string()
+    }
+}
+impl InferenceBackend for MockBackend {
+    fn set_input(
+        &mut self,
+        index: usize,
+        _tensor_type: TensorType,
+        _dimensions: &[usize],
+        data: &[u8],
+    ) -> Result<(), InferenceError> {
+        match index {
+            PROMPT_TENSOR => {
+                self.prompt = String::from_utf8_lossy(data).into_owned();
+                self.generated = 0;
+
+                Ok(())
+            }
+            METADATA_TENSOR => Ok(()),
+            _ => Err(InferenceError::InvalidArgument),
+        }
+    }
+
+    fn compute(&mut self) -> Result<(), InferenceError> {
+        self.generated = self.tokens.len();
+        Ok(())
+    }
+
+    fn compute_single(&mut self) -> Result<(), InferenceError> {
+        match self.generated < self.tokens.len() {
+            true => {
+                self.generated += 1;
+                Ok(())
+            }
+            false => Err(InferenceError::EndOfSequence),
+        }
+    }
+
+    fn get_output(&self, index: usize, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        let output = match index {
+            OUTPUT_TENSOR => match &self.embedding {
+                Some(embedding) => serde_json::json!({
+                    "n_embedding": embedding.len(),
+                    "embedding": embedding,
+                })
+                .to_string(),
+                None => self.generation(),
+            },
+            INFO_TENSOR => self.info(),
+            _ => return Err(InferenceError::InvalidArgument),
+        };
+
+        copy_output(output.as_bytes(), out_buffer)
+    }
+
+    fn get_output_single(
+        &self,
+        index: usize,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        // the token generated by the last `compute_single`
+        let last = &self.tokens[self.generated.saturating_sub(1)..self.generated];
+
+        let output = match index {
+            OUTPUT_TENSOR => last.concat(),
+            INFO_TENSOR => self.info(),
+            _ => return Err(InferenceError::InvalidArgument),
+        };
+
+        copy_output(output.as_bytes(), out_buffer)
+    }
+
+    fn finish_single(&mut self) -> Result<(), InferenceError> {
+        self.generated = 0;
+        Ok(())
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        true
+    }
+
+    fn get_logprobs(&self, single: bool, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        let tokens = match single {
+            true => &self.tokens[self.generated.saturating_sub(1)..self.generated],
+            false => &self.tokens[..self.generated],
+        };
+
+        copy_output(Self::logprobs(tokens).as_bytes(), out_buffer)
+    }
+
+    fn supports_tokenize(&self) -> bool {
+        true
+    }
+
+    fn tokenize(&mut self, text: &str, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        let mut token_ids = vec![];
+        for word in text.split_whitespace() {
+            let id = match self.vocab.iter().position(|known| known == word) {
+                Some(id) => id,
+                None => {
+                    self.vocab.push(word.to_string());
+                    self.vocab.len() - 1
+                }
+            };
+            token_ids.push(id as u32);
+        }
+
+        copy_output(
+            serde_json::Value::from(token_ids).to_string().as_bytes(),
+            out_buffer,
+        )
+    }
+
+    fn detokenize(
+        &mut self,
+        token_ids: &str,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        let ids: Vec<usize> =
+            serde_json::from_str(token_ids).map_err(|_| InferenceError::InvalidArgument)?;
+        let words = ids
+            .iter()
+            .map(|id| self.vocab.get(*id).map(String::as_str))
+            .collect::<Option<Vec<&str>>>()
+            .ok_or(InferenceError::InvalidArgument)?;
+
+        copy_output(words.join(" ").as_bytes(), out_buffer)
+    }
+}
+
+fn copy_output(output: &[u8], out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+    if output.len() > out_buffer.len() {
+        return Err(InferenceError::TooLarge);
+    }
+
+    out_buffer[..output.len()].copy_from_slice(output);
+
+    Ok(output.len())
+}
+
+#[test]
+fn test_mock_backend_generate() {
+    use crate::{
+        utils::{get_output_buffer, get_output_buffer_single, get_token_info_by_graph},
+        GgmlMetadata, Graph,
+    };
+
+    let backend = MockBackend::default().with_tokens(["Hello", ",", " world", "!"]);
+    let mut graph: Graph<GgmlMetadata> = Graph::with_backend(
+        GgmlMetadata::default(),
+        Box::new(backend) as Box<dyn InferenceBackend>,
+    )
+    .unwrap();
+
+    // generate all the tokens at once
+    graph
+        .set_input(PROMPT_TENSOR, TensorType::U8, &[1], "Say hello".as_bytes())
+        .unwrap();
+    graph.compute().unwrap();
+    let output = get_output_buffer(&graph, OUTPUT_TENSOR).unwrap();
+    assert_eq!(output, b"Hello, world!");
+    let token_info = get_token_info_by_graph(&graph).unwrap();
+    assert_eq!(token_info.prompt_tokens, 2);
+    assert_eq!(token_info.completion_tokens, 4);
+
+    // generate one token at a time
+    graph
+        .set_input(PROMPT_TENSOR, TensorType::U8, &[1], "Say hello".as_bytes())
+        .unwrap();
+    let mut tokens = vec![];
+    while graph.compute_single().is_ok() {
+        let token = get_output_buffer_single(&graph, OUTPUT_TENSOR).unwrap();
+        tokens.push(String::from_utf8(token).unwrap());
+    }
+    assert_eq!(tokens, ["Hello", ",", " world", "!"]);
+    assert!(matches!(
+        graph.compute_single(),
+        Err(InferenceError::EndOfSequence)
+    ));
+    graph.finish_single().unwrap();
+    assert_eq!(
+        get_token_info_by_graph(&graph).unwrap().completion_tokens,
+        0
+    );
+}
+
+#[test]
+fn test_mock_backend_embedding() {
+    let mut backend = MockBackend::default().with_embedding(vec![0.5, -0.25]);
+    backend
+        .set_input(PROMPT_TENSOR, TensorType::U8, &[1], b"hello")
+        .unwrap();
+    backend.compute().unwrap();
+
+    let mut out_buffer = vec![0u8; 128];
+    let size = backend.get_output(OUTPUT_TENSOR, &mut out_buffer).unwrap();
+    let embedding: serde_json::Value = serde_json::from_slice(&out_buffer[..size]).unwrap();
+    assert_eq!(embedding["n_embedding"], 2);
+    assert_eq!(embedding["embedding"], serde_json::json!([0.5, -0.25]));
+
+    // the output does not fit in the buffer
+    assert!(backend.get_output(OUTPUT_TENSOR, &mut [0u8; 4]).is_err());
+}
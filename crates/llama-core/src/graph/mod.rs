@@ -0,0 +1,651 @@
+//! Define Graph and GraphBuilder APIs for creating a new computation graph.
+
+pub mod mock;
+
+use crate::{error::LlamaCoreError, BaseMetadata};
+use thiserror::Error;
+use wasmedge_wasi_nn::{Graph as WasiNnGraph, GraphExecutionContext};
+
+/// The index of the input tensor for the metadata of the model.
+const METADATA_TENSOR: usize = 1;
+/// The index of the output tensor for the token information.
+const TOKEN_INFO_TENSOR: usize = 1;
+
+/// Type of the elements of a tensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TensorType {
+    F16,
+    F32,
+    F64,
+    U8,
+    I32,
+    I64,
+}
+impl From<TensorType> for wasmedge_wasi_nn::TensorType {
+    fn from(tensor_type: TensorType) -> Self {
+        match tensor_type {
+            TensorType::F16 => wasmedge_wasi_nn::TensorType::F16,
+            TensorType::F32 => wasmedge_wasi_nn::TensorType::F32,
+            TensorType::F64 => wasmedge_wasi_nn::TensorType::F64,
+            TensorType::U8 => wasmedge_wasi_nn::TensorType::U8,
+            TensorType::I32 => wasmedge_wasi_nn::TensorType::I32,
+            TensorType::I64 => wasmedge_wasi_nn::TensorType::I64,
+        }
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Plain numeric type of the elements of a tensor, whose values are passed to the backend as their raw bytes.
+///
+/// The trait is sealed, as the raw bytes are only meaningful for the types without padding, pointers or invalid bit patterns.
+pub trait TensorElement: private::Sealed + Copy {}
+
+macro_rules! impl_tensor_element {
+    ($($ty:ty),*) => {
+        $(
+            impl private::Sealed for $ty {}
+            impl TensorElement for $ty {}
+        )*
+    };
+}
+impl_tensor_element!(u8, i32, i64, f32, f64);
+
+/// Error types for the inference backends.
+#[derive(Error, Debug)]
+pub enum InferenceError {
+    /// The generation reaches the end of the sequence.
+    #[error("End of sequence")]
+    EndOfSequence,
+    /// The context of the model is full during the generation.
+    #[error("Context full")]
+    ContextFull,
+    /// The prompt does not fit in the context of the model.
+    #[error("Prompt too long")]
+    PromptTooLong,
+    /// The input is invalid, e.g., the index of the tensor is out of range.
+    #[error("Invalid argument")]
+    InvalidArgument,
+    /// The output does not fit in the output buffer.
+    #[error("Output too large")]
+    TooLarge,
+    /// Other errors thrown by the backend.
+    #[error("{0}")]
+    Other(String),
+}
+impl From<wasmedge_wasi_nn::Error> for InferenceError {
+    fn from(e: wasmedge_wasi_nn::Error) -> Self {
+        use wasmedge_wasi_nn::BackendError;
+
+        match e {
+            wasmedge_wasi_nn::Error::BackendError(BackendError::EndOfSequence) => {
+                InferenceError::EndOfSequence
+            }
+            wasmedge_wasi_nn::Error::BackendError(BackendError::ContextFull) => {
+                InferenceError::ContextFull
+            }
+            wasmedge_wasi_nn::Error::BackendError(BackendError::PromptTooLong) => {
+                InferenceError::PromptTooLong
+            }
+            wasmedge_wasi_nn::Error::BackendError(BackendError::InvalidArgument) => {
+                InferenceError::InvalidArgument
+            }
+            wasmedge_wasi_nn::Error::BackendError(BackendError::TooLarge) => {
+                InferenceError::TooLarge
+            }
+            e => InferenceError::Other(e.to_string()),
+        }
+    }
+}
+
+/// Inference backend which runs the computation of a graph.
+///
+/// The tensors are exchanged as bytes. The input tensor `0` is the prompt, and the output tensor `0` is the generation. [`WasiNnBackend`] runs the computation with the WasmEdge WASI-NN plugins, and [`mock::MockBackend`] returns the canned tokens and embeddings without loading a model.
+pub trait InferenceBackend: std::fmt::Debug + Send {
+    /// Set the data of the input tensor at the given index.
+    fn set_input(
+        &mut self,
+        index: usize,
+        tensor_type: TensorType,
+        dimensions: &[usize],
+        data: &[u8],
+    ) -> Result<(), InferenceError>;
+
+    /// Compute the inference on the given inputs.
+    fn compute(&mut self) -> Result<(), InferenceError>;
+
+    /// Compute the inference on the given inputs, which generates one token at a time.
+    fn compute_single(&mut self) -> Result<(), InferenceError>;
+
+    /// Copy the output tensor at the given index to out_buffer, return the output's size in bytes.
+    fn get_output(&self, index: usize, out_buffer: &mut [u8]) -> Result<usize, InferenceError>;
+
+    /// Copy the output tensor at the given index to out_buffer after generating one token, return the output's size in bytes.
+    fn get_output_single(
+        &self,
+        index: usize,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError>;
+
+    /// Clear the computation context after generating one token at a time.
+    fn finish_single(&mut self) -> Result<(), InferenceError>;
+
+    /// Copy the token information, i.e., `{"input_tokens": .., "output_tokens": ..}` in JSON, to out_buffer, return its size in bytes.
+    fn get_token_info(&self, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        self.get_output(TOKEN_INFO_TENSOR, out_buffer)
+    }
+
+    /// Whether the backend outputs the log probabilities of the generated tokens. The WASI-NN ggml plugin does not.
+    fn supports_logprobs(&self) -> bool {
+        false
+    }
+
+    /// Copy the log probabilities of the generated tokens, i.e., `[{"token": .., "bytes": [..], "logprob": .., "top_logprobs": [..]}, ..]` in JSON, to out_buffer, return its size in bytes. If `single` is `true`, only the token generated by the last `compute_single` is included. Only called if [`InferenceBackend::supports_logprobs`] returns `true`.
+    fn get_logprobs(&self, _single: bool, _out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        Err(InferenceError::InvalidArgument)
+    }
+
+    /// Whether the backend converts the text to the token ids of the model and back. The WASI-NN ggml plugin does not expose the vocabulary of the model.
+    fn supports_tokenize(&self) -> bool {
+        false
+    }
+
+    /// Convert the text to the token ids, copy the ids, i.e., `[1, 15043, ..]` in JSON, to out_buffer, return their size in bytes. Only called if [`InferenceBackend::supports_tokenize`] returns `true`.
+    fn tokenize(&mut self, _text: &str, _out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        Err(InferenceError::InvalidArgument)
+    }
+
+    /// Convert the token ids, i.e., `[1, 15043, ..]` in JSON, to text, copy the text to out_buffer, return its size in bytes. Only called if [`InferenceBackend::supports_tokenize`] returns `true`.
+    fn detokenize(
+        &mut self,
+        _token_ids: &str,
+        _out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        Err(InferenceError::InvalidArgument)
+    }
+
+    /// Update the metadata of the model with the given metadata in JSON.
+    fn update_metadata(&mut self, config: &str) -> Result<(), InferenceError> {
+        self.set_input(METADATA_TENSOR, TensorType::U8, &[1], config.as_bytes())
+    }
+}
+impl<B: InferenceBackend + ?Sized> InferenceBackend for Box<B> {
+    fn set_input(
+        &mut self,
+        index: usize,
+        tensor_type: TensorType,
+        dimensions: &[usize],
+        data: &[u8],
+    ) -> Result<(), InferenceError> {
+        (**self).set_input(index, tensor_type, dimensions, data)
+    }
+
+    fn compute(&mut self) -> Result<(), InferenceError> {
+        (**self).compute()
+    }
+
+    fn compute_single(&mut self) -> Result<(), InferenceError> {
+        (**self).compute_single()
+    }
+
+    fn get_output(&self, index: usize, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        (**self).get_output(index, out_buffer)
+    }
+
+    fn get_output_single(
+        &self,
+        index: usize,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        (**self).get_output_single(index, out_buffer)
+    }
+
+    fn finish_single(&mut self) -> Result<(), InferenceError> {
+        (**self).finish_single()
+    }
+
+    fn get_token_info(&self, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        (**self).get_token_info(out_buffer)
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        (**self).supports_logprobs()
+    }
+
+    fn get_logprobs(&self, single: bool, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        (**self).get_logprobs(single, out_buffer)
+    }
+
+    fn supports_tokenize(&self) -> bool {
+        (**self).supports_tokenize()
+    }
+
+    fn tokenize(&mut self, text: &str, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        (**self).tokenize(text, out_buffer)
+    }
+
+    fn detokenize(
+        &mut self,
+        token_ids: &str,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        (**self).detokenize(token_ids, out_buffer)
+    }
+
+    fn update_metadata(&mut self, config: &str) -> Result<(), InferenceError> {
+        (**self).update_metadata(config)
+    }
+}
+
+/// Inference backend running on the WasmEdge WASI-NN plugins.
+#[derive(Debug)]
+pub struct WasiNnBackend {
+    _graph: WasiNnGraph,
+    context: GraphExecutionContext,
+}
+impl WasiNnBackend {
+    /// Create a backend from the loaded WASI-NN graph.
+    pub fn new(graph: WasiNnGraph) -> Result<Self, LlamaCoreError> {
+        // initialize the execution context
+        let context = graph.init_execution_context().map_err(|e| {
+            let err_msg = e.to_string();
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })?;
+
+        Ok(Self {
+            _graph: graph,
+            context,
+        })
+    }
+}
+impl InferenceBackend for WasiNnBackend {
+    fn set_input(
+        &mut self,
+        index: usize,
+        tensor_type: TensorType,
+        dimensions: &[usize],
+        data: &[u8],
+    ) -> Result<(), InferenceError> {
+        Ok(self
+            .context
+            .set_input(index, tensor_type.into(), dimensions, data)?)
+    }
+
+    fn compute(&mut self) -> Result<(), InferenceError> {
+        Ok(self.context.compute()?)
+    }
+
+    fn compute_single(&mut self) -> Result<(), InferenceError> {
+        Ok(self.context.compute_single()?)
+    }
+
+    fn get_output(&self, index: usize, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        Ok(self.context.get_output(index, out_buffer)?)
+    }
+
+    fn get_output_single(
+        &self,
+        index: usize,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        Ok(self.context.get_output_single(index, out_buffer)?)
+    }
+
+    fn finish_single(&mut self) -> Result<(), InferenceError> {
+        Ok(self.context.fini_single()?)
+    }
+}
+
+/// Builder for creating a new computation graph.
+#[derive(Debug)]
+pub struct GraphBuilder<M: BaseMetadata + serde::Serialize + Clone + Default> {
+    metadata: Option<M>,
+    wasi_nn_graph_builder: wasmedge_wasi_nn::GraphBuilder,
+}
+impl<M: BaseMetadata + serde::Serialize + Clone + Default> GraphBuilder<M> {
+    /// Create a new computation graph builder.
+    pub fn new(ty: EngineType) -> Result<Self, LlamaCoreError> {
+        let encoding = match ty {
+            EngineType::Ggml => wasmedge_wasi_nn::GraphEncoding::Ggml,
+            EngineType::Whisper => wasmedge_wasi_nn::GraphEncoding::Whisper,
+            EngineType::Piper => wasmedge_wasi_nn::GraphEncoding::Piper,
+        };
+
+        let wasi_nn_graph_builder =
+            wasmedge_wasi_nn::GraphBuilder::new(encoding, wasmedge_wasi_nn::ExecutionTarget::AUTO);
+
+        Ok(Self {
+            metadata: None,
+            wasi_nn_graph_builder,
+        })
+    }
+
+    pub fn with_config(mut self, metadata: M) -> Result<Self, LlamaCoreError> {
+        let config = serde_json::to_string(&metadata).map_err(|e| {
+            let err_msg = e.to_string();
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })?;
+        self.wasi_nn_graph_builder = self.wasi_nn_graph_builder.config(config);
+        self.metadata = Some(metadata.clone());
+
+        Ok(self)
+    }
+
+    pub fn use_cpu(mut self) -> Self {
+        self.wasi_nn_graph_builder = self.wasi_nn_graph_builder.cpu();
+        self
+    }
+
+    pub fn use_gpu(mut self) -> Self {
+        self.wasi_nn_graph_builder = self.wasi_nn_graph_builder.gpu();
+        self
+    }
+
+    pub fn use_tpu(mut self) -> Self {
+        self.wasi_nn_graph_builder = self.wasi_nn_graph_builder.tpu();
+        self
+    }
+
+    pub fn build_from_buffer<B>(
+        self,
+        bytes_array: impl AsRef<[B]>,
+    ) -> Result<Graph<M>, LlamaCoreError>
+    where
+        B: AsRef<[u8]>,
+    {
+        // load the model
+        let graph = self
+            .wasi_nn_graph_builder
+            .build_from_bytes(bytes_array)
+            .map_err(|e| {
+                let err_msg = e.to_string();
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                LlamaCoreError::Operation(err_msg)
+            })?;
+
+        Graph::with_backend(
+            self.metadata.clone().unwrap_or_default(),
+            Box::new(WasiNnBackend::new(graph)?),
+        )
+    }
+
+    pub fn build_from_files<P>(self, files: impl AsRef<[P]>) -> Result<Graph<M>, LlamaCoreError>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        // load the model
+        let graph = self
+            .wasi_nn_graph_builder
+            .build_from_files(files)
+            .map_err(|e| {
+                let err_msg = e.to_string();
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                LlamaCoreError::Operation(err_msg)
+            })?;
+
+        Graph::with_backend(
+            self.metadata.clone().unwrap_or_default(),
+            Box::new(WasiNnBackend::new(graph)?),
+        )
+    }
+
+    pub fn build_from_cache(self) -> Result<Graph<M>, LlamaCoreError> {
+        match &self.metadata {
+            Some(metadata) => {
+                // load the model
+                let graph = self
+                    .wasi_nn_graph_builder
+                    .build_from_cache(metadata.model_alias())
+                    .map_err(|e| {
+                        let err_msg = e.to_string();
+
+                        #[cfg(feature = "logging")]
+                        error!(target: "stdout", "{}", &err_msg);
+
+                        LlamaCoreError::Operation(err_msg)
+                    })?;
+
+                Graph::with_backend(metadata.clone(), Box::new(WasiNnBackend::new(graph)?))
+            }
+            None => {
+                let err_msg =
+                    "Failed to create a Graph from cache. Reason: Metadata is not provided."
+                        .to_string();
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                Err(LlamaCoreError::Operation(err_msg))
+            }
+        }
+    }
+}
+
+/// Computation graph of a model, which runs the inference on the given backend.
+#[derive(Debug)]
+pub struct Graph<M, B = Box<dyn InferenceBackend>>
+where
+    M: BaseMetadata + serde::Serialize + Clone + Default,
+    B: InferenceBackend,
+{
+    pub created: std::time::Duration,
+    pub metadata: M,
+    backend: B,
+}
+impl<M: BaseMetadata + serde::Serialize + Clone + Default> Graph<M> {
+    /// Create a new computation graph from the given metadata.
+    pub fn new(metadata: M) -> Result<Self, LlamaCoreError> {
+        let config = serde_json::to_string(&metadata).map_err(|e| {
+            let err_msg = e.to_string();
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })?;
+
+        // load the model
+        let graph = wasmedge_wasi_nn::GraphBuilder::new(
+            wasmedge_wasi_nn::GraphEncoding::Ggml,
+            wasmedge_wasi_nn::ExecutionTarget::AUTO,
+        )
+        .config(config)
+        .build_from_cache(metadata.model_alias())
+        .map_err(|e| {
+            let err_msg = e.to_string();
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })?;
+
+        Self::with_backend(metadata, Box::new(WasiNnBackend::new(graph)?))
+    }
+}
+impl<M, B> Graph<M, B>
+where
+    M: BaseMetadata + serde::Serialize + Clone + Default,
+    B: InferenceBackend,
+{
+    /// Create a new computation graph running on the given backend.
+    pub fn with_backend(metadata: M, backend: B) -> Result<Self, LlamaCoreError> {
+        let created = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|e| {
+                let err_msg = e.to_string();
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                LlamaCoreError::Operation(err_msg)
+            })?;
+
+        Ok(Self {
+            created,
+            metadata,
+            backend,
+        })
+    }
+
+    /// Get the name of the model
+    pub fn name(&self) -> &str {
+        self.metadata.model_name()
+    }
+
+    /// Get the alias of the model
+    pub fn alias(&self) -> &str {
+        self.metadata.model_alias()
+    }
+
+    /// Update metadata
+    pub fn update_metadata(&mut self) -> Result<(), LlamaCoreError> {
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Update metadata for the model named {}", self.name());
+
+        // update metadata
+        let config = match serde_json::to_string(&self.metadata) {
+            Ok(config) => config,
+            Err(e) => {
+                let err_msg = format!("Failed to update metadta. Reason: Fail to serialize metadata to a JSON string. {}", e);
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                return Err(LlamaCoreError::Operation(err_msg));
+            }
+        };
+
+        if self.backend.update_metadata(&config).is_err() {
+            let err_msg = format!("Fail to set input tensor at index {}", METADATA_TENSOR);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::Operation(err_msg));
+        }
+
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Metadata updated successfully.");
+
+        Ok(())
+    }
+
+    /// Set input uses the data, not only [u8](https://doc.rust-lang.org/nightly/std/primitive.u8.html), but also [f32](https://doc.rust-lang.org/nightly/std/primitive.f32.html), [i32](https://doc.rust-lang.org/nightly/std/primitive.i32.html), etc. See [`TensorElement`] for the supported types.
+    pub fn set_input<T: TensorElement>(
+        &mut self,
+        index: usize,
+        tensor_type: TensorType,
+        dimensions: &[usize],
+        data: impl AsRef<[T]>,
+    ) -> Result<(), InferenceError> {
+        let data = data.as_ref();
+
+        // SAFETY: `T` is a plain numeric type, so every byte of the elements is initialized and the bytes live as long as `data`
+        let bytes = unsafe {
+            std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+        };
+
+        self.backend
+            .set_input(index, tensor_type, dimensions, bytes)
+    }
+
+    /// Compute the inference on the given inputs.
+    pub fn compute(&mut self) -> Result<(), InferenceError> {
+        self.backend.compute()
+    }
+
+    /// Compute the inference on the given inputs.
+    ///
+    /// Note that this method is used for the stream mode. It generates one token at a time.
+    pub fn compute_single(&mut self) -> Result<(), InferenceError> {
+        self.backend.compute_single()
+    }
+
+    /// Copy output tensor to out_buffer, return the output’s **size in bytes**.
+    pub fn get_output(&self, index: usize, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        self.backend.get_output(index, out_buffer)
+    }
+
+    /// Copy output tensor to out_buffer, return the output’s **size in bytes**.
+    ///
+    /// Note that this method is used for the stream mode. It returns one token at a time.
+    pub fn get_output_single(
+        &self,
+        index: usize,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        self.backend.get_output_single(index, out_buffer)
+    }
+
+    /// Copy the token information in JSON to out_buffer, return its **size in bytes**.
+    pub fn get_token_info(&self, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        self.backend.get_token_info(out_buffer)
+    }
+
+    /// Whether the backend outputs the log probabilities of the generated tokens.
+    pub fn supports_logprobs(&self) -> bool {
+        self.backend.supports_logprobs()
+    }
+
+    /// Copy the log probabilities of the generated tokens in JSON to out_buffer, return its **size in bytes**.
+    ///
+    /// Note that if `single` is `true`, it returns the log probability of the token generated by the last `compute_single`.
+    pub fn get_logprobs(
+        &self,
+        single: bool,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        self.backend.get_logprobs(single, out_buffer)
+    }
+
+    /// Whether the backend converts the text to the token ids of the model and back.
+    pub fn supports_tokenize(&self) -> bool {
+        self.backend.supports_tokenize()
+    }
+
+    /// Convert the text to the token ids in JSON, copy the ids to out_buffer, return their **size in bytes**.
+    pub fn tokenize(&mut self, text: &str, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        self.backend.tokenize(text, out_buffer)
+    }
+
+    /// Convert the token ids in JSON to text, copy the text to out_buffer, return its **size in bytes**.
+    pub fn detokenize(
+        &mut self,
+        token_ids: &str,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        self.backend.detokenize(token_ids, out_buffer)
+    }
+
+    /// Clear the computation context.
+    ///
+    /// Note that this method is used for the stream mode. It clears the context after the stream mode is finished.
+    pub fn finish_single(&mut self) -> Result<(), InferenceError> {
+        self.backend.finish_single()
+    }
+}
+
+/// Engine type
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EngineType {
+    Ggml,
+    Whisper,
+    Piper,
+}
@@ -6,12 +6,16 @@
 #[macro_use]
 extern crate log;
 
+pub mod assistants;
 pub mod audio;
+pub mod batches;
+pub mod cancellation;
 pub mod chat;
 pub mod completions;
 pub mod embeddings;
 pub mod error;
 pub mod files;
+pub mod grammar;
 pub mod graph;
 pub mod images;
 pub mod metadata;
@@ -19,13 +23,15 @@ pub mod models;
 #[cfg(feature = "rag")]
 #[cfg_attr(docsrs, doc(cfg(feature = "rag")))]
 pub mod rag;
+pub mod scheduler;
 #[cfg(feature = "search")]
 #[cfg_attr(docsrs, doc(cfg(feature = "search")))]
 pub mod search;
+pub mod tokenize;
 pub mod utils;
 
 pub use error::LlamaCoreError;
-pub use graph::{EngineType, Graph, GraphBuilder};
+pub use graph::{EngineType, Graph, GraphBuilder, InferenceBackend, InferenceError};
 pub use metadata::{
     ggml::GgmlMetadata, piper::PiperMetadata, whisper::WhisperMetadata, BaseMetadata,
 };
@@ -62,6 +68,7 @@ pub(crate) const MAX_BUFFER_SIZE: usize = 2usize.pow(14) * 15 + 128;
 pub(crate) const OUTPUT_TENSOR: usize = 0;
 const PLUGIN_VERSION: usize = 1;
 pub const ARCHIVES_DIR: &str = "archives";
+pub const ASSISTANTS_DIR: &str = "assistants";
 
 /// Initialize the ggml context
 pub fn init_ggml_context(
@@ -80,13 +87,48 @@ pub fn init_ggml_context(
         return Err(LlamaCoreError::InitContext(err_msg.into()));
     }
 
+    let graphs_for_chats = match metadata_for_chats {
+        Some(metadata_chats) => Some(
+            metadata_chats
+                .iter()
+                .map(|metadata| Graph::new(metadata.clone()))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+
+    let graphs_for_embeddings = match metadata_for_embeddings {
+        Some(metadata_embeddings) => Some(
+            metadata_embeddings
+                .iter()
+                .map(|metadata| Graph::new(metadata.clone()))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+
+    init_ggml_context_with_graphs(graphs_for_chats, graphs_for_embeddings)
+}
+
+/// Initialize the ggml context with the graphs created in advance, e.g., the graphs running on [`graph::mock::MockBackend`] for testing.
+pub fn init_ggml_context_with_graphs(
+    graphs_for_chats: Option<Vec<Graph<GgmlMetadata>>>,
+    graphs_for_embeddings: Option<Vec<Graph<GgmlMetadata>>>,
+) -> Result<(), LlamaCoreError> {
+    if graphs_for_chats.is_none() && graphs_for_embeddings.is_none() {
+        let err_msg = "Failed to initialize the core context. Please set graphs for chat completions and/or embeddings.";
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", err_msg);
+
+        return Err(LlamaCoreError::InitContext(err_msg.into()));
+    }
+
     let mut mode = RunningMode::Embeddings;
 
-    if let Some(metadata_chats) = metadata_for_chats {
+    if let Some(graphs) = graphs_for_chats {
         let mut chat_graphs = HashMap::new();
-        for metadata in metadata_chats {
-            let graph = Graph::new(metadata.clone())?;
-
+        for graph in graphs {
             chat_graphs.insert(graph.name().to_string(), graph);
         }
         CHAT_GRAPHS.set(Mutex::new(chat_graphs)).map_err(|_| {
@@ -101,11 +143,9 @@ pub fn init_ggml_context(
         mode = RunningMode::Chat
     }
 
-    if let Some(metadata_embeddings) = metadata_for_embeddings {
+    if let Some(graphs) = graphs_for_embeddings {
         let mut embedding_graphs = HashMap::new();
-        for metadata in metadata_embeddings {
-            let graph = Graph::new(metadata.clone())?;
-
+        for graph in graphs {
             embedding_graphs.insert(graph.name().to_string(), graph);
         }
         EMBEDDING_GRAPHS
@@ -305,7 +345,7 @@ pub fn get_plugin_info() -> Result<PluginInfo, LlamaCoreError> {
     }
 }
 
-fn get_plugin_info_by_graph<M: BaseMetadata + serde::Serialize + Clone + Default>(
+pub(crate) fn get_plugin_info_by_graph<M: BaseMetadata + serde::Serialize + Clone + Default>(
     graph: &Graph<M>,
 ) -> Result<PluginInfo, LlamaCoreError> {
     #[cfg(feature = "logging")]
@@ -1,5 +1,6 @@
 use super::BaseMetadata;
-use chat_prompts::PromptTemplateType;
+use chat_prompts::{infill::InfillTemplateType, PromptTemplateType};
+use endpoints::chat::ContextTruncation;
 use serde::{Deserialize, Serialize};
 
 /// Builder for creating a ggml metadata
@@ -134,6 +135,26 @@ impl GgmlMetadataBuilder {
         self
     }
 
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.metadata.seed = seed;
+        self
+    }
+
+    pub fn with_infill_template(mut self, template: Option<InfillTemplateType>) -> Self {
+        self.metadata.infill_template = template;
+        self
+    }
+
+    pub fn with_tool_call_parser(mut self, name: Option<String>) -> Self {
+        self.metadata.tool_call_parser = name;
+        self
+    }
+
+    pub fn with_context_truncation(mut self, truncation: ContextTruncation) -> Self {
+        self.metadata.context_truncation = truncation;
+        self
+    }
+
     pub fn build(self) -> GgmlMetadata {
         self.metadata
     }
@@ -154,6 +175,18 @@ pub struct GgmlMetadata {
     // this field not defined for the beckend plugin
     #[serde(skip_serializing)]
     pub prompt_template: PromptTemplateType,
+    // this field not defined for the beckend plugin
+    /// The template of the prompts for filling in the middle of the code. Defaults to the one matching the prompt template, if any.
+    #[serde(skip_serializing)]
+    pub infill_template: Option<InfillTemplateType>,
+    // this field not defined for the beckend plugin
+    /// The name of the parser of the tool calls generated by the model, which is registered by [`chat_prompts::tool::register_tool_call_parser`]. Defaults to the one of the prompt template.
+    #[serde(skip_serializing)]
+    pub tool_call_parser: Option<String>,
+    // this field not defined for the beckend plugin
+    /// How the chat history is truncated when the prompt exceeds the budget of prompt tokens. Can be overridden by the requests.
+    #[serde(skip_serializing)]
+    pub context_truncation: ContextTruncation,
 
     // * Plugin parameters (used by this plugin):
     #[serde(rename = "enable-log")]
@@ -207,6 +240,12 @@ pub struct GgmlMetadata {
     pub presence_penalty: f64,
     #[serde(rename = "frequency-penalty")]
     pub frequency_penalty: f64,
+    /// Number of the most likely tokens to output along with their log probabilities at each position. The log probabilities are not output if None. Defaults to None.
+    #[serde(skip_serializing_if = "Option::is_none", rename = "n-probs")]
+    pub n_probs: Option<u64>,
+    /// RNG seed for sampling. A random seed is used if None. Defaults to None.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 
     // * grammar parameters
     /// BNF-like grammar to constrain generations (see samples in grammars/ dir). Defaults to empty string.
@@ -214,6 +253,9 @@ pub struct GgmlMetadata {
     /// JSON schema to constrain generations (<https://json-schema.org/>), e.g. `{}` for any JSON object. For schemas w/ external $refs, use --grammar + example/json_schema_to_grammar.py instead.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub json_schema: Option<String>,
+    /// Bias added to the logits of the given token ids, given as `[token_id, bias]` pairs. Defaults to None.
+    #[serde(skip_serializing_if = "Option::is_none", rename = "logit-bias")]
+    pub logit_bias: Option<Vec<(u32, f64)>>,
 }
 impl Default for GgmlMetadata {
     fn default() -> Self {
@@ -223,6 +265,9 @@ impl Default for GgmlMetadata {
             log_prompts: false,
             debug_log: false,
             prompt_template: PromptTemplateType::Llama2Chat,
+            infill_template: None,
+            tool_call_parser: None,
+            context_truncation: ContextTruncation::default(),
             log_enable: false,
             embeddings: false,
             n_predict: 1024,
@@ -241,8 +286,11 @@ impl Default for GgmlMetadata {
             repeat_penalty: 1.1,
             presence_penalty: 0.0,
             frequency_penalty: 0.0,
+            n_probs: None,
+            seed: None,
             grammar: String::new(),
             json_schema: None,
+            logit_bias: None,
         }
     }
 }
@@ -259,4 +307,10 @@ impl GgmlMetadata {
     pub fn prompt_template(&self) -> PromptTemplateType {
         self.prompt_template
     }
+
+    /// Returns the infill template of the model, or `None` if the model is not trained to fill in the middle.
+    pub fn infill_template(&self) -> Option<InfillTemplateType> {
+        self.infill_template
+            .or_else(|| InfillTemplateType::from_prompt_template(self.prompt_template))
+    }
 }
@@ -1,7 +1,19 @@
-//! Define APIs for querying models.
+//! Define APIs for querying, loading and unloading models.
 
-use crate::{error::LlamaCoreError, CHAT_GRAPHS, EMBEDDING_GRAPHS};
+use crate::{
+    error::LlamaCoreError, scheduler, EngineType, GgmlMetadata, Graph, GraphBuilder, RunningMode,
+    CHAT_GRAPHS, EMBEDDING_GRAPHS, RUNNING_MODE,
+};
 use endpoints::models::{ListModelsResponse, Model};
+use once_cell::sync::OnceCell;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Mutex, MutexGuard, RwLock},
+};
+
+// names of the models serving the requests for the unknown models
+static DEFAULT_MODELS: OnceCell<Mutex<Vec<String>>> = OnceCell::new();
 
 /// Lists models available
 pub async fn models() -> Result<ListModelsResponse, LlamaCoreError> {
@@ -59,3 +71,315 @@ pub async fn models() -> Result<ListModelsResponse, LlamaCoreError> {
         data: models,
     })
 }
+
+/// Retrieves the model with the given name. Returns None if the model is not loaded.
+pub fn retrieve_model(name: impl AsRef<str>) -> Result<Option<Model>, LlamaCoreError> {
+    let name = name.as_ref();
+
+    if let Some(graph) = lock_graphs(&CHAT_GRAPHS, "CHAT_GRAPHS")?.get(name) {
+        return Ok(Some(model_object(graph)));
+    }
+
+    if let Some(graph) = lock_graphs(&EMBEDDING_GRAPHS, "EMBEDDING_GRAPHS")?.get(name) {
+        return Ok(Some(model_object(graph)));
+    }
+
+    Ok(None)
+}
+
+/// Sets the default models, which serve the requests without a model or for the unknown models, instead of rejecting them with [`LlamaCoreError::ModelNotFound`].
+///
+/// # Arguments
+///
+/// * `model_names`: The names of the default models, e.g., one chat model and one embedding model. The requests are rejected if the list is empty, which is the default.
+pub fn set_default_models(model_names: Vec<String>) -> Result<(), LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Set the default models: {}", model_names.join(","));
+
+    *lock_default_models()? = model_names;
+
+    Ok(())
+}
+
+/// Returns the name of the loaded model that serves the requests for the given model. The model is looked up by name first, then by alias.
+///
+/// If the model is not found, the default model set by [`set_default_models`] serves the requests, and [`LlamaCoreError::ModelNotFound`] is returned if no default model is set. If the model is not given, the default model, or the first model in the order of the names, serves the requests.
+pub(crate) fn resolve_model_name(
+    graphs: &HashMap<String, Graph<GgmlMetadata>>,
+    model_name: Option<&str>,
+) -> Result<String, LlamaCoreError> {
+    let default_model = lock_default_models()?
+        .iter()
+        .find(|name| graphs.contains_key(*name))
+        .cloned();
+
+    let model_name = match model_name {
+        Some(model_name) => model_name,
+        None => {
+            return match default_model.or_else(|| graphs.keys().min().cloned()) {
+                Some(name) => Ok(name),
+                None => {
+                    let err_msg = "There is no model available.";
+
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", err_msg);
+
+                    Err(LlamaCoreError::Operation(err_msg.into()))
+                }
+            };
+        }
+    };
+
+    if graphs.contains_key(model_name) {
+        return Ok(model_name.to_string());
+    }
+
+    // the models may share an alias, so pick the first one in the order of the names
+    let aliased = graphs
+        .iter()
+        .filter(|(_, graph)| graph.alias() == model_name)
+        .map(|(name, _)| name)
+        .min();
+    if let Some(name) = aliased {
+        return Ok(name.clone());
+    }
+
+    match default_model {
+        Some(name) => {
+            #[cfg(feature = "logging")]
+            warn!(target: "stdout", "The model `{}` does not exist. Fall back to the default model `{}`.", model_name, &name);
+
+            Ok(name)
+        }
+        None => {
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "The model `{}` does not exist.", model_name);
+
+            Err(LlamaCoreError::ModelNotFound(model_name.to_string()))
+        }
+    }
+}
+
+/// Returns the graph of the model resolved by [`resolve_model_name`].
+pub(crate) fn get_graph<'a>(
+    graphs: &'a HashMap<String, Graph<GgmlMetadata>>,
+    model_name: Option<&str>,
+) -> Result<&'a Graph<GgmlMetadata>, LlamaCoreError> {
+    let name = resolve_model_name(graphs, model_name)?;
+    graphs.get(&name).ok_or(LlamaCoreError::ModelNotFound(name))
+}
+
+/// Returns the mutable graph of the model resolved by [`resolve_model_name`].
+pub(crate) fn get_graph_mut<'a>(
+    graphs: &'a mut HashMap<String, Graph<GgmlMetadata>>,
+    model_name: Option<&str>,
+) -> Result<&'a mut Graph<GgmlMetadata>, LlamaCoreError> {
+    let name = resolve_model_name(graphs, model_name)?;
+    graphs
+        .get_mut(&name)
+        .ok_or(LlamaCoreError::ModelNotFound(name))
+}
+
+/// Returns the metadata of the chat models, sorted by the model name.
+pub fn chat_model_metadata() -> Result<Vec<GgmlMetadata>, LlamaCoreError> {
+    graph_metadata(&CHAT_GRAPHS, "CHAT_GRAPHS")
+}
+
+/// Returns the metadata of the embedding models, sorted by the model name.
+pub fn embedding_model_metadata() -> Result<Vec<GgmlMetadata>, LlamaCoreError> {
+    graph_metadata(&EMBEDDING_GRAPHS, "EMBEDDING_GRAPHS")
+}
+
+/// Loads a model at runtime. If a model with the same name is loaded, it is replaced after the requests in progress are served.
+///
+/// # Arguments
+///
+/// * `metadata`: The metadata of the model. The model is loaded as an embedding model if `metadata.embeddings` is true, otherwise as a chat model.
+///
+/// * `path`: The path to the model file. If None, the model preloaded with the alias `metadata.model_alias` is loaded.
+pub async fn load_model(
+    metadata: GgmlMetadata,
+    path: Option<&Path>,
+) -> Result<Model, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Load the model: {}", &metadata.model_name);
+
+    let builder = GraphBuilder::new(EngineType::Ggml)?.with_config(metadata)?;
+    let graph = match path {
+        Some(path) => builder.build_from_files([path])?,
+        None => builder.build_from_cache()?,
+    };
+
+    load_graph(graph).await
+}
+
+/// Loads the graph created in advance, e.g., a graph running on [`crate::graph::mock::MockBackend`]. If a model with the same name is loaded, it is replaced after the requests in progress are served.
+pub async fn load_graph(graph: Graph<GgmlMetadata>) -> Result<Model, LlamaCoreError> {
+    let name = graph.name().to_string();
+    let model = model_object(&graph);
+
+    // the name of a model is unique across the chat and embedding models
+    let conflict = match graph.metadata.embeddings {
+        true => lock_graphs(&CHAT_GRAPHS, "CHAT_GRAPHS")?.contains_key(&name),
+        false => lock_graphs(&EMBEDDING_GRAPHS, "EMBEDDING_GRAPHS")?.contains_key(&name),
+    };
+    if conflict {
+        let err_msg = format!(
+            "Failed to load the model. Reason: A model of another type named '{}' is loaded.",
+            &name
+        );
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        return Err(LlamaCoreError::Operation(err_msg));
+    }
+
+    match graph.metadata.embeddings {
+        true => {
+            lock_graphs(&EMBEDDING_GRAPHS, "EMBEDDING_GRAPHS")?.insert(name, graph);
+        }
+        false => {
+            // wait for the requests served by the chat model to be replaced
+            let loaded = lock_graphs(&CHAT_GRAPHS, "CHAT_GRAPHS")?.contains_key(&name);
+            let _permit = match loaded {
+                true => Some(scheduler::drain(&name).await?),
+                false => None,
+            };
+
+            lock_graphs(&CHAT_GRAPHS, "CHAT_GRAPHS")?.insert(name, graph);
+        }
+    }
+
+    update_running_mode()?;
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "The model is loaded: {}", &model.id);
+
+    Ok(model)
+}
+
+/// Unloads the model with the given name after the requests in progress are served. Returns None if the model is not loaded.
+pub async fn unload_model(name: impl AsRef<str>) -> Result<Option<Model>, LlamaCoreError> {
+    let name = name.as_ref();
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Unload the model: {}", name);
+
+    // the embedding models are locked while serving a request
+    let removed = lock_graphs(&EMBEDDING_GRAPHS, "EMBEDDING_GRAPHS")?.remove(name);
+    let model = match removed {
+        Some(graph) => Some(model_object(&graph)),
+        None => {
+            let loaded = lock_graphs(&CHAT_GRAPHS, "CHAT_GRAPHS")?.contains_key(name);
+            match loaded {
+                true => {
+                    // wait for the requests served by the chat model
+                    let permit = scheduler::drain(name).await?;
+
+                    let removed = lock_graphs(&CHAT_GRAPHS, "CHAT_GRAPHS")?.remove(name);
+                    scheduler::remove_queue(name)?;
+                    drop(permit);
+
+                    removed.as_ref().map(model_object)
+                }
+                false => None,
+            }
+        }
+    };
+
+    if model.is_some() {
+        update_running_mode()?;
+
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "The model is unloaded: {}", name);
+    }
+
+    Ok(model)
+}
+
+fn model_object(graph: &Graph<GgmlMetadata>) -> Model {
+    Model {
+        id: graph.name().to_string(),
+        created: graph.created.as_secs(),
+        object: String::from("model"),
+        owned_by: String::from("Not specified"),
+    }
+}
+
+fn lock_graphs(
+    graphs: &'static OnceCell<Mutex<HashMap<String, Graph<GgmlMetadata>>>>,
+    name: &str,
+) -> Result<MutexGuard<'static, HashMap<String, Graph<GgmlMetadata>>>, LlamaCoreError> {
+    graphs
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|e| {
+            let err_msg = format!("Fail to acquire the lock of `{}`. {}", name, e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })
+}
+
+fn graph_metadata(
+    graphs: &'static OnceCell<Mutex<HashMap<String, Graph<GgmlMetadata>>>>,
+    name: &str,
+) -> Result<Vec<GgmlMetadata>, LlamaCoreError> {
+    let mut metadata: Vec<GgmlMetadata> = lock_graphs(graphs, name)?
+        .values()
+        .map(|graph| graph.metadata.clone())
+        .collect();
+    metadata.sort_by(|a, b| a.model_name.cmp(&b.model_name));
+
+    Ok(metadata)
+}
+
+fn lock_default_models() -> Result<MutexGuard<'static, Vec<String>>, LlamaCoreError> {
+    DEFAULT_MODELS
+        .get_or_init(|| Mutex::new(Vec::new()))
+        .lock()
+        .map_err(|e| {
+            let err_msg = format!("Fail to acquire the lock of `DEFAULT_MODELS`. {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })
+}
+
+/// Updates the running mode to match the loaded models. The RAG mode is kept as is.
+fn update_running_mode() -> Result<(), LlamaCoreError> {
+    let has_chat = !lock_graphs(&CHAT_GRAPHS, "CHAT_GRAPHS")?.is_empty();
+    let has_embedding = !lock_graphs(&EMBEDDING_GRAPHS, "EMBEDDING_GRAPHS")?.is_empty();
+
+    let mut mode = RUNNING_MODE
+        .get_or_init(|| RwLock::new(RunningMode::Chat))
+        .write()
+        .map_err(|e| {
+            let err_msg = format!("Fail to acquire the lock of `RUNNING_MODE`. {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })?;
+
+    if *mode != RunningMode::Rag {
+        match (has_chat, has_embedding) {
+            (true, true) => *mode = RunningMode::ChatEmbedding,
+            (true, false) => *mode = RunningMode::Chat,
+            (false, true) => *mode = RunningMode::Embeddings,
+            // keep the mode if no model is loaded
+            (false, false) => (),
+        }
+    }
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "running mode: {}", *mode);
+
+    Ok(())
+}
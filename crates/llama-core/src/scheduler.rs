@@ -0,0 +1,526 @@
+//! Define the scheduler that queues the requests for the chat models.
+//!
+//! A chat model serves one request at a time. The requests arriving while the model is busy wait in a FIFO queue of the model, which is bounded by [`SchedulerConfig::max_queue_depth`]. A request is rejected with [`SchedulerError::QueueFull`] if the queue is full, and with [`SchedulerError::Timeout`] if it waits longer than [`SchedulerConfig::queue_timeout`].
+
+use crate::{
+    error::{LlamaCoreError, SchedulerError},
+    models, CHAT_GRAPHS,
+};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+// scheduler config
+static SCHEDULER_CONFIG: OnceCell<SchedulerConfig> = OnceCell::new();
+// key: model_name, value: the queue of the requests for the model
+static QUEUES: OnceCell<Mutex<HashMap<String, ModelQueue>>> = OnceCell::new();
+
+/// Configuration of the request scheduler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerConfig {
+    /// The maximum number of requests waiting in the queue of a model. If zero, the requests are rejected while the model is busy. Defaults to 16.
+    pub max_queue_depth: usize,
+    /// The maximum time that a request waits in the queue. If None, the requests wait until the model is available. Defaults to 120 seconds.
+    pub queue_timeout: Option<Duration>,
+}
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_queue_depth: 16,
+            queue_timeout: Some(Duration::from_secs(120)),
+        }
+    }
+}
+
+/// Initialize the request scheduler. If not initialized, the scheduler uses the default configuration.
+pub fn init_scheduler(config: SchedulerConfig) -> Result<(), LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Initializing the request scheduler: {:?}", &config);
+
+    SCHEDULER_CONFIG.set(config).map_err(|_| {
+        let err_msg = "Failed to initialize the request scheduler. Reason: The `SCHEDULER_CONFIG` has already been initialized";
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", err_msg);
+
+        LlamaCoreError::InitContext(err_msg.into())
+    })
+}
+
+fn scheduler_config() -> SchedulerConfig {
+    SCHEDULER_CONFIG.get().copied().unwrap_or_default()
+}
+
+/// Metrics of the request queue of a model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueMetrics {
+    /// The name of the model.
+    pub model: String,
+    /// Whether the model is serving a request.
+    pub busy: bool,
+    /// The number of the requests waiting in the queue.
+    pub queued: usize,
+    /// The maximum number of the requests waiting in the queue.
+    pub max_queue_depth: usize,
+    /// The number of the requests admitted to the model, either immediately or after waiting in the queue.
+    pub admitted: u64,
+    /// The number of the requests rejected because the queue was full.
+    pub rejected: u64,
+    /// The number of the requests that timed out in the queue.
+    pub timed_out: u64,
+    /// The number of the requests served by the model.
+    pub completed: u64,
+    /// The average time in milliseconds that the admitted requests waited in the queue.
+    pub avg_wait_ms: u64,
+    /// The average time in milliseconds that the model took to serve a request.
+    pub avg_service_ms: u64,
+}
+
+/// Return the metrics of the request queues of the chat models.
+pub fn queue_metrics() -> Result<Vec<QueueMetrics>, LlamaCoreError> {
+    let max_queue_depth = scheduler_config().max_queue_depth;
+    let queues = lock_queues()?;
+
+    let mut metrics: Vec<QueueMetrics> = queues
+        .iter()
+        .map(|(model, queue)| queue.metrics(model, max_queue_depth))
+        .collect();
+    metrics.sort_by(|a, b| a.model.cmp(&b.model));
+
+    Ok(metrics)
+}
+
+/// A permit to run the inference on a chat model. The next request in the queue of the model is admitted when the permit is dropped.
+#[derive(Debug)]
+pub(crate) struct SchedulerPermit {
+    model: String,
+    admitted_at: Instant,
+}
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        if let Ok(mut queues) = lock_queues() {
+            if let Some(queue) = queues.get_mut(&self.model) {
+                queue.release(self.admitted_at.elapsed());
+            }
+        }
+
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Release the model: {}", &self.model);
+    }
+}
+
+/// Wait in the queue of the given chat model until the model is available. The model is resolved by [`models::resolve_model_name`].
+pub(crate) async fn acquire(
+    model_name: Option<&String>,
+) -> Result<SchedulerPermit, LlamaCoreError> {
+    let model = chat_graph_name(model_name)?;
+    let config = scheduler_config();
+
+    let ticket = {
+        let mut queues = lock_queues()?;
+        let queue = queues.entry(model.clone()).or_default();
+
+        match queue.admit(config.max_queue_depth) {
+            Admission::Granted => {
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "Acquire the model: {}", &model);
+
+                return Ok(SchedulerPermit {
+                    model,
+                    admitted_at: Instant::now(),
+                });
+            }
+            Admission::Queued(ticket) => ticket,
+            Admission::Rejected { retry_after } => {
+                let err = SchedulerError::QueueFull { model, retry_after };
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err);
+
+                return Err(LlamaCoreError::Scheduler(err));
+            }
+        }
+    };
+
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Wait in the queue of the model: {}", &model);
+
+    let wait = WaitForTurn {
+        model: model.clone(),
+        ticket,
+        done: false,
+    };
+
+    match config.queue_timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, wait).await {
+            Ok(permit) => permit,
+            Err(_) => {
+                // the waiting request has been removed from the queue when the `WaitForTurn` future was dropped
+                let retry_after = match lock_queues()?.get_mut(&model) {
+                    Some(queue) => {
+                        queue.timed_out += 1;
+                        queue.retry_after()
+                    }
+                    None => 1,
+                };
+
+                let err = SchedulerError::Timeout {
+                    model,
+                    waited: timeout,
+                    retry_after,
+                };
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err);
+
+                Err(LlamaCoreError::Scheduler(err))
+            }
+        },
+        None => wait.await,
+    }
+}
+
+/// Wait until the requests in service and in the queue of the given chat model are served, and return a permit that holds the model exclusively, e.g., to unload or replace the model. The wait is bounded by neither the queue depth nor the queue timeout.
+pub(crate) async fn drain(model: &str) -> Result<SchedulerPermit, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Drain the requests of the model: {}", model);
+
+    let ticket = {
+        let mut queues = lock_queues()?;
+        let queue = queues.entry(model.to_string()).or_default();
+
+        match queue.admit(usize::MAX) {
+            Admission::Granted => {
+                return Ok(SchedulerPermit {
+                    model: model.to_string(),
+                    admitted_at: Instant::now(),
+                })
+            }
+            Admission::Queued(ticket) => ticket,
+            Admission::Rejected { retry_after } => {
+                return Err(LlamaCoreError::Scheduler(SchedulerError::QueueFull {
+                    model: model.to_string(),
+                    retry_after,
+                }))
+            }
+        }
+    };
+
+    WaitForTurn {
+        model: model.to_string(),
+        ticket,
+        done: false,
+    }
+    .await
+}
+
+/// Remove the queue of the unloaded chat model. The requests still waiting in the queue fail.
+pub(crate) fn remove_queue(model: &str) -> Result<(), LlamaCoreError> {
+    if let Some(queue) = lock_queues()?.remove(model) {
+        for waker in queue.waiters.into_iter().filter_map(|waiter| waiter.waker) {
+            waker.wake();
+        }
+    }
+
+    Ok(())
+}
+
+/// Future that resolves when the waiting request is at the front of the queue and the model is available.
+struct WaitForTurn {
+    model: String,
+    ticket: u64,
+    done: bool,
+}
+impl Future for WaitForTurn {
+    type Output = Result<SchedulerPermit, LlamaCoreError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut queues = match lock_queues() {
+            Ok(queues) => queues,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+
+        let state = match queues.get_mut(&self.model) {
+            Some(queue) => queue.poll_turn(self.ticket, cx.waker()),
+            None => None,
+        };
+        drop(queues);
+
+        match state {
+            Some(true) => {
+                self.done = true;
+
+                #[cfg(feature = "logging")]
+                info!(target: "stdout", "Acquire the model: {}", &self.model);
+
+                Poll::Ready(Ok(SchedulerPermit {
+                    model: self.model.clone(),
+                    admitted_at: Instant::now(),
+                }))
+            }
+            Some(false) => Poll::Pending,
+            None => {
+                self.done = true;
+
+                let err_msg = format!(
+                    "The request is no longer in the queue of the model '{}'.",
+                    &self.model
+                );
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                Poll::Ready(Err(LlamaCoreError::Operation(err_msg)))
+            }
+        }
+    }
+}
+impl Drop for WaitForTurn {
+    fn drop(&mut self) {
+        // the request leaves the queue before its turn, e.g., timed out or cancelled by the client
+        if !self.done {
+            if let Ok(mut queues) = lock_queues() {
+                if let Some(queue) = queues.get_mut(&self.model) {
+                    queue.cancel(self.ticket);
+                }
+            }
+        }
+    }
+}
+
+/// The result of the admission of a request.
+#[derive(Debug, PartialEq, Eq)]
+enum Admission {
+    /// The model is available, and the request is admitted immediately.
+    Granted,
+    /// The request waits in the queue with the ticket.
+    Queued(u64),
+    /// The queue is full. The client is suggested to retry after the given seconds.
+    Rejected { retry_after: u64 },
+}
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    enqueued_at: Instant,
+    granted: bool,
+    waker: Option<Waker>,
+}
+
+/// The FIFO queue of the requests for a model.
+#[derive(Debug, Default)]
+struct ModelQueue {
+    busy: bool,
+    next_ticket: u64,
+    waiters: VecDeque<Waiter>,
+    admitted: u64,
+    rejected: u64,
+    timed_out: u64,
+    completed: u64,
+    total_wait: Duration,
+    total_service: Duration,
+}
+impl ModelQueue {
+    fn admit(&mut self, max_queue_depth: usize) -> Admission {
+        if !self.busy && self.waiters.is_empty() {
+            self.busy = true;
+            self.admitted += 1;
+            return Admission::Granted;
+        }
+
+        if self.waiters.len() >= max_queue_depth {
+            self.rejected += 1;
+            return Admission::Rejected {
+                retry_after: self.retry_after(),
+            };
+        }
+
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.waiters.push_back(Waiter {
+            ticket,
+            enqueued_at: Instant::now(),
+            granted: false,
+            waker: None,
+        });
+
+        Admission::Queued(ticket)
+    }
+
+    /// Returns `Some(true)` if it is the turn of the request, `Some(false)` if the request is still waiting, and `None` if the request is not in the queue.
+    fn poll_turn(&mut self, ticket: u64, waker: &Waker) -> Option<bool> {
+        let pos = self.waiters.iter().position(|w| w.ticket == ticket)?;
+
+        if self.waiters[pos].granted {
+            let waiter = self.waiters.remove(pos)?;
+            self.admitted += 1;
+            self.total_wait += waiter.enqueued_at.elapsed();
+            return Some(true);
+        }
+
+        self.waiters[pos].waker = Some(waker.clone());
+        Some(false)
+    }
+
+    /// Removes the request from the queue. If it was the turn of the request, the model is passed on to the next one.
+    fn cancel(&mut self, ticket: u64) {
+        if let Some(pos) = self.waiters.iter().position(|w| w.ticket == ticket) {
+            if let Some(waiter) = self.waiters.remove(pos) {
+                if waiter.granted {
+                    self.hand_over();
+                }
+            }
+        }
+    }
+
+    /// Releases the model after serving a request.
+    fn release(&mut self, service: Duration) {
+        self.completed += 1;
+        self.total_service += service;
+        self.hand_over();
+    }
+
+    /// Passes the model on to the request at the front of the queue, or marks the model as available if the queue is empty.
+    fn hand_over(&mut self) {
+        match self.waiters.front_mut() {
+            Some(next) => {
+                next.granted = true;
+                if let Some(waker) = next.waker.take() {
+                    waker.wake();
+                }
+            }
+            None => self.busy = false,
+        }
+    }
+
+    /// Estimates the seconds until the model can take one more request.
+    fn retry_after(&self) -> u64 {
+        let avg_service = match self.completed {
+            0 => Duration::from_secs(1),
+            n => self.total_service / n as u32,
+        };
+
+        let pending = self.waiters.len() as u32 + 1;
+        (avg_service * pending).as_secs_f64().ceil().max(1.0) as u64
+    }
+
+    fn metrics(&self, model: &str, max_queue_depth: usize) -> QueueMetrics {
+        let avg_ms = |total: Duration, n: u64| match n {
+            0 => 0,
+            n => (total.as_millis() / n as u128) as u64,
+        };
+
+        QueueMetrics {
+            model: model.to_string(),
+            busy: self.busy,
+            queued: self.waiters.len(),
+            max_queue_depth,
+            admitted: self.admitted,
+            rejected: self.rejected,
+            timed_out: self.timed_out,
+            completed: self.completed,
+            avg_wait_ms: avg_ms(self.total_wait, self.admitted),
+            avg_service_ms: avg_ms(self.total_service, self.completed),
+        }
+    }
+}
+
+fn lock_queues() -> Result<MutexGuard<'static, HashMap<String, ModelQueue>>, LlamaCoreError> {
+    QUEUES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .map_err(|e| {
+            let err_msg = format!("Fail to acquire the lock of the request queues. {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })
+}
+
+/// Return the name of the chat model that serves the requests for the given model name.
+fn chat_graph_name(model_name: Option<&String>) -> Result<String, LlamaCoreError> {
+    let chat_graphs = match CHAT_GRAPHS.get() {
+        Some(chat_graphs) => chat_graphs,
+        None => {
+            let err_msg = "Fail to get the underlying value of `CHAT_GRAPHS`.";
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::Operation(err_msg.into()));
+        }
+    };
+
+    let chat_graphs = chat_graphs.lock().map_err(|e| {
+        let err_msg = format!("Fail to acquire the lock of `CHAT_GRAPHS`. {}", e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })?;
+
+    models::resolve_model_name(&chat_graphs, model_name.map(String::as_str))
+}
+
+#[test]
+fn test_scheduler_fifo_queue() {
+    let waker = futures::task::noop_waker();
+    let mut queue = ModelQueue::default();
+
+    assert_eq!(queue.admit(2), Admission::Granted);
+    assert_eq!(queue.admit(2), Admission::Queued(0));
+    assert_eq!(queue.admit(2), Admission::Queued(1));
+    assert!(matches!(queue.admit(2), Admission::Rejected { .. }));
+
+    // the requests wait until the model is released
+    assert_eq!(queue.poll_turn(0, &waker), Some(false));
+    assert_eq!(queue.poll_turn(1, &waker), Some(false));
+
+    // the model is passed on in the order of arrival
+    queue.release(Duration::from_secs(3));
+    assert_eq!(queue.poll_turn(1, &waker), Some(false));
+    assert_eq!(queue.poll_turn(0, &waker), Some(true));
+    assert_eq!(queue.poll_turn(0, &waker), None);
+
+    queue.release(Duration::from_secs(1));
+    assert_eq!(queue.poll_turn(1, &waker), Some(true));
+    queue.release(Duration::from_secs(2));
+    assert!(!queue.busy);
+
+    let metrics = queue.metrics("model", 2);
+    assert_eq!(metrics.admitted, 3);
+    assert_eq!(metrics.rejected, 1);
+    assert_eq!(metrics.completed, 3);
+    assert_eq!(metrics.avg_service_ms, 2000);
+}
+
+#[test]
+fn test_scheduler_cancel_and_retry_after() {
+    let waker = futures::task::noop_waker();
+    let mut queue = ModelQueue::default();
+
+    assert_eq!(queue.admit(0), Admission::Granted);
+    assert_eq!(queue.admit(0), Admission::Rejected { retry_after: 1 });
+
+    assert_eq!(queue.admit(2), Admission::Queued(0));
+    assert_eq!(queue.admit(2), Admission::Queued(1));
+
+    // the granted request leaves the queue, so the model is passed on to the next one
+    queue.release(Duration::from_secs(4));
+    queue.cancel(0);
+    assert_eq!(queue.poll_turn(1, &waker), Some(true));
+    assert!(queue.busy);
+
+    // one request in service, so the next one waits for about the average service time
+    assert_eq!(queue.admit(0), Admission::Rejected { retry_after: 4 });
+}
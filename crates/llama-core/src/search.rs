@@ -355,21 +355,12 @@ fn summarize(
     let input = initial_prompt + search_output_string.as_str() + final_prompt.as_str();
     let tensor_data = input.as_bytes().to_vec();
 
-    // Use first available chat graph
-    let graph: &mut crate::Graph<GgmlMetadata> = match chat_graphs.values_mut().next() {
-        Some(graph) => graph,
-        None => {
-            let err_msg = "No available chat graph.";
-
-            #[cfg(feature = "logging")]
-            error!(target: "stdout", "{}", err_msg);
-
-            return Err(LlamaCoreError::Search(err_msg.into()));
-        }
-    };
+    // Use the default chat graph
+    let graph: &mut crate::Graph<GgmlMetadata> =
+        crate::models::get_graph_mut(&mut chat_graphs, None)?;
 
     graph
-        .set_input(0, wasmedge_wasi_nn::TensorType::U8, &[1], &tensor_data)
+        .set_input(0, crate::graph::TensorType::U8, &[1], &tensor_data)
         .expect("Failed to set prompt as the input tensor");
 
     #[cfg(feature = "logging")]
@@ -0,0 +1,116 @@
+//! Define APIs for tokenizing and detokenizing the text with the vocabulary of the chat models.
+
+use crate::{
+    chat::with_chat_graph,
+    error::{BackendError, LlamaCoreError},
+    graph::{Graph, InferenceError},
+    metadata::ggml::GgmlMetadata,
+    scheduler,
+    utils::{check_tokenize_support, get_token_info_by_graph, read_output, set_tensor_data_u8},
+};
+use endpoints::tokenize::{
+    DetokenizeRequest, DetokenizeResponse, TokenizeRequest, TokenizeResponse,
+};
+
+/// Converts the text to the token ids of the model, e.g., to count the tokens of the text before sending a request. If the backend does not expose the vocabulary of the model, see [`InferenceBackend::supports_tokenize`](crate::InferenceBackend::supports_tokenize), the tokens are only counted.
+pub async fn tokenize(request: &TokenizeRequest) -> Result<TokenizeResponse, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Tokenize the text");
+
+    // wait for the turn to use the model, as counting the tokens replaces the prompt of the model
+    let _permit = scheduler::acquire(request.model.as_ref()).await?;
+
+    with_chat_graph(request.model.as_ref(), |graph| {
+        let (count, tokens) = match graph.supports_tokenize() {
+            true => {
+                let tokens = tokenize_by_graph(graph, &request.content)?;
+                (tokens.len() as u64, Some(tokens))
+            }
+            false => {
+                // count the tokens of the text set as the prompt, as the token ids are not exposed
+                set_tensor_data_u8(graph, 0, request.content.as_bytes())?;
+                (get_token_info_by_graph(graph)?.prompt_tokens, None)
+            }
+        };
+
+        #[cfg(feature = "logging")]
+        info!(target: "stdout", "Number of the tokens: {}", count);
+
+        Ok(TokenizeResponse {
+            model: graph.name().to_string(),
+            tokens,
+            count,
+        })
+    })
+}
+
+/// Converts the text to the token ids with the vocabulary of the model. The backend of the model must support the tokenization, see [`check_tokenize_support`].
+pub(crate) fn tokenize_by_graph(
+    graph: &mut Graph<GgmlMetadata>,
+    text: &str,
+) -> Result<Vec<u32>, LlamaCoreError> {
+    let output_buffer = read_output(|buffer| graph.tokenize(text, buffer)).map_err(|e| {
+        let err_msg = format!("Fail to get the token ids. {msg}", msg = e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Backend(BackendError::GetOutput(err_msg))
+    })?;
+
+    serde_json::from_slice(&output_buffer).map_err(|e| {
+        let err_msg = format!("Fail to deserialize the token ids: {msg}", msg = e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })
+}
+
+/// Converts the token ids of the model to text. Requires a backend which exposes the vocabulary of the model, like [`tokenize`].
+pub async fn detokenize(request: &DetokenizeRequest) -> Result<DetokenizeResponse, LlamaCoreError> {
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "Detokenize {} tokens", request.tokens.len());
+
+    let token_ids = serde_json::to_string(&request.tokens).map_err(|e| {
+        let err_msg = format!("Fail to serialize the token ids: {msg}", msg = e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Operation(err_msg)
+    })?;
+
+    // wait for the turn to use the model
+    let _permit = scheduler::acquire(request.model.as_ref()).await?;
+
+    with_chat_graph(request.model.as_ref(), |graph| {
+        check_tokenize_support(graph, "model")?;
+
+        let output_buffer = match read_output(|buffer| graph.detokenize(&token_ids, buffer)) {
+            Ok(output_buffer) => output_buffer,
+            Err(InferenceError::InvalidArgument) => {
+                let err_msg = "The token ids are not in the vocabulary of the model.";
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", err_msg);
+
+                return Err(LlamaCoreError::invalid_request(err_msg, Some("tokens")));
+            }
+            Err(e) => {
+                let err_msg = format!("Fail to detokenize the token ids. {msg}", msg = e);
+
+                #[cfg(feature = "logging")]
+                error!(target: "stdout", "{}", &err_msg);
+
+                return Err(LlamaCoreError::Backend(BackendError::GetOutput(err_msg)));
+            }
+        };
+
+        Ok(DetokenizeResponse {
+            model: graph.name().to_string(),
+            content: String::from_utf8_lossy(&output_buffer).into_owned(),
+        })
+    })
+}
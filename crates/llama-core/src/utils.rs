@@ -2,15 +2,152 @@
 
 use crate::{
     error::{BackendError, LlamaCoreError},
+    get_plugin_info_by_graph,
+    graph::InferenceError,
+    metadata::ggml::GgmlMetadata,
+    models,
+    tokenize::tokenize_by_graph,
     BaseMetadata, Graph, CHAT_GRAPHS, EMBEDDING_GRAPHS, MAX_BUFFER_SIZE,
 };
 use chat_prompts::PromptTemplateType;
+use endpoints::{
+    chat::{TokenLogProb, TopLogProb},
+    common::LlamaCppLogitBiasType,
+};
+use serde::Deserialize;
 use serde_json::Value;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    time::SystemTime,
+};
+
+thread_local! {
+    /// Buffer receiving the output tensors of the models, which is allocated once per thread rather than for every generated token.
+    static OUTPUT_BUFFER: RefCell<Vec<u8>> = RefCell::new(vec![0u8; MAX_BUFFER_SIZE]);
+}
+
+/// Read an output of the backend into the output buffer of the thread, and return a copy of the bytes of the output.
+pub(crate) fn read_output(
+    read: impl FnOnce(&mut [u8]) -> Result<usize, InferenceError>,
+) -> Result<Vec<u8>, InferenceError> {
+    OUTPUT_BUFFER.with(|output_buffer| {
+        let mut output_buffer = output_buffer.borrow_mut();
+        let output_size = read(&mut output_buffer)?;
+
+        Ok(output_buffer[..output_size].to_vec())
+    })
+}
 
 pub(crate) fn gen_chat_id() -> String {
     format!("chatcmpl-{}", uuid::Uuid::new_v4())
 }
 
+pub(crate) fn gen_tool_call_id() -> String {
+    format!("call_{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Returns the current Unix timestamp in seconds.
+pub(crate) fn current_timestamp() -> Result<u64, LlamaCoreError> {
+    SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|n| n.as_secs())
+        .map_err(|e| {
+            let err_msg = format!("Failed to get the current time. Reason: {}", e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            LlamaCoreError::Operation(err_msg)
+        })
+}
+
+/// Generate the `system_fingerprint` of a response from the build of the `wasi-nn_ggml` plugin and the name of the model, so that the clients can tell when the backend changes and the results of seeded requests may differ.
+pub(crate) fn gen_system_fingerprint<M>(graph: &Graph<M>) -> Result<String, LlamaCoreError>
+where
+    M: BaseMetadata + serde::Serialize + Clone + Default,
+{
+    let plugin_info = get_plugin_info_by_graph(graph)?;
+
+    let config = format!(
+        "b{}-{}-{}",
+        plugin_info.build_number,
+        plugin_info.commit_id,
+        graph.name()
+    );
+
+    // 64-bit FNV-1a, which is stable across builds and platforms
+    let hash = config
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    Ok(format!("fp_{:010x}", hash & 0xff_ffff_ffff))
+}
+
+/// Parse the `logit_bias` of a request into `(token_id, bias)` pairs sorted by token ids. The keys are either token ids, or token strings which are converted to token ids with the vocabulary of the model, and the bias values are in the range of [-100, 100]. The token strings require a backend which supports the tokenization.
+pub(crate) fn parse_logit_bias<T>(
+    graph: &mut Graph<GgmlMetadata>,
+    logit_bias: &HashMap<String, T>,
+) -> Result<Vec<(u32, f64)>, LlamaCoreError>
+where
+    T: Into<f64> + Copy,
+{
+    let mut pairs = BTreeMap::new();
+    for (key, bias) in logit_bias {
+        let bias: f64 = (*bias).into();
+        if !(-100.0..=100.0).contains(&bias) {
+            let err_msg = format!(
+                "Invalid logit bias: the bias {} of `{}` is out of the range of [-100, 100].",
+                bias, key
+            );
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("logit_bias")));
+        }
+
+        let token_ids = match LlamaCppLogitBiasType::of_key(key) {
+            LlamaCppLogitBiasType::input_ids => vec![key.trim().parse::<u32>().unwrap_or_default()],
+            LlamaCppLogitBiasType::tokens => match graph.supports_tokenize() {
+                true => tokenize_by_graph(graph, key)?,
+                false => {
+                    let err_msg = format!(
+                        "Invalid logit bias: `{}` is not a token id, and the backend of the model `{}` does not expose the vocabulary to convert the token strings to token ids.",
+                        key,
+                        graph.name()
+                    );
+
+                    #[cfg(feature = "logging")]
+                    error!(target: "stdout", "{}", &err_msg);
+
+                    return Err(LlamaCoreError::invalid_request(err_msg, Some("logit_bias")));
+                }
+            },
+        };
+        if token_ids.is_empty() {
+            let err_msg = format!(
+                "Invalid logit bias: `{}` is neither a token id nor a token of the model.",
+                key
+            );
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::invalid_request(err_msg, Some("logit_bias")));
+        }
+
+        // the bias applies to all the tokens of a token string
+        for token_id in token_ids {
+            pairs.insert(token_id, bias);
+        }
+    }
+
+    Ok(pairs.into_iter().collect())
+}
+
 /// Return the names of the chat models.
 pub fn chat_model_names() -> Result<Vec<String>, LlamaCoreError> {
     #[cfg(feature = "logging")]
@@ -79,7 +216,7 @@ pub fn embedding_model_names() -> Result<Vec<String>, LlamaCoreError> {
     Ok(model_names)
 }
 
-/// Get the chat prompt template type from the given model name.
+/// Get the chat prompt template type from the given model name or alias.
 pub fn chat_prompt_template(name: Option<&str>) -> Result<PromptTemplateType, LlamaCoreError> {
     #[cfg(feature = "logging")]
     match name {
@@ -112,55 +249,13 @@ pub fn chat_prompt_template(name: Option<&str>) -> Result<PromptTemplateType, Ll
         LlamaCoreError::Operation(err_msg)
     })?;
 
-    match name {
-        Some(model_name) => match chat_graphs.contains_key(model_name) {
-            true => {
-                let graph = chat_graphs.get(model_name).unwrap();
-                let prompt_template = graph.metadata.prompt_template();
-
-                #[cfg(feature = "logging")]
-                info!(target: "stdout", "prompt_template: {}", &prompt_template);
-
-                Ok(prompt_template)
-            }
-            false => match chat_graphs.iter().next() {
-                Some((_, graph)) => {
-                    let prompt_template = graph.metadata.prompt_template();
-
-                    #[cfg(feature = "logging")]
-                    info!(target: "stdout", "prompt_template: {}", &prompt_template);
-
-                    Ok(prompt_template)
-                }
-                None => {
-                    let err_msg = "There is no model available in the chat graphs.";
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
-
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            },
-        },
-        None => match chat_graphs.iter().next() {
-            Some((_, graph)) => {
-                let prompt_template = graph.metadata.prompt_template();
-
-                #[cfg(feature = "logging")]
-                info!(target: "stdout", "prompt_template: {}", &prompt_template);
-
-                Ok(prompt_template)
-            }
-            None => {
-                let err_msg = "There is no model available in the chat graphs.";
+    let graph = models::get_graph(&chat_graphs, name)?;
+    let prompt_template = graph.metadata.prompt_template();
 
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
+    #[cfg(feature = "logging")]
+    info!(target: "stdout", "prompt_template: {}", &prompt_template);
 
-                Err(LlamaCoreError::Operation(err_msg.into()))
-            }
-        },
-    }
+    Ok(prompt_template)
 }
 
 /// Get output buffer generated by model.
@@ -174,9 +269,7 @@ where
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Get the output buffer generated by the model named {}", graph.name());
 
-    let mut output_buffer: Vec<u8> = Vec::with_capacity(MAX_BUFFER_SIZE);
-
-    let output_size: usize = graph.get_output(index, &mut output_buffer).map_err(|e| {
+    let output_buffer = read_output(|buffer| graph.get_output(index, buffer)).map_err(|e| {
         let err_msg = format!("Fail to get the generated output tensor. {msg}", msg = e);
 
         #[cfg(feature = "logging")]
@@ -185,12 +278,8 @@ where
         LlamaCoreError::Backend(BackendError::GetOutput(err_msg))
     })?;
 
-    unsafe {
-        output_buffer.set_len(output_size);
-    }
-
     #[cfg(feature = "logging")]
-    info!(target: "stdout", "Output buffer size: {}", output_size);
+    info!(target: "stdout", "Output buffer size: {}", output_buffer.len());
 
     Ok(output_buffer)
 }
@@ -206,11 +295,8 @@ where
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Get output buffer generated by the model named {} in the stream mode.", graph.name());
 
-    let mut output_buffer: Vec<u8> = Vec::with_capacity(MAX_BUFFER_SIZE);
-
-    let output_size: usize = graph
-        .get_output_single(index, &mut output_buffer)
-        .map_err(|e| {
+    let output_buffer =
+        read_output(|buffer| graph.get_output_single(index, buffer)).map_err(|e| {
             let err_msg = format!("Fail to get plugin metadata. {msg}", msg = e);
 
             #[cfg(feature = "logging")]
@@ -219,10 +305,6 @@ where
             LlamaCoreError::Backend(BackendError::GetOutput(err_msg))
         })?;
 
-    unsafe {
-        output_buffer.set_len(output_size);
-    }
-
     Ok(output_buffer)
 }
 
@@ -235,7 +317,7 @@ where
     M: BaseMetadata + serde::Serialize + Clone + Default,
 {
     if graph
-        .set_input(idx, wasmedge_wasi_nn::TensorType::U8, &[1], tensor_data)
+        .set_input(idx, crate::graph::TensorType::U8, &[1], tensor_data)
         .is_err()
     {
         let err_msg = format!("Fail to set input tensor at index {}", idx);
@@ -257,7 +339,15 @@ where
     #[cfg(feature = "logging")]
     info!(target: "stdout", "Get token info from the model named {}.", graph.name());
 
-    let output_buffer = get_output_buffer(graph, 1)?;
+    let output_buffer = read_output(|buffer| graph.get_token_info(buffer)).map_err(|e| {
+        let err_msg = format!("Fail to get the token info. {msg}", msg = e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Backend(BackendError::GetOutput(err_msg))
+    })?;
+
     let token_info: Value = match serde_json::from_slice(&output_buffer[..]) {
         Ok(token_info) => token_info,
         Err(e) => {
@@ -302,6 +392,119 @@ where
     })
 }
 
+/// Get the log probabilities of the output token from the graph in the stream mode.
+pub(crate) fn get_logprobs_by_graph_single<M>(
+    graph: &Graph<M>,
+) -> Result<Vec<TokenLogProb>, LlamaCoreError>
+where
+    M: BaseMetadata + serde::Serialize + Clone + Default,
+{
+    get_logprobs(graph, true)
+}
+
+/// Get the log probabilities produced by the backend, which are in the form of `[{"token": ..., "bytes": [...], "logprob": ..., "top_logprobs": [{"token": ..., "bytes": [...], "logprob": ...}]}]`.
+fn get_logprobs<M>(graph: &Graph<M>, single: bool) -> Result<Vec<TokenLogProb>, LlamaCoreError>
+where
+    M: BaseMetadata + serde::Serialize + Clone + Default,
+{
+    #[derive(Deserialize)]
+    struct BackendTopLogProb {
+        token: String,
+        bytes: Option<Vec<u8>>,
+        logprob: f64,
+    }
+
+    #[derive(Deserialize)]
+    struct BackendTokenLogProb {
+        token: String,
+        bytes: Option<Vec<u8>>,
+        logprob: f64,
+        #[serde(default)]
+        top_logprobs: Vec<BackendTopLogProb>,
+    }
+
+    let output_buffer = read_output(|buffer| graph.get_logprobs(single, buffer)).map_err(|e| {
+        let err_msg = format!("Fail to get the log probabilities. {msg}", msg = e);
+
+        #[cfg(feature = "logging")]
+        error!(target: "stdout", "{}", &err_msg);
+
+        LlamaCoreError::Backend(BackendError::GetOutput(err_msg))
+    })?;
+
+    let logprobs: Vec<BackendTokenLogProb> = match serde_json::from_slice(&output_buffer) {
+        Ok(logprobs) => logprobs,
+        Err(e) => {
+            let err_msg = format!("Fail to deserialize the log probabilities: {msg}", msg = e);
+
+            #[cfg(feature = "logging")]
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(LlamaCoreError::Operation(err_msg));
+        }
+    };
+
+    // the raw bytes of a token may be an incomplete UTF-8 character, which the token string cannot represent
+    Ok(logprobs
+        .into_iter()
+        .map(|logprob| TokenLogProb {
+            token: logprob.token,
+            bytes: logprob.bytes,
+            logprob: logprob.logprob,
+            top_logprobs: logprob
+                .top_logprobs
+                .into_iter()
+                .map(|top| TopLogProb {
+                    token: top.token,
+                    bytes: top.bytes,
+                    logprob: top.logprob,
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Check if the backend of the model outputs the log probabilities, which are required by the given parameter of the request.
+pub(crate) fn check_logprobs_support<M>(graph: &Graph<M>, param: &str) -> Result<(), LlamaCoreError>
+where
+    M: BaseMetadata + serde::Serialize + Clone + Default,
+{
+    if graph.supports_logprobs() {
+        return Ok(());
+    }
+
+    let err_msg = format!(
+        "`{}` is not supported, as the backend of the model `{}` does not output the log probabilities.",
+        param,
+        graph.name()
+    );
+
+    #[cfg(feature = "logging")]
+    error!(target: "stdout", "{}", &err_msg);
+
+    Err(LlamaCoreError::invalid_request(err_msg, Some(param)))
+}
+
+/// Check if the backend of the model converts the text to the token ids and back, which is required by the given parameter of the request.
+pub(crate) fn check_tokenize_support<M>(graph: &Graph<M>, param: &str) -> Result<(), LlamaCoreError>
+where
+    M: BaseMetadata + serde::Serialize + Clone + Default,
+{
+    if graph.supports_tokenize() {
+        return Ok(());
+    }
+
+    let err_msg = format!(
+        "The tokenization is not supported, as the backend of the model `{}` does not expose the vocabulary of the model.",
+        graph.name()
+    );
+
+    #[cfg(feature = "logging")]
+    error!(target: "stdout", "{}", &err_msg);
+
+    Err(LlamaCoreError::invalid_request(err_msg, Some(param)))
+}
+
 /// Get the token information from the graph by the model name.
 pub(crate) fn get_token_info_by_graph_name(
     name: Option<&String>,
@@ -327,36 +530,9 @@ pub(crate) fn get_token_info_by_graph_name(
         LlamaCoreError::Operation(err_msg)
     })?;
 
-    match name {
-        Some(model_name) => match chat_graphs.contains_key(model_name) {
-            true => {
-                let graph = chat_graphs.get(model_name).unwrap();
-                get_token_info_by_graph(graph)
-            }
-            false => match chat_graphs.iter().next() {
-                Some((_, graph)) => get_token_info_by_graph(graph),
-                None => {
-                    let err_msg = "There is no model available in the chat graphs.";
-
-                    #[cfg(feature = "logging")]
-                    error!(target: "stdout", "{}", &err_msg);
+    let graph = models::get_graph(&chat_graphs, name.map(String::as_str))?;
 
-                    Err(LlamaCoreError::Operation(err_msg.into()))
-                }
-            },
-        },
-        None => match chat_graphs.iter().next() {
-            Some((_, graph)) => get_token_info_by_graph(graph),
-            None => {
-                let err_msg = "There is no model available in the chat graphs.";
-
-                #[cfg(feature = "logging")]
-                error!(target: "stdout", "{}", &err_msg);
-
-                Err(LlamaCoreError::Operation(err_msg.into()))
-            }
-        },
-    }
+    get_token_info_by_graph(graph)
 }
 
 #[derive(Debug)]
@@ -365,22 +541,22 @@ pub(crate) struct TokenInfo {
     pub(crate) completion_tokens: u64,
 }
 
-pub(crate) trait TensorType {
-    fn tensor_type() -> wasmedge_wasi_nn::TensorType;
+pub(crate) trait TensorType: crate::graph::TensorElement {
+    fn tensor_type() -> crate::graph::TensorType;
     fn shape(shape: impl AsRef<[usize]>) -> Vec<usize> {
         shape.as_ref().to_vec()
     }
 }
 
 impl TensorType for u8 {
-    fn tensor_type() -> wasmedge_wasi_nn::TensorType {
-        wasmedge_wasi_nn::TensorType::U8
+    fn tensor_type() -> crate::graph::TensorType {
+        crate::graph::TensorType::U8
     }
 }
 
 impl TensorType for f32 {
-    fn tensor_type() -> wasmedge_wasi_nn::TensorType {
-        wasmedge_wasi_nn::TensorType::F32
+    fn tensor_type() -> crate::graph::TensorType {
+        crate::graph::TensorType::F32
     }
 }
 
@@ -408,3 +584,114 @@ where
 
     Ok(())
 }
+
+/// Backend with a fixed vocabulary, which converts the words of the text to the token ids.
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct VocabBackend {
+    vocab: HashMap<&'static str, u32>,
+}
+#[cfg(test)]
+impl crate::InferenceBackend for VocabBackend {
+    fn set_input(
+        &mut self,
+        _index: usize,
+        _tensor_type: crate::graph::TensorType,
+        _dimensions: &[usize],
+        _data: &[u8],
+    ) -> Result<(), crate::InferenceError> {
+        Ok(())
+    }
+
+    fn compute(&mut self) -> Result<(), crate::InferenceError> {
+        Ok(())
+    }
+
+    fn compute_single(&mut self) -> Result<(), crate::InferenceError> {
+        Err(crate::InferenceError::EndOfSequence)
+    }
+
+    fn get_output(
+        &self,
+        _index: usize,
+        _out_buffer: &mut [u8],
+    ) -> Result<usize, crate::InferenceError> {
+        Ok(0)
+    }
+
+    fn get_output_single(
+        &self,
+        _index: usize,
+        _out_buffer: &mut [u8],
+    ) -> Result<usize, crate::InferenceError> {
+        Ok(0)
+    }
+
+    fn finish_single(&mut self) -> Result<(), crate::InferenceError> {
+        Ok(())
+    }
+
+    fn supports_tokenize(&self) -> bool {
+        !self.vocab.is_empty()
+    }
+
+    fn tokenize(
+        &mut self,
+        text: &str,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, crate::InferenceError> {
+        let token_ids: Vec<u32> = text
+            .split_whitespace()
+            .filter_map(|word| self.vocab.get(word).copied())
+            .collect();
+        let output = serde_json::to_vec(&token_ids).unwrap();
+        out_buffer[..output.len()].copy_from_slice(&output);
+
+        Ok(output.len())
+    }
+}
+
+#[test]
+fn test_utils_parse_logit_bias() {
+    let backend = VocabBackend {
+        vocab: HashMap::from([("hello", 15043), ("world", 3186)]),
+    };
+    let mut graph: Graph<GgmlMetadata> = Graph::with_backend(
+        GgmlMetadata::default(),
+        Box::new(backend) as Box<dyn crate::InferenceBackend>,
+    )
+    .unwrap();
+
+    // the token strings are converted to the token ids by the tokenizer of the backend
+    let logit_bias = HashMap::from([("hello world".to_string(), 5.0), ("42".to_string(), -100.0)]);
+    assert_eq!(
+        parse_logit_bias(&mut graph, &logit_bias).unwrap(),
+        [(42, -100.0), (3186, 5.0), (15043, 5.0)]
+    );
+
+    // a word out of the vocabulary, and a bias out of the range
+    for (key, bias) in [("unknown", 1.0), ("hello", 101.0)] {
+        let logit_bias = HashMap::from([(key.to_string(), bias)]);
+        assert!(matches!(
+            parse_logit_bias(&mut graph, &logit_bias),
+            Err(LlamaCoreError::InvalidRequest { param: Some(param), .. }) if param == "logit_bias"
+        ));
+    }
+
+    // the token strings are rejected if the backend does not expose the vocabulary
+    let mut graph: Graph<GgmlMetadata> = Graph::with_backend(
+        GgmlMetadata::default(),
+        Box::new(VocabBackend::default()) as Box<dyn crate::InferenceBackend>,
+    )
+    .unwrap();
+    let logit_bias = HashMap::from([("hello".to_string(), 1.0)]);
+    assert!(matches!(
+        parse_logit_bias(&mut graph, &logit_bias),
+        Err(LlamaCoreError::InvalidRequest { param: Some(param), .. }) if param == "logit_bias"
+    ));
+    let logit_bias = HashMap::from([("15043".to_string(), 1.0)]);
+    assert_eq!(
+        parse_logit_bias(&mut graph, &logit_bias).unwrap(),
+        [(15043, 1.0)]
+    );
+}
@@ -0,0 +1,505 @@
+use chat_prompts::PromptTemplateType;
+use endpoints::{
+    chat::{
+        ChatCompletionChunk, ChatCompletionRequestBuilder, ChatCompletionRequestMessage,
+        ChatCompletionUserMessageContent,
+    },
+    common::FinishReason,
+    completions::{CompletionChunk, CompletionRequest},
+    embeddings::EmbeddingRequest,
+    infill::{InfillRequest, InfillResponse},
+    tokenize::{DetokenizeRequest, TokenizeRequest},
+};
+use futures::TryStreamExt;
+use llama_core::{
+    error::LlamaCoreError,
+    graph::{mock::MockBackend, TensorType},
+    metadata::ggml::GgmlMetadataBuilder,
+    Graph, InferenceBackend, InferenceError,
+};
+use std::sync::Once;
+
+static INIT: Once = Once::new();
+
+/// Initializes the context with a chat model and an embedding model once, as the context is shared by the tests in this file.
+fn init_context() {
+    INIT.call_once(|| {
+        let chat_graph = Graph::with_backend(
+            GgmlMetadataBuilder::new("mock-chat", "default", PromptTemplateType::ChatML).build(),
+            Box::new(MockBackend::default().with_tokens(["Hello", ",", " world", "!"]))
+                as Box<dyn InferenceBackend>,
+        )
+        .unwrap();
+        let embedding_graph = Graph::with_backend(
+            GgmlMetadataBuilder::new("mock-embedding", "embedding", PromptTemplateType::Embedding)
+                .enable_embeddings(true)
+                .build(),
+            Box::new(MockBackend::default().with_embedding(vec![0.5, -0.25]))
+                as Box<dyn InferenceBackend>,
+        )
+        .unwrap();
+        llama_core::init_ggml_context_with_graphs(
+            Some(vec![chat_graph]),
+            Some(vec![embedding_graph]),
+        )
+        .unwrap();
+    });
+}
+
+fn messages() -> Vec<ChatCompletionRequestMessage> {
+    vec![ChatCompletionRequestMessage::new_user_message(
+        ChatCompletionUserMessageContent::Text("Say hello".to_string()),
+        None,
+    )]
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_chat() {
+    init_context();
+
+    // non-stream chat completion
+    let mut request = ChatCompletionRequestBuilder::new("mock-chat", messages()).build();
+    let object = llama_core::chat::chat(&mut request)
+        .await
+        .unwrap()
+        .right()
+        .unwrap();
+    assert_eq!(object.model, "mock-chat");
+    assert_eq!(
+        object.choices[0].message.content.as_deref(),
+        Some("Hello, world!")
+    );
+    assert_eq!(object.choices[0].finish_reason, FinishReason::stop);
+    assert_eq!(object.usage.completion_tokens, 4);
+
+    // stream chat completion
+    let mut request = ChatCompletionRequestBuilder::new("mock-chat", messages())
+        .enable_stream(true)
+        .build();
+    let stream = llama_core::chat::chat(&mut request)
+        .await
+        .unwrap()
+        .left()
+        .unwrap();
+    let chunks: Vec<String> = stream.try_collect().await.unwrap();
+    assert_eq!(chunks.last().map(String::as_str), Some("data: [DONE]\n\n"));
+    let content: String = chunks[..chunks.len() - 1]
+        .iter()
+        .map(|chunk| {
+            let chunk: ChatCompletionChunk =
+                serde_json::from_str(chunk.trim_start_matches("data: ").trim_end()).unwrap();
+            chunk.choices[0].delta.content.clone().unwrap_or_default()
+        })
+        .collect();
+    assert_eq!(content, "Hello, world!");
+
+    // reject an invalid request
+    let mut request = ChatCompletionRequestBuilder::new("mock-chat", vec![]).build();
+    assert!(matches!(
+        llama_core::chat::chat(&mut request).await,
+        Err(LlamaCoreError::InvalidRequest { param: Some(param), .. }) if param == "messages"
+    ));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_embeddings() {
+    init_context();
+
+    let request: EmbeddingRequest =
+        serde_json::from_str(r#"{"model": "mock-embedding", "input": "Say hello"}"#).unwrap();
+    let response = llama_core::embeddings::embeddings(&request).await.unwrap();
+    assert_eq!(response.data[0].embedding, vec![0.5, -0.25]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_routing() {
+    init_context();
+
+    // route by the alias of the model
+    let mut request = ChatCompletionRequestBuilder::new("default", messages()).build();
+    let object = llama_core::chat::chat(&mut request)
+        .await
+        .unwrap()
+        .right()
+        .unwrap();
+    assert_eq!(object.model, "mock-chat");
+
+    // reject an unknown model unless the default models are set
+    let mut request = ChatCompletionRequestBuilder::new("gpt-4", messages()).build();
+    assert!(matches!(
+        llama_core::chat::chat(&mut request).await,
+        Err(LlamaCoreError::ModelNotFound(model)) if model == "gpt-4"
+    ));
+    llama_core::models::set_default_models(vec![
+        "mock-chat".to_string(),
+        "mock-embedding".to_string(),
+    ])
+    .unwrap();
+    let object = llama_core::chat::chat(&mut request)
+        .await
+        .unwrap()
+        .right()
+        .unwrap();
+    assert_eq!(object.model, "mock-chat");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_tokenize() {
+    init_context();
+
+    // tokenize and detokenize the text
+    let response = llama_core::tokenize::tokenize(&TokenizeRequest {
+        model: Some("mock-chat".to_string()),
+        content: "Say hello Say".to_string(),
+    })
+    .await
+    .unwrap();
+    assert_eq!(response.count, 3);
+    let tokens = response.tokens.unwrap();
+    assert_eq!(tokens[0], tokens[2]);
+    let response = llama_core::tokenize::detokenize(&DetokenizeRequest {
+        model: Some("mock-chat".to_string()),
+        tokens,
+    })
+    .await
+    .unwrap();
+    assert_eq!(response.content, "Say hello Say");
+    assert!(matches!(
+        llama_core::tokenize::detokenize(&DetokenizeRequest {
+            model: Some("mock-chat".to_string()),
+            tokens: vec![u32::MAX],
+        })
+        .await,
+        Err(LlamaCoreError::InvalidRequest { param: Some(param), .. }) if param == "tokens"
+    ));
+}
+
+/// Backend which generates the tokens of [`MockBackend`], but does not expose the vocabulary of the model, like the WASI-NN ggml plugin.
+#[derive(Debug)]
+struct NoVocabBackend(MockBackend);
+impl InferenceBackend for NoVocabBackend {
+    fn set_input(
+        &mut self,
+        index: usize,
+        tensor_type: TensorType,
+        dimensions: &[usize],
+        data: &[u8],
+    ) -> Result<(), InferenceError> {
+        self.0.set_input(index, tensor_type, dimensions, data)
+    }
+
+    fn compute(&mut self) -> Result<(), InferenceError> {
+        self.0.compute()
+    }
+
+    fn compute_single(&mut self) -> Result<(), InferenceError> {
+        self.0.compute_single()
+    }
+
+    fn get_output(&self, index: usize, out_buffer: &mut [u8]) -> Result<usize, InferenceError> {
+        self.0.get_output(index, out_buffer)
+    }
+
+    fn get_output_single(
+        &self,
+        index: usize,
+        out_buffer: &mut [u8],
+    ) -> Result<usize, InferenceError> {
+        self.0.get_output_single(index, out_buffer)
+    }
+
+    fn finish_single(&mut self) -> Result<(), InferenceError> {
+        self.0.finish_single()
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_no_vocabulary() {
+    init_context();
+
+    let graph = Graph::with_backend(
+        GgmlMetadataBuilder::new("mock-no-vocab", "no-vocab", PromptTemplateType::ChatML).build(),
+        Box::new(NoVocabBackend(
+            MockBackend::default().with_tokens(["Hello", "!"]),
+        )) as Box<dyn InferenceBackend>,
+    )
+    .unwrap();
+    llama_core::models::load_graph(graph).await.unwrap();
+
+    // the tokens are counted as the prompt, but the token ids are not exposed
+    let response = llama_core::tokenize::tokenize(&TokenizeRequest {
+        model: Some("mock-no-vocab".to_string()),
+        content: "Say hello".to_string(),
+    })
+    .await
+    .unwrap();
+    assert_eq!(response.count, 2);
+    assert!(response.tokens.is_none());
+
+    // the token ids cannot be converted to text without the vocabulary
+    assert!(matches!(
+        llama_core::tokenize::detokenize(&DetokenizeRequest {
+            model: Some("mock-no-vocab".to_string()),
+            tokens: vec![0],
+        })
+        .await,
+        Err(LlamaCoreError::InvalidRequest { param: Some(param), .. }) if param == "model"
+    ));
+
+    // the token ids in `logit_bias` need no vocabulary, but the token strings do
+    let request: CompletionRequest = serde_json::from_str(
+        r#"{"model": "mock-no-vocab", "prompt": "Say hello", "logit_bias": {"15043": 10}}"#,
+    )
+    .unwrap();
+    let object = llama_core::completions::completions(&request)
+        .await
+        .unwrap();
+    assert_eq!(object.choices[0].text, "Hello!");
+    let request: CompletionRequest = serde_json::from_str(
+        r#"{"model": "mock-no-vocab", "prompt": "Say hello", "logit_bias": {"hello": 10}}"#,
+    )
+    .unwrap();
+    assert!(matches!(
+        llama_core::completions::completions(&request).await,
+        Err(LlamaCoreError::InvalidRequest { param: Some(param), .. }) if param == "logit_bias"
+    ));
+
+    llama_core::models::unload_model("mock-no-vocab")
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_count_tokens() {
+    init_context();
+
+    // count the tokens of the chat prompt without generation
+    let mut request = ChatCompletionRequestBuilder::new("mock-chat", messages()).build();
+    let response = llama_core::chat::count_tokens(&mut request).await.unwrap();
+    assert_eq!(response.model, "mock-chat");
+    assert!(response.prompt_tokens > 0);
+    // the completion gets the context left by the budget of the prompt, which is 80% of the context by default
+    assert_eq!(
+        response.remaining_tokens,
+        response.context_size - response.context_size * 4 / 5
+    );
+    assert!(response.context_truncation.is_none());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_completions() {
+    init_context();
+
+    // complete a list of prompts with several choices each
+    let request: CompletionRequest = serde_json::from_str(
+        r#"{"model": "mock-chat", "prompt": ["Say", "Say hello"], "n": 2, "echo": true, "stop": [" world"]}"#,
+    )
+    .unwrap();
+    let object = llama_core::completions::completions(&request)
+        .await
+        .unwrap();
+    let texts: Vec<(u32, &str)> = object
+        .choices
+        .iter()
+        .map(|choice| (choice.index, choice.text.as_str()))
+        .collect();
+    assert_eq!(
+        texts,
+        [
+            (0, "SayHello,"),
+            (1, "SayHello,"),
+            (2, "Say helloHello,"),
+            (3, "Say helloHello,")
+        ]
+    );
+    assert_eq!(object.usage.prompt_tokens, 3);
+    assert_eq!(object.usage.completion_tokens, 12);
+
+    let request: CompletionRequest = serde_json::from_str(
+        r#"{"model": "mock-chat", "prompt": "Say hello", "max_tokens": 2, "logprobs": 0, "suffix": "?"}"#,
+    )
+    .unwrap();
+    let object = llama_core::completions::completions(&request)
+        .await
+        .unwrap();
+    assert_eq!(object.choices[0].text, "Hello,?");
+    assert_eq!(object.choices[0].finish_reason, FinishReason::length);
+    assert_eq!(
+        object.choices[0].logprobs.as_ref().unwrap().text_offset,
+        [0, 5]
+    );
+
+    // stream the completion
+    let request: CompletionRequest = serde_json::from_str(
+        r#"{"model": "mock-chat", "prompt": "Say hello", "stream": true, "stop": ["!"]}"#,
+    )
+    .unwrap();
+    let stream = llama_core::completions::completions_stream(&request)
+        .await
+        .unwrap();
+    let chunks: Vec<String> = stream.try_collect().await.unwrap();
+    assert_eq!(chunks.last().map(String::as_str), Some("data: [DONE]\n\n"));
+    let chunks: Vec<CompletionChunk> = chunks[..chunks.len() - 1]
+        .iter()
+        .map(|chunk| serde_json::from_str(chunk.trim_start_matches("data: ").trim_end()).unwrap())
+        .collect();
+    let text: String = chunks
+        .iter()
+        .map(|chunk| chunk.choices[0].text.as_str())
+        .collect();
+    assert_eq!(text, "Hello, world");
+    assert_eq!(
+        chunks.last().unwrap().choices[0].finish_reason,
+        Some(FinishReason::stop)
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_infill() {
+    init_context();
+
+    // fill in the middle with a code model
+    let graph = Graph::with_backend(
+        GgmlMetadataBuilder::new("mock-coder", "default", PromptTemplateType::DeepseekCoder)
+            .build(),
+        Box::new(MockBackend::default().with_tokens(["return", " a + b", "<|EOT|>", "\n"]))
+            as Box<dyn InferenceBackend>,
+    )
+    .unwrap();
+    llama_core::models::load_graph(graph).await.unwrap();
+
+    let request = InfillRequest {
+        model: Some("mock-coder".to_string()),
+        input_prefix: "def add(a, b):\n    ".to_string(),
+        input_suffix: "\n\nprint(add(1, 2))".to_string(),
+        ..Default::default()
+    };
+    let response = llama_core::completions::infill(&request).await.unwrap();
+    assert_eq!(response.content, "return a + b");
+    assert!(response.stopped_eos && !response.stopped_word);
+    assert_eq!(response.tokens_predicted, 3);
+
+    let stream = llama_core::completions::infill_stream(&InfillRequest {
+        stream: Some(true),
+        stop: Some(vec![" +".to_string()]),
+        ..request.clone()
+    })
+    .await
+    .unwrap();
+    let chunks: Vec<String> = stream.try_collect().await.unwrap();
+    let response: InfillResponse = serde_json::from_str(
+        chunks
+            .last()
+            .unwrap()
+            .trim_start_matches("data: ")
+            .trim_end(),
+    )
+    .unwrap();
+    assert!(response.stop && response.stopped_word);
+    assert_eq!(response.stopping_word, " +");
+    let content: String = chunks
+        .iter()
+        .map(|chunk| {
+            let chunk: serde_json::Value =
+                serde_json::from_str(chunk.trim_start_matches("data: ").trim_end()).unwrap();
+            chunk["content"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(content, "return a");
+
+    // the suffix of a completion is filled in the middle as well
+    let request: CompletionRequest = serde_json::from_str(
+        r#"{"model": "mock-coder", "prompt": "def add(a, b):", "suffix": "print(add(1, 2))"}"#,
+    )
+    .unwrap();
+    let object = llama_core::completions::completions(&request)
+        .await
+        .unwrap();
+    assert_eq!(object.choices[0].text, "return a + b");
+
+    // the chat model is not trained to fill in the middle
+    assert!(matches!(
+        llama_core::completions::infill(&InfillRequest {
+            model: Some("mock-chat".to_string()),
+            ..Default::default()
+        })
+        .await,
+        Err(LlamaCoreError::InvalidRequest { param: Some(param), .. }) if param == "model"
+    ));
+    llama_core::models::unload_model("mock-coder")
+        .await
+        .unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_load_model() {
+    init_context();
+
+    // load another chat model at runtime
+    let graph = Graph::with_backend(
+        GgmlMetadataBuilder::new("mock-chat-2", "default", PromptTemplateType::ChatML).build(),
+        Box::new(MockBackend::default().with_tokens(["Hi", "!"])) as Box<dyn InferenceBackend>,
+    )
+    .unwrap();
+    let model = llama_core::models::load_graph(graph).await.unwrap();
+    assert_eq!(model.id, "mock-chat-2");
+    assert!(llama_core::models::models()
+        .await
+        .unwrap()
+        .data
+        .iter()
+        .any(|model| model.id == "mock-chat-2"));
+    assert!(llama_core::models::chat_model_metadata()
+        .unwrap()
+        .iter()
+        .any(|metadata| metadata.model_name == "mock-chat-2"));
+
+    let mut request = ChatCompletionRequestBuilder::new("mock-chat-2", messages()).build();
+    let object = llama_core::chat::chat(&mut request)
+        .await
+        .unwrap()
+        .right()
+        .unwrap();
+    assert_eq!(object.choices[0].message.content.as_deref(), Some("Hi!"));
+
+    assert!(llama_core::models::unload_model("mock-chat-2")
+        .await
+        .unwrap()
+        .is_some());
+    assert!(llama_core::models::retrieve_model("mock-chat-2")
+        .unwrap()
+        .is_none());
+    assert!(llama_core::models::unload_model("mock-chat-2")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_mock_backend_unload_model() {
+    init_context();
+
+    // the model is unloaded after the stream in progress is finished
+    let graph = Graph::with_backend(
+        GgmlMetadataBuilder::new("mock-chat-3", "chat-3", PromptTemplateType::ChatML).build(),
+        Box::new(MockBackend::default().with_tokens(["Bye", "!"])) as Box<dyn InferenceBackend>,
+    )
+    .unwrap();
+    llama_core::models::load_graph(graph).await.unwrap();
+
+    let mut request = ChatCompletionRequestBuilder::new("mock-chat-3", messages())
+        .enable_stream(true)
+        .build();
+    let stream = llama_core::chat::chat(&mut request)
+        .await
+        .unwrap()
+        .left()
+        .unwrap();
+    let mut unload = Box::pin(llama_core::models::unload_model("mock-chat-3"));
+    assert!(futures::poll!(unload.as_mut()).is_pending());
+    drop(stream);
+    assert!(unload.await.unwrap().is_some());
+    assert!(llama_core::models::retrieve_model("mock-chat-3")
+        .unwrap()
+        .is_none());
+}
@@ -1,12 +1,22 @@
-use crate::{error, utils::gen_chat_id, SERVER_INFO};
+use crate::{error, utils::gen_chat_id, ModelConfig, SERVER_INFO};
+use chat_prompts::PromptTemplateType;
 use endpoints::{
-    chat::ChatCompletionRequest,
+    assistants::{
+        AssistantRequest, MessageRequest, RunObject, RunRequest, SubmitToolOutputsRequest,
+        ThreadRequest,
+    },
+    batches::{BatchObject, BatchRequest},
+    chat::{ChatCompletionCancelStatus, ChatCompletionRequest},
     completions::CompletionRequest,
     embeddings::{ChunksRequest, ChunksResponse, EmbeddingRequest},
     files::{DeleteFileStatus, FileObject},
+    infill::InfillRequest,
+    models::{DeleteModelStatus, LoadModelRequest},
+    tokenize::{DetokenizeRequest, TokenizeRequest},
 };
 use futures_util::TryStreamExt;
 use hyper::{body::to_bytes, Body, Method, Request, Response};
+use llama_core::{error::LlamaCoreError, metadata::ggml::GgmlMetadataBuilder};
 use multipart::server::{Multipart, ReadEntry, ReadEntryResult};
 use multipart_2021 as multipart;
 use std::{
@@ -16,10 +26,16 @@ use std::{
     time::SystemTime,
 };
 
-/// List all models available.
-pub(crate) async fn models_handler() -> Response<Body> {
+/// List all models available, or load a model at runtime.
+pub(crate) async fn models_handler(req: Request<Body>) -> Response<Body> {
     // log
-    info!(target: "stdout", "Handling the coming model list request.");
+    info!(target: "stdout", "Handling the coming models request.");
+
+    if req.method() == Method::POST {
+        return load_model(req).await;
+    } else if req.method() == Method::OPTIONS {
+        return options_response();
+    }
 
     let list_models_response = match llama_core::models::models().await {
         Ok(list_models_response) => list_models_response,
@@ -71,6 +87,253 @@ pub(crate) async fn models_handler() -> Response<Body> {
     res
 }
 
+/// Retrieve or unload the model with the id in the path `/v1/models/{id}`.
+pub(crate) async fn model_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming model request.");
+
+    let id = req
+        .uri()
+        .path()
+        .trim_start_matches("/v1/models/")
+        .to_string();
+
+    let s = if req.method() == Method::GET {
+        let model = match llama_core::models::retrieve_model(&id) {
+            Ok(Some(model)) => model,
+            Ok(None) => return error::not_found(format!("The model '{}' is not loaded.", id)),
+            Err(e) => {
+                let err_msg = format!("Failed to retrieve the model '{}'. Reason: {}", id, e);
+
+                // log
+                error!(target: "stdout", "{}", &err_msg);
+
+                return error::internal_server_error(err_msg);
+            }
+        };
+
+        serde_json::to_string(&model)
+    } else if req.method() == Method::DELETE {
+        // the model is freed after the requests in progress are served
+        let model = match llama_core::models::unload_model(&id).await {
+            Ok(Some(model)) => model,
+            Ok(None) => return error::not_found(format!("The model '{}' is not loaded.", id)),
+            Err(e) => {
+                let err_msg = format!("Failed to unload the model '{}'. Reason: {}", id, e);
+
+                // log
+                error!(target: "stdout", "{}", &err_msg);
+
+                return error::internal_server_error(err_msg);
+            }
+        };
+
+        info!(target: "stdout", "The model '{}' is unloaded.", &model.id);
+
+        serde_json::to_string(&DeleteModelStatus {
+            id: model.id,
+            object: String::from("model"),
+            deleted: true,
+        })
+    } else if req.method() == Method::OPTIONS {
+        return options_response();
+    } else {
+        let err_msg = "Invalid HTTP Method.";
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        return error::bad_request(err_msg);
+    };
+
+    // serialize response
+    let s = match s {
+        Ok(s) => s,
+        Err(e) => {
+            let err_msg = format!("Failed to serialize the model. Reason: {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    // return response
+    let result = Response::builder()
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "*")
+        .header("Access-Control-Allow-Headers", "*")
+        .header("Content-Type", "application/json")
+        .body(Body::from(s));
+    match result {
+        Ok(response) => response,
+        Err(e) => {
+            let err_msg = e.to_string();
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            error::internal_server_error(err_msg)
+        }
+    }
+}
+
+/// Load a model at runtime, or replace the loaded model with the same id after the requests in progress are served.
+async fn load_model(req: Request<Body>) -> Response<Body> {
+    // parse request
+    let body_bytes = match to_bytes(req.into_body()).await {
+        Ok(body_bytes) => body_bytes,
+        Err(e) => {
+            let err_msg = format!("Fail to read buffer from request body. {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+    let load_request: LoadModelRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(load_request) => load_request,
+        Err(e) => {
+            let err_msg = format!(
+                "Fail to deserialize the model loading request: {msg}",
+                msg = e
+            );
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::bad_request(err_msg);
+        }
+    };
+
+    let prompt_template = match (&load_request.prompt_template, load_request.embeddings) {
+        (Some(template), _) => match template.parse::<PromptTemplateType>() {
+            Ok(template) => template,
+            Err(e) => {
+                let err_msg = format!("Invalid prompt template '{}'. {}", template, e);
+
+                // log
+                error!(target: "stdout", "{}", &err_msg);
+
+                return error::bad_request(err_msg);
+            }
+        },
+        (None, true) => PromptTemplateType::Embedding,
+        (None, false) => {
+            let err_msg = "The prompt template of the chat model is not provided.";
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::bad_request(err_msg);
+        }
+    };
+
+    let alias = match (&load_request.alias, load_request.embeddings) {
+        (Some(alias), _) => alias.clone(),
+        (None, true) => String::from("embedding"),
+        (None, false) => String::from("default"),
+    };
+
+    let mut builder = GgmlMetadataBuilder::new(load_request.id.clone(), alias, prompt_template)
+        .enable_embeddings(load_request.embeddings)
+        .enable_plugin_log(true);
+    if let Some(ctx_size) = load_request.ctx_size {
+        builder = builder.with_ctx_size(ctx_size);
+    }
+    if let Some(batch_size) = load_request.batch_size {
+        builder = builder.with_batch_size(batch_size);
+    }
+    if let Some(n_predict) = load_request.n_predict {
+        builder = builder.with_n_predict(n_predict);
+    }
+    if let Some(n_gpu_layers) = load_request.n_gpu_layers {
+        builder = builder.with_n_gpu_layers(n_gpu_layers);
+    }
+    if let Some(threads) = load_request.threads {
+        builder = builder.with_threads(threads);
+    }
+    let metadata = builder.build();
+
+    info!(target: "stdout", "Load the model '{}'.", &metadata.model_name);
+
+    let path = load_request.path.as_ref().map(Path::new);
+    let model = match llama_core::models::load_model(metadata, path).await {
+        Ok(model) => model,
+        Err(e) => {
+            let err_msg = format!(
+                "Failed to load the model '{}'. Reason: {}",
+                load_request.id, e
+            );
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    // serialize response
+    let s = match serde_json::to_string(&model) {
+        Ok(s) => s,
+        Err(e) => {
+            let err_msg = format!("Failed to serialize the model. Reason: {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    // return response
+    let result = Response::builder()
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "*")
+        .header("Access-Control-Allow-Headers", "*")
+        .header("Content-Type", "application/json")
+        .body(Body::from(s));
+    let res = match result {
+        Ok(response) => response,
+        Err(e) => {
+            let err_msg = e.to_string();
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            error::internal_server_error(err_msg)
+        }
+    };
+
+    info!(target: "stdout", "The model '{}' is loaded.", &model.id);
+
+    res
+}
+
+/// Respond to the CORS preflight requests.
+fn options_response() -> Response<Body> {
+    let result = Response::builder()
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "*")
+        .header("Access-Control-Allow-Headers", "*")
+        .header("Content-Type", "application/json")
+        .body(Body::empty());
+
+    match result {
+        Ok(response) => response,
+        Err(e) => {
+            let err_msg = e.to_string();
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            error::internal_server_error(err_msg)
+        }
+    }
+}
+
 /// Compute embeddings for the input text and return the embeddings object.
 pub(crate) async fn embeddings_handler(mut req: Request<Body>) -> Response<Body> {
     // log
@@ -168,14 +431,7 @@ pub(crate) async fn embeddings_handler(mut req: Request<Body>) -> Response<Body>
                 }
             }
         }
-        Err(e) => {
-            let err_msg = e.to_string();
-
-            // log
-            error!(target: "stdout", "{}", &err_msg);
-
-            error::internal_server_error(err_msg)
-        }
+        Err(e) => error::llama_core_error(e),
     };
 
     info!(target: "stdout", "Send the embeddings response");
@@ -245,6 +501,41 @@ pub(crate) async fn completions_handler(mut req: Request<Body>) -> Response<Body
     // log user id
     info!(target: "stdout", "user: {}", &id);
 
+    if completion_request.stream == Some(true) {
+        let res = match llama_core::completions::completions_stream(&completion_request).await {
+            Ok(stream) => {
+                let stream = stream.map_err(|e| e.to_string());
+
+                let result = Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .header("Access-Control-Allow-Methods", "*")
+                    .header("Access-Control-Allow-Headers", "*")
+                    .header("Content-Type", "text/event-stream")
+                    .header("Cache-Control", "no-cache")
+                    .header("Connection", "keep-alive")
+                    .header("user", id)
+                    .body(Body::wrap_stream(stream));
+
+                match result {
+                    Ok(response) => response,
+                    Err(e) => {
+                        let err_msg = format!("Failed completions in stream mode. Reason: {}", e);
+
+                        // log
+                        error!(target: "stdout", "{}", &err_msg);
+
+                        error::internal_server_error(err_msg)
+                    }
+                }
+            }
+            Err(e) => error::llama_core_error(e),
+        };
+
+        info!(target: "stdout", "Send the completions response in stream mode.");
+
+        return res;
+    }
+
     let res = match llama_core::completions::completions(&completion_request).await {
         Ok(completion_object) => {
             // serialize completion object
@@ -280,14 +571,7 @@ pub(crate) async fn completions_handler(mut req: Request<Body>) -> Response<Body
                 }
             }
         }
-        Err(e) => {
-            let err_msg = e.to_string();
-
-            // log
-            error!(target: "stdout", "{}", &err_msg);
-
-            error::internal_server_error(err_msg)
-        }
+        Err(e) => error::llama_core_error(e),
     };
 
     info!(target: "stdout", "Send the completions response.");
@@ -295,37 +579,167 @@ pub(crate) async fn completions_handler(mut req: Request<Body>) -> Response<Body
     res
 }
 
-/// Process a chat-completion request and returns a chat-completion response with the answer from the model.
-pub(crate) async fn chat_completions_handler(mut req: Request<Body>) -> Response<Body> {
-    info!(target: "stdout", "Handling the coming chat completion request.");
+/// Fill in the middle of the code between the prefix and the suffix in the same way as the `/infill` endpoint of llama.cpp.
+pub(crate) async fn infill_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming infill request.");
 
-    if req.method().eq(&hyper::http::Method::OPTIONS) {
-        let result = Response::builder()
-            .header("Access-Control-Allow-Origin", "*")
-            .header("Access-Control-Allow-Methods", "*")
-            .header("Access-Control-Allow-Headers", "*")
-            .header("Content-Type", "application/json")
-            .body(Body::empty());
+    if req.method() == Method::OPTIONS {
+        return options_response();
+    }
 
-        match result {
-            Ok(response) => return response,
-            Err(e) => {
-                let err_msg = e.to_string();
+    let infill_request: InfillRequest = match parse_request_body(req).await {
+        Ok(infill_request) => infill_request,
+        Err(response) => return response,
+    };
 
-                // log
-                error!(target: "stdout", "{}", &err_msg);
+    if infill_request.stream == Some(true) {
+        let res = match llama_core::completions::infill_stream(&infill_request).await {
+            Ok(stream) => {
+                let stream = stream.map_err(|e| e.to_string());
 
-                return error::internal_server_error(err_msg);
-            }
-        }
-    }
+                let result = Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .header("Access-Control-Allow-Methods", "*")
+                    .header("Access-Control-Allow-Headers", "*")
+                    .header("Content-Type", "text/event-stream")
+                    .header("Cache-Control", "no-cache")
+                    .header("Connection", "keep-alive")
+                    .body(Body::wrap_stream(stream));
 
-    info!(target: "stdout", "Prepare the chat completion request.");
+                match result {
+                    Ok(response) => response,
+                    Err(e) => {
+                        let err_msg = format!("Failed infill in stream mode. Reason: {}", e);
 
-    // parse request
-    let body_bytes = match to_bytes(req.body_mut()).await {
-        Ok(body_bytes) => body_bytes,
-        Err(e) => {
+                        // log
+                        error!(target: "stdout", "{}", &err_msg);
+
+                        error::internal_server_error(err_msg)
+                    }
+                }
+            }
+            Err(e) => error::llama_core_error(e),
+        };
+
+        info!(target: "stdout", "Send the infill response in stream mode.");
+
+        return res;
+    }
+
+    let res = match llama_core::completions::infill(&infill_request).await {
+        Ok(infill_response) => json_response(serde_json::to_string(&infill_response)),
+        Err(e) => error::llama_core_error(e),
+    };
+
+    info!(target: "stdout", "Send the infill response.");
+
+    res
+}
+
+/// Convert the text to the token ids of the model.
+pub(crate) async fn tokenize_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming tokenize request.");
+
+    if req.method() == Method::OPTIONS {
+        return options_response();
+    }
+
+    let tokenize_request: TokenizeRequest = match parse_request_body(req).await {
+        Ok(tokenize_request) => tokenize_request,
+        Err(response) => return response,
+    };
+
+    let res = match llama_core::tokenize::tokenize(&tokenize_request).await {
+        Ok(tokenize_response) => json_response(serde_json::to_string(&tokenize_response)),
+        Err(e) => error::llama_core_error(e),
+    };
+
+    info!(target: "stdout", "Send the tokenize response.");
+
+    res
+}
+
+/// Convert the token ids of the model to text.
+pub(crate) async fn detokenize_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming detokenize request.");
+
+    if req.method() == Method::OPTIONS {
+        return options_response();
+    }
+
+    let detokenize_request: DetokenizeRequest = match parse_request_body(req).await {
+        Ok(detokenize_request) => detokenize_request,
+        Err(response) => return response,
+    };
+
+    let res = match llama_core::tokenize::detokenize(&detokenize_request).await {
+        Ok(detokenize_response) => json_response(serde_json::to_string(&detokenize_response)),
+        Err(e) => error::llama_core_error(e),
+    };
+
+    info!(target: "stdout", "Send the detokenize response.");
+
+    res
+}
+
+/// Count the tokens of the prompt built from a chat completion request without generating the completion.
+pub(crate) async fn count_tokens_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming count tokens request.");
+
+    if req.method() == Method::OPTIONS {
+        return options_response();
+    }
+
+    let mut chat_request: ChatCompletionRequest = match parse_request_body(req).await {
+        Ok(chat_request) => chat_request,
+        Err(response) => return response,
+    };
+
+    let res = match llama_core::chat::count_tokens(&mut chat_request).await {
+        Ok(count_tokens_response) => json_response(serde_json::to_string(&count_tokens_response)),
+        Err(e) => error::llama_core_error(e),
+    };
+
+    info!(target: "stdout", "Send the count tokens response.");
+
+    res
+}
+
+/// Process a chat-completion request and returns a chat-completion response with the answer from the model.
+pub(crate) async fn chat_completions_handler(mut req: Request<Body>) -> Response<Body> {
+    info!(target: "stdout", "Handling the coming chat completion request.");
+
+    if req.method().eq(&hyper::http::Method::OPTIONS) {
+        let result = Response::builder()
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "*")
+            .header("Access-Control-Allow-Headers", "*")
+            .header("Content-Type", "application/json")
+            .body(Body::empty());
+
+        match result {
+            Ok(response) => return response,
+            Err(e) => {
+                let err_msg = e.to_string();
+
+                // log
+                error!(target: "stdout", "{}", &err_msg);
+
+                return error::internal_server_error(err_msg);
+            }
+        }
+    }
+
+    info!(target: "stdout", "Prepare the chat completion request.");
+
+    // parse request
+    let body_bytes = match to_bytes(req.body_mut()).await {
+        Ok(body_bytes) => body_bytes,
+        Err(e) => {
             let err_msg = format!("Fail to read buffer from request body. {}", e);
 
             // log
@@ -359,6 +773,10 @@ pub(crate) async fn chat_completions_handler(mut req: Request<Body>) -> Response
     // log user id
     info!(target: "stdout", "user: {}", chat_request.user.clone().unwrap());
 
+    // the chat id is unique for each request, so that the request can be cancelled by `/v1/chat/completions/{id}/cancel`
+    let chat_id = gen_chat_id();
+    chat_request.chat_id = Some(chat_id.clone());
+
     let res = match llama_core::chat::chat(&mut chat_request).await {
         Ok(result) => match result {
             either::Left(stream) => {
@@ -372,6 +790,7 @@ pub(crate) async fn chat_completions_handler(mut req: Request<Body>) -> Response
                     .header("Cache-Control", "no-cache")
                     .header("Connection", "keep-alive")
                     .header("user", id)
+                    .header("chat-id", chat_id)
                     .body(Body::wrap_stream(stream));
 
                 match result {
@@ -413,6 +832,7 @@ pub(crate) async fn chat_completions_handler(mut req: Request<Body>) -> Response
                     .header("Access-Control-Allow-Headers", "*")
                     .header("Content-Type", "application/json")
                     .header("user", id)
+                    .header("chat-id", chat_id)
                     .body(Body::from(s));
 
                 match result {
@@ -434,8 +854,110 @@ pub(crate) async fn chat_completions_handler(mut req: Request<Body>) -> Response
                 }
             }
         },
+        Err(e) => error::llama_core_error(e),
+    };
+
+    // log
+    info!(target: "stdout", "Send the chat completion response.");
+
+    res
+}
+
+/// Cancel the chat completion in progress with the given id.
+pub(crate) async fn cancel_chat_completion_handler(req: Request<Body>) -> Response<Body> {
+    info!(target: "stdout", "Handling the coming chat completion cancellation request.");
+
+    if req.method().eq(&hyper::http::Method::OPTIONS) {
+        let result = Response::builder()
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Access-Control-Allow-Methods", "*")
+            .header("Access-Control-Allow-Headers", "*")
+            .header("Content-Type", "application/json")
+            .body(Body::empty());
+
+        match result {
+            Ok(response) => return response,
+            Err(e) => {
+                let err_msg = e.to_string();
+
+                // log
+                error!(target: "stdout", "{}", &err_msg);
+
+                return error::internal_server_error(err_msg);
+            }
+        }
+    }
+
+    if req.method() != Method::POST {
+        let err_msg = "Invalid HTTP Method.";
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        return error::bad_request(err_msg);
+    }
+
+    // the path is in the form of `/v1/chat/completions/{id}/cancel`
+    let id = req
+        .uri()
+        .path()
+        .trim_start_matches("/v1/chat/completions/")
+        .trim_end_matches("/cancel");
+
+    let cancelled = match llama_core::cancellation::cancel(id) {
+        Ok(cancelled) => cancelled,
         Err(e) => {
-            let err_msg = format!("Failed to get chat completions. Reason: {}", e);
+            let err_msg = format!("Failed to cancel the chat completion with id {}. {}", id, e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    if !cancelled {
+        let err_msg = format!("No chat completion in progress with id {}.", id);
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        return error::not_found(err_msg);
+    }
+
+    let status = ChatCompletionCancelStatus {
+        id: id.to_string(),
+        object: "chat.completion".to_string(),
+        cancelled,
+    };
+
+    // serialize status
+    let s = match serde_json::to_string(&status) {
+        Ok(s) => s,
+        Err(e) => {
+            let err_msg = format!(
+                "Failed to serialize the status of the chat completion cancellation. {}",
+                e
+            );
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    // return response
+    let result = Response::builder()
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "*")
+        .header("Access-Control-Allow-Headers", "*")
+        .header("Content-Type", "application/json")
+        .body(Body::from(s));
+    let res = match result {
+        Ok(response) => response,
+        Err(e) => {
+            let err_msg = e.to_string();
 
             // log
             error!(target: "stdout", "{}", &err_msg);
@@ -444,8 +966,7 @@ pub(crate) async fn chat_completions_handler(mut req: Request<Body>) -> Response
         }
     };
 
-    // log
-    info!(target: "stdout", "Send the chat completion response.");
+    info!(target: "stdout", "Send the chat completion cancellation response.");
 
     res
 }
@@ -508,10 +1029,12 @@ pub(crate) async fn files_handler(req: Request<Body>) -> Response<Body> {
                 if !((filename).to_lowercase().ends_with(".txt")
                     || (filename).to_lowercase().ends_with(".md")
                     || (filename).to_lowercase().ends_with(".png")
+                    || (filename).to_lowercase().ends_with(".jpg")
+                    || (filename).to_lowercase().ends_with(".jpeg")
                     || (filename).to_lowercase().ends_with(".wav"))
                 {
                     let err_msg = format!(
-                        "Failed to upload the target file. Only files with 'txt', 'md', 'png', 'wav' extensions are supported. The file to be uploaded is {}.",
+                        "Failed to upload the target file. Only files with 'txt', 'md', 'png', 'jpg', 'jpeg', 'wav' extensions are supported. The file to be uploaded is {}.",
                         &filename
                     );
 
@@ -1140,14 +1663,7 @@ pub(crate) async fn chunks_handler(mut req: Request<Body>) -> Response<Body> {
                 }
             }
         }
-        Err(e) => {
-            let err_msg = e.to_string();
-
-            // log
-            error!(target: "stdout", "{}", &err_msg);
-
-            error::internal_server_error(err_msg)
-        }
+        Err(e) => error::llama_core_error(e),
     };
 
     info!(target: "stdout", "Send the chunks response.");
@@ -1161,8 +1677,8 @@ pub(crate) async fn server_info_handler() -> Response<Body> {
     info!(target: "stdout", "Handling the coming server info request.");
 
     // get the server info
-    let server_info = match SERVER_INFO.get() {
-        Some(server_info) => server_info,
+    let mut server_info = match SERVER_INFO.get() {
+        Some(server_info) => server_info.clone(),
         None => {
             let err_msg = "The server info is not set.";
 
@@ -1173,6 +1689,24 @@ pub(crate) async fn server_info_handler() -> Response<Body> {
         }
     };
 
+    // report the models loaded at present, including the ones loaded at runtime
+    let metadata = llama_core::models::chat_model_metadata()
+        .and_then(|chats| Ok((chats, llama_core::models::embedding_model_metadata()?)));
+    match metadata {
+        Ok((chats, embeddings)) => {
+            server_info.chat_models = chats.iter().map(ModelConfig::chat).collect();
+            server_info.embedding_models = embeddings.iter().map(ModelConfig::embedding).collect();
+        }
+        Err(e) => {
+            let err_msg = format!("Failed to get the metadata of the models. Reason: {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    }
+
     // serialize server info
     let s = match serde_json::to_string(&server_info) {
         Ok(s) => s,
@@ -1209,3 +1743,465 @@ pub(crate) async fn server_info_handler() -> Response<Body> {
 
     res
 }
+
+/// Return the metrics of the request queues of the chat models.
+pub(crate) async fn queue_metrics_handler() -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming queue metrics request.");
+
+    let metrics = match llama_core::scheduler::queue_metrics() {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            let err_msg = format!("Failed to get the queue metrics. Reason: {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    // serialize queue metrics
+    let s = match serde_json::to_string(&metrics) {
+        Ok(s) => s,
+        Err(e) => {
+            let err_msg = format!("Fail to serialize the queue metrics. {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    // return response
+    let result = Response::builder()
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "*")
+        .header("Access-Control-Allow-Headers", "*")
+        .header("Content-Type", "application/json")
+        .body(Body::from(s));
+    let res = match result {
+        Ok(response) => response,
+        Err(e) => {
+            let err_msg = e.to_string();
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            error::internal_server_error(err_msg)
+        }
+    };
+
+    info!(target: "stdout", "Send the queue metrics response.");
+
+    res
+}
+
+/// List all batches, or create a batch from an uploaded file of requests, which is processed in the background.
+pub(crate) async fn batches_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming batches request.");
+
+    let s = if req.method() == Method::POST {
+        let batch = match create_batch(req).await {
+            Ok(batch) => batch,
+            Err(response) => return response,
+        };
+
+        serde_json::to_string(&batch)
+    } else if req.method() == Method::GET {
+        let list_batches_response = match llama_core::batches::list_batches() {
+            Ok(list_batches_response) => list_batches_response,
+            Err(e) => {
+                let err_msg = format!("Failed to get the batch list. Reason: {}", e);
+
+                // log
+                error!(target: "stdout", "{}", &err_msg);
+
+                return error::internal_server_error(err_msg);
+            }
+        };
+
+        serde_json::to_string(&list_batches_response)
+    } else if req.method() == Method::OPTIONS {
+        return options_response();
+    } else {
+        let err_msg = "Invalid HTTP Method.";
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        return error::bad_request(err_msg);
+    };
+
+    json_response(s)
+}
+
+/// Retrieve the batch with the id in the path `/v1/batches/{id}`, or cancel it by `POST /v1/batches/{id}/cancel`.
+pub(crate) async fn batch_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming batch request.");
+
+    let path = req.uri().path().trim_start_matches("/v1/batches/");
+    let batch = if req.method() == Method::GET {
+        llama_core::batches::retrieve_batch(path)
+    } else if req.method() == Method::POST && path.ends_with("/cancel") {
+        llama_core::batches::cancel_batch(path.trim_end_matches("/cancel"))
+    } else if req.method() == Method::OPTIONS {
+        return options_response();
+    } else {
+        let err_msg = "Invalid HTTP Method.";
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        return error::bad_request(err_msg);
+    };
+
+    let s = match batch {
+        Ok(Some(batch)) => serde_json::to_string(&batch),
+        Ok(None) => {
+            let id = path.trim_end_matches("/cancel");
+            return error::not_found(format!("The batch '{}' does not exist.", id));
+        }
+        Err(e) => {
+            let err_msg = format!("Failed to get the batch. Reason: {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    json_response(s)
+}
+
+/// Create a batch from the request body, and process it in the background.
+async fn create_batch(mut req: Request<Body>) -> Result<BatchObject, Response<Body>> {
+    // parse request
+    let body_bytes = match to_bytes(req.body_mut()).await {
+        Ok(body_bytes) => body_bytes,
+        Err(e) => {
+            let err_msg = format!("Fail to read buffer from request body. {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(error::internal_server_error(err_msg));
+        }
+    };
+    let batch_request: BatchRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(batch_request) => batch_request,
+        Err(e) => {
+            let err_msg = format!("Fail to deserialize batch request: {msg}", msg = e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(error::bad_request(err_msg));
+        }
+    };
+
+    let batch =
+        llama_core::batches::create_batch(batch_request).map_err(error::llama_core_error)?;
+
+    if !batch.status.is_terminal() {
+        info!(target: "stdout", "Run the batch {} in the background.", &batch.id);
+
+        let id = batch.id.clone();
+        tokio::spawn(async move {
+            if let Err(e) = llama_core::batches::run_batch(&id).await {
+                // log
+                error!(target: "stdout", "Failed to run the batch {}. Reason: {}", &id, e);
+            }
+        });
+    }
+
+    Ok(batch)
+}
+
+fn json_response(s: serde_json::Result<String>) -> Response<Body> {
+    // serialize response
+    let s = match s {
+        Ok(s) => s,
+        Err(e) => {
+            let err_msg = format!("Failed to serialize the response. Reason: {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return error::internal_server_error(err_msg);
+        }
+    };
+
+    // return response
+    let result = Response::builder()
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "*")
+        .header("Access-Control-Allow-Headers", "*")
+        .header("Content-Type", "application/json")
+        .body(Body::from(s));
+    match result {
+        Ok(response) => response,
+        Err(e) => {
+            let err_msg = e.to_string();
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            error::internal_server_error(err_msg)
+        }
+    }
+}
+
+/// List all assistants, or create an assistant.
+pub(crate) async fn assistants_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming assistants request.");
+
+    let s = if req.method() == Method::POST {
+        let assistant_request: AssistantRequest = match parse_request_body(req).await {
+            Ok(assistant_request) => assistant_request,
+            Err(response) => return response,
+        };
+
+        match llama_core::assistants::create_assistant(assistant_request) {
+            Ok(assistant) => serde_json::to_string(&assistant),
+            Err(e) => return error::llama_core_error(e),
+        }
+    } else if req.method() == Method::GET {
+        match llama_core::assistants::list_assistants() {
+            Ok(list_assistants_response) => serde_json::to_string(&list_assistants_response),
+            Err(e) => return error::llama_core_error(e),
+        }
+    } else if req.method() == Method::OPTIONS {
+        return options_response();
+    } else {
+        let err_msg = "Invalid HTTP Method.";
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        return error::bad_request(err_msg);
+    };
+
+    json_response(s)
+}
+
+/// Retrieve or delete the assistant with the id in the path `/v1/assistants/{id}`.
+pub(crate) async fn assistant_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming assistant request.");
+
+    let id = req.uri().path().trim_start_matches("/v1/assistants/");
+    if req.method() == Method::GET {
+        found_response(llama_core::assistants::retrieve_assistant(id), || {
+            format!("The assistant '{}' does not exist.", id)
+        })
+    } else if req.method() == Method::DELETE {
+        found_response(llama_core::assistants::delete_assistant(id), || {
+            format!("The assistant '{}' does not exist.", id)
+        })
+    } else if req.method() == Method::OPTIONS {
+        options_response()
+    } else {
+        let err_msg = "Invalid HTTP Method.";
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        error::bad_request(err_msg)
+    }
+}
+
+/// Create a thread, optionally with the messages to start with.
+pub(crate) async fn threads_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming threads request.");
+
+    if req.method() == Method::OPTIONS {
+        return options_response();
+    } else if req.method() != Method::POST {
+        let err_msg = "Invalid HTTP Method.";
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        return error::bad_request(err_msg);
+    }
+
+    let thread_request: ThreadRequest = match parse_request_body(req).await {
+        Ok(thread_request) => thread_request,
+        Err(response) => return response,
+    };
+
+    match llama_core::assistants::create_thread(thread_request) {
+        Ok(thread) => json_response(serde_json::to_string(&thread)),
+        Err(e) => error::llama_core_error(e),
+    }
+}
+
+/// Handle the requests to a thread and its messages and runs:
+///
+/// - `GET` or `DELETE /v1/threads/{thread_id}`
+/// - `GET` or `POST /v1/threads/{thread_id}/messages`
+/// - `GET` or `POST /v1/threads/{thread_id}/runs`
+/// - `GET /v1/threads/{thread_id}/runs/{run_id}`
+/// - `POST /v1/threads/{thread_id}/runs/{run_id}/cancel`
+/// - `POST /v1/threads/{thread_id}/runs/{run_id}/submit_tool_outputs`
+pub(crate) async fn thread_handler(req: Request<Body>) -> Response<Body> {
+    // log
+    info!(target: "stdout", "Handling the coming thread request.");
+
+    if req.method() == Method::OPTIONS {
+        return options_response();
+    }
+
+    let path = req
+        .uri()
+        .path()
+        .trim_start_matches("/v1/threads/")
+        .to_string();
+    let segments: Vec<&str> = path.split('/').collect();
+    let thread_not_found = || format!("The thread '{}' does not exist.", segments[0]);
+    let run_not_found = || {
+        format!(
+            "The run '{}' of the thread '{}' does not exist.",
+            segments[2], segments[0]
+        )
+    };
+
+    let method = req.method().clone();
+    match (&method, segments.as_slice()) {
+        (&Method::GET, [thread_id]) => found_response(
+            llama_core::assistants::retrieve_thread(thread_id),
+            thread_not_found,
+        ),
+        (&Method::DELETE, [thread_id]) => found_response(
+            llama_core::assistants::delete_thread(thread_id),
+            thread_not_found,
+        ),
+        (&Method::GET, [thread_id, "messages"]) => found_response(
+            llama_core::assistants::list_messages(thread_id),
+            thread_not_found,
+        ),
+        (&Method::POST, [thread_id, "messages"]) => {
+            let message_request: MessageRequest = match parse_request_body(req).await {
+                Ok(message_request) => message_request,
+                Err(response) => return response,
+            };
+
+            found_response(
+                llama_core::assistants::create_message(thread_id, message_request),
+                thread_not_found,
+            )
+        }
+        (&Method::GET, [thread_id, "runs"]) => found_response(
+            llama_core::assistants::list_runs(thread_id),
+            thread_not_found,
+        ),
+        (&Method::POST, [thread_id, "runs"]) => {
+            let run_request: RunRequest = match parse_request_body(req).await {
+                Ok(run_request) => run_request,
+                Err(response) => return response,
+            };
+
+            let run = llama_core::assistants::create_run(thread_id, run_request);
+            if let Ok(Some(run)) = &run {
+                execute_run(run);
+            }
+
+            found_response(run, thread_not_found)
+        }
+        (&Method::GET, [thread_id, "runs", run_id]) => found_response(
+            llama_core::assistants::retrieve_run(thread_id, run_id),
+            run_not_found,
+        ),
+        (&Method::POST, [thread_id, "runs", run_id, "cancel"]) => found_response(
+            llama_core::assistants::cancel_run(thread_id, run_id),
+            run_not_found,
+        ),
+        (&Method::POST, [thread_id, "runs", run_id, "submit_tool_outputs"]) => {
+            let submit_request: SubmitToolOutputsRequest = match parse_request_body(req).await {
+                Ok(submit_request) => submit_request,
+                Err(response) => return response,
+            };
+
+            let run =
+                llama_core::assistants::submit_tool_outputs(thread_id, run_id, submit_request);
+            if let Ok(Some(run)) = &run {
+                execute_run(run);
+            }
+
+            found_response(run, run_not_found)
+        }
+        _ => {
+            let err_msg = format!("Invalid endpoint: {} {}", &method, req.uri().path());
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            error::invalid_endpoint(err_msg)
+        }
+    }
+}
+
+/// Process a queued run of a thread in the background.
+fn execute_run(run: &RunObject) {
+    info!(target: "stdout", "Run the thread {} in the background.", &run.thread_id);
+
+    let (thread_id, run_id) = (run.thread_id.clone(), run.id.clone());
+    tokio::spawn(async move {
+        if let Err(e) = llama_core::assistants::execute_run(&thread_id, &run_id).await {
+            // log
+            error!(target: "stdout", "Failed to execute the run {}. Reason: {}", &run_id, e);
+        }
+    });
+}
+
+/// Return the object found by a lookup, or the error response if the object does not exist or the lookup fails.
+fn found_response<T: serde::Serialize>(
+    result: Result<Option<T>, LlamaCoreError>,
+    not_found: impl FnOnce() -> String,
+) -> Response<Body> {
+    match result {
+        Ok(Some(object)) => json_response(serde_json::to_string(&object)),
+        Ok(None) => {
+            let err_msg = not_found();
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            error::not_found(err_msg)
+        }
+        Err(e) => error::llama_core_error(e),
+    }
+}
+
+/// Deserialize the JSON request body.
+async fn parse_request_body<T: serde::de::DeserializeOwned>(
+    mut req: Request<Body>,
+) -> Result<T, Response<Body>> {
+    let body_bytes = match to_bytes(req.body_mut()).await {
+        Ok(body_bytes) => body_bytes,
+        Err(e) => {
+            let err_msg = format!("Fail to read buffer from request body. {}", e);
+
+            // log
+            error!(target: "stdout", "{}", &err_msg);
+
+            return Err(error::internal_server_error(err_msg));
+        }
+    };
+
+    serde_json::from_slice(&body_bytes).map_err(|e| {
+        let err_msg = format!("Fail to deserialize the request body: {msg}", msg = e);
+
+        // log
+        error!(target: "stdout", "{}", &err_msg);
+
+        error::bad_request(err_msg)
+    })
+}
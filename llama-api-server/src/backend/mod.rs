@@ -9,10 +9,25 @@ pub(crate) async fn handle_llama_request(req: Request<Body>) -> Response<Body> {
     match req.uri().path() {
         "/v1/chat/completions" => ggml::chat_completions_handler(req).await,
         "/v1/completions" => ggml::completions_handler(req).await,
-        "/v1/models" => ggml::models_handler().await,
+        "/v1/infill" => ggml::infill_handler(req).await,
+        "/v1/tokenize" => ggml::tokenize_handler(req).await,
+        "/v1/detokenize" => ggml::detokenize_handler(req).await,
+        "/v1/chat/completions/count_tokens" => ggml::count_tokens_handler(req).await,
+        "/v1/models" => ggml::models_handler(req).await,
         "/v1/embeddings" => ggml::embeddings_handler(req).await,
         "/v1/chunks" => ggml::chunks_handler(req).await,
         "/v1/info" => ggml::server_info_handler().await,
+        "/v1/queue/metrics" => ggml::queue_metrics_handler().await,
+        "/v1/batches" => ggml::batches_handler(req).await,
+        "/v1/assistants" => ggml::assistants_handler(req).await,
+        "/v1/threads" => ggml::threads_handler(req).await,
+        path if path.starts_with("/v1/models/") => ggml::model_handler(req).await,
+        path if path.starts_with("/v1/batches/") => ggml::batch_handler(req).await,
+        path if path.starts_with("/v1/assistants/") => ggml::assistant_handler(req).await,
+        path if path.starts_with("/v1/threads/") => ggml::thread_handler(req).await,
+        path if path.starts_with("/v1/chat/completions/") && path.ends_with("/cancel") => {
+            ggml::cancel_chat_completion_handler(req).await
+        }
         path => {
             if path.starts_with("/v1/files") {
                 ggml::files_handler(req).await
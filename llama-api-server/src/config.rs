@@ -0,0 +1,699 @@
+//! Define the configuration file of the API server.
+//!
+//! The configuration file is a TOML or YAML file passed by `--config`, which describes the listen address, the web UI, CORS, logging and the models. The options given on the command line override the ones in the file.
+
+use crate::{error::ServerError, utils::LogLevel, Cli, DEFAULT_PORT};
+use chat_prompts::{infill::InfillTemplateType, PromptTemplateType};
+use clap::{parser::ValueSource, ArgMatches};
+use endpoints::chat::{ContextTruncation, TruncationStrategy};
+use llama_core::metadata::ggml::{GgmlMetadata, GgmlMetadataBuilder};
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Configuration of the API server.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(default)]
+    pub(crate) server: ServerSection,
+    #[serde(default)]
+    pub(crate) cors: CorsSection,
+    #[serde(default)]
+    pub(crate) logging: LoggingSection,
+    #[serde(default)]
+    pub(crate) models: Vec<ModelSection>,
+}
+impl Config {
+    /// Loads the configuration from a TOML file, or a YAML file with the `yaml` or `yml` extension.
+    pub(crate) fn load(path: &Path) -> Result<Self, ServerError> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            ServerError::ArgumentError(format!(
+                "Failed to read the config file {}. {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        let config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&content).map_err(|e| e.to_string()),
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(|e| e.to_string()),
+            _ => {
+                return Err(ServerError::ArgumentError(format!(
+                    "Unsupported config file {}. The extension should be `toml`, `yaml` or `yml`.",
+                    path.display()
+                )))
+            }
+        };
+
+        config.map_err(|e| {
+            ServerError::ArgumentError(format!("Invalid config file {}. {}", path.display(), e))
+        })
+    }
+
+    /// Overrides the configuration with the options given on the command line. If no config file is loaded, all the options, including the default values, are taken from the command line.
+    pub(crate) fn merge_cli(
+        &mut self,
+        cli: &Cli,
+        matches: &ArgMatches,
+        from_file: bool,
+    ) -> Result<(), ServerError> {
+        let explicit =
+            |id: &str| !from_file || matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        // the models in the file are overridden in order
+        if !from_file {
+            self.models = cli
+                .model_name
+                .iter()
+                .map(|name| ModelSection {
+                    name: name.clone(),
+                    ..Default::default()
+                })
+                .collect();
+        } else if explicit("model_name") {
+            for (model, name) in self.models.iter_mut().zip(&cli.model_name) {
+                model.name.clone_from(name);
+            }
+        }
+        if explicit("model_alias") {
+            if !from_file && cli.model_alias.len() < self.models.len() {
+                return Err(ServerError::ArgumentError(
+                    "Each model requires a model alias. Please specify the aliases of the models preloaded by `--nn-preload` in the same order as the model names.".to_owned(),
+                ));
+            }
+            for (model, alias) in self.models.iter_mut().zip(&cli.model_alias) {
+                model.alias = Some(alias.clone());
+            }
+        }
+        if explicit("prompt_template") {
+            if !from_file && cli.prompt_template.len() != self.models.len() {
+                return Err(ServerError::ArgumentError(
+                    "The number of model names and prompt templates must be the same.".to_owned(),
+                ));
+            }
+            for (model, template) in self.models.iter_mut().zip(&cli.prompt_template) {
+                model.prompt_template = Some(*template);
+            }
+        }
+
+        // per-model options
+        let models = &mut self.models;
+        merge(
+            models,
+            &cli.infill_template,
+            explicit("infill_template"),
+            "infill template",
+            |m| &mut m.infill_template,
+        )?;
+        merge(
+            models,
+            &cli.ctx_size,
+            explicit("ctx_size"),
+            "context size",
+            |m| &mut m.ctx_size,
+        )?;
+        merge(
+            models,
+            &cli.batch_size,
+            explicit("batch_size"),
+            "batch size",
+            |m| &mut m.batch_size,
+        )?;
+        merge(
+            models,
+            &cli.n_predict,
+            explicit("n_predict"),
+            "n_predict",
+            |m| &mut m.n_predict,
+        )?;
+        merge(models, &cli.temp, explicit("temp"), "temperature", |m| {
+            &mut m.temp
+        })?;
+        merge(models, &cli.top_p, explicit("top_p"), "top_p", |m| {
+            &mut m.top_p
+        })?;
+        merge(
+            models,
+            &cli.repeat_penalty,
+            explicit("repeat_penalty"),
+            "repeat penalty",
+            |m| &mut m.repeat_penalty,
+        )?;
+        merge(
+            models,
+            &cli.presence_penalty,
+            explicit("presence_penalty"),
+            "presence penalty",
+            |m| &mut m.presence_penalty,
+        )?;
+        merge(
+            models,
+            &cli.frequency_penalty,
+            explicit("frequency_penalty"),
+            "frequency penalty",
+            |m| &mut m.frequency_penalty,
+        )?;
+
+        // the options shared by all the models
+        merge(
+            models,
+            &[cli.n_gpu_layers],
+            explicit("n_gpu_layers"),
+            "n_gpu_layers",
+            |m| &mut m.n_gpu_layers,
+        )?;
+        merge(
+            models,
+            cli.main_gpu.as_slice(),
+            explicit("main_gpu"),
+            "main_gpu",
+            |m| &mut m.main_gpu,
+        )?;
+        merge(
+            models,
+            cli.tensor_split.as_slice(),
+            explicit("tensor_split"),
+            "tensor_split",
+            |m| &mut m.tensor_split,
+        )?;
+        merge(
+            models,
+            &[cli.threads],
+            explicit("threads"),
+            "threads",
+            |m| &mut m.threads,
+        )?;
+        merge(
+            models,
+            cli.no_mmap.as_slice(),
+            explicit("no_mmap"),
+            "no_mmap",
+            |m| &mut m.no_mmap,
+        )?;
+        merge(
+            models,
+            cli.reverse_prompt.as_slice(),
+            explicit("reverse_prompt"),
+            "reverse prompt",
+            |m| &mut m.reverse_prompt,
+        )?;
+        merge(
+            models,
+            std::slice::from_ref(&cli.grammar),
+            explicit("grammar"),
+            "grammar",
+            |m| &mut m.grammar,
+        )?;
+        merge(
+            models,
+            cli.json_schema.as_slice(),
+            explicit("json_schema"),
+            "json schema",
+            |m| &mut m.json_schema,
+        )?;
+        merge(
+            models,
+            cli.llava_mmproj.as_slice(),
+            explicit("llava_mmproj"),
+            "llava_mmproj",
+            |m| &mut m.llava_mmproj,
+        )?;
+        merge(
+            models,
+            &[cli.context_truncation],
+            explicit("context_truncation"),
+            "context truncation",
+            |m| &mut m.context_truncation,
+        )?;
+        merge(
+            models,
+            &[cli.last_turns],
+            explicit("last_turns"),
+            "last turns",
+            |m| &mut m.last_turns,
+        )?;
+        merge(
+            models,
+            &[cli.prompt_ratio],
+            explicit("prompt_ratio"),
+            "prompt ratio",
+            |m| &mut m.prompt_ratio,
+        )?;
+
+        // server options
+        let server = &mut self.server;
+        if cli.socket_addr.is_some() && explicit("socket_addr") {
+            server.socket_addr = cli.socket_addr;
+            server.port = None;
+        } else if matches.value_source("port") == Some(ValueSource::CommandLine) {
+            server.socket_addr = None;
+            server.port = Some(cli.port);
+        }
+        if !cli.default_model.is_empty() && explicit("default_model") {
+            server.default_model = Some(cli.default_model.clone());
+        }
+        if explicit("web_ui") || server.web_ui.is_none() {
+            server.web_ui = Some(cli.web_ui.clone());
+        }
+        if explicit("max_queue_depth") || server.max_queue_depth.is_none() {
+            server.max_queue_depth = Some(cli.max_queue_depth);
+        }
+        if explicit("queue_timeout") || server.queue_timeout.is_none() {
+            server.queue_timeout = Some(cli.queue_timeout);
+        }
+
+        Ok(())
+    }
+
+    /// Validates the settings shared by the models. The settings of each model are validated by [`ModelSection::metadata`].
+    pub(crate) fn validate(&self) -> Result<(), ServerError> {
+        if self.server.socket_addr.is_some() && self.server.port.is_some() {
+            return Err(ServerError::ArgumentError(
+                "Invalid server settings. Please set either `socket_addr` or `port`, not both."
+                    .to_owned(),
+            ));
+        }
+
+        if self.models.is_empty() {
+            return Err(ServerError::ArgumentError(
+                "No model is defined. Please specify at least one model.".to_owned(),
+            ));
+        }
+
+        for (i, model) in self.models.iter().enumerate() {
+            if model.name.is_empty() {
+                return Err(ServerError::ArgumentError(format!(
+                    "The name of the model #{} is empty.",
+                    i
+                )));
+            }
+            if self.models[..i].iter().any(|m| m.name == model.name) {
+                return Err(ServerError::ArgumentError(format!(
+                    "The model name '{}' is duplicated. The model names must be unique.",
+                    model.name
+                )));
+            }
+        }
+
+        for name in self.server.default_model.iter().flatten() {
+            if !self.models.iter().any(|model| &model.name == name) {
+                return Err(ServerError::ArgumentError(format!(
+                    "The default model '{}' is not defined.",
+                    name
+                )));
+            }
+        }
+
+        if self.cors.allow_origins.is_empty() {
+            return Err(ServerError::ArgumentError(
+                "Invalid CORS settings. `allow_origins` should not be empty.".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings of the listen address and the web UI.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ServerSection {
+    /// Socket address, for example, `0.0.0.0:8080`
+    pub(crate) socket_addr: Option<SocketAddr>,
+    /// Port number, which listens on `0.0.0.0`
+    pub(crate) port: Option<u16>,
+    /// Root path for the web UI files
+    pub(crate) web_ui: Option<PathBuf>,
+    /// Names of the models serving the requests without a model or for an unknown model
+    pub(crate) default_model: Option<Vec<String>>,
+    /// Maximum number of requests waiting in the queue of a chat model
+    pub(crate) max_queue_depth: Option<usize>,
+    /// Maximum time in seconds that a request waits in the queue of a chat model. Zero means no timeout.
+    pub(crate) queue_timeout: Option<u64>,
+}
+impl ServerSection {
+    /// Returns the socket address to listen on.
+    pub(crate) fn addr(&self) -> SocketAddr {
+        match self.socket_addr {
+            Some(addr) => addr,
+            None => {
+                let port = self
+                    .port
+                    .unwrap_or_else(|| DEFAULT_PORT.parse().unwrap_or(8080));
+                SocketAddr::from(([0, 0, 0, 0], port))
+            }
+        }
+    }
+}
+
+/// Settings of the cross-origin resource sharing.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CorsSection {
+    /// Origins allowed to access the API server. `*` allows any origin.
+    pub(crate) allow_origins: Vec<String>,
+}
+impl Default for CorsSection {
+    fn default() -> Self {
+        Self {
+            allow_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+/// Settings of the logging.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LoggingSection {
+    /// Log level, which is overridden by the `RUST_LOG` environment variable
+    pub(crate) level: Option<LogLevel>,
+}
+
+/// Type of a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ModelType {
+    Chat,
+    Embedding,
+}
+
+/// Settings of a chat or embedding model.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ModelSection {
+    /// Model name used in the requests
+    pub(crate) name: String,
+    /// Name of the model preloaded by `--nn-preload`. Defaults to the model name.
+    pub(crate) alias: Option<String>,
+    /// Model type. Defaults to `embedding` for the `embedding` prompt template, and `chat` otherwise.
+    #[serde(rename = "type")]
+    pub(crate) ty: Option<ModelType>,
+    /// Path to the model file, which is loaded at startup instead of the preloaded model
+    pub(crate) path: Option<PathBuf>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub(crate) prompt_template: Option<PromptTemplateType>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub(crate) infill_template: Option<InfillTemplateType>,
+    pub(crate) ctx_size: Option<u64>,
+    pub(crate) batch_size: Option<u64>,
+    pub(crate) n_predict: Option<u64>,
+    pub(crate) n_gpu_layers: Option<u64>,
+    pub(crate) main_gpu: Option<u64>,
+    pub(crate) tensor_split: Option<String>,
+    pub(crate) threads: Option<u64>,
+    pub(crate) no_mmap: Option<bool>,
+    pub(crate) temp: Option<f64>,
+    pub(crate) top_p: Option<f64>,
+    pub(crate) repeat_penalty: Option<f64>,
+    pub(crate) presence_penalty: Option<f64>,
+    pub(crate) frequency_penalty: Option<f64>,
+    pub(crate) reverse_prompt: Option<String>,
+    pub(crate) grammar: Option<String>,
+    pub(crate) json_schema: Option<String>,
+    pub(crate) llava_mmproj: Option<String>,
+    #[serde(default, deserialize_with = "from_str")]
+    pub(crate) context_truncation: Option<TruncationStrategy>,
+    pub(crate) last_turns: Option<usize>,
+    pub(crate) prompt_ratio: Option<f64>,
+}
+impl ModelSection {
+    /// Returns the model type, which should match the prompt template.
+    pub(crate) fn model_type(&self) -> Result<ModelType, ServerError> {
+        let is_embedding_template = self.prompt_template == Some(PromptTemplateType::Embedding);
+        match (self.ty, &self.prompt_template) {
+            (Some(ModelType::Embedding), Some(_)) if !is_embedding_template => {
+                Err(ServerError::ArgumentError(format!(
+                    "The embedding model '{}' requires the `embedding` prompt template.",
+                    self.name
+                )))
+            }
+            (Some(ModelType::Chat), _) if is_embedding_template => {
+                Err(ServerError::ArgumentError(format!(
+                    "The chat model '{}' cannot use the `embedding` prompt template.",
+                    self.name
+                )))
+            }
+            (Some(ty), _) => Ok(ty),
+            (None, _) if is_embedding_template => Ok(ModelType::Embedding),
+            (None, _) => Ok(ModelType::Chat),
+        }
+    }
+
+    /// Validates the settings and creates the metadata of the model.
+    pub(crate) fn metadata(&self, plugin_debug: bool) -> Result<GgmlMetadata, ServerError> {
+        let model_type = self.model_type()?;
+        let prompt_template = match (self.prompt_template, model_type) {
+            (Some(template), _) => template,
+            (None, ModelType::Embedding) => PromptTemplateType::Embedding,
+            (None, ModelType::Chat) => {
+                return Err(ServerError::ArgumentError(format!(
+                    "The chat model '{}' requires a prompt template.",
+                    self.name
+                )))
+            }
+        };
+        let alias = self.alias.clone().unwrap_or_else(|| self.name.clone());
+        let ctx_size = self.ctx_size.unwrap_or(match model_type {
+            ModelType::Chat => 4096,
+            ModelType::Embedding => 384,
+        });
+
+        let mut builder = GgmlMetadataBuilder::new(self.name.clone(), alias, prompt_template)
+            .with_ctx_size(ctx_size)
+            .with_main_gpu(self.main_gpu)
+            .with_tensor_split(self.tensor_split.clone())
+            .enable_plugin_log(true)
+            .enable_debug_log(plugin_debug);
+        if let Some(batch_size) = self.batch_size {
+            builder = builder.with_batch_size(batch_size);
+        }
+        if let Some(threads) = self.threads {
+            builder = builder.with_threads(threads);
+        }
+
+        if model_type == ModelType::Embedding {
+            return Ok(builder.enable_embeddings(true).build());
+        }
+
+        let prompt_ratio = self.prompt_ratio.unwrap_or(0.8);
+        if !(prompt_ratio > 0.0 && prompt_ratio <= 1.0) {
+            return Err(ServerError::ArgumentError(format!(
+                "Invalid prompt ratio of the model '{}': {}. The value should be greater than 0.0 and no more than 1.0.",
+                self.name, prompt_ratio
+            )));
+        }
+        let context_truncation = ContextTruncation {
+            strategy: self.context_truncation.unwrap_or_default(),
+            last_turns: Some(self.last_turns.unwrap_or(1)),
+            prompt_ratio: Some(prompt_ratio),
+            ..Default::default()
+        };
+
+        builder = builder
+            .disable_mmap(self.no_mmap)
+            .with_grammar(self.grammar.clone().unwrap_or_default())
+            .with_json_schema(self.json_schema.clone())
+            .with_reverse_prompt(self.reverse_prompt.clone())
+            .with_infill_template(self.infill_template)
+            .with_context_truncation(context_truncation)
+            .with_mmproj(self.llava_mmproj.clone());
+        if let Some(n_predict) = self.n_predict {
+            builder = builder.with_n_predict(n_predict);
+        }
+        if let Some(n_gpu_layers) = self.n_gpu_layers {
+            builder = builder.with_n_gpu_layers(n_gpu_layers);
+        }
+        if let Some(temp) = self.temp {
+            builder = builder.with_temperature(temp);
+        }
+        if let Some(top_p) = self.top_p {
+            builder = builder.with_top_p(top_p);
+        }
+        if let Some(repeat_penalty) = self.repeat_penalty {
+            builder = builder.with_repeat_penalty(repeat_penalty);
+        }
+        if let Some(presence_penalty) = self.presence_penalty {
+            builder = builder.with_presence_penalty(presence_penalty);
+        }
+        if let Some(frequency_penalty) = self.frequency_penalty {
+            builder = builder.with_frequency_penalty(frequency_penalty);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+/// Sets the value of each model from the command line. The explicit values override the ones in the file, and the default values only fill the missing ones.
+fn merge<T: Clone>(
+    models: &mut [ModelSection],
+    values: &[T],
+    explicit: bool,
+    name: &str,
+    field: impl Fn(&mut ModelSection) -> &mut Option<T>,
+) -> Result<(), ServerError> {
+    if values.is_empty() || models.is_empty() {
+        return Ok(());
+    }
+
+    let values = per_model(values, models.len(), name)?;
+    for (model, value) in models.iter_mut().zip(values) {
+        let slot = field(model);
+        if explicit || slot.is_none() {
+            *slot = Some(value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the value for each of the `n_models` models. The last value is used for the models without a value.
+fn per_model<T: Clone>(values: &[T], n_models: usize, name: &str) -> Result<Vec<T>, ServerError> {
+    match values.last() {
+        Some(last) if values.len() <= n_models => {
+            let mut values = values.to_vec();
+            values.resize(n_models, last.clone());
+            Ok(values)
+        }
+        _ => Err(ServerError::ArgumentError(format!(
+            "Invalid setting for {}. Please specify one value for each model, or a single value for all models.",
+            name
+        ))),
+    }
+}
+
+/// Deserializes the value from a string in the same format as the command line, e.g., `llama-3-chat` for the prompt template.
+fn from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) => s.parse().map(Some).map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+fn parse_config(toml: &str, args: &[&str]) -> Result<Config, ServerError> {
+    use clap::{CommandFactory, FromArgMatches};
+
+    let matches = Cli::command()
+        .try_get_matches_from(["llama-api-server"].iter().chain(args))
+        .unwrap();
+    let cli = Cli::from_arg_matches(&matches).unwrap();
+
+    let mut config: Config = toml::from_str(toml).unwrap();
+    config.merge_cli(&cli, &matches, true)?;
+
+    Ok(config)
+}
+
+#[test]
+fn test_config_cli_overrides_file() {
+    let toml = r#"
+[server]
+socket_addr = "127.0.0.1:8000"
+
+[[models]]
+name = "llama-3-8b"
+prompt_template = "llama-3-chat"
+ctx_size = 2048
+temp = 0.5
+top_p = 0.9
+
+[[models]]
+name = "llama-3-70b"
+prompt_template = "llama-3-chat"
+"#;
+
+    let config = parse_config(
+        toml,
+        &["--config", "config.toml", "--temp", "0.2", "--port", "9000"],
+    )
+    .unwrap();
+
+    // the options given on the command line override the ones in the file
+    assert!(config.models.iter().all(|model| model.temp == Some(0.2)));
+    assert_eq!(config.server.socket_addr, None);
+    assert_eq!(config.server.port, Some(9000));
+
+    // the default values of the command line only fill the missing ones
+    assert_eq!(config.models[0].ctx_size, Some(2048));
+    assert_eq!(config.models[0].top_p, Some(0.9));
+    assert_eq!(config.models[1].top_p, Some(1.0));
+    assert_eq!(config.models[1].n_predict, Some(1024));
+    assert_eq!(config.server.max_queue_depth, Some(16));
+
+    // a value for each model, or a single value for all models
+    let config = parse_config(
+        toml,
+        &["--config", "config.toml", "--ctx-size", "1024,8192"],
+    )
+    .unwrap();
+    assert_eq!(config.models[0].ctx_size, Some(1024));
+    assert_eq!(config.models[1].ctx_size, Some(8192));
+
+    assert!(parse_config(
+        toml,
+        &["--config", "config.toml", "--ctx-size", "1024,2048,4096"],
+    )
+    .is_err());
+}
+
+#[test]
+fn test_config_validate() {
+    let validate = |toml: &str| match toml::from_str::<Config>(toml).unwrap().validate() {
+        Ok(()) => None,
+        Err(ServerError::ArgumentError(msg)) => Some(msg),
+        Err(e) => panic!("unexpected error: {}", e),
+    };
+
+    let model = r#"
+[[models]]
+name = "llama-3-8b"
+prompt_template = "llama-3-chat"
+"#;
+    assert_eq!(validate(model), None);
+
+    assert!(validate(&format!(
+        "[server]\nsocket_addr = \"127.0.0.1:8000\"\nport = 9000\n{}",
+        model
+    ))
+    .is_some_and(|msg| msg.contains("either `socket_addr` or `port`")));
+    assert!(validate("").is_some_and(|msg| msg.contains("No model is defined")));
+    assert!(validate("[[models]]\nname = \"\"")
+        .is_some_and(|msg| msg.contains("The name of the model #0 is empty")));
+    assert!(validate(&format!("{}{}", model, model))
+        .is_some_and(|msg| msg.contains("The model name 'llama-3-8b' is duplicated")));
+    assert!(validate(&format!(
+        "[server]\ndefault_model = [\"llama-3-70b\"]\n{}",
+        model
+    ))
+    .is_some_and(|msg| msg.contains("The default model 'llama-3-70b' is not defined")));
+    assert!(validate(&format!("[cors]\nallow_origins = []\n{}", model))
+        .is_some_and(|msg| msg.contains("`allow_origins` should not be empty")));
+
+    // the settings of each model are validated when the metadata is created
+    let metadata = |toml: &str| {
+        let config: Config = toml::from_str(toml).unwrap();
+        config.models[0]
+            .metadata(false)
+            .err()
+            .map(|e| e.to_string())
+    };
+    assert_eq!(metadata(model), None);
+    assert!(metadata("[[models]]\nname = \"llama-3-8b\"")
+        .is_some_and(|msg| msg.contains("requires a prompt template")));
+    assert!(metadata(&format!("{}prompt_ratio = 1.5", model))
+        .is_some_and(|msg| msg.contains("Invalid prompt ratio")));
+    assert!(metadata(
+        "[[models]]\nname = \"all-minilm\"\ntype = \"embedding\"\nprompt_template = \"llama-3-chat\""
+    )
+    .is_some_and(|msg| msg.contains("requires the `embedding` prompt template")));
+}
@@ -1,4 +1,5 @@
-use hyper::{Body, Response};
+use hyper::{header::HeaderValue, Body, Response, StatusCode};
+use llama_core::LlamaCoreError;
 use thiserror::Error;
 
 #[allow(dead_code)]
@@ -6,69 +7,215 @@ pub(crate) fn not_implemented() -> Response<Body> {
     // log error
     error!(target: "stdout", "501 Not Implemented");
 
-    Response::builder()
-        .header("Access-Control-Allow-Origin", "*")
-        .header("Access-Control-Allow-Methods", "*")
-        .header("Access-Control-Allow-Headers", "*")
-        .status(hyper::StatusCode::NOT_IMPLEMENTED)
-        .body(Body::from("501 Not Implemented"))
-        .unwrap()
+    error_response(
+        StatusCode::NOT_IMPLEMENTED,
+        "Not Implemented",
+        "invalid_request_error",
+        None,
+        None,
+    )
 }
 
 pub(crate) fn internal_server_error(msg: impl AsRef<str>) -> Response<Body> {
     let err_msg = match msg.as_ref().is_empty() {
-        true => "500 Internal Server Error".to_string(),
-        false => format!("500 Internal Server Error: {}", msg.as_ref()),
+        true => "Internal Server Error",
+        false => msg.as_ref(),
     };
 
     // log error
-    error!(target: "stdout", "{}", &err_msg);
+    error!(target: "stdout", "500 Internal Server Error: {}", err_msg);
 
-    Response::builder()
-        .header("Access-Control-Allow-Origin", "*")
-        .header("Access-Control-Allow-Methods", "*")
-        .header("Access-Control-Allow-Headers", "*")
-        .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
-        .body(Body::from(err_msg))
-        .unwrap()
+    error_response(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        err_msg,
+        "server_error",
+        None,
+        None,
+    )
 }
 
 pub(crate) fn bad_request(msg: impl AsRef<str>) -> Response<Body> {
     let err_msg = match msg.as_ref().is_empty() {
-        true => "400 Bad Request".to_string(),
-        false => format!("400 Bad Request: {}", msg.as_ref()),
+        true => "Bad Request",
+        false => msg.as_ref(),
     };
 
     // log error
-    error!(target: "stdout", "{}", &err_msg);
+    error!(target: "stdout", "400 Bad Request: {}", err_msg);
 
-    Response::builder()
-        .header("Access-Control-Allow-Origin", "*")
-        .header("Access-Control-Allow-Methods", "*")
-        .header("Access-Control-Allow-Headers", "*")
-        .status(hyper::StatusCode::BAD_REQUEST)
-        .body(Body::from(err_msg))
-        .unwrap()
+    error_response(
+        StatusCode::BAD_REQUEST,
+        err_msg,
+        "invalid_request_error",
+        None,
+        None,
+    )
 }
 
 pub(crate) fn invalid_endpoint(msg: impl AsRef<str>) -> Response<Body> {
     let err_msg = match msg.as_ref().is_empty() {
-        true => "404 The requested service endpoint is not found".to_string(),
+        true => "The requested service endpoint is not found".to_string(),
         false => format!(
-            "404 The requested service endpoint is not found: {}",
+            "The requested service endpoint is not found: {}",
             msg.as_ref()
         ),
     };
 
     // log error
-    error!(target: "stdout", "{}", &err_msg);
+    error!(target: "stdout", "404 {}", &err_msg);
+
+    error_response(
+        StatusCode::NOT_FOUND,
+        err_msg,
+        "invalid_request_error",
+        None,
+        None,
+    )
+}
+
+pub(crate) fn not_found(msg: impl AsRef<str>) -> Response<Body> {
+    let err_msg = match msg.as_ref().is_empty() {
+        true => "Not Found",
+        false => msg.as_ref(),
+    };
+
+    // log error
+    error!(target: "stdout", "404 Not Found: {}", err_msg);
+
+    error_response(
+        StatusCode::NOT_FOUND,
+        err_msg,
+        "invalid_request_error",
+        None,
+        None,
+    )
+}
+
+pub(crate) fn model_not_found(model: impl AsRef<str>) -> Response<Body> {
+    let err_msg = format!("The model `{}` does not exist.", model.as_ref());
+
+    // log error
+    error!(target: "stdout", "404 Not Found: {}", &err_msg);
+
+    error_response(
+        StatusCode::NOT_FOUND,
+        err_msg,
+        "invalid_request_error",
+        Some("model"),
+        Some("model_not_found"),
+    )
+}
+
+pub(crate) fn service_unavailable(msg: impl AsRef<str>, retry_after: u64) -> Response<Body> {
+    let err_msg = match msg.as_ref().is_empty() {
+        true => "Service Unavailable",
+        false => msg.as_ref(),
+    };
+
+    // log error
+    error!(target: "stdout", "503 Service Unavailable: {}", err_msg);
+
+    let mut response = error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        err_msg,
+        "server_error",
+        None,
+        Some("model_busy"),
+    );
+    response
+        .headers_mut()
+        .insert("Retry-After", HeaderValue::from(retry_after));
+
+    response
+}
+
+/// Turns the error returned by llama-core into the response with the status code and the error object of the OpenAI API.
+pub(crate) fn llama_core_error(e: LlamaCoreError) -> Response<Body> {
+    match e {
+        LlamaCoreError::InvalidRequest { message, param } => {
+            // log error
+            error!(target: "stdout", "400 Bad Request: {}", &message);
+
+            error_response(
+                StatusCode::BAD_REQUEST,
+                message,
+                "invalid_request_error",
+                param.as_deref(),
+                None,
+            )
+        }
+        e @ LlamaCoreError::ContextLengthExceeded { .. } => {
+            let err_msg = e.to_string();
+
+            // log error
+            error!(target: "stdout", "400 Bad Request: {}", &err_msg);
+
+            error_response(
+                StatusCode::BAD_REQUEST,
+                err_msg,
+                "invalid_request_error",
+                Some("messages"),
+                Some("context_length_exceeded"),
+            )
+        }
+        LlamaCoreError::ModelNotFound(model) => model_not_found(model),
+        e @ LlamaCoreError::FileNotFound => {
+            let err_msg = e.to_string();
+
+            // log error
+            error!(target: "stdout", "404 Not Found: {}", &err_msg);
+
+            error_response(
+                StatusCode::NOT_FOUND,
+                err_msg,
+                "invalid_request_error",
+                None,
+                Some("file_not_found"),
+            )
+        }
+        LlamaCoreError::Scheduler(e) => service_unavailable(e.to_string(), e.retry_after()),
+        LlamaCoreError::Backend(e) => {
+            let err_msg = e.to_string();
+
+            // log error
+            error!(target: "stdout", "500 Internal Server Error: {}", &err_msg);
+
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                err_msg,
+                "server_error",
+                None,
+                Some("backend_failure"),
+            )
+        }
+        e => internal_server_error(e.to_string()),
+    }
+}
+
+/// Builds the response with the error object of the OpenAI API, i.e., `{"error": {"message", "type", "param", "code"}}`.
+fn error_response(
+    status: StatusCode,
+    message: impl AsRef<str>,
+    ty: &str,
+    param: Option<&str>,
+    code: Option<&str>,
+) -> Response<Body> {
+    let body = serde_json::json!({
+        "error": {
+            "message": message.as_ref(),
+            "type": ty,
+            "param": param,
+            "code": code,
+        }
+    });
 
     Response::builder()
         .header("Access-Control-Allow-Origin", "*")
         .header("Access-Control-Allow-Methods", "*")
         .header("Access-Control-Allow-Headers", "*")
-        .status(hyper::StatusCode::NOT_FOUND)
-        .body(Body::from(err_msg))
+        .header("Content-Type", "application/json")
+        .status(status)
+        .body(Body::from(body.to_string()))
         .unwrap()
 }
 
@@ -2,12 +2,15 @@
 extern crate log;
 
 mod backend;
+mod config;
 mod error;
 mod utils;
 
 use anyhow::Result;
-use chat_prompts::PromptTemplateType;
-use clap::{ArgGroup, Parser};
+use chat_prompts::{infill::InfillTemplateType, PromptTemplateType};
+use clap::{ArgGroup, CommandFactory, FromArgMatches, Parser};
+use config::Config;
+use endpoints::chat::TruncationStrategy;
 use error::ServerError;
 use hyper::{
     body::HttpBody,
@@ -16,10 +19,10 @@ use hyper::{
     service::{make_service_fn, service_fn},
     Body, Request, Response, Server, StatusCode,
 };
-use llama_core::metadata::ggml::GgmlMetadataBuilder;
+use llama_core::{metadata::ggml::GgmlMetadata, scheduler::SchedulerConfig};
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, net::SocketAddr, path::PathBuf};
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
 use utils::LogLevel;
 
@@ -35,10 +38,10 @@ const DEFAULT_PORT: &str = "8080";
 #[command(name = "LlamaEdge API Server", version = env!("CARGO_PKG_VERSION"), author = env!("CARGO_PKG_AUTHORS"), about = "LlamaEdge API Server")]
 #[command(group = ArgGroup::new("socket_address_group").multiple(false).args(&["socket_addr", "port"]))]
 struct Cli {
-    /// Sets names for chat and/or embedding models. To run both chat and embedding models, the names should be separated by comma without space, for example, '--model-name Llama-2-7b,all-minilm'. The first value is for the chat model, and the second is for the embedding model.
+    /// Sets names for chat and/or embedding models. To run multiple models, the names should be separated by comma without space, for example, '--model-name Llama-3-8b,CodeLlama-7b,all-minilm'. Each model is a chat or embedding model according to its prompt template.
     #[arg(short, long, value_delimiter = ',', default_value = "default")]
     model_name: Vec<String>,
-    /// Model aliases for chat and embedding models
+    /// Model aliases for chat and embedding models, which are the names of the models preloaded by `--nn-preload`. The aliases should be in the same order as the model names.
     #[arg(
         short = 'a',
         long,
@@ -46,27 +49,24 @@ struct Cli {
         default_value = "default,embedding"
     )]
     model_alias: Vec<String>,
-    /// Sets context sizes for chat and/or embedding models. To run both chat and embedding models, the sizes should be separated by comma without space, for example, '--ctx-size 4096,384'. The first value is for the chat model, and the second is for the embedding model.
-    #[arg(
-        short = 'c',
-        long,
-        value_delimiter = ',',
-        default_value = "4096,384",
-        value_parser = clap::value_parser!(u64)
-    )]
+    /// Sets context sizes for chat and/or embedding models. To run multiple models, the sizes should be separated by comma without space, for example, '--ctx-size 4096,16384,384'. The sizes are in the same order as the model names, and the last size is used for the rest of the models. Defaults to 4096 for chat models and 384 for embedding models.
+    #[arg(short = 'c', long, value_delimiter = ',', value_parser = clap::value_parser!(u64))]
     ctx_size: Vec<u64>,
-    /// Sets batch sizes for chat and/or embedding models. To run both chat and embedding models, the sizes should be separated by comma without space, for example, '--batch-size 128,64'. The first value is for the chat model, and the second is for the embedding model.
-    #[arg(short, long, value_delimiter = ',', default_value = "512,512", value_parser = clap::value_parser!(u64))]
+    /// Sets batch sizes for chat and/or embedding models. To run multiple models, the sizes should be separated by comma without space, for example, '--batch-size 128,64'. The sizes are in the same order as the model names, and the last size is used for the rest of the models.
+    #[arg(short, long, value_delimiter = ',', default_value = "512", value_parser = clap::value_parser!(u64))]
     batch_size: Vec<u64>,
-    /// Sets prompt templates for chat and/or embedding models, respectively. To run both chat and embedding models, the prompt templates should be separated by comma without space, for example, '--prompt-template llama-2-chat,embedding'. The first value is for the chat model, and the second is for the embedding model.
-    #[arg(short, long, value_delimiter = ',', value_parser = clap::value_parser!(PromptTemplateType), required = true)]
+    /// Sets prompt templates for chat and/or embedding models. To run multiple models, the prompt templates should be separated by comma without space, for example, '--prompt-template llama-3-chat,codellama-instruct,embedding'. The prompt templates are in the same order as the model names. The models with the `embedding` prompt template are embedding models, and the others are chat models.
+    #[arg(short, long, value_delimiter = ',', value_parser = clap::value_parser!(PromptTemplateType), required_unless_present = "config")]
     prompt_template: Vec<PromptTemplateType>,
+    /// Sets infill templates for the chat models trained to fill in the middle of the code. Like `--n-predict`, the values for multiple models are separated by comma without space. Defaults to the infill template matching the prompt template, if any.
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(InfillTemplateType))]
+    infill_template: Vec<InfillTemplateType>,
     /// Halt generation at PROMPT, return control.
     #[arg(short, long)]
     reverse_prompt: Option<String>,
-    /// Number of tokens to predict
-    #[arg(short, long, default_value = "1024")]
-    n_predict: u64,
+    /// Number of tokens to predict. To run multiple models, the values should be separated by comma without space, in the same order as the model names. The last value is used for the rest of the models.
+    #[arg(short, long, value_delimiter = ',', default_value = "1024")]
+    n_predict: Vec<u64>,
     /// Number of layers to run on the GPU
     #[arg(short = 'g', long, default_value = "100")]
     n_gpu_layers: u64,
@@ -82,21 +82,21 @@ struct Cli {
     /// Disable memory mapping for file access of chat models
     #[arg(long)]
     no_mmap: Option<bool>,
-    /// Temperature for sampling
-    #[arg(long, default_value = "1.0")]
-    temp: f64,
-    /// An alternative to sampling with temperature, called nucleus sampling, where the model considers the results of the tokens with top_p probability mass. 1.0 = disabled
-    #[arg(long, default_value = "1.0")]
-    top_p: f64,
-    /// Penalize repeat sequence of tokens
-    #[arg(long, default_value = "1.1")]
-    repeat_penalty: f64,
-    /// Repeat alpha presence penalty. 0.0 = disabled
-    #[arg(long, default_value = "0.0")]
-    presence_penalty: f64,
-    /// Repeat alpha frequency penalty. 0.0 = disabled
-    #[arg(long, default_value = "0.0")]
-    frequency_penalty: f64,
+    /// Temperature for sampling. Like `--n-predict`, the values for multiple models are separated by comma without space.
+    #[arg(long, value_delimiter = ',', default_value = "1.0")]
+    temp: Vec<f64>,
+    /// An alternative to sampling with temperature, called nucleus sampling, where the model considers the results of the tokens with top_p probability mass. 1.0 = disabled. Like `--n-predict`, the values for multiple models are separated by comma without space.
+    #[arg(long, value_delimiter = ',', default_value = "1.0")]
+    top_p: Vec<f64>,
+    /// Penalize repeat sequence of tokens. Like `--n-predict`, the values for multiple models are separated by comma without space.
+    #[arg(long, value_delimiter = ',', default_value = "1.1")]
+    repeat_penalty: Vec<f64>,
+    /// Repeat alpha presence penalty. 0.0 = disabled. Like `--n-predict`, the values for multiple models are separated by comma without space.
+    #[arg(long, value_delimiter = ',', default_value = "0.0")]
+    presence_penalty: Vec<f64>,
+    /// Repeat alpha frequency penalty. 0.0 = disabled. Like `--n-predict`, the values for multiple models are separated by comma without space.
+    #[arg(long, value_delimiter = ',', default_value = "0.0")]
+    frequency_penalty: Vec<f64>,
     /// BNF-like grammar to constrain generations (see samples in grammars/ dir).
     #[arg(long, default_value = "")]
     pub grammar: String,
@@ -106,6 +106,24 @@ struct Cli {
     /// Path to the multimodal projector file
     #[arg(long)]
     llava_mmproj: Option<String>,
+    /// Strategy to truncate the chat history when the prompt exceeds the budget of prompt tokens. Possible values: drop-oldest, keep-last-turns, truncate-middle, summarize.
+    #[arg(long, default_value = "drop-oldest", value_parser = clap::value_parser!(TruncationStrategy))]
+    context_truncation: TruncationStrategy,
+    /// Number of the latest turns kept by the `keep-last-turns` truncation strategy
+    #[arg(long, default_value = "1")]
+    last_turns: usize,
+    /// Ratio of the context size for the prompt, between 0.0 and 1.0. The rest is left for the completion.
+    #[arg(long, default_value = "0.8")]
+    prompt_ratio: f64,
+    /// Maximum number of requests waiting in the queue of a chat model. The requests beyond the limit are rejected with `503 Service Unavailable`.
+    #[arg(long, default_value = "16")]
+    max_queue_depth: usize,
+    /// Maximum time in seconds that a request waits in the queue of a chat model. Zero means no timeout.
+    #[arg(long, default_value = "120")]
+    queue_timeout: u64,
+    /// Names of the default models, which serve the requests without a model or for an unknown model, for example, '--default-model Llama-3-8b,all-minilm'. By default, the requests for an unknown model are rejected with `404 Not Found`.
+    #[arg(long, value_delimiter = ',')]
+    default_model: Vec<String>,
     /// Socket address of LlamaEdge API Server instance. For example, `0.0.0.0:8080`.
     #[arg(long, default_value = None, value_parser = clap::value_parser!(SocketAddr), group = "socket_address_group")]
     socket_addr: Option<SocketAddr>,
@@ -115,6 +133,9 @@ struct Cli {
     /// Root path for the Web UI files
     #[arg(long, default_value = "chatbot-ui")]
     web_ui: PathBuf,
+    /// Path to the config file in TOML or YAML format. The options given on the command line override the ones in the file.
+    #[arg(long)]
+    config: Option<PathBuf>,
     /// Deprecated. Print prompt strings to stdout
     #[arg(long)]
     log_prompts: bool,
@@ -129,11 +150,9 @@ struct Cli {
 #[allow(clippy::needless_return)]
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), ServerError> {
-    let mut plugin_debug = false;
-
     // get the environment variable `RUST_LOG`
     let rust_log = std::env::var("RUST_LOG").unwrap_or_default().to_lowercase();
-    let (_, log_level) = match rust_log.is_empty() {
+    let (_, mut log_level) = match rust_log.is_empty() {
         true => ("stdout", LogLevel::Info),
         false => match rust_log.split_once("=") {
             Some((target, level)) => (target, level.parse().unwrap_or(LogLevel::Info)),
@@ -141,310 +160,100 @@ async fn main() -> Result<(), ServerError> {
         },
     };
 
-    if log_level == LogLevel::Debug || log_level == LogLevel::Trace {
-        plugin_debug = true;
+    // parse the command line arguments
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // load the config file, which is overridden by the command line arguments
+    let mut config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    config.merge_cli(&cli, &matches, cli.config.is_some())?;
+    config.validate()?;
+
+    // the log level in the config file is overridden by `RUST_LOG`
+    if rust_log.is_empty() {
+        if let Some(level) = config.logging.level {
+            log_level = level;
+        }
     }
+    let plugin_debug = log_level == LogLevel::Debug || log_level == LogLevel::Trace;
 
     // set global logger
     wasi_logger::Logger::install().expect("failed to install wasi_logger::Logger");
     log::set_max_level(log_level.into());
 
-    // parse the command line arguments
-    let cli = Cli::parse();
-
     // log the version of the server
     info!(target: "stdout", "server version: {}", env!("CARGO_PKG_VERSION"));
 
-    // log model names
-    if cli.model_name.is_empty() && cli.model_name.len() > 2 {
-        return Err(ServerError::ArgumentError(
-            "Invalid setting for model name. For running chat or embedding model, please specify a single model name. For running both chat and embedding models, please specify two model names: the first one for chat model, the other for embedding model.".to_owned(),
-        ));
-    }
-    info!(target: "stdout", "model_name: {}", cli.model_name.join(",").to_string());
-
-    // log model alias
-    let mut model_alias = String::new();
-    if cli.model_name.len() == 1 {
-        model_alias.clone_from(&cli.model_alias[0]);
-    } else if cli.model_alias.len() == 2 {
-        model_alias = cli.model_alias.join(",").to_string();
-    }
-    info!(target: "stdout", "model_alias: {}", model_alias);
-
-    // log context size
-    if cli.ctx_size.is_empty() && cli.ctx_size.len() > 2 {
-        return Err(ServerError::ArgumentError(
-            "Invalid setting for context size. For running chat or embedding model, please specify a single context size. For running both chat and embedding models, please specify two context sizes: the first one for chat model, the other for embedding model.".to_owned(),
-        ));
-    }
-    let mut ctx_sizes_str = String::new();
-    if cli.model_name.len() == 1 {
-        ctx_sizes_str = cli.ctx_size[0].to_string();
-    } else if cli.model_name.len() == 2 {
-        ctx_sizes_str = cli
-            .ctx_size
-            .iter()
-            .map(|n| n.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
-    }
-    info!(target: "stdout", "ctx_size: {}", ctx_sizes_str);
-
-    // log batch size
-    if cli.batch_size.is_empty() && cli.batch_size.len() > 2 {
-        return Err(ServerError::ArgumentError(
-            "Invalid setting for batch size. For running chat or embedding model, please specify a single batch size. For running both chat and embedding models, please specify two batch sizes: the first one for chat model, the other for embedding model.".to_owned(),
-        ));
+    // log the config file
+    if let Some(path) = &cli.config {
+        info!(target: "stdout", "config: {}", path.display());
     }
-    let mut batch_sizes_str = String::new();
-    if cli.model_name.len() == 1 {
-        batch_sizes_str = cli.batch_size[0].to_string();
-    } else if cli.model_name.len() == 2 {
-        batch_sizes_str = cli
-            .batch_size
-            .iter()
-            .map(|n| n.to_string())
-            .collect::<Vec<String>>()
-            .join(",");
-    }
-    info!(target: "stdout", "batch_size: {}", batch_sizes_str);
-
-    // log prompt template
-    if cli.prompt_template.is_empty() && cli.prompt_template.len() > 2 {
-        return Err(ServerError::ArgumentError(
-            "LlamaEdge API server requires prompt templates. For running chat or embedding model, please specify a single prompt template. For running both chat and embedding models, please specify two prompt templates: the first one for chat model, the other for embedding model.".to_owned(),
-        ));
-    }
-    let prompt_template_str: String = cli
-        .prompt_template
-        .iter()
-        .map(|n| n.to_string())
-        .collect::<Vec<String>>()
-        .join(",");
-    info!(target: "stdout", "prompt_template: {}", prompt_template_str);
-    if cli.model_name.len() != cli.prompt_template.len() {
-        return Err(ServerError::ArgumentError(
-            "The number of model names and prompt templates must be the same.".to_owned(),
-        ));
-    }
-
-    // log reverse prompt
-    if let Some(reverse_prompt) = &cli.reverse_prompt {
-        info!(target: "stdout", "reverse_prompt: {}", reverse_prompt);
-    }
-
-    // log n_predict
-    info!(target: "stdout", "n_predict: {}", cli.n_predict);
-
-    // log n_gpu_layers
-    info!(target: "stdout", "n_gpu_layers: {}", cli.n_gpu_layers);
-
-    // log main_gpu
-    if let Some(main_gpu) = &cli.main_gpu {
-        info!(target: "stdout", "main_gpu: {}", main_gpu);
-    }
-
-    // log tensor_split
-    if let Some(tensor_split) = &cli.tensor_split {
-        info!(target: "stdout", "tensor_split: {}", tensor_split);
-    }
-
-    // log threads
-    info!(target: "stdout", "threads: {}", cli.threads);
-
-    // log no_mmap
-    if let Some(no_mmap) = &cli.no_mmap {
-        info!(target: "stdout", "no_mmap: {}", no_mmap);
-    }
-
-    // log temperature
-    info!(target: "stdout", "temp: {}", cli.temp);
-
-    // log top-p sampling
-    info!(target: "stdout", "top_p: {}", cli.top_p);
-
-    // repeat penalty
-    info!(target: "stdout", "repeat_penalty: {}", cli.repeat_penalty);
 
-    // log presence penalty
-    info!(target: "stdout", "presence_penalty: {}", cli.presence_penalty);
-
-    // log frequency penalty
-    info!(target: "stdout", "frequency_penalty: {}", cli.frequency_penalty);
+    // log and initialize the request scheduler
+    let max_queue_depth = config.server.max_queue_depth.unwrap_or(cli.max_queue_depth);
+    let queue_timeout = config.server.queue_timeout.unwrap_or(cli.queue_timeout);
+    info!(target: "stdout", "max_queue_depth: {}", max_queue_depth);
+    info!(target: "stdout", "queue_timeout: {}", queue_timeout);
+    let scheduler_config = SchedulerConfig {
+        max_queue_depth,
+        queue_timeout: match queue_timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
+    };
+    llama_core::scheduler::init_scheduler(scheduler_config)
+        .map_err(|e| ServerError::Operation(format!("{}", e)))?;
+
+    // create the metadata of the models
+    let mut metadata_chats = vec![];
+    let mut metadata_embeddings = vec![];
+    let mut models_from_files = vec![];
+    for model in config.models.iter() {
+        let metadata = model.metadata(plugin_debug)?;
+
+        // log the model settings
+        let settings = serde_json::to_string(&metadata).unwrap_or_default();
+        match metadata.embeddings {
+            true => info!(target: "stdout", "embedding model: {}", settings),
+            false => info!(target: "stdout", "chat model: {}", settings),
+        }
 
-    // log grammar
-    if !cli.grammar.is_empty() {
-        info!(target: "stdout", "grammar: {}", &cli.grammar);
+        match (&model.path, metadata.embeddings) {
+            (Some(path), _) => models_from_files.push((metadata, path.clone())),
+            (None, true) => metadata_embeddings.push(metadata),
+            (None, false) => metadata_chats.push(metadata),
+        }
     }
 
-    // log json schema
-    if let Some(json_schema) = &cli.json_schema {
-        info!(target: "stdout", "json_schema: {}", json_schema);
+    // initialize the core context with the preloaded models
+    if !metadata_chats.is_empty() || !metadata_embeddings.is_empty() {
+        let metadata_chats = match metadata_chats.is_empty() {
+            true => None,
+            false => Some(metadata_chats.as_slice()),
+        };
+        let metadata_embeddings = match metadata_embeddings.is_empty() {
+            true => None,
+            false => Some(metadata_embeddings.as_slice()),
+        };
+        llama_core::init_ggml_context(metadata_chats, metadata_embeddings)
+            .map_err(|e| ServerError::Operation(format!("{}", e)))?;
     }
 
-    // log multimodal projector
-    if let Some(llava_mmproj) = &cli.llava_mmproj {
-        info!(target: "stdout", "llava_mmproj: {}", llava_mmproj);
+    // load the models from the files
+    for (metadata, path) in models_from_files {
+        llama_core::models::load_model(metadata, Some(&path))
+            .await
+            .map_err(|e| ServerError::Operation(format!("{}", e)))?;
     }
 
-    // initialize the core context
-    let mut chat_model_config = None;
-    let mut embedding_model_config = None;
-    if cli.prompt_template.len() == 1 {
-        match cli.prompt_template[0] {
-            PromptTemplateType::Embedding => {
-                // create a Metadata instance
-                let metadata_embedding = GgmlMetadataBuilder::new(
-                    cli.model_name[0].clone(),
-                    cli.model_alias[0].clone(),
-                    cli.prompt_template[0],
-                )
-                .with_ctx_size(cli.ctx_size[0])
-                .with_batch_size(cli.batch_size[0])
-                .with_main_gpu(cli.main_gpu)
-                .with_tensor_split(cli.tensor_split)
-                .with_threads(cli.threads)
-                .enable_plugin_log(true)
-                .enable_debug_log(plugin_debug)
-                .build();
-
-                // set the embedding model config
-                embedding_model_config = Some(ModelConfig {
-                    name: metadata_embedding.model_name.clone(),
-                    ty: "embedding".to_string(),
-                    ctx_size: metadata_embedding.ctx_size,
-                    batch_size: metadata_embedding.batch_size,
-                    ..Default::default()
-                });
-
-                // initialize the core context
-                llama_core::init_ggml_context(None, Some(&[metadata_embedding]))
-                    .map_err(|e| ServerError::Operation(format!("{}", e)))?;
-            }
-            _ => {
-                // create a Metadata instance
-                let metadata_chat = GgmlMetadataBuilder::new(
-                    cli.model_name[0].clone(),
-                    cli.model_alias[0].clone(),
-                    cli.prompt_template[0],
-                )
-                .with_ctx_size(cli.ctx_size[0])
-                .with_batch_size(cli.batch_size[0])
-                .with_n_predict(cli.n_predict)
-                .with_n_gpu_layers(cli.n_gpu_layers)
-                .with_main_gpu(cli.main_gpu)
-                .with_tensor_split(cli.tensor_split)
-                .with_threads(cli.threads)
-                .disable_mmap(cli.no_mmap)
-                .with_temperature(cli.temp)
-                .with_top_p(cli.top_p)
-                .with_repeat_penalty(cli.repeat_penalty)
-                .with_presence_penalty(cli.presence_penalty)
-                .with_frequency_penalty(cli.frequency_penalty)
-                .with_grammar(cli.grammar)
-                .with_json_schema(cli.json_schema)
-                .with_reverse_prompt(cli.reverse_prompt)
-                .with_mmproj(cli.llava_mmproj.clone())
-                .enable_plugin_log(true)
-                .enable_debug_log(plugin_debug)
-                .build();
-
-                // set the chat model config
-                chat_model_config = Some(ModelConfig {
-                    name: metadata_chat.model_name.clone(),
-                    ty: "chat".to_string(),
-                    ctx_size: metadata_chat.ctx_size,
-                    batch_size: metadata_chat.batch_size,
-                    prompt_template: Some(metadata_chat.prompt_template),
-                    n_predict: Some(metadata_chat.n_predict),
-                    reverse_prompt: metadata_chat.reverse_prompt.clone(),
-                    n_gpu_layers: Some(metadata_chat.n_gpu_layers),
-                    use_mmap: metadata_chat.use_mmap,
-                    temperature: Some(metadata_chat.temperature),
-                    top_p: Some(metadata_chat.top_p),
-                    repeat_penalty: Some(metadata_chat.repeat_penalty),
-                    presence_penalty: Some(metadata_chat.presence_penalty),
-                    frequency_penalty: Some(metadata_chat.frequency_penalty),
-                });
-
-                // initialize the core context
-                llama_core::init_ggml_context(Some(&[metadata_chat]), None)
-                    .map_err(|e| ServerError::Operation(format!("{}", e)))?;
-            }
-        }
-    } else if cli.prompt_template.len() == 2 {
-        // create a Metadata instance
-        let metadata_chat = GgmlMetadataBuilder::new(
-            cli.model_name[0].clone(),
-            cli.model_alias[0].clone(),
-            cli.prompt_template[0],
-        )
-        .with_ctx_size(cli.ctx_size[0])
-        .with_batch_size(cli.batch_size[0])
-        .with_n_predict(cli.n_predict)
-        .with_n_gpu_layers(cli.n_gpu_layers)
-        .with_main_gpu(cli.main_gpu)
-        .with_tensor_split(cli.tensor_split.clone())
-        .with_threads(cli.threads)
-        .disable_mmap(cli.no_mmap)
-        .with_temperature(cli.temp)
-        .with_top_p(cli.top_p)
-        .with_repeat_penalty(cli.repeat_penalty)
-        .with_presence_penalty(cli.presence_penalty)
-        .with_frequency_penalty(cli.frequency_penalty)
-        .with_grammar(cli.grammar)
-        .with_json_schema(cli.json_schema)
-        .with_reverse_prompt(cli.reverse_prompt)
-        .with_mmproj(cli.llava_mmproj.clone())
-        .enable_plugin_log(true)
-        .enable_debug_log(plugin_debug)
-        .build();
-
-        // set the chat model config
-        chat_model_config = Some(ModelConfig {
-            name: metadata_chat.model_name.clone(),
-            ty: "chat".to_string(),
-            ctx_size: metadata_chat.ctx_size,
-            batch_size: metadata_chat.batch_size,
-            prompt_template: Some(metadata_chat.prompt_template),
-            n_predict: Some(metadata_chat.n_predict),
-            reverse_prompt: metadata_chat.reverse_prompt.clone(),
-            n_gpu_layers: Some(metadata_chat.n_gpu_layers),
-            use_mmap: metadata_chat.use_mmap,
-            temperature: Some(metadata_chat.temperature),
-            top_p: Some(metadata_chat.top_p),
-            repeat_penalty: Some(metadata_chat.repeat_penalty),
-            presence_penalty: Some(metadata_chat.presence_penalty),
-            frequency_penalty: Some(metadata_chat.frequency_penalty),
-        });
-
-        // create a Metadata instance
-        let metadata_embedding = GgmlMetadataBuilder::new(
-            cli.model_name[1].clone(),
-            cli.model_alias[1].clone(),
-            cli.prompt_template[1],
-        )
-        .with_ctx_size(cli.ctx_size[1])
-        .with_batch_size(cli.batch_size[1])
-        .with_main_gpu(cli.main_gpu)
-        .with_tensor_split(cli.tensor_split)
-        .with_threads(cli.threads)
-        .enable_plugin_log(true)
-        .enable_debug_log(plugin_debug)
-        .build();
-
-        // set the embedding model config
-        embedding_model_config = Some(ModelConfig {
-            name: metadata_embedding.model_name.clone(),
-            ty: "embedding".to_string(),
-            ctx_size: metadata_embedding.ctx_size,
-            batch_size: metadata_embedding.batch_size,
-            ..Default::default()
-        });
+    // log and set the default models
+    if let Some(default_model) = &config.server.default_model {
+        info!(target: "stdout", "default_model: {}", default_model.join(","));
 
-        // initialize the core context
-        llama_core::init_ggml_context(Some(&[metadata_chat]), Some(&[metadata_embedding]))
+        llama_core::models::set_default_models(default_model.clone())
             .map_err(|e| ServerError::Operation(format!("{}", e)))?;
     }
 
@@ -459,10 +268,7 @@ async fn main() -> Result<(), ServerError> {
     info!(target: "stdout", "plugin_ggml_version: {}", plugin_version);
 
     // socket address
-    let addr = match cli.socket_addr {
-        Some(addr) => addr,
-        None => SocketAddr::from(([0, 0, 0, 0], cli.port)),
-    };
+    let addr = config.server.addr();
     let port = addr.port().to_string();
 
     // get the environment variable `NODE_VERSION`
@@ -482,22 +288,36 @@ async fn main() -> Result<(), ServerError> {
             plugin_version,
             port,
         },
-        chat_model: chat_model_config,
-        embedding_model: embedding_model_config,
+        chat_models: vec![],
+        embedding_models: vec![],
         extras: HashMap::new(),
     };
     SERVER_INFO
         .set(server_info)
         .map_err(|_| ServerError::Operation("Failed to set `SERVER_INFO`.".to_string()))?;
 
+    let web_ui = match &config.server.web_ui {
+        Some(web_ui) => web_ui.to_string_lossy().to_string(),
+        None => cli.web_ui.to_string_lossy().to_string(),
+    };
+    let allow_origins = Arc::new(config.cors.allow_origins);
+    info!(target: "stdout", "allow_origins: {}", allow_origins.join(","));
+
     let new_service = make_service_fn(move |conn: &AddrStream| {
         // log socket address
         info!(target: "stdout", "remote_addr: {}, local_addr: {}", conn.remote_addr().to_string(), conn.local_addr().to_string());
 
         // web ui
-        let web_ui = cli.web_ui.to_string_lossy().to_string();
+        let web_ui = web_ui.clone();
 
-        async move { Ok::<_, Error>(service_fn(move |req| handle_request(req, web_ui.clone()))) }
+        // origins allowed by CORS
+        let allow_origins = allow_origins.clone();
+
+        async move {
+            Ok::<_, Error>(service_fn(move |req| {
+                handle_request(req, web_ui.clone(), allow_origins.clone())
+            }))
+        }
     });
 
     let tcp_listener = TcpListener::bind(addr).await.unwrap();
@@ -516,6 +336,7 @@ async fn main() -> Result<(), ServerError> {
 async fn handle_request(
     req: Request<Body>,
     web_ui: String,
+    allow_origins: Arc<Vec<String>>,
 ) -> Result<Response<Body>, hyper::Error> {
     let path_str = req.uri().path();
     let path_buf = PathBuf::from(path_str);
@@ -543,12 +364,34 @@ async fn handle_request(
         }
     }
 
-    let response = match root_path.as_str() {
+    let origin = req.headers().get(header::ORIGIN).cloned();
+
+    let mut response = match root_path.as_str() {
         "/echo" => Response::new(Body::from("echo test")),
         "/v1" => backend::handle_llama_request(req).await,
+        // the path used by the clients of llama.cpp server, e.g. the editor plugins
+        "/infill" => backend::ggml::infill_handler(req).await,
         _ => static_response(path_str, web_ui),
     };
 
+    // restrict the origins allowed by CORS
+    if !allow_origins.iter().any(|allowed| allowed == "*") {
+        let headers = response.headers_mut();
+        match origin {
+            Some(origin)
+                if allow_origins
+                    .iter()
+                    .any(|allowed| origin == allowed.as_str()) =>
+            {
+                headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+                headers.insert(header::VARY, header::HeaderValue::from_static("Origin"));
+            }
+            _ => {
+                headers.remove(header::ACCESS_CONTROL_ALLOW_ORIGIN);
+            }
+        }
+    }
+
     // log response
     {
         let status_code = response.status();
@@ -611,21 +454,22 @@ pub struct AppState {
     pub state_thing: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct ServerInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "node_version")]
     node: Option<String>,
     #[serde(rename = "api_server")]
     server: ApiServer,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    chat_model: Option<ModelConfig>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    embedding_model: Option<ModelConfig>,
+    // the models loaded when the server info is requested
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    chat_models: Vec<ModelConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    embedding_models: Vec<ModelConfig>,
     extras: HashMap<String, String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct ApiServer {
     #[serde(rename = "type")]
     ty: String,
@@ -635,7 +479,7 @@ pub(crate) struct ApiServer {
     port: String,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub(crate) struct ModelConfig {
     // model name
     name: String,
@@ -665,3 +509,33 @@ pub(crate) struct ModelConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub frequency_penalty: Option<f64>,
 }
+impl ModelConfig {
+    pub(crate) fn chat(metadata: &GgmlMetadata) -> Self {
+        Self {
+            name: metadata.model_name.clone(),
+            ty: "chat".to_string(),
+            ctx_size: metadata.ctx_size,
+            batch_size: metadata.batch_size,
+            prompt_template: Some(metadata.prompt_template),
+            n_predict: Some(metadata.n_predict),
+            reverse_prompt: metadata.reverse_prompt.clone(),
+            n_gpu_layers: Some(metadata.n_gpu_layers),
+            use_mmap: metadata.use_mmap,
+            temperature: Some(metadata.temperature),
+            top_p: Some(metadata.top_p),
+            repeat_penalty: Some(metadata.repeat_penalty),
+            presence_penalty: Some(metadata.presence_penalty),
+            frequency_penalty: Some(metadata.frequency_penalty),
+        }
+    }
+
+    pub(crate) fn embedding(metadata: &GgmlMetadata) -> Self {
+        Self {
+            name: metadata.model_name.clone(),
+            ty: "embedding".to_string(),
+            ctx_size: metadata.ctx_size,
+            batch_size: metadata.batch_size,
+            ..Default::default()
+        }
+    }
+}